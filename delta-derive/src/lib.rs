@@ -0,0 +1,244 @@
+// `#[derive(DeltaSchema)]`, re-exported by the `delta` crate. Generates the
+// table schema for a struct from its fields, so a table created for the
+// struct always matches what `insert_records` writes for it.
+//
+// Column names follow the field names, honouring serde's `rename`,
+// `rename_all` and `skip` since `insert_records` goes through serde. Column types are picked from
+// the field types; anything else can be given explicitly with
+// `#[delta(type = "BIGINT")]`. `Option` fields are nullable columns, every
+// other column is NOT NULL.
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{
+    parenthesized, parse_macro_input, token, Attribute, Data, DeriveInput, Error, Expr, ExprLit,
+    Fields, GenericArgument, Lit, LitStr, PathArguments, Token, Type,
+};
+
+#[proc_macro_derive(DeltaSchema, attributes(delta))]
+pub fn derive_delta_schema(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(input: &DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new(
+                    Span::call_site(),
+                    "DeltaSchema can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new(
+                Span::call_site(),
+                "DeltaSchema can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut rename_all = None;
+    for attr in &input.attrs {
+        if attr.path().is_ident("serde") {
+            for (key, value) in serde_items(attr)? {
+                if key == "rename_all" {
+                    rename_all = value;
+                }
+            }
+        }
+    }
+
+    let mut columns = vec![];
+    for field in fields {
+        let mut name = field.ident.as_ref().unwrap().to_string();
+        let mut renamed = false;
+        let mut skipped = false;
+        let mut typ = None;
+
+        for attr in &field.attrs {
+            if attr.path().is_ident("delta") {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("type") {
+                        typ = Some(meta.value()?.parse::<LitStr>()?.value());
+                        Ok(())
+                    } else if meta.path.is_ident("rename") {
+                        name = meta.value()?.parse::<LitStr>()?.value();
+                        renamed = true;
+                        Ok(())
+                    } else {
+                        Err(meta.error("expected `type` or `rename`"))
+                    }
+                })?;
+            } else if attr.path().is_ident("serde") {
+                for (key, value) in serde_items(attr)? {
+                    match (key.as_str(), value) {
+                        ("rename", Some(value)) => {
+                            name = value;
+                            renamed = true;
+                        }
+                        ("skip" | "skip_serializing", _) => skipped = true,
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        // `insert_records` never sees skipped fields
+        if skipped {
+            continue;
+        }
+
+        if !renamed {
+            if let Some(rule) = &rename_all {
+                name = rename(&name, rule)
+                    .ok_or_else(|| Error::new_spanned(field, "unknown serde rename_all rule"))?;
+            }
+        }
+
+        // `Option` fields are nullable columns of the inner type
+        let (ty, nullable) = match option_inner(&field.ty) {
+            Some(inner) => (inner, true),
+            None => (&field.ty, false),
+        };
+        let mut typ = match typ {
+            Some(typ) => typ,
+            None => sql_type(ty)?.to_owned(),
+        };
+        if nullable && !typ.to_uppercase().ends_with("NULL") {
+            typ.push_str(" NULL");
+        }
+        columns.push(quote! { (#name, #typ) });
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::delta::DeltaSchema for #ident #ty_generics #where_clause {
+            fn delta_schema() -> ::std::vec::Vec<(&'static str, &'static str)> {
+                ::std::vec![#(#columns),*]
+            }
+        }
+    })
+}
+
+// The items of a `#[serde(...)]` attribute, with the value for
+// `key = "..."` items. Values that aren't strings and nested lists like
+// `rename(serialize = "...")` are skipped over.
+fn serde_items(attr: &Attribute) -> Result<Vec<(String, Option<String>)>, Error> {
+    let mut items = vec![];
+    attr.parse_nested_meta(|meta| {
+        let key = meta
+            .path
+            .get_ident()
+            .map(|ident| ident.to_string())
+            .unwrap_or_default();
+
+        if meta.input.peek(Token![=]) {
+            match meta.value()?.parse::<Expr>()? {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(value),
+                    ..
+                }) => items.push((key, Some(value.value()))),
+                _ => items.push((key, None)),
+            }
+        } else if meta.input.peek(token::Paren) {
+            let content;
+            parenthesized!(content in meta.input);
+            content.parse::<proc_macro2::TokenStream>()?;
+            items.push((key, None));
+        } else {
+            items.push((key, None));
+        }
+        Ok(())
+    })?;
+
+    Ok(items)
+}
+
+// Apply a serde `rename_all` rule to a snake_case field name
+fn rename(name: &str, rule: &str) -> Option<String> {
+    let capitalize = |word: &str| {
+        let mut chars = word.chars();
+        chars
+            .next()
+            .map(|c| c.to_uppercase().chain(chars).collect::<String>())
+            .unwrap_or_default()
+    };
+    let words = name.split('_').collect::<Vec<&str>>();
+
+    match rule {
+        "lowercase" => Some(name.to_lowercase()),
+        "UPPERCASE" => Some(name.to_uppercase()),
+        "snake_case" => Some(name.to_owned()),
+        "SCREAMING_SNAKE_CASE" => Some(name.to_uppercase()),
+        "kebab-case" => Some(name.replace('_', "-")),
+        "SCREAMING-KEBAB-CASE" => Some(name.replace('_', "-").to_uppercase()),
+        "PascalCase" => Some(words.iter().map(|w| capitalize(w)).collect()),
+        "camelCase" => Some(
+            words
+                .iter()
+                .enumerate()
+                .map(|(i, w)| match i {
+                    0 => w.to_string(),
+                    _ => capitalize(w),
+                })
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+// `T` for an `Option<T>`
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) => match args.args.first()? {
+            GenericArgument::Type(inner) => Some(inner),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Types are matched on their last path segment, so `chrono::NaiveDate` and
+// `NaiveDate` are the same thing.
+fn sql_type(ty: &Type) -> Result<&'static str, Error> {
+    let name = match ty {
+        Type::Path(path) => path.path.segments.last().map(|s| s.ident.to_string()),
+        Type::Reference(reference) => return sql_type(&reference.elem),
+        _ => None,
+    };
+
+    match name.as_deref() {
+        Some("i64") => Ok("BIGINT"),
+        Some("i32" | "u16") => Ok("INT"),
+        Some("i16" | "u8") => Ok("SMALLINT"),
+        Some("i8") => Ok("TINYINT"),
+        Some("u32") => Ok("BIGINT"),
+        Some("f32") => Ok("FLOAT"),
+        Some("f64") => Ok("DOUBLE"),
+        Some("bool") => Ok("BOOL"),
+        Some("String" | "str") => Ok("TEXT"),
+        Some("NaiveDate") => Ok("DATE"),
+        Some("NaiveDateTime" | "DateTime") => Ok("TIMESTAMP"),
+        Some("Option") => Err(Error::new_spanned(ty, "nested options aren't supported")),
+        _ => Err(Error::new_spanned(
+            ty,
+            "no column type for this field, set one with #[delta(type = \"...\")]",
+        )),
+    }
+}
@@ -1,21 +1,634 @@
-use crate::metadata::DeltaTableMetadata;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use crate::{
+    error::DeltaError, metadata::DeltaTableMetadata, partition::PartitionValues,
+    stats::DeltaFileStats,
+};
+use log::debug;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
+// Field names are written in camelCase as the protocol says, but snake_case
+// spellings written by some early writers are accepted too.
 #[derive(Serialize, Deserialize)]
-#[serde(rename_all = "camelCase", rename_all_fields = "camelCase")]
+#[serde(rename_all = "camelCase")]
 pub enum Action {
-    Add {
-        path: String,
-        partition_values: HashMap<String, String>,
-        size: u64,
-        modification_time: u128,
-        data_change: bool,
-    },
-    Remove {
-        path: String,
-        data_change: bool,
-    },
-    #[serde(rename = "metaData")]
+    Add(Add),
+    Remove(Remove),
+    #[serde(rename = "metaData", alias = "metadata", alias = "meta_data")]
     Metadata(DeltaTableMetadata),
+    Protocol(Protocol),
+    CommitInfo(CommitInfo),
+    Txn(Txn),
+}
+
+// Maps are written with their keys sorted, so the same actions always
+// serialize to the same commit
+pub(crate) fn sorted<S: Serializer, V: Serialize>(
+    map: &HashMap<String, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
+fn sorted_tags<S: Serializer>(
+    tags: &Option<HashMap<String, String>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match tags {
+        Some(tags) => sorted(tags, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+impl Action {
+    // A commit's contents exactly as this crate writes them to the log, one
+    // action per line
+    pub fn serialize_commit(actions: &[Action]) -> Result<String, DeltaError> {
+        Ok(actions
+            .iter()
+            .map(serde_json::to_string)
+            .collect::<Result<Vec<String>, _>>()?
+            .join("\n"))
+    }
+
+    // The actions of a commit, read strictly like this crate reads its own
+    // tables. Blank lines, e.g. a trailing newline, and actions we don't
+    // know are ignored.
+    pub fn parse_commit(contents: &str) -> Result<Vec<Action>, DeltaError> {
+        contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| parse_action(line, false).transpose())
+            .collect()
+    }
+}
+
+// What a commit did, for people and tools looking at the table's history.
+// Nothing reading the table depends on it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitInfo {
+    // Milliseconds since the unix epoch
+    pub timestamp: i64,
+    // e.g. WRITE, named like Spark's operations
+    pub operation: String,
+    // Values are JSON encoded strings where Spark writes them that way,
+    // e.g. lists of columns
+    #[serde(default, serialize_with = "sorted")]
+    pub operation_parameters: HashMap<String, Value>,
+    // Free-form string the writer attached to the commit, see
+    // `WriteOptions::user_metadata`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_metadata: Option<String>,
+    // Counts of what the commit did, e.g. `numAddedFiles`. Like Spark, the
+    // numbers are written as strings.
+    #[serde(
+        default,
+        skip_serializing_if = "HashMap::is_empty",
+        serialize_with = "sorted"
+    )]
+    pub operation_metrics: HashMap<String, String>,
+    // The writer and its version, e.g. `delta/0.1.0`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub engine_info: Option<String>,
+}
+
+// Reader and writer versions below 3 and 7 each imply a fixed set of
+// features. From those versions on the features a table uses are listed
+// explicitly instead.
+// https://github.com/delta-io/delta/blob/master/PROTOCOL.md#protocol-evolution
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Protocol {
+    #[serde(alias = "min_reader_version")]
+    pub min_reader_version: i32,
+    #[serde(alias = "min_writer_version")]
+    pub min_writer_version: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reader_features: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub writer_features: Option<Vec<String>>,
+}
+
+// Tables we wrote before writing protocol actions have none in their log.
+// These are the versions they satisfy.
+impl Default for Protocol {
+    fn default() -> Self {
+        Protocol {
+            min_reader_version: 1,
+            min_writer_version: 2,
+            reader_features: None,
+            writer_features: None,
+        }
+    }
+}
+
+// The latest version of an application's writes that made it into the
+// table, so a job retrying a write can tell whether it was committed.
+// Versions are the application's own, e.g. a batch number.
+// https://github.com/delta-io/delta/blob/master/PROTOCOL.md#transaction-identifiers
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Txn {
+    #[serde(alias = "app_id")]
+    pub app_id: String,
+    pub version: i64,
+    // Milliseconds since the unix epoch
+    #[serde(
+        default,
+        alias = "last_updated",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub last_updated: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Add {
+    pub path: String,
+    #[serde(alias = "partition_values", serialize_with = "sorted")]
+    pub partition_values: PartitionValues,
+    pub size: u64,
+    // Milliseconds since the unix epoch
+    #[serde(alias = "modification_time")]
+    pub modification_time: i64,
+    #[serde(alias = "data_change")]
+    pub data_change: bool,
+    // Per the protocol this is a JSON encoded `DeltaFileStats`, not a
+    // nested object. Older log entries won't have it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<String>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "sorted_tags"
+    )]
+    pub tags: Option<HashMap<String, String>>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Remove {
+    pub path: String,
+    #[serde(alias = "data_change")]
+    pub data_change: bool,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "sorted_tags"
+    )]
+    pub tags: Option<HashMap<String, String>>,
+}
+
+// Features readers have to support as well as writers. Every other
+// feature only concerns writers.
+const READER_WRITER_FEATURES: &[&str] = &[
+    "columnMapping",
+    "deletionVectors",
+    "timestampNtz",
+    "typeWidening",
+    "v2Checkpoint",
+    "vacuumProtocolCheck",
+];
+const WRITER_FEATURES: &[&str] = &[
+    "appendOnly",
+    "changeDataFeed",
+    "checkConstraints",
+    "clustering",
+    "domainMetadata",
+    "generatedColumns",
+    "icebergCompatV1",
+    "icebergCompatV2",
+    "identityColumns",
+    "inCommitTimestamp",
+    "invariants",
+    "rowTracking",
+];
+
+// The highest protocol versions this crate reads and writes tables with,
+// as long as they need no features besides the supported ones below
+pub const MAX_READER_VERSION: i32 = 3;
+pub const MAX_WRITER_VERSION: i32 = 7;
+// `vacuumProtocolCheck` only asks that vacuum checks the protocol like any
+// other write, which it does.
+const SUPPORTED_READER_FEATURES: &[&str] = &["vacuumProtocolCheck"];
+// Writer version 2 is what tables created here have always had. Its
+// `appendOnly` and `invariants` only matter when a table turns them on,
+// through the `delta.appendOnly` property or invariants in its schema,
+// which this crate never does. Likewise `changeDataFeed`, implied by
+// writer version 4 along with `generatedColumns`, only matters once
+// `delta.enableChangeDataFeed` is set, and writes to such tables are
+// refused, see `DeltaTableMetadata::check_writable`.
+const SUPPORTED_WRITER_FEATURES: &[&str] = &[
+    "appendOnly",
+    "changeDataFeed",
+    "checkConstraints",
+    "generatedColumns",
+    "invariants",
+    "vacuumProtocolCheck",
+];
+
+// Writer features and the legacy writer versions that imply them
+const IMPLIED_WRITER_FEATURES: &[(i32, &[&str])] = &[
+    (2, &["appendOnly", "invariants"]),
+    (3, &["checkConstraints"]),
+    (4, &["changeDataFeed", "generatedColumns"]),
+    (5, &["columnMapping"]),
+    (6, &["identityColumns"]),
+];
+
+impl Protocol {
+    // Check this crate can read a table with this protocol
+    pub fn check_readable(&self) -> Result<(), DeltaError> {
+        Protocol::check_supported(
+            "reader",
+            self.min_reader_version,
+            MAX_READER_VERSION,
+            self.reader_features(),
+            SUPPORTED_READER_FEATURES,
+        )
+    }
+
+    // Check this crate can write to a table with this protocol
+    pub fn check_writable(&self) -> Result<(), DeltaError> {
+        Protocol::check_supported(
+            "writer",
+            self.min_writer_version,
+            MAX_WRITER_VERSION,
+            self.writer_features(),
+            SUPPORTED_WRITER_FEATURES,
+        )
+    }
+
+    fn check_supported(
+        kind: &str,
+        version: i32,
+        max_version: i32,
+        features: Vec<String>,
+        supported: &[&str],
+    ) -> Result<(), DeltaError> {
+        if version > max_version {
+            return Err(DeltaError::UnsupportedProtocol(format!(
+                "table requires {} version {}, only up to {} is supported",
+                kind, version, max_version
+            )));
+        }
+        let mut unsupported = features
+            .into_iter()
+            .filter(|feature| !supported.contains(&feature.as_str()))
+            .collect::<Vec<_>>();
+        if !unsupported.is_empty() {
+            unsupported.sort();
+            return Err(DeltaError::UnsupportedProtocol(format!(
+                "table requires unsupported {} features: {}",
+                kind,
+                unsupported.join(", ")
+            )));
+        }
+
+        Ok(())
+    }
+
+    // This protocol raised to at least the given versions, with `features`
+    // added. Versions can't be lowered, and features can only be listed
+    // from reader version 3 and writer version 7 on. Moving to those
+    // versions lists the features the old versions implied.
+    pub fn upgrade(
+        &self,
+        min_reader: i32,
+        min_writer: i32,
+        features: &[&str],
+    ) -> Result<Protocol, DeltaError> {
+        if !(1..=3).contains(&min_reader) || !(1..=7).contains(&min_writer) {
+            return Err(DeltaError::InvalidData(format!(
+                "unsupported protocol versions ({}, {})",
+                min_reader, min_writer
+            )));
+        }
+        if min_reader < self.min_reader_version || min_writer < self.min_writer_version {
+            return Err(DeltaError::InvalidData(format!(
+                "can't downgrade protocol from ({}, {}) to ({}, {})",
+                self.min_reader_version, self.min_writer_version, min_reader, min_writer
+            )));
+        }
+        if min_reader == 3 && min_writer < 7 {
+            return Err(DeltaError::InvalidData(
+                "reader version 3 requires writer version 7".to_owned(),
+            ));
+        }
+        for feature in features {
+            let reader = READER_WRITER_FEATURES.contains(feature);
+            if !reader && !WRITER_FEATURES.contains(feature) {
+                return Err(DeltaError::InvalidData(format!(
+                    "unknown table feature '{}'",
+                    feature
+                )));
+            }
+            if min_writer < 7 {
+                return Err(DeltaError::InvalidData(format!(
+                    "table feature '{}' requires writer version 7",
+                    feature
+                )));
+            }
+            if reader && min_reader < 3 {
+                return Err(DeltaError::InvalidData(format!(
+                    "table feature '{}' requires reader version 3",
+                    feature
+                )));
+            }
+        }
+
+        let mut writer_features = self.writer_features().into_iter().collect::<BTreeSet<_>>();
+        writer_features.extend(features.iter().map(|f| f.to_string()));
+        let mut reader_features = self.reader_features().into_iter().collect::<BTreeSet<_>>();
+        reader_features.extend(
+            writer_features
+                .iter()
+                .filter(|f| READER_WRITER_FEATURES.contains(&f.as_str()))
+                .cloned(),
+        );
+
+        Ok(Protocol {
+            min_reader_version: min_reader,
+            min_writer_version: min_writer,
+            reader_features: (min_reader == 3).then(|| reader_features.into_iter().collect()),
+            writer_features: (min_writer == 7).then(|| writer_features.into_iter().collect()),
+        })
+    }
+
+    // This protocol with writer feature `feature`, e.g. once a table gets
+    // its first CHECK constraint, so other writers know to honour it. A
+    // table with legacy versions moves to the writer version implying the
+    // feature rather than listing it.
+    pub fn with_writer_feature(&self, feature: &str) -> Result<Protocol, DeltaError> {
+        if self.writer_features().iter().any(|f| f == feature) {
+            return Ok(self.clone());
+        }
+        if self.min_writer_version == 7 {
+            return self.upgrade(self.min_reader_version, 7, &[feature]);
+        }
+
+        let version = IMPLIED_WRITER_FEATURES
+            .iter()
+            .find(|(_, features)| features.contains(&feature))
+            .map_or(7, |(version, _)| *version);
+        match version {
+            7 => self.upgrade(self.min_reader_version, 7, &[feature]),
+            _ => self.upgrade(self.min_reader_version, version, &[]),
+        }
+    }
+
+    // The writer features this protocol supports, listed or implied by its
+    // writer version
+    fn writer_features(&self) -> Vec<String> {
+        if let Some(features) = &self.writer_features {
+            return features.clone();
+        }
+
+        IMPLIED_WRITER_FEATURES
+            .iter()
+            .filter(|(version, _)| *version <= self.min_writer_version)
+            .flat_map(|(_, features)| features.iter().map(|f| f.to_string()))
+            .collect()
+    }
+
+    fn reader_features(&self) -> Vec<String> {
+        match &self.reader_features {
+            Some(features) => features.clone(),
+            None if self.min_reader_version >= 2 => vec!["columnMapping".to_owned()],
+            None => vec![],
+        }
+    }
+}
+
+impl Add {
+    // Stats are only an optimization, so unreadable stats are ignored
+    // rather than treated as an error.
+    pub fn parsed_stats(&self) -> Option<DeltaFileStats> {
+        serde_json::from_str(self.stats.as_ref()?).ok()
+    }
+}
+
+// Actions as `Action` names them when we write them, and the aliases it
+// reads
+const ACTIONS: &[&str] = &[
+    "add",
+    "remove",
+    "metaData",
+    "metadata",
+    "meta_data",
+    "protocol",
+    "commitInfo",
+    "txn",
+];
+
+// Parse a line of the log. Actions other writers use that we don't, like
+// Spark's `cdc` or delta-rs' `domainMetadata`, are skipped: any the table
+// depends on come with a table feature, which the protocol check refuses.
+// So is a `commitInfo` that doesn't parse, see `parse_commit_info`.
+// Strictly, every action we know has to be spelled as we write it.
+// Leniently, for tables written by other or older writers:
+// - action and field names are matched ignoring case and underscores
+// - fields older writers left out get the protocol's defaults
+pub fn parse_action(line: &str, lenient: bool) -> Result<Option<Action>, DeltaError> {
+    if !lenient {
+        let action = serde_json::from_str::<Value>(line)?;
+        if let Some(kind) = action
+            .as_object()
+            .filter(|action| action.len() == 1)
+            .and_then(|action| action.keys().next())
+            .filter(|kind| !ACTIONS.contains(&kind.as_str()))
+        {
+            debug!(target: "delta::log", "skipping unknown action '{}'", kind);
+            return Ok(None);
+        }
+        if action.get("commitInfo").is_some() {
+            return Ok(parse_commit_info(action));
+        }
+        return Ok(Some(serde_json::from_value(action)?));
+    }
+
+    let Value::Object(action) = serde_json::from_str(line)? else {
+        return Err(DeltaError::InvalidData(format!("not an action: {}", line)));
+    };
+    let Some((kind, Value::Object(body))) = action.into_iter().next() else {
+        return Err(DeltaError::InvalidData(format!("not an action: {}", line)));
+    };
+
+    let (kind, fields, defaults) = match normalize(&kind).as_str() {
+        "add" => (
+            "add",
+            ADD_FIELDS,
+            json!({"partitionValues": {}, "modificationTime": 0, "dataChange": true}),
+        ),
+        "remove" => ("remove", REMOVE_FIELDS, json!({"dataChange": true})),
+        "protocol" => ("protocol", PROTOCOL_FIELDS, json!({})),
+        "txn" => ("txn", TXN_FIELDS, json!({})),
+        "metadata" => (
+            "metaData",
+            METADATA_FIELDS,
+            json!({
+                "name": "",
+                "format": {"provider": "parquet", "options": {}},
+                "partitionColumns": [],
+                "configuration": {},
+            }),
+        ),
+        "commitinfo" => ("commitInfo", COMMIT_INFO_FIELDS, json!({})),
+        _ => {
+            debug!(target: "delta::log", "skipping unknown action '{}'", kind);
+            return Ok(None);
+        }
+    };
+
+    let mut body = rename_fields(body, fields);
+    if let Value::Object(defaults) = defaults {
+        for (field, default) in defaults {
+            match body.get(&field) {
+                None | Some(Value::Null) => {
+                    body.insert(field, default);
+                }
+                _ => {}
+            }
+        }
+    }
+    if let Some(Value::Object(format)) = body.get_mut("format") {
+        format.entry("options").or_insert_with(|| json!({}));
+    }
+
+    if kind == "commitInfo" {
+        if let Some(Value::Object(metrics)) = body.get_mut("operationMetrics") {
+            for value in metrics.values_mut() {
+                if !value.is_string() {
+                    *value = Value::from(value.to_string());
+                }
+            }
+        }
+        return Ok(parse_commit_info(json!({ kind: body })));
+    }
+
+    Ok(Some(serde_json::from_value(json!({ kind: body }))?))
+}
+
+// Nothing reading the table depends on commitInfo, and other writers put
+// what they like in it, so one we can't make sense of, e.g. without an
+// operation, is skipped rather than failing the read
+fn parse_commit_info(action: Value) -> Option<Action> {
+    match serde_json::from_value(action) {
+        Ok(action) => Some(action),
+        Err(e) => {
+            debug!(target: "delta::log", "skipping unreadable commitInfo: {}", e);
+            None
+        }
+    }
+}
+
+const ADD_FIELDS: &[&str] = &[
+    "path",
+    "partitionValues",
+    "size",
+    "modificationTime",
+    "dataChange",
+    "stats",
+    "tags",
+];
+const REMOVE_FIELDS: &[&str] = &["path", "dataChange", "tags"];
+const PROTOCOL_FIELDS: &[&str] = &[
+    "minReaderVersion",
+    "minWriterVersion",
+    "readerFeatures",
+    "writerFeatures",
+];
+const TXN_FIELDS: &[&str] = &["appId", "version", "lastUpdated"];
+const COMMIT_INFO_FIELDS: &[&str] = &[
+    "timestamp",
+    "operation",
+    "operationParameters",
+    "userMetadata",
+    "operationMetrics",
+    "engineInfo",
+];
+const METADATA_FIELDS: &[&str] = &[
+    "id",
+    "name",
+    "format",
+    "schemaString",
+    "partitionColumns",
+    "configuration",
+];
+
+// Give fields matching one of `fields` that spelling. Others are left as
+// they are, and ignored when deserializing.
+fn rename_fields(body: Map<String, Value>, fields: &[&str]) -> Map<String, Value> {
+    body.into_iter()
+        .map(|(key, value)| {
+            let key = match fields.iter().find(|f| normalize(f) == normalize(&key)) {
+                Some(field) => field.to_string(),
+                None => key,
+            };
+            (key, value)
+        })
+        .collect()
+}
+
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| *c != '_')
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lenient_parsing_matches_variant_spellings_and_fills_defaults() {
+        let line = r#"{"Add": {"PATH": "part-0.parquet", "size": 10, "data_change": false}}"#;
+        assert!(parse_action(line, false).unwrap().is_none());
+
+        let Some(Action::Add(add)) = parse_action(line, true).unwrap() else {
+            panic!("expected an add");
+        };
+        assert_eq!(add.path, "part-0.parquet");
+        assert_eq!(add.size, 10);
+        assert!(!add.data_change);
+        assert!(add.partition_values.is_empty());
+        assert_eq!(add.modification_time, 0);
+    }
+
+    #[test]
+    fn lenient_parsing_keeps_commit_info() {
+        let line = r#"{"commit_info": {"timestamp": 1, "operation": "WRITE",
+            "operationParameters": {"mode": "Append"}, "userMetadata": "job-1",
+            "operationMetrics": {"numFiles": 2, "numOutputRows": "5"}}}"#;
+        let Some(Action::CommitInfo(info)) = parse_action(line, true).unwrap() else {
+            panic!("expected a commitInfo");
+        };
+        assert_eq!(info.operation, "WRITE");
+        assert_eq!(info.operation_parameters["mode"], "Append");
+        assert_eq!(info.user_metadata.as_deref(), Some("job-1"));
+        assert_eq!(info.operation_metrics["numFiles"], "2");
+        assert_eq!(info.operation_metrics["numOutputRows"], "5");
+    }
+
+    #[test]
+    fn commit_info_that_cant_be_read_is_skipped() {
+        let line = r#"{"commitInfo": {"timestamp": 1}}"#;
+        assert!(parse_action(line, false).unwrap().is_none());
+        assert!(parse_action(line, true).unwrap().is_none());
+
+        let line = r#"{"commitInfo": {"timestamp": 1, "operation": "WRITE"}}"#;
+        assert!(matches!(
+            parse_action(line, false).unwrap(),
+            Some(Action::CommitInfo(_))
+        ));
+    }
+
+    #[test]
+    fn unknown_actions_are_skipped() {
+        let line = r#"{"cdc": {"path": "_change_data/part-0.parquet"}}"#;
+        assert!(parse_action(line, false).unwrap().is_none());
+        assert!(parse_action(line, true).unwrap().is_none());
+    }
 }
@@ -0,0 +1,96 @@
+use crate::{error::DeltaError, row_groups::chunk_to_frame};
+use polars::prelude::*;
+use polars_parquet::read::{self, FileReader};
+use std::{collections::VecDeque, fs::File, path::PathBuf};
+
+// A data file still to be read, with its partition values as literal
+// columns
+pub struct PendingFile {
+    pub path: PathBuf,
+    pub partition: Vec<Expr>,
+}
+
+// Reads data files one after the other, a batch of at most `batch_size`
+// rows at a time. Only the file being read is open, and only the row group
+// the current batch comes from is decoded. Batches don't span files, so
+// the last batch of each file can be smaller.
+pub struct Batches {
+    files: VecDeque<PendingFile>,
+    // Puts each batch in the table's shape, like `DeltaTable::scan`
+    columns: Vec<Expr>,
+    batch_size: usize,
+    current: Option<(FileReader<File>, ArrowSchema, Vec<Expr>)>,
+}
+
+impl Batches {
+    pub fn new(files: Vec<PendingFile>, columns: Vec<Expr>, batch_size: usize) -> Batches {
+        Batches {
+            files: files.into(),
+            columns,
+            batch_size: batch_size.max(1),
+            current: None,
+        }
+    }
+
+    fn open(
+        &self,
+        file: PendingFile,
+    ) -> Result<(FileReader<File>, ArrowSchema, Vec<Expr>), DeltaError> {
+        let mut reader = File::open(&file.path)?;
+        let metadata = read::read_metadata(&mut reader)?;
+        let schema = read::infer_schema(&metadata)?;
+        let reader = FileReader::new(
+            reader,
+            metadata.row_groups,
+            schema.clone(),
+            Some(self.batch_size),
+            None,
+            None,
+        );
+
+        Ok((reader, schema, file.partition))
+    }
+
+    fn next_batch(&mut self) -> Result<Option<DataFrame>, DeltaError> {
+        loop {
+            if self.current.is_none() {
+                match self.files.pop_front() {
+                    Some(file) => self.current = Some(self.open(file)?),
+                    None => return Ok(None),
+                }
+            }
+
+            let Some((reader, schema, partition)) = &mut self.current else {
+                continue;
+            };
+            match reader.next() {
+                Some(chunk) => {
+                    return Ok(Some(
+                        chunk_to_frame(chunk?, schema)?
+                            .lazy()
+                            .with_columns(partition.as_slice())
+                            .select(&self.columns)
+                            .collect()?,
+                    ))
+                }
+                None => self.current = None,
+            }
+        }
+    }
+}
+
+impl Iterator for Batches {
+    type Item = Result<DataFrame, DeltaError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_batch() {
+            Ok(batch) => batch.map(Ok),
+            Err(e) => {
+                // Don't keep reading after a failure
+                self.files.clear();
+                self.current = None;
+                Some(Err(e))
+            }
+        }
+    }
+}
@@ -0,0 +1,164 @@
+// Bloom filters over the unique key of each data file, kept in a tag on
+// its Add action. Stats only hold a file's min and max, which rule out
+// next to nothing for keys like UUIDs that are spread over every file. A
+// bloom filter rules out most files holding none of a set of keys, so
+// writes matching rows by key only read the files that can hold them.
+
+use crate::error::DeltaError;
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use xxhash_rust::xxh3::xxh3_128;
+
+pub const BLOOM_FILTER_TAG: &str = "bloomFilter";
+
+// About a 1% false positive rate
+const BITS_PER_KEY: usize = 10;
+// Caps the tag at 32 KiB of hex, since it's repeated in every checkpoint
+const MAX_BITS: usize = 1 << 17;
+// Below this a filter matches too many keys to be worth its space
+const MIN_BITS_PER_KEY: usize = 2;
+
+pub(crate) struct BloomFilter {
+    // The key columns the filter was built from, in order
+    columns: Vec<String>,
+    hashes: u32,
+    bits: Vec<u64>,
+}
+
+// How the filter is stored in the tag, with the bits as hex
+#[derive(Serialize, Deserialize)]
+struct Encoded {
+    columns: Vec<String>,
+    hashes: u32,
+    bits: String,
+}
+
+impl BloomFilter {
+    // A filter over the values of `columns` in `df`'s rows, or `None` when
+    // there are too many rows for one to be of use. Rows with a null in
+    // one of the columns are left out, since a null key never matches.
+    pub(crate) fn build(
+        df: &DataFrame,
+        columns: &[String],
+    ) -> Result<Option<BloomFilter>, DeltaError> {
+        let keys = key_hashes(df, columns)?
+            .into_iter()
+            .flatten()
+            .collect::<Vec<u128>>();
+        let size = (keys.len() * BITS_PER_KEY)
+            .next_power_of_two()
+            .clamp(64, MAX_BITS);
+        if size < keys.len() * MIN_BITS_PER_KEY {
+            return Ok(None);
+        }
+
+        // The number of hashes that minimizes false positives
+        let hashes = match keys.len() {
+            0 => 1,
+            n => ((size / n) as f64 * std::f64::consts::LN_2).round() as u32,
+        };
+        let mut filter = BloomFilter {
+            columns: columns.to_vec(),
+            hashes: hashes.clamp(1, 16),
+            bits: vec![0; size / 64],
+        };
+        for key in keys {
+            for bit in filter.positions(key).collect::<Vec<usize>>() {
+                filter.bits[bit / 64] |= 1 << (bit % 64);
+            }
+        }
+
+        Ok(Some(filter))
+    }
+
+    // The filter in a file's tags, if it has a readable one
+    pub(crate) fn from_tags(tags: &Option<HashMap<String, String>>) -> Option<BloomFilter> {
+        let encoded: Encoded = serde_json::from_str(tags.as_ref()?.get(BLOOM_FILTER_TAG)?).ok()?;
+        if encoded.bits.is_empty() || !encoded.bits.len().is_multiple_of(16) {
+            return None;
+        }
+
+        let bits = (0..encoded.bits.len())
+            .step_by(16)
+            .map(|i| u64::from_str_radix(encoded.bits.get(i..i + 16)?, 16).ok())
+            .collect::<Option<Vec<u64>>>()?;
+        Some(BloomFilter {
+            columns: encoded.columns,
+            hashes: encoded.hashes,
+            bits,
+        })
+    }
+
+    pub(crate) fn to_tag(&self) -> Result<String, DeltaError> {
+        let bits = self
+            .bits
+            .iter()
+            .map(|word| format!("{:016x}", word))
+            .collect::<String>();
+        Ok(serde_json::to_string(&Encoded {
+            columns: self.columns.clone(),
+            hashes: self.hashes,
+            bits,
+        })?)
+    }
+
+    // Whether the file could hold a row matching one of `keys`' rows. The
+    // filter only rules files out when `keys` has all of its columns, and
+    // may say yes for keys the file doesn't hold.
+    pub(crate) fn may_contain_any(&self, keys: &DataFrame) -> Result<bool, DeltaError> {
+        let names = keys.get_column_names();
+        if !self.columns.iter().all(|c| names.contains(&c.as_str())) {
+            return Ok(true);
+        }
+
+        Ok(key_hashes(keys, &self.columns)?
+            .into_iter()
+            .flatten()
+            .any(|key| {
+                self.positions(key)
+                    .all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+            }))
+    }
+
+    fn positions(&self, key: u128) -> impl Iterator<Item = usize> + '_ {
+        // Double hashing: the two halves of the key's hash make the rest
+        let (h1, h2) = (key as u64, (key >> 64) as u64 | 1);
+        let size = self.bits.len() as u64 * 64;
+        (0..self.hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % size) as usize)
+    }
+}
+
+// A hash of each row's values in `columns`, or `None` for rows with a null
+// in one of them. Values are hashed as strings, so both sides of a match
+// have to have the table's column types.
+fn key_hashes(df: &DataFrame, columns: &[String]) -> Result<Vec<Option<u128>>, DeltaError> {
+    let values = columns
+        .iter()
+        .map(|c| df.column(c)?.cast(&DataType::Utf8))
+        .collect::<PolarsResult<Vec<Series>>>()?;
+    let values = values
+        .iter()
+        .map(|c| c.utf8())
+        .collect::<PolarsResult<Vec<&Utf8Chunked>>>()?;
+
+    let mut hashes = vec![];
+    let mut key = vec![];
+    for row in 0..df.height() {
+        key.clear();
+        let mut has_null = false;
+        for column in &values {
+            match column.get(row) {
+                Some(value) => {
+                    key.extend_from_slice(value.as_bytes());
+                    // Can't appear in UTF-8, so values can't run together
+                    key.push(0xff);
+                }
+                None => has_null = true,
+            }
+        }
+        hashes.push((!has_null).then(|| xxh3_128(&key)));
+    }
+
+    Ok(hashes)
+}
@@ -0,0 +1,223 @@
+// Snapshots of every table opened in the process are cached here, so
+// reading a table only replays the commits made since it was last read
+// rather than its whole log. The cache is shared by all tables, with a
+// limit on how much memory it takes up in total: once over the limit, the
+// snapshots that were used least recently are dropped.
+
+use crate::{actions::Add, metrics::CacheStats, table::Snapshot};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+const DEFAULT_MEMORY_LIMIT: usize = 256 * 1024 * 1024;
+
+// Rough per-file overhead of an Add on top of its strings
+const ADD_OVERHEAD: usize = 128;
+
+struct Entry {
+    snapshot: Arc<Snapshot>,
+    size: usize,
+    // Value of `SnapshotCache::clock` when the entry was last used
+    last_used: u64,
+}
+
+struct SnapshotCache {
+    entries: HashMap<String, Entry>,
+    memory_limit: usize,
+    size: usize,
+    clock: u64,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+fn cache() -> &'static Mutex<SnapshotCache> {
+    static CACHE: OnceLock<Mutex<SnapshotCache>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(SnapshotCache::new(DEFAULT_MEMORY_LIMIT)))
+}
+
+// Run `f` on the cache. A panic while holding the lock can't leave the
+// cache in a state that's unsafe to use, so a poisoned lock is ignored.
+fn with_cache<T>(f: impl FnOnce(&mut SnapshotCache) -> T) -> T {
+    let mut cache = cache().lock().unwrap_or_else(|e| e.into_inner());
+    f(&mut cache)
+}
+
+// How much memory cached snapshots can take up across all tables, in
+// bytes. Zero turns caching off. Defaults to 256 MiB.
+pub fn set_memory_limit(bytes: usize) {
+    with_cache(|cache| {
+        cache.memory_limit = bytes;
+        cache.evict();
+    })
+}
+
+pub fn memory_limit() -> usize {
+    with_cache(|cache| cache.memory_limit)
+}
+
+// Drop every cached snapshot
+pub fn clear() {
+    with_cache(|cache| {
+        cache.entries.clear();
+        cache.size = 0;
+    })
+}
+
+pub fn stats() -> CacheStats {
+    with_cache(|cache| CacheStats {
+        tables: cache.entries.len(),
+        size: cache.size,
+        hits: cache.hits,
+        misses: cache.misses,
+        evictions: cache.evictions,
+    })
+}
+
+// The latest snapshot cached for the table `key`
+pub(crate) fn get(key: &str) -> Option<Arc<Snapshot>> {
+    with_cache(|cache| cache.get(key))
+}
+
+// Cache `snapshot` for the table `key`, unless a later version of it is
+// already cached
+pub(crate) fn put(key: &str, snapshot: Snapshot) {
+    let size = estimated_size(&snapshot);
+    with_cache(|cache| cache.put(key, snapshot, size))
+}
+
+impl SnapshotCache {
+    fn new(memory_limit: usize) -> SnapshotCache {
+        SnapshotCache {
+            entries: HashMap::new(),
+            memory_limit,
+            size: 0,
+            clock: 0,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<Arc<Snapshot>> {
+        self.clock += 1;
+        match self.entries.get_mut(key) {
+            Some(entry) => {
+                entry.last_used = self.clock;
+                self.hits += 1;
+                Some(entry.snapshot.clone())
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    // `size` is the snapshot's `estimated_size`, worked out before taking
+    // the lock
+    fn put(&mut self, key: &str, snapshot: Snapshot, size: usize) {
+        if size > self.memory_limit {
+            return;
+        }
+        if let Some(entry) = self.entries.get(key) {
+            if entry.snapshot.version >= snapshot.version {
+                return;
+            }
+        }
+
+        self.clock += 1;
+        let entry = Entry {
+            snapshot: Arc::new(snapshot),
+            size,
+            last_used: self.clock,
+        };
+        self.size += size;
+        if let Some(old) = self.entries.insert(key.to_owned(), entry) {
+            self.size -= old.size;
+        }
+        self.evict();
+    }
+
+    // Drop the least recently used snapshots until the cache fits its limit
+    fn evict(&mut self) {
+        while self.size > self.memory_limit {
+            let Some(key) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+
+            if let Some(entry) = self.entries.remove(&key) {
+                self.size -= entry.size;
+                self.evictions += 1;
+            }
+        }
+    }
+}
+
+// Roughly how much memory a snapshot takes up. Almost all of it is the
+// Add actions, so only those are counted in any detail.
+fn estimated_size(snapshot: &Snapshot) -> usize {
+    let add_size = |add: &Add| {
+        let partition_values = add
+            .partition_values
+            .iter()
+            .map(|(k, v)| k.len() + v.as_ref().map_or(0, String::len))
+            .sum::<usize>();
+        let tags = add
+            .tags
+            .iter()
+            .flatten()
+            .map(|(k, v)| k.len() + v.len())
+            .sum::<usize>();
+        // The path is also the file's key in `added_in`
+        ADD_OVERHEAD
+            + 2 * add.path.len()
+            + add.stats.as_ref().map_or(0, String::len)
+            + partition_values
+            + tags
+    };
+
+    std::mem::size_of::<Snapshot>()
+        + snapshot.metadata.schema_string().len()
+        + snapshot.files.iter().map(add_size).sum::<usize>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempTable;
+
+    #[test]
+    fn least_recently_used_snapshots_are_evicted() {
+        let temp = TempTable::new(vec![("id", "int")]).unwrap();
+        let table = temp.table().unwrap();
+        let old = table.snapshot().unwrap();
+        table.insert(vec![vec!["1"]]).unwrap();
+        let snapshot = table.snapshot().unwrap();
+        let size = estimated_size(&snapshot);
+
+        // Room for two snapshots
+        let mut cache = SnapshotCache::new(2 * size);
+        cache.put("a", snapshot.clone(), size);
+        cache.put("b", snapshot.clone(), size);
+        assert!(cache.get("a").is_some());
+        cache.put("c", snapshot.clone(), size);
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").is_some() && cache.get("c").is_some());
+        assert_eq!((cache.evictions, cache.size), (1, 2 * size));
+
+        // An older version never replaces a newer one
+        cache.put("a", old, size);
+        assert_eq!(cache.get("a").unwrap().version, snapshot.version);
+        // Nor is a snapshot bigger than the whole cache kept
+        cache.put("d", snapshot, 2 * size + 1);
+        assert!(cache.get("d").is_none());
+        assert_eq!((cache.hits, cache.misses), (4, 2));
+    }
+}
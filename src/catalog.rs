@@ -0,0 +1,329 @@
+use crate::{
+    error::DeltaError,
+    identifier::{join_qualified_name, split_qualified_name},
+    information_schema,
+    sql::{parse_statement, run_query, QueryScan},
+    table::DeltaTable,
+    value::deserialize_rows,
+};
+use polars::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sqlparser::ast::{Ident, Statement};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+// The registry file's contents. Names are stored in their canonical form,
+// see `canonical_name`.
+#[derive(Serialize, Deserialize, Default)]
+struct Registry {
+    // Logical name -> table URI
+    tables: BTreeMap<String, String>,
+    // Alias -> logical name
+    #[serde(default)]
+    aliases: BTreeMap<String, String>,
+}
+
+// Maps logical table names like `prod.events` to where the tables actually
+// live, so callers don't need to know their paths. The mapping is kept in a
+// small JSON registry file that is rewritten on every change.
+//
+// Relative table paths are resolved against the registry file's directory,
+// so a registry can be moved around together with its tables.
+pub struct DeltaCatalog {
+    path: PathBuf,
+    registry: Registry,
+}
+
+impl DeltaCatalog {
+    // Load the catalog stored at `path`. A missing file is an empty catalog,
+    // and is only created once something is registered.
+    pub fn open(path: &str) -> Result<DeltaCatalog, DeltaError> {
+        let registry = match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Registry::default(),
+            Err(e) => return Err(DeltaError::IOError(e)),
+        };
+
+        Ok(DeltaCatalog {
+            path: PathBuf::from(path),
+            registry,
+        })
+    }
+
+    // Register the table at `uri` under `name`, replacing any table that
+    // was registered under that name before.
+    pub fn register(&mut self, name: &str, uri: &str) -> Result<(), DeltaError> {
+        let name = DeltaCatalog::canonical_name(name)?;
+        if self.registry.aliases.contains_key(&name) {
+            return Err(DeltaError::TableAlreadyExists);
+        }
+
+        self.registry.tables.insert(name, uri.to_owned());
+        self.save()
+    }
+
+    // Make `alias` another name for the registered table `name`
+    pub fn alias(&mut self, alias: &str, name: &str) -> Result<(), DeltaError> {
+        let alias = DeltaCatalog::canonical_name(alias)?;
+        let name = self.resolve_name(name)?;
+        if self.registry.tables.contains_key(&alias) {
+            return Err(DeltaError::TableAlreadyExists);
+        }
+
+        self.registry.aliases.insert(alias, name);
+        self.save()
+    }
+
+    // Remove a table or an alias. Removing a table also removes its aliases.
+    // The table's data is left alone.
+    pub fn unregister(&mut self, name: &str) -> Result<(), DeltaError> {
+        let canonical = DeltaCatalog::canonical_name(name)?;
+        if self.registry.aliases.remove(&canonical).is_none() {
+            if self.registry.tables.remove(&canonical).is_none() {
+                return Err(DeltaError::TableNotFound(name.to_owned()));
+            }
+            self.registry
+                .aliases
+                .retain(|_, target| *target != canonical);
+        }
+
+        self.save()
+    }
+
+    // The URI of the table registered as `name`, following aliases
+    pub fn resolve(&self, name: &str) -> Result<&str, DeltaError> {
+        let name = self.resolve_name(name)?;
+        Ok(&self.registry.tables[&name])
+    }
+
+    pub fn open_table(&self, name: &str) -> Result<DeltaTable, DeltaError> {
+        let path = self.local_path(self.resolve(name)?)?;
+        DeltaTable::open(&path.to_string_lossy())
+    }
+
+    // Registered tables and their URIs, sorted by name. Aliases aren't
+    // included.
+    pub fn tables(&self) -> Vec<(&str, &str)> {
+        self.registry
+            .tables
+            .iter()
+            .map(|(name, uri)| (name.as_str(), uri.as_str()))
+            .collect()
+    }
+
+    // Aliases and the names they point to, sorted by alias
+    pub fn aliases(&self) -> Vec<(&str, &str)> {
+        self.registry
+            .aliases
+            .iter()
+            .map(|(alias, name)| (alias.as_str(), name.as_str()))
+            .collect()
+    }
+
+    // Run a SQL statement against the catalog's tables. Tables are referred
+    // to by their registered names or aliases, and the catalog itself can be
+    // queried through the `information_schema.tables` and
+    // `information_schema.columns` virtual tables. `SHOW TABLES` and
+    // `SHOW COLUMNS FROM <table>` are shorthands for those.
+    pub fn query(&self, sql: &str) -> Result<DataFrame, DeltaError> {
+        match parse_statement(sql)? {
+            Statement::Query(query) => run_query(query, |name, scan| self.lazy_frame(name, scan)),
+            Statement::ShowTables {
+                db_name, filter, ..
+            } => {
+                if filter.is_some() {
+                    return Err(DeltaError::InvalidQuery(
+                        "SHOW TABLES doesn't support filters".to_owned(),
+                    ));
+                }
+
+                let tables = information_schema::tables(self)?;
+                match db_name {
+                    Some(schema) => Ok(tables
+                        .lazy()
+                        .filter(col("table_schema").eq(lit(schema.value)))
+                        .collect()?),
+                    None => Ok(tables),
+                }
+            }
+            Statement::ShowColumns {
+                table_name, filter, ..
+            } => {
+                if filter.is_some() {
+                    return Err(DeltaError::InvalidQuery(
+                        "SHOW COLUMNS doesn't support filters".to_owned(),
+                    ));
+                }
+
+                let name = DeltaCatalog::ident_name(&table_name.0);
+                self.resolve_name(&name)?;
+                let mut parts = split_qualified_name(&name);
+                let table = parts.pop().unwrap_or_default();
+                let schema = match parts.is_empty() {
+                    true => lit(NULL).cast(DataType::Utf8),
+                    false => lit(join_qualified_name(&parts)),
+                };
+
+                Ok(information_schema::columns(self)?
+                    .lazy()
+                    .filter(
+                        col("table_name")
+                            .eq(lit(table))
+                            .and(col("table_schema").eq_missing(schema)),
+                    )
+                    .select([
+                        col("column_name"),
+                        col("data_type"),
+                        col("is_nullable"),
+                        col("is_partition_column"),
+                    ])
+                    .collect()?)
+            }
+            statement => Err(DeltaError::InvalidQuery(format!(
+                "unsupported statement: {}",
+                statement
+            ))),
+        }
+    }
+
+    // Run a query and deserialize each row of the result into a `T`, with
+    // columns matched to fields by name
+    pub fn query_as<T: DeserializeOwned>(&self, sql: &str) -> Result<Vec<T>, DeltaError> {
+        deserialize_rows(&self.query(sql)?)
+    }
+
+    // The rows of a table referenced by a query, which may be one of the
+    // virtual information_schema tables
+    fn lazy_frame(&self, name: &str, scan: &QueryScan) -> Result<LazyFrame, DeltaError> {
+        let parts = split_qualified_name(name);
+        if let [schema, table] = parts.as_slice() {
+            if schema.eq_ignore_ascii_case("information_schema") {
+                match table.to_lowercase().as_str() {
+                    "tables" => return Ok(information_schema::tables(self)?.lazy()),
+                    "columns" => return Ok(information_schema::columns(self)?.lazy()),
+                    _ => {}
+                }
+            }
+        }
+
+        self.open_table(name)?.scan_for_query(scan)
+    }
+
+    // The name of a table as parsed from SQL, in the form used by the
+    // registry
+    fn ident_name(parts: &[Ident]) -> String {
+        join_qualified_name(
+            &parts
+                .iter()
+                .map(|p| p.value.clone())
+                .collect::<Vec<String>>(),
+        )
+    }
+
+    fn resolve_name(&self, name: &str) -> Result<String, DeltaError> {
+        let canonical = DeltaCatalog::canonical_name(name)?;
+        let canonical = match self.registry.aliases.get(&canonical) {
+            Some(target) => target.clone(),
+            None => canonical,
+        };
+
+        match self.registry.tables.contains_key(&canonical) {
+            true => Ok(canonical),
+            false => Err(DeltaError::TableNotFound(name.to_owned())),
+        }
+    }
+
+    // `prod.events`, `"prod".events` and `prod . events` all refer to the
+    // same table, so they're stored the same way.
+    fn canonical_name(name: &str) -> Result<String, DeltaError> {
+        let parts = split_qualified_name(name);
+        if parts.iter().any(|part| part.is_empty()) {
+            return Err(DeltaError::InvalidTable);
+        }
+
+        Ok(join_qualified_name(&parts))
+    }
+
+    // Only local tables can be opened for now, either as a `file://` URI or
+    // a plain path.
+    fn local_path(&self, uri: &str) -> Result<PathBuf, DeltaError> {
+        let path = match uri.split_once("://") {
+            Some(("file", path)) => path,
+            Some(_) => return Err(DeltaError::UnsupportedStorage(uri.to_owned())),
+            None => uri,
+        };
+
+        let path = Path::new(path);
+        if path.is_absolute() {
+            return Ok(path.to_path_buf());
+        }
+
+        match self.path.parent() {
+            Some(dir) => Ok(dir.join(path)),
+            None => Ok(path.to_path_buf()),
+        }
+    }
+
+    // Write to a temporary file and rename it into place, so a crash
+    // half way through never leaves a truncated registry behind.
+    fn save(&self) -> Result<(), DeltaError> {
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(&self.registry)?)?;
+        fs::rename(tmp, &self.path)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempTable;
+
+    #[test]
+    fn registry_persists_names_and_aliases() {
+        let temp = TempTable::new(vec![("id", "int")]).unwrap();
+        temp.table()
+            .unwrap()
+            .insert(vec![vec!["1"], vec!["2"]])
+            .unwrap();
+        let dir = temp.path().strip_suffix("/table").unwrap();
+        let path = format!("{}/catalog.json", dir);
+
+        let mut catalog = DeltaCatalog::open(&path).unwrap();
+        // Relative to the registry file
+        catalog.register("prod.events", "table").unwrap();
+        catalog.alias("events", "\"prod\" . events").unwrap();
+        assert!(matches!(
+            catalog.register("events", "elsewhere"),
+            Err(DeltaError::TableAlreadyExists)
+        ));
+
+        let mut catalog = DeltaCatalog::open(&path).unwrap();
+        assert_eq!(catalog.tables(), [("prod.events", "table")]);
+        assert_eq!(catalog.aliases(), [("events", "prod.events")]);
+        let rows = catalog
+            .query("SELECT count(*) AS n FROM prod.events JOIN events USING (id)")
+            .unwrap();
+        assert_eq!(
+            rows["n"]
+                .cast(&DataType::Int64)
+                .unwrap()
+                .i64()
+                .unwrap()
+                .get(0),
+            Some(2)
+        );
+
+        catalog.unregister("prod.events").unwrap();
+        let catalog = DeltaCatalog::open(&path).unwrap();
+        assert!(catalog.tables().is_empty() && catalog.aliases().is_empty());
+        assert!(matches!(
+            catalog.open_table("events"),
+            Err(DeltaError::TableNotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,501 @@
+use crate::{
+    actions::{parse_action, Action, Add, Protocol, Txn},
+    error::DeltaError,
+    metadata::DeltaTableMetadata,
+    metrics::CheckpointInfo,
+    storage::LogStore,
+};
+use polars::{
+    export::arrow::{
+        array::{Array, BooleanArray, ListArray, MapArray, PrimitiveArray, StructArray, Utf8Array},
+        bitmap::Bitmap,
+        chunk::Chunk,
+        offset::OffsetsBuffer,
+    },
+    io::parquet::ParquetCompression,
+    prelude::*,
+};
+use polars_parquet::{
+    read::{self, FileReader},
+    write::{transverse, Encoding, FileWriter, RowGroupIterator, Version, WriteOptions},
+};
+use serde_json::{json, Map, Value};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, Cursor},
+};
+
+// Points readers at the latest checkpoint, so they don't have to list the
+// log to find it
+const LAST_CHECKPOINT: &str = "_last_checkpoint";
+
+fn checkpoint_file(version: i64) -> String {
+    format!("{:0>20}.checkpoint.parquet", version)
+}
+
+// Write a checkpoint of the table as of `version` into `log_store`, holding
+// the table's protocol and metadata, a txn action for every application's
+// latest version and an Add action for every live file, then point
+// `_last_checkpoint` at it. A checkpoint of `version` that's already there
+// is left alone, since it holds the same actions.
+pub fn write_checkpoint(
+    log_store: &dyn LogStore,
+    version: i64,
+    protocol: &Protocol,
+    metadata: &DeltaTableMetadata,
+    app_versions: &HashMap<String, i64>,
+    files: &[Add],
+) -> Result<CheckpointInfo, DeltaError> {
+    let mut actions = vec![
+        serde_json::to_value(Action::Protocol(protocol.clone()))?,
+        serde_json::to_value(Action::Metadata(metadata.clone()))?,
+    ];
+    let mut apps = app_versions.iter().collect::<Vec<_>>();
+    apps.sort();
+    for (app_id, version) in apps {
+        actions.push(serde_json::to_value(Action::Txn(Txn {
+            app_id: app_id.clone(),
+            version: *version,
+            last_updated: None,
+        }))?);
+    }
+    for add in files {
+        actions.push(serde_json::to_value(Action::Add(add.clone()))?);
+    }
+
+    // One column per kind of action, with each row holding one action and
+    // null in the other columns
+    let schema = checkpoint_schema();
+    let columns = schema
+        .fields
+        .iter()
+        .map(|field| {
+            let values = actions
+                .iter()
+                .map(|action| action.get(&field.name))
+                .collect::<Vec<_>>();
+            to_array(&values, &field.data_type)
+        })
+        .collect::<Result<Vec<_>, DeltaError>>()?;
+
+    let options = WriteOptions {
+        write_statistics: false,
+        version: Version::V2,
+        compression: ParquetCompression::default().into(),
+        data_pagesize_limit: None,
+    };
+    let encodings = schema
+        .fields
+        .iter()
+        .map(|field| transverse(&field.data_type, |_| Encoding::Plain))
+        .collect();
+    let chunks = vec![Ok(Chunk::new(columns))];
+
+    let name = checkpoint_file(version);
+    let mut writer = FileWriter::try_new(vec![], schema.clone(), options)?;
+    for row_group in RowGroupIterator::try_new(chunks.into_iter(), &schema, options, encodings)? {
+        writer.write(row_group?)?;
+    }
+    let size = writer.end(None)?;
+    log_store.put_if_absent(&name, &writer.into_inner())?;
+
+    let info = CheckpointInfo {
+        version,
+        path: format!("_delta_log/{}", name),
+        size,
+        num_actions: actions.len(),
+        num_add_files: files.len(),
+    };
+    update_last_checkpoint(log_store, &info)?;
+
+    Ok(info)
+}
+
+// The versions of the complete checkpoints among the files of a log, with
+// the files each one is made of. Spark splits the checkpoints of large
+// tables into parts named `<version>.checkpoint.<part>.<parts>.parquet`,
+// and one whose parts aren't all there yet can't be read.
+//
+// `_last_checkpoint` is only a shortcut to the latest one for readers that
+// don't list the log, and the commits after it have to be listed anyway.
+pub fn complete_checkpoints(names: &[String]) -> BTreeMap<i64, Vec<String>> {
+    let mut parts: BTreeMap<(i64, usize), Vec<(usize, String)>> = BTreeMap::new();
+    for name in names {
+        let Some(rest) = name.strip_suffix(".parquet") else {
+            continue;
+        };
+        let mut fields = rest.split('.');
+        let (Some(version), Some("checkpoint")) = (fields.next(), fields.next()) else {
+            continue;
+        };
+        let Ok(version) = version.parse::<i64>() else {
+            continue;
+        };
+        let (part, count) = match (fields.next(), fields.next(), fields.next()) {
+            (None, _, _) => (1, 1),
+            (Some(part), Some(count), None) => match (part.parse(), count.parse()) {
+                (Ok(part), Ok(count)) => (part, count),
+                _ => continue,
+            },
+            _ => continue,
+        };
+        parts
+            .entry((version, count))
+            .or_default()
+            .push((part, name.clone()));
+    }
+
+    let mut checkpoints = BTreeMap::new();
+    for ((version, count), mut files) in parts {
+        files.sort();
+        files.dedup_by_key(|(part, _)| *part);
+        if files.len() == count {
+            checkpoints.insert(version, files.into_iter().map(|(_, name)| name).collect());
+        }
+    }
+    checkpoints
+}
+
+// The protocol, metadata, txn and Add actions of the checkpoint made of
+// `files`, see `complete_checkpoints`. Checkpoints are read leniently, see
+// `parse_action`, as other writers leave out or add fields, e.g. Spark's
+// parsed stats. Remove actions are only kept in a checkpoint until vacuum
+// can delete their files, so they're skipped.
+pub fn read_checkpoint(
+    log_store: &dyn LogStore,
+    files: &[String],
+) -> Result<Vec<Action>, DeltaError> {
+    let mut actions = vec![];
+    for name in files {
+        let mut reader = Cursor::new(log_store.read(name)?);
+        let metadata = read::read_metadata(&mut reader)?;
+        let schema = read::infer_schema(&metadata)?;
+        let kinds = schema
+            .fields
+            .iter()
+            .map(|field| field.name.clone())
+            .collect::<Vec<_>>();
+        for chunk in FileReader::new(reader, metadata.row_groups, schema, None, None, None) {
+            let chunk = chunk?;
+            for row in 0..chunk.len() {
+                for (kind, column) in kinds.iter().zip(chunk.arrays()) {
+                    if !matches!(kind.as_str(), "protocol" | "metaData" | "txn" | "add") {
+                        continue;
+                    }
+                    let body = to_json(column.as_ref(), row);
+                    if body.is_null() {
+                        continue;
+                    }
+                    let line = serde_json::to_string(&json!({ kind: body }))?;
+                    actions.extend(parse_action(&line, true)?);
+                }
+            }
+        }
+    }
+    Ok(actions)
+}
+
+// The value at `row` of `array` as JSON, the inverse of `to_array`. Maps,
+// which polars reads as lists of key-value structs, become objects. Types
+// no action we read uses are null.
+fn to_json(array: &dyn Array, row: usize) -> Value {
+    if array.is_null(row) {
+        return Value::Null;
+    }
+
+    let any = array.as_any();
+    macro_rules! primitive {
+        ($($typ:ty),*) => {
+            $(if let Some(array) = any.downcast_ref::<PrimitiveArray<$typ>>() {
+                return json!(array.value(row));
+            })*
+        };
+    }
+    primitive!(i8, i16, i32, i64, u8, u16, u32, u64, f32, f64);
+    if let Some(array) = any.downcast_ref::<Utf8Array<i32>>() {
+        return json!(array.value(row));
+    }
+    if let Some(array) = any.downcast_ref::<Utf8Array<i64>>() {
+        return json!(array.value(row));
+    }
+    if let Some(array) = any.downcast_ref::<BooleanArray>() {
+        return json!(array.value(row));
+    }
+    if let Some(array) = any.downcast_ref::<StructArray>() {
+        let body = array
+            .fields()
+            .iter()
+            .zip(array.values())
+            .map(|(field, values)| (field.name.clone(), to_json(values.as_ref(), row)))
+            .collect::<Map<String, Value>>();
+        return Value::Object(body);
+    }
+    let items = if let Some(array) = any.downcast_ref::<ListArray<i32>>() {
+        array.value(row)
+    } else if let Some(array) = any.downcast_ref::<ListArray<i64>>() {
+        array.value(row)
+    } else if let Some(array) = any.downcast_ref::<MapArray>() {
+        array.value(row)
+    } else {
+        return Value::Null;
+    };
+    let items = (0..items.len())
+        .map(|item| to_json(items.as_ref(), item))
+        .collect::<Vec<_>>();
+    match is_map(array.data_type()) {
+        true => Value::Object(
+            items
+                .into_iter()
+                .filter_map(|item| {
+                    let Value::Object(mut entry) = item else {
+                        return None;
+                    };
+                    let key = entry.remove("key")?.as_str()?.to_owned();
+                    Some((key, entry.remove("value")?))
+                })
+                .collect(),
+        ),
+        false => Value::Array(items),
+    }
+}
+
+// Whether `data_type` is a map, or how polars reads one: a list of structs
+// with a key and a value
+fn is_map(data_type: &ArrowDataType) -> bool {
+    let item = match data_type {
+        ArrowDataType::Map(..) => return true,
+        ArrowDataType::List(item) | ArrowDataType::LargeList(item) => item,
+        _ => return false,
+    };
+    match &item.data_type {
+        ArrowDataType::Struct(fields) => {
+            fields.len() == 2 && fields[0].name == "key" && fields[1].name == "value"
+        }
+        _ => false,
+    }
+}
+
+// Point `_last_checkpoint` at `info`, unless another writer already
+// pointed it at a later checkpoint
+fn update_last_checkpoint(
+    log_store: &dyn LogStore,
+    info: &CheckpointInfo,
+) -> Result<(), DeltaError> {
+    match log_store.read(LAST_CHECKPOINT) {
+        Ok(contents) => {
+            let last = serde_json::from_slice::<Value>(&contents)
+                .ok()
+                .and_then(|last| last.get("version")?.as_i64());
+            if last.is_some_and(|last| last > info.version) {
+                return Ok(());
+            }
+        }
+        Err(DeltaError::IOError(e)) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+
+    let contents = json!({
+        "version": info.version,
+        "size": info.num_actions,
+        "sizeInBytes": info.size,
+        "numOfAddFiles": info.num_add_files,
+    });
+    log_store.put(
+        LAST_CHECKPOINT,
+        serde_json::to_string(&contents)?.as_bytes(),
+    )
+}
+
+// The layout of a checkpoint file, as described by the protocol
+// https://github.com/delta-io/delta/blob/master/PROTOCOL.md#checkpoint-schema
+fn checkpoint_schema() -> ArrowSchema {
+    let string = || ArrowDataType::Utf8;
+    let map = || {
+        ArrowDataType::Map(
+            Box::new(ArrowField::new(
+                "key_value",
+                ArrowDataType::Struct(vec![
+                    ArrowField::new("key", ArrowDataType::Utf8, false),
+                    ArrowField::new("value", ArrowDataType::Utf8, true),
+                ]),
+                false,
+            )),
+            false,
+        )
+    };
+    let list = |item| ArrowDataType::List(Box::new(ArrowField::new("element", item, true)));
+    let action = |name: &str, fields: Vec<(&str, ArrowDataType)>| {
+        let fields = fields
+            .into_iter()
+            .map(|(name, data_type)| ArrowField::new(name, data_type, true))
+            .collect();
+        ArrowField::new(name, ArrowDataType::Struct(fields), true)
+    };
+
+    ArrowSchema::from(vec![
+        action(
+            "protocol",
+            vec![
+                ("minReaderVersion", ArrowDataType::Int32),
+                ("minWriterVersion", ArrowDataType::Int32),
+                ("readerFeatures", list(string())),
+                ("writerFeatures", list(string())),
+            ],
+        ),
+        action(
+            "metaData",
+            vec![
+                ("id", string()),
+                ("name", string()),
+                ("description", string()),
+                (
+                    "format",
+                    ArrowDataType::Struct(vec![
+                        ArrowField::new("provider", string(), true),
+                        ArrowField::new("options", map(), true),
+                    ]),
+                ),
+                ("schemaString", string()),
+                ("partitionColumns", list(string())),
+                ("configuration", map()),
+                ("createdTime", ArrowDataType::Int64),
+            ],
+        ),
+        action(
+            "txn",
+            vec![
+                ("appId", string()),
+                ("version", ArrowDataType::Int64),
+                ("lastUpdated", ArrowDataType::Int64),
+            ],
+        ),
+        action(
+            "add",
+            vec![
+                ("path", string()),
+                ("partitionValues", map()),
+                ("size", ArrowDataType::Int64),
+                ("modificationTime", ArrowDataType::Int64),
+                ("dataChange", ArrowDataType::Boolean),
+                ("stats", string()),
+                ("tags", map()),
+            ],
+        ),
+        action(
+            "remove",
+            vec![
+                ("path", string()),
+                ("deletionTimestamp", ArrowDataType::Int64),
+                ("dataChange", ArrowDataType::Boolean),
+                ("tags", map()),
+            ],
+        ),
+    ])
+}
+
+// An array of `data_type` holding `values`, where a missing or null value
+// is a null. Only the types used by `checkpoint_schema` are supported.
+fn to_array(
+    values: &[Option<&Value>],
+    data_type: &ArrowDataType,
+) -> Result<Box<dyn Array>, DeltaError> {
+    let values = values
+        .iter()
+        .map(|value| value.filter(|value| !value.is_null()))
+        .collect::<Vec<_>>();
+    let validity = || {
+        Some(Bitmap::from(
+            values.iter().map(Option::is_some).collect::<Vec<_>>(),
+        ))
+    };
+
+    Ok(match data_type {
+        ArrowDataType::Utf8 => Box::new(Utf8Array::<i32>::from(
+            values
+                .iter()
+                .map(|value| value.and_then(Value::as_str))
+                .collect::<Vec<_>>(),
+        )),
+        ArrowDataType::Int32 => Box::new(PrimitiveArray::<i32>::from(
+            values
+                .iter()
+                .map(|value| value.and_then(Value::as_i64).map(|value| value as i32))
+                .collect::<Vec<_>>(),
+        )),
+        ArrowDataType::Int64 => Box::new(PrimitiveArray::<i64>::from(
+            values
+                .iter()
+                .map(|value| value.and_then(Value::as_i64))
+                .collect::<Vec<_>>(),
+        )),
+        ArrowDataType::Boolean => Box::new(BooleanArray::from(
+            values
+                .iter()
+                .map(|value| value.and_then(Value::as_bool))
+                .collect::<Vec<_>>(),
+        )),
+        ArrowDataType::Struct(fields) => {
+            let children = fields
+                .iter()
+                .map(|field| {
+                    let values = values
+                        .iter()
+                        .map(|value| value.and_then(|value| value.get(&field.name)))
+                        .collect::<Vec<_>>();
+                    to_array(&values, &field.data_type)
+                })
+                .collect::<Result<Vec<_>, DeltaError>>()?;
+            Box::new(StructArray::new(data_type.clone(), children, validity()))
+        }
+        ArrowDataType::List(item) => {
+            let mut offsets = vec![0i32];
+            let mut items = vec![];
+            for value in &values {
+                items.extend(
+                    value
+                        .and_then(Value::as_array)
+                        .into_iter()
+                        .flatten()
+                        .map(Some),
+                );
+                offsets.push(items.len() as i32);
+            }
+            Box::new(ListArray::<i32>::new(
+                data_type.clone(),
+                OffsetsBuffer::try_from(offsets)?,
+                to_array(&items, &item.data_type)?,
+                validity(),
+            ))
+        }
+        ArrowDataType::Map(entries, _) => {
+            let ArrowDataType::Struct(fields) = &entries.data_type else {
+                return Err(DeltaError::InvalidType);
+            };
+            let mut offsets = vec![0i32];
+            let mut keys = vec![];
+            let mut entry_values = vec![];
+            for value in &values {
+                for (key, value) in value.and_then(Value::as_object).into_iter().flatten() {
+                    keys.push(Some(key.as_str()));
+                    entry_values.push(Some(value));
+                }
+                offsets.push(keys.len() as i32);
+            }
+            let entries = StructArray::new(
+                entries.data_type.clone(),
+                vec![
+                    Box::new(Utf8Array::<i32>::from(keys)),
+                    to_array(&entry_values, &fields[1].data_type)?,
+                ],
+                None,
+            );
+            Box::new(MapArray::new(
+                data_type.clone(),
+                OffsetsBuffer::try_from(offsets)?,
+                Box::new(entries),
+                validity(),
+            ))
+        }
+        _ => return Err(DeltaError::InvalidType),
+    })
+}
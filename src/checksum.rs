@@ -0,0 +1,65 @@
+use crate::error::DeltaError;
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    path::Path,
+};
+use xxhash_rust::xxh3::Xxh3;
+
+// Tag on Add actions holding the data file's checksum. The value names the
+// hash it was made with, e.g. `xxh3-64:0123456789abcdef`, so the hash can
+// be changed later without old checksums looking corrupt.
+pub const CHECKSUM_TAG: &str = "checksum";
+const ALGORITHM: &str = "xxh3-64";
+
+// Hashes everything written through it, so a data file's checksum comes
+// for free while writing it instead of reading it back afterwards
+pub struct ChecksumWriter<W> {
+    inner: W,
+    hasher: Xxh3,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    pub fn new(inner: W) -> Self {
+        ChecksumWriter {
+            inner,
+            hasher: Xxh3::new(),
+        }
+    }
+
+    pub fn checksum(&self) -> String {
+        format!("{}:{:016x}", ALGORITHM, self.hasher.digest())
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// The checksum of a file on disk, in the same form as `ChecksumWriter`'s.
+// `None` if `expected` was made with a hash we don't know.
+pub fn file_checksum(path: &Path, expected: &str) -> Result<Option<String>, DeltaError> {
+    if !expected.starts_with(&format!("{}:", ALGORITHM)) {
+        return Ok(None);
+    }
+
+    let mut file = File::open(path)?;
+    let mut hasher = Xxh3::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        match file.read(&mut buf)? {
+            0 => break,
+            n => hasher.update(&buf[..n]),
+        }
+    }
+
+    Ok(Some(format!("{}:{:016x}", ALGORITHM, hasher.digest())))
+}
@@ -0,0 +1,77 @@
+use crate::{
+    error::DeltaError,
+    identifier::{join_qualified_name, split_qualified_name},
+    sql::{parse_statement, run_query},
+    table::DeltaTable,
+    value::deserialize_rows,
+};
+use polars::prelude::*;
+use serde::de::DeserializeOwned;
+use sqlparser::ast::Statement;
+use std::collections::BTreeMap;
+
+// A set of already opened tables that can be queried together, e.g. to
+// join `orders` against `customers`. Unlike a `DeltaCatalog` nothing is
+// stored on disk: tables are registered under a name for as long as the
+// context lives.
+#[derive(Clone, Default)]
+pub struct DeltaContext {
+    tables: BTreeMap<String, DeltaTable>,
+}
+
+impl DeltaContext {
+    pub fn new() -> DeltaContext {
+        DeltaContext::default()
+    }
+
+    // Register `table` under `name`, replacing any table registered under
+    // that name before
+    pub fn register(&mut self, name: &str, table: DeltaTable) -> Result<(), DeltaError> {
+        self.tables
+            .insert(DeltaContext::canonical_name(name)?, table);
+        Ok(())
+    }
+
+    pub fn deregister(&mut self, name: &str) -> Result<DeltaTable, DeltaError> {
+        self.tables
+            .remove(&DeltaContext::canonical_name(name)?)
+            .ok_or_else(|| DeltaError::TableNotFound(name.to_owned()))
+    }
+
+    pub fn table(&self, name: &str) -> Result<&DeltaTable, DeltaError> {
+        self.tables
+            .get(&DeltaContext::canonical_name(name)?)
+            .ok_or_else(|| DeltaError::TableNotFound(name.to_owned()))
+    }
+
+    // Registered table names, sorted
+    pub fn tables(&self) -> Vec<&str> {
+        self.tables.keys().map(String::as_str).collect()
+    }
+
+    // Run a SELECT over the registered tables. Each table referenced by the
+    // query is read once, however many times it's referenced.
+    pub fn query(&self, sql: &str) -> Result<DataFrame, DeltaError> {
+        let Statement::Query(query) = parse_statement(sql)? else {
+            return Err(DeltaError::InvalidQuery(
+                "only SELECT queries are supported".to_owned(),
+            ));
+        };
+
+        run_query(query, |name, scan| self.table(name)?.scan_for_query(scan))
+    }
+
+    // Like `query`, with each row deserialized into a `T` by column name
+    pub fn query_as<T: DeserializeOwned>(&self, sql: &str) -> Result<Vec<T>, DeltaError> {
+        deserialize_rows(&self.query(sql)?)
+    }
+
+    fn canonical_name(name: &str) -> Result<String, DeltaError> {
+        let parts = split_qualified_name(name);
+        if parts.iter().any(|part| part.is_empty()) {
+            return Err(DeltaError::InvalidTable);
+        }
+
+        Ok(join_qualified_name(&parts))
+    }
+}
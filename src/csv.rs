@@ -0,0 +1,355 @@
+use crate::{
+    error::DeltaError,
+    metrics::InsertResult,
+    schema::{DeltaTableSchema, DeltaTableType},
+};
+use polars::prelude::*;
+use std::{
+    fs,
+    io::{BufRead, Cursor},
+};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CsvFileEncoding {
+    Utf8,
+    // Invalid UTF-8 sequences are replaced instead of failing the load
+    LossyUtf8,
+    // ISO-8859-1, common for files exported from older tools
+    Latin1,
+}
+
+#[derive(Clone)]
+pub struct CsvOptions {
+    pub delimiter: u8,
+    // `None` disables quoting entirely
+    pub quote: Option<u8>,
+    pub has_header: bool,
+    // Cells exactly matching this token are loaded as null
+    pub null_token: Option<String>,
+    // chrono style formats, e.g. `%d/%m/%Y`. When unset, dates and
+    // timestamps have to be ISO-8601.
+    pub date_format: Option<String>,
+    pub timestamp_format: Option<String>,
+    pub encoding: CsvFileEncoding,
+    pub on_bad_row: BadRowPolicy,
+}
+
+// What a load does with rows that don't fit the table: values that can't
+// be parsed as their column's type, and missing values in NOT NULL columns
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BadRowPolicy {
+    // Fail the whole load with `DeltaError::InvalidValue` for the first one
+    #[default]
+    Fail,
+    // Leave them out and load the rest, see `CsvLoadResult::rejected`
+    Skip,
+}
+
+#[derive(Debug, Clone)]
+pub struct CsvLoadResult {
+    pub insert: InsertResult,
+    // Rows left out by `BadRowPolicy::Skip`, in file order
+    pub rejected: Vec<RejectedRow>,
+}
+
+// A row of a CSV file that didn't fit the table
+#[derive(Debug, Clone)]
+pub struct RejectedRow {
+    // Position among the file's rows, not counting the header
+    pub row: usize,
+    // The first column found not to fit
+    pub column: String,
+    pub message: String,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: b',',
+            quote: Some(b'"'),
+            has_header: true,
+            null_token: None,
+            date_format: None,
+            timestamp_format: None,
+            encoding: CsvFileEncoding::Utf8,
+            on_bad_row: BadRowPolicy::default(),
+        }
+    }
+}
+
+// Read a CSV file into a frame matching the table schema. Every column is
+// read as a string first and then parsed per the schema, so the result can
+// go through the regular insert path. Rows that don't fit the schema are
+// handled per `options.on_bad_row`, and returned when they're skipped.
+pub fn read_csv(
+    path: &str,
+    options: &CsvOptions,
+    schema: &DeltaTableSchema,
+) -> Result<(DataFrame, Vec<RejectedRow>), DeltaError> {
+    parse_csv(fs::read(path)?, options, schema)
+}
+
+// Like `read_csv`, for CSV already in memory, e.g. a chunk of a stream
+pub(crate) fn parse_csv(
+    bytes: Vec<u8>,
+    options: &CsvOptions,
+    schema: &DeltaTableSchema,
+) -> Result<(DataFrame, Vec<RejectedRow>), DeltaError> {
+    let bytes = match options.encoding {
+        CsvFileEncoding::Utf8 => {
+            String::from_utf8(bytes).map_err(|e| DeltaError::InvalidData(e.to_string()))?
+        }
+        CsvFileEncoding::LossyUtf8 => String::from_utf8_lossy(&bytes).into_owned(),
+        CsvFileEncoding::Latin1 => bytes.iter().map(|b| *b as char).collect(),
+    };
+
+    let mut reader = CsvReader::new(Cursor::new(bytes))
+        .with_separator(options.delimiter)
+        .with_quote_char(options.quote)
+        .has_header(options.has_header)
+        .infer_schema(Some(0))
+        .with_null_values(
+            options
+                .null_token
+                .as_ref()
+                .map(|token| NullValues::AllColumnsSingle(token.clone())),
+        );
+
+    // Without a header the columns are matched to the schema by position
+    if !options.has_header {
+        let fields = schema
+            .fields()
+            .iter()
+            .map(|field| Field::new(&field.name, DataType::Utf8));
+        reader = reader.with_schema(Some(Arc::new(Schema::from_iter(fields))));
+    }
+
+    let mut df = reader.finish()?;
+
+    // Polars strips the quotes around the header's names, but leaves the
+    // doubled quotes escaping a quote inside one
+    if let (true, Some(quote)) = (options.has_header, options.quote) {
+        let quote = (quote as char).to_string();
+        let names = df
+            .get_column_names()
+            .iter()
+            .map(|name| name.replace(&quote.repeat(2), &quote))
+            .collect::<Vec<String>>();
+        df.set_column_names(&names)?;
+    }
+
+    let mut parsed = vec![];
+    for field in schema.fields() {
+        let Ok(raw) = df.column(&field.name) else {
+            continue;
+        };
+        let name = field.name.as_str();
+        let format = match field.typ {
+            DeltaTableType::Date => options.date_format.clone(),
+            DeltaTableType::Timestamp => options.timestamp_format.clone(),
+            _ => None,
+        };
+        // Values that can't be parsed become nulls, which is how they're
+        // found below
+        let strptime = StrptimeOptions {
+            format,
+            strict: false,
+            ..Default::default()
+        };
+        let expr = match field.typ {
+            DeltaTableType::Date => col(name).str().to_date(strptime),
+            DeltaTableType::Timestamp => col(name).str().to_datetime(
+                Some(TimeUnit::Microseconds),
+                None,
+                strptime,
+                lit("raise"),
+            ),
+            // Polars can't cast strings to booleans
+            DeltaTableType::Boolean => {
+                let value = col(name).str().to_lowercase();
+                when(value.clone().eq(lit("true")))
+                    .then(lit(true))
+                    .when(value.eq(lit("false")))
+                    .then(lit(false))
+                    .otherwise(lit(NULL).cast(DataType::Boolean))
+            }
+            _ => col(name).cast(field.typ.to_polars_type()),
+        };
+        let value = df.clone().lazy().select([expr.alias(name)]).collect()?;
+        parsed.push((field, raw.clone(), value.column(name)?.clone()));
+    }
+
+    // The first reason each bad row doesn't fit, by row
+    let mut bad = std::collections::BTreeMap::new();
+    for (field, raw, value) in &parsed {
+        let required = !field.nullable && field.generation_expression().is_none();
+        for (row, (raw, value)) in raw.utf8()?.into_iter().zip(value.iter()).enumerate() {
+            let message = match (raw, value) {
+                (Some(raw), AnyValue::Null) => {
+                    format!("'{}' is not a valid {}", raw, field.typ.to_sql_type())
+                }
+                (None, _) if required => {
+                    "null value is not allowed in a non-nullable column".to_owned()
+                }
+                _ => continue,
+            };
+            bad.entry(row).or_insert_with(|| RejectedRow {
+                row,
+                column: field.name.clone(),
+                message,
+            });
+        }
+    }
+
+    if let (BadRowPolicy::Fail, Some(rejected)) = (options.on_bad_row, bad.values().next()) {
+        return Err(DeltaError::InvalidValue {
+            row: rejected.row,
+            column: rejected.column.clone(),
+            message: rejected.message.clone(),
+        });
+    }
+
+    let columns = parsed
+        .into_iter()
+        .map(|(_, _, value)| value)
+        .collect::<Vec<Series>>();
+    let mut df = DataFrame::new(columns)?;
+    if !bad.is_empty() {
+        let keep = (0..df.height())
+            .map(|row| !bad.contains_key(&row))
+            .collect::<BooleanChunked>();
+        df = df.filter(&keep)?;
+    }
+
+    Ok((df, bad.into_values().collect()))
+}
+
+// Splits CSV read from a stream into chunks of whole records, each at
+// least `size` bytes but the last, so they can be parsed one at a time.
+// When the stream has a header, every chunk starts with it. Records are
+// only split on newlines outside quotes, which holds for every supported
+// encoding since the delimiter and quote are ASCII.
+pub(crate) struct CsvChunks<R> {
+    reader: R,
+    quote: Option<u8>,
+    has_header: bool,
+    header: Option<Vec<u8>>,
+    size: usize,
+}
+
+impl<R: BufRead> CsvChunks<R> {
+    pub(crate) fn new(reader: R, options: &CsvOptions, size: usize) -> Self {
+        CsvChunks {
+            reader,
+            quote: options.quote,
+            has_header: options.has_header,
+            header: None,
+            size,
+        }
+    }
+
+    fn read_chunk(&mut self) -> Result<Option<Vec<u8>>, DeltaError> {
+        if self.has_header && self.header.is_none() {
+            let mut header = vec![];
+            if !self.read_record(&mut header)? {
+                return Ok(None);
+            }
+            self.header = Some(header);
+        }
+
+        let mut chunk = self.header.clone().unwrap_or_default();
+        let start = chunk.len();
+        while self.read_record(&mut chunk)? && chunk.len() - start < self.size {}
+        Ok((chunk.len() > start).then_some(chunk))
+    }
+
+    // Append the next record to `buf`, returning false at the end of the
+    // stream. A record goes on over as many lines as its quotes do.
+    fn read_record(&mut self, buf: &mut Vec<u8>) -> Result<bool, DeltaError> {
+        let start = buf.len();
+        let mut quotes = 0;
+        loop {
+            let line = buf.len();
+            if self.reader.read_until(b'\n', buf)? == 0 {
+                return Ok(buf.len() > start);
+            }
+            if let Some(quote) = self.quote {
+                quotes += buf[line..].iter().filter(|b| **b == quote).count();
+            }
+            if quotes % 2 == 0 {
+                return Ok(true);
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for CsvChunks<R> {
+    type Item = Result<Vec<u8>, DeltaError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_chunk().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polars::export::chrono::NaiveDate;
+
+    fn schema() -> DeltaTableSchema {
+        DeltaTableSchema::from_sql(vec![
+            ("id", "int"),
+            ("name", "text NULL"),
+            ("born", "date NULL"),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn options_control_how_the_file_is_read() {
+        let options = CsvOptions {
+            delimiter: b';',
+            quote: Some(b'\''),
+            has_header: false,
+            null_token: Some("-".to_owned()),
+            date_format: Some("%d/%m/%Y".to_owned()),
+            encoding: CsvFileEncoding::Latin1,
+            ..Default::default()
+        };
+        let bytes = b"1;'Jos\xe9; Jr';31/12/1999\n2;-;-\n".to_vec();
+
+        let (df, rejected) = parse_csv(bytes, &options, &schema()).unwrap();
+        assert!(rejected.is_empty());
+        let expected = df!(
+            "id" => [1, 2],
+            "name" => [Some("José; Jr"), None],
+            "born" => [Some(NaiveDate::from_ymd_opt(1999, 12, 31).unwrap()), None],
+        )
+        .unwrap();
+        assert!(df.frame_equal_missing(&expected));
+    }
+
+    #[test]
+    fn bad_rows_fail_the_load_or_are_skipped() {
+        let bytes = b"id,name,born\n1,a,2000-01-01\nx,b,\n3,c,2000-02-30\n,d,\n5,e,\n".to_vec();
+
+        match parse_csv(bytes.clone(), &CsvOptions::default(), &schema()) {
+            Err(DeltaError::InvalidValue { row, column, .. }) => {
+                assert_eq!((row, column.as_str()), (1, "id"));
+            }
+            result => panic!("expected an invalid value, got {:?}", result.map(|r| r.1)),
+        }
+
+        let options = CsvOptions {
+            on_bad_row: BadRowPolicy::Skip,
+            ..Default::default()
+        };
+        let (df, rejected) = parse_csv(bytes, &options, &schema()).unwrap();
+        assert_eq!(df["id"], Series::new("id", [1, 5]));
+        let rejected = rejected
+            .iter()
+            .map(|rejected| (rejected.row, rejected.column.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(rejected, [(1, "id"), (2, "born"), (3, "id")]);
+    }
+}
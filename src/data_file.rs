@@ -1,4 +1,14 @@
+use crate::{partition::PartitionValues, stats::DeltaFileStats};
+use std::collections::HashMap;
+
 pub struct DataFile {
     pub name: String,
     pub size: u64,
+    pub stats: DeltaFileStats,
+    pub partition_values: PartitionValues,
+    // Checksum of the file's contents, see `checksum::ChecksumWriter`
+    pub checksum: Option<String>,
+    // Bloom filter over the file's unique key, see `bloom::BloomFilter`
+    pub bloom_filter: Option<String>,
+    pub tags: HashMap<String, String>,
 }
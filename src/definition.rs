@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Everything needed to create a table like an existing one, without its
+// data, see `DeltaTable::definition`. Serializes to JSON meant to be kept
+// under version control, so maps are sorted and columns are described with
+// the SQL types `DeltaTable::create` takes.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TableDefinition {
+    pub columns: Vec<ColumnDefinition>,
+    #[serde(default)]
+    pub partition_by: Vec<String>,
+    #[serde(default)]
+    pub unique_key: Vec<String>,
+    // CHECK constraints by name
+    #[serde(default)]
+    pub constraints: BTreeMap<String, String>,
+    #[serde(default)]
+    pub properties: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ColumnDefinition {
+    pub name: String,
+    // e.g. `BIGINT`, see `DeltaTableType::from_sql_type`
+    #[serde(rename = "type")]
+    pub typ: String,
+    #[serde(default)]
+    pub nullable: bool,
+    // The SQL expression of a generated column
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generated_as: Option<String>,
+}
+
+impl ColumnDefinition {
+    // The column's type the way `DeltaTable::create` takes it
+    pub fn sql_type(&self) -> String {
+        let mut typ = self.typ.clone();
+        if self.nullable {
+            typ.push_str(" NULL");
+        }
+        if let Some(expr) = &self.generated_as {
+            typ.push_str(&format!(" GENERATED ALWAYS AS ({})", expr));
+        }
+        typ
+    }
+}
@@ -0,0 +1,5 @@
+// Implemented by `#[derive(DeltaSchema)]`. The schema is in the same form
+// `DeltaTable::create` takes: column names paired with their SQL types.
+pub trait DeltaSchema {
+    fn delta_schema() -> Vec<(&'static str, &'static str)>;
+}
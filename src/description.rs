@@ -0,0 +1,51 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+// A table's schema and settings as of a version, see
+// `DeltaTable::describe`
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TableDescription {
+    pub id: Uuid,
+    pub name: String,
+    // The version described
+    pub version: i64,
+    // In schema order
+    pub columns: Vec<ColumnDescription>,
+    pub partition_columns: Vec<String>,
+    // All of the table's configuration, including the entries holding its
+    // unique key and CHECK constraints
+    pub configuration: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ColumnDescription {
+    pub name: String,
+    // The SQL type, e.g. `BIGINT`, see `DeltaTableType::from_sql_type`
+    #[serde(rename = "type")]
+    pub typ: String,
+    // The type as the schema string stores it, e.g. `long`
+    pub delta_type: String,
+    pub nullable: bool,
+    pub partition_column: bool,
+    // e.g. `delta.generationExpression` for a generated column
+    pub metadata: BTreeMap<String, String>,
+}
+
+// A table found by `DeltaTable::list_tables`
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TableListing {
+    // The table's directory relative to the listed root, with `/` between
+    // nested directories
+    pub name: String,
+    pub path: String,
+    // The latest version
+    pub version: i64,
+    // When the table's first commit was written, in milliseconds since the
+    // unix epoch. Once log cleanup removed it, when the earliest commit or
+    // checkpoint left was.
+    pub created_time: i64,
+}
@@ -0,0 +1,152 @@
+use crate::{actions::Add, error::DeltaError, metadata::DeltaTableMetadata};
+use std::collections::HashSet;
+
+// How a table changed between two versions, worked out by comparing the
+// files live at each version
+#[derive(Debug, Clone)]
+pub struct TableDiff {
+    pub from: i64,
+    pub to: i64,
+    pub files_added: Vec<DiffFile>,
+    pub files_removed: Vec<DiffFile>,
+    // Rows in added files minus rows in removed files. `None` when a file
+    // has no stats to count its rows from.
+    pub rows_delta: Option<i64>,
+    pub schema_changes: Vec<SchemaChange>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DiffFile {
+    pub path: String,
+    pub size: u64,
+    pub rows: Option<u64>,
+}
+
+// Column types are given as SQL types, e.g. BIGINT
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaChange {
+    ColumnAdded {
+        name: String,
+        typ: String,
+    },
+    ColumnRemoved {
+        name: String,
+    },
+    TypeChanged {
+        name: String,
+        from: String,
+        to: String,
+    },
+    NullabilityChanged {
+        name: String,
+        nullable: bool,
+    },
+    PartitionColumnsChanged {
+        from: Vec<String>,
+        to: Vec<String>,
+    },
+}
+
+// The table as of one version, as far as diffing is concerned
+pub(crate) struct DiffSide<'a> {
+    pub version: i64,
+    pub metadata: &'a DeltaTableMetadata,
+    pub files: &'a [Add],
+}
+
+pub(crate) fn diff(from: DiffSide, to: DiffSide) -> Result<TableDiff, DeltaError> {
+    let from_paths = from
+        .files
+        .iter()
+        .map(|add| &add.path)
+        .collect::<HashSet<_>>();
+    let to_paths = to.files.iter().map(|add| &add.path).collect::<HashSet<_>>();
+
+    let files_added = to
+        .files
+        .iter()
+        .filter(|add| !from_paths.contains(&add.path))
+        .map(diff_file)
+        .collect::<Vec<DiffFile>>();
+    let files_removed = from
+        .files
+        .iter()
+        .filter(|add| !to_paths.contains(&add.path))
+        .map(diff_file)
+        .collect::<Vec<DiffFile>>();
+
+    let rows = |files: &[DiffFile]| -> Option<i64> {
+        files.iter().map(|f| f.rows.map(|r| r as i64)).sum()
+    };
+    let rows_delta = match (rows(&files_added), rows(&files_removed)) {
+        (Some(added), Some(removed)) => Some(added - removed),
+        _ => None,
+    };
+
+    Ok(TableDiff {
+        from: from.version,
+        to: to.version,
+        files_added,
+        files_removed,
+        rows_delta,
+        schema_changes: schema_changes(from.metadata, to.metadata)?,
+    })
+}
+
+fn diff_file(add: &Add) -> DiffFile {
+    DiffFile {
+        path: add.path.clone(),
+        size: add.size,
+        rows: add.parsed_stats().map(|stats| stats.num_records),
+    }
+}
+
+fn schema_changes(
+    from: &DeltaTableMetadata,
+    to: &DeltaTableMetadata,
+) -> Result<Vec<SchemaChange>, DeltaError> {
+    let from_schema = from.schema()?;
+    let to_schema = to.schema()?;
+
+    let mut changes = vec![];
+    for field in to_schema.fields() {
+        let Some(old) = from_schema.fields().iter().find(|f| f.name == field.name) else {
+            changes.push(SchemaChange::ColumnAdded {
+                name: field.name.clone(),
+                typ: field.typ.to_sql_type().to_owned(),
+            });
+            continue;
+        };
+
+        if old.typ.to_sql_type() != field.typ.to_sql_type() {
+            changes.push(SchemaChange::TypeChanged {
+                name: field.name.clone(),
+                from: old.typ.to_sql_type().to_owned(),
+                to: field.typ.to_sql_type().to_owned(),
+            });
+        }
+        if old.nullable != field.nullable {
+            changes.push(SchemaChange::NullabilityChanged {
+                name: field.name.clone(),
+                nullable: field.nullable,
+            });
+        }
+    }
+
+    for field in from_schema.fields() {
+        if !to_schema.fields().iter().any(|f| f.name == field.name) {
+            changes.push(SchemaChange::ColumnRemoved {
+                name: field.name.clone(),
+            });
+        }
+    }
+
+    if from.partition_columns() != to.partition_columns() {
+        changes.push(SchemaChange::PartitionColumnsChanged {
+            from: from.partition_columns().clone(),
+            to: to.partition_columns().clone(),
+        });
+    }
+
+    Ok(changes)
+}
@@ -8,6 +8,51 @@ pub enum DeltaError {
     InvalidType,
     InvalidTable,
     TableAlreadyExists,
+    // No table is registered under this name in the catalog
+    TableNotFound(String),
+    // The table lives somewhere we can't read from, e.g. an s3:// URI
+    UnsupportedStorage(String),
+    // The table's protocol requires a reader or writer version, or table
+    // features, this crate doesn't support
+    UnsupportedProtocol(String),
+    // Another writer committed this version first
+    VersionAlreadyExists(i64),
+    // There's no commit with this version in the log
+    VersionNotFound(i64),
+    // The handle is pinned to this past version of the table, see
+    // `DeltaTable::at_version`, so it can't change the table
+    PinnedVersion(i64),
+    // Another writer committed a change that this commit can't be applied
+    // on top of
+    CommitConflict(String),
+    // The table's metadata as of `version` can't be used, e.g. because its
+    // schemaString doesn't parse
+    CorruptMetadata {
+        version: i64,
+        detail: String,
+    },
+    SchemaMismatch(String),
+    InvalidData(String),
+    InvalidPredicate(String),
+    // A SQL statement that couldn't be parsed or isn't supported
+    InvalidQuery(String),
+    InvalidValue {
+        row: usize,
+        column: String,
+        message: String,
+    },
+    // Rows whose unique key is already in the table, or repeats an earlier
+    // row of the same write
+    UniqueKeyViolation {
+        columns: Vec<String>,
+        rows: usize,
+    },
+    // Rows that don't satisfy one of the table's CHECK constraints
+    ConstraintViolation {
+        name: String,
+        expression: String,
+        rows: usize,
+    },
 }
 
 impl From<std::io::Error> for DeltaError {
@@ -0,0 +1,28 @@
+use std::collections::HashMap;
+
+// A live data file of a table, as listed by `DeltaTable::files`
+#[derive(Debug, Clone)]
+pub struct FileInfo {
+    // Relative to the table's directory, URI encoded as in the log
+    pub path: String,
+    pub size: u64,
+    // Milliseconds since the unix epoch
+    pub modification_time: i64,
+    pub partition_values: HashMap<String, Option<String>>,
+    // `None` when the file was added without stats
+    pub num_records: Option<u64>,
+    // Tags given when the file was written, plus its `checksum`
+    pub tags: HashMap<String, String>,
+}
+
+// A partition with live data files, as listed by `DeltaTable::partitions`
+#[derive(Debug, Clone)]
+pub struct PartitionInfo {
+    // The partition's value for each partition column, in the order the
+    // table is partitioned by
+    pub values: Vec<(String, Option<String>)>,
+    pub num_files: usize,
+    pub size: u64,
+    // `None` when any of the partition's files was added without stats
+    pub num_records: Option<u64>,
+}
@@ -0,0 +1,308 @@
+// An Arrow Flight server for remote producers to write to tables without
+// access to their files. Only DoPut is served: each DoPut stream is
+// ingested into a table with `ingest::StreamIngest`, so its batches are
+// committed a few at a time, and a producer that didn't hear back can
+// resend the whole stream without its rows being written twice.
+//
+// A stream's descriptor is a path of two parts, the table's name and the
+// stream's id, e.g. `["events", "producer-7-2024-01-01"]`. Tables are
+// looked up by name under the server's root directory, like
+// `DeltaTable::read_table` does under `tables/`. Once the stream ended and
+// its last commit is made, the server answers with a single PutResult
+// whose app_metadata is the stream's `IngestResult` as JSON. Only
+// available with the `flight` feature.
+
+use crate::{
+    error::DeltaError, ingest::StreamIngest, nonblocking::run_blocking, table::DeltaTable,
+};
+use arrow_format::flight::{
+    data::{
+        flight_descriptor::DescriptorType, Action, ActionType, Criteria, Empty, FlightData,
+        FlightDescriptor, FlightInfo, HandshakeRequest, HandshakeResponse, PutResult,
+        Result as ActionResult, SchemaResult, Ticket,
+    },
+    service::flight_service_server::{FlightService, FlightServiceServer},
+};
+use futures::stream::{self, BoxStream};
+use polars_arrow::io::{
+    flight::{deserialize_message, deserialize_schemas},
+    ipc::read::Dictionaries,
+};
+use std::{io, net::SocketAddr};
+use tokio::net::TcpListener;
+use tonic::{
+    transport::{server::TcpIncoming, Server},
+    Request, Response, Status, Streaming,
+};
+
+pub struct FlightIngest {
+    root: String,
+    batch_rows: usize,
+}
+
+impl FlightIngest {
+    // Serve the tables under `root`, committing about every `batch_rows`
+    // rows of a stream, see `StreamIngest::new`
+    pub fn new(root: &str, batch_rows: usize) -> FlightIngest {
+        FlightIngest {
+            root: root.trim_end_matches('/').to_owned(),
+            batch_rows,
+        }
+    }
+
+    // Serve on `addr` until the server fails
+    pub async fn serve(self, addr: SocketAddr) -> Result<(), DeltaError> {
+        self.serve_with_listener(TcpListener::bind(addr).await?)
+            .await
+    }
+
+    // Like `serve`, on a listener that's already bound, e.g. to port 0
+    pub async fn serve_with_listener(self, listener: TcpListener) -> Result<(), DeltaError> {
+        let server_error = |e| DeltaError::IOError(io::Error::other(e));
+        let incoming = TcpIncoming::from_listener(listener, true, None).map_err(server_error)?;
+        Server::builder()
+            .add_service(FlightServiceServer::new(self))
+            .serve_with_incoming(incoming)
+            .await
+            .map_err(|e| server_error(e.into()))
+    }
+
+    // The path of the table and the stream id a DoPut's descriptor names
+    fn target(
+        &self,
+        descriptor: Option<&FlightDescriptor>,
+    ) -> Result<(String, String), DeltaError> {
+        let path = match descriptor {
+            Some(descriptor) if descriptor.r#type == DescriptorType::Path as i32 => {
+                descriptor.path.as_slice()
+            }
+            _ => &[],
+        };
+        let [table, stream_id] = path else {
+            return Err(DeltaError::InvalidData(
+                "a DoPut's descriptor has to be a path of a table name and a stream id".to_owned(),
+            ));
+        };
+
+        let name = DeltaTable::table_name(table)?;
+        Ok((format!("{}/{}", self.root, name), stream_id.clone()))
+    }
+}
+
+// How a failed ingest is reported to the producer
+fn status(e: DeltaError) -> Status {
+    let message = format!("{:?}", e);
+    match e {
+        DeltaError::TableNotFound(_) | DeltaError::InvalidTable => Status::not_found(message),
+        DeltaError::SchemaMismatch(_)
+        | DeltaError::InvalidData(_)
+        | DeltaError::InvalidType
+        | DeltaError::InvalidValue { .. } => Status::invalid_argument(message),
+        DeltaError::UniqueKeyViolation { .. } | DeltaError::ConstraintViolation { .. } => {
+            Status::failed_precondition(message)
+        }
+        DeltaError::CommitConflict(_) => Status::aborted(message),
+        DeltaError::IOError(e) if e.kind() == io::ErrorKind::NotFound => Status::not_found(message),
+        _ => Status::internal(message),
+    }
+}
+
+fn unsupported(method: &str) -> Status {
+    Status::unimplemented(format!("{} isn't supported, only DoPut is", method))
+}
+
+#[tonic::async_trait]
+impl FlightService for FlightIngest {
+    type HandshakeStream = BoxStream<'static, Result<HandshakeResponse, Status>>;
+    type ListFlightsStream = BoxStream<'static, Result<FlightInfo, Status>>;
+    type DoGetStream = BoxStream<'static, Result<FlightData, Status>>;
+    type DoPutStream = BoxStream<'static, Result<PutResult, Status>>;
+    type DoExchangeStream = BoxStream<'static, Result<FlightData, Status>>;
+    type DoActionStream = BoxStream<'static, Result<ActionResult, Status>>;
+    type ListActionsStream = BoxStream<'static, Result<ActionType, Status>>;
+
+    // The first message holds the descriptor and the batches' schema, the
+    // rest a batch or a dictionary each. Batches are pushed into the
+    // stream's ingest on the IO thread pool as they arrive, so they're
+    // committed while the rest of the stream is still coming in.
+    async fn do_put(
+        &self,
+        request: Request<Streaming<FlightData>>,
+    ) -> Result<Response<Self::DoPutStream>, Status> {
+        let mut messages = request.into_inner();
+        let Some(first) = messages.message().await? else {
+            return Err(Status::invalid_argument("the DoPut stream is empty"));
+        };
+        let (path, stream_id) = self
+            .target(first.flight_descriptor.as_ref())
+            .map_err(status)?;
+        let invalid = |e: polars::error::PolarsError| Status::invalid_argument(e.to_string());
+        let (schema, ipc_schema) = deserialize_schemas(&first.data_header).map_err(invalid)?;
+
+        let batch_rows = self.batch_rows;
+        let mut ingest = run_blocking(move || {
+            let table = DeltaTable::open(&path)?;
+            Ok(StreamIngest::new(table, &stream_id, batch_rows))
+        })
+        .await
+        .map_err(status)?;
+
+        let mut dictionaries = Dictionaries::default();
+        while let Some(data) = messages.message().await? {
+            let chunk = deserialize_message(&data, &schema.fields, &ipc_schema, &mut dictionaries)
+                .map_err(invalid)?;
+            // Dictionary messages only add to `dictionaries`
+            let Some(chunk) = chunk else {
+                continue;
+            };
+            let batch = DeltaTable::arrow_frame(&schema, vec![chunk]).map_err(status)?;
+            ingest = run_blocking(move || {
+                ingest.push(batch)?;
+                Ok(ingest)
+            })
+            .await
+            .map_err(status)?;
+        }
+
+        let result = run_blocking(move || ingest.finish())
+            .await
+            .map_err(status)?;
+        let put = PutResult {
+            app_metadata: serde_json::to_vec(&result)
+                .map_err(|e| Status::internal(e.to_string()))?,
+        };
+        Ok(Response::new(Box::pin(stream::once(async { Ok(put) }))))
+    }
+
+    async fn handshake(
+        &self,
+        _: Request<Streaming<HandshakeRequest>>,
+    ) -> Result<Response<Self::HandshakeStream>, Status> {
+        Err(unsupported("Handshake"))
+    }
+
+    async fn list_flights(
+        &self,
+        _: Request<Criteria>,
+    ) -> Result<Response<Self::ListFlightsStream>, Status> {
+        Err(unsupported("ListFlights"))
+    }
+
+    async fn get_flight_info(
+        &self,
+        _: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        Err(unsupported("GetFlightInfo"))
+    }
+
+    async fn get_schema(
+        &self,
+        _: Request<FlightDescriptor>,
+    ) -> Result<Response<SchemaResult>, Status> {
+        Err(unsupported("GetSchema"))
+    }
+
+    async fn do_get(&self, _: Request<Ticket>) -> Result<Response<Self::DoGetStream>, Status> {
+        Err(unsupported("DoGet"))
+    }
+
+    async fn do_exchange(
+        &self,
+        _: Request<Streaming<FlightData>>,
+    ) -> Result<Response<Self::DoExchangeStream>, Status> {
+        Err(unsupported("DoExchange"))
+    }
+
+    async fn do_action(
+        &self,
+        _: Request<Action>,
+    ) -> Result<Response<Self::DoActionStream>, Status> {
+        Err(unsupported("DoAction"))
+    }
+
+    async fn list_actions(
+        &self,
+        _: Request<Empty>,
+    ) -> Result<Response<Self::ListActionsStream>, Status> {
+        Err(unsupported("ListActions"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{assert_log_invariants, TempTable};
+    use arrow_format::flight::service::flight_service_client::FlightServiceClient;
+    use polars::prelude::*;
+    use polars_arrow::io::flight::{
+        default_ipc_fields, serialize_batch, serialize_schema, WriteOptions,
+    };
+    use serde_json::Value;
+    use tonic::transport::Channel;
+
+    // The messages of a DoPut of `batches` as stream `stream_id` of `table`
+    fn do_put_messages(table: &str, stream_id: &str, batches: &[&[i32]]) -> Vec<FlightData> {
+        let schema = df!("id" => batches[0]).unwrap().schema().to_arrow();
+        let fields = default_ipc_fields(&schema.fields);
+        let mut messages = vec![FlightData {
+            flight_descriptor: Some(FlightDescriptor {
+                r#type: DescriptorType::Path as i32,
+                path: vec![table.to_owned(), stream_id.to_owned()],
+                ..Default::default()
+            }),
+            ..serialize_schema(&schema, Some(&fields))
+        }];
+        for batch in batches {
+            for chunk in df!("id" => batch).unwrap().iter_chunks() {
+                let options = WriteOptions { compression: None };
+                let (dictionaries, data) = serialize_batch(&chunk, &fields, &options).unwrap();
+                messages.extend(dictionaries);
+                messages.push(data);
+            }
+        }
+        messages
+    }
+
+    // The stream's `IngestResult`, as the server sent it back
+    async fn do_put(
+        client: &mut FlightServiceClient<Channel>,
+        messages: Vec<FlightData>,
+    ) -> Result<Value, Status> {
+        let mut results = client.do_put(stream::iter(messages)).await?.into_inner();
+        let result = results.message().await?.expect("a PutResult");
+        Ok(serde_json::from_slice(&result.app_metadata).unwrap())
+    }
+
+    #[test]
+    fn resent_do_put_is_skipped() {
+        let temp = TempTable::new(vec![("id", "int")]).unwrap();
+        let root = temp.path().strip_suffix("/table").unwrap().to_owned();
+        let batches: &[&[i32]] = &[&[1, 2], &[3, 4], &[5]];
+
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(FlightIngest::new(&root, 3).serve_with_listener(listener));
+            let mut client = FlightServiceClient::connect(format!("http://{}", addr))
+                .await
+                .unwrap();
+
+            let messages = do_put_messages("table", "stream-1", batches);
+            let first = do_put(&mut client, messages.clone()).await.unwrap();
+            assert_eq!(first["commits"], 2);
+            assert_eq!(first["rowsWritten"], 5);
+
+            let resent = do_put(&mut client, messages).await.unwrap();
+            assert_eq!(resent["commitsSkipped"], 2);
+            assert_eq!(resent["rowsWritten"], 0);
+            assert_eq!(resent["version"], first["version"]);
+
+            let missing = do_put_messages("missing", "stream-1", batches);
+            let error = do_put(&mut client, missing).await.unwrap_err();
+            assert_eq!(error.code(), tonic::Code::NotFound);
+        });
+
+        assert_eq!(temp.table().unwrap().count().unwrap(), 5);
+        assert_log_invariants(temp.path());
+    }
+}
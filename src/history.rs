@@ -0,0 +1,28 @@
+use serde_json::Value;
+use std::collections::HashMap;
+
+// A version of a table, as listed by `DeltaTable::history`
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub version: i64,
+    // Milliseconds since the unix epoch, from the commit's commitInfo or,
+    // for commits without one, when its log file was last modified
+    pub timestamp: i64,
+    // e.g. WRITE or DELETE, from the commit's commitInfo. Commits without
+    // one are `None`, except the first which is always a CREATE TABLE.
+    pub operation: Option<String>,
+    pub operation_parameters: HashMap<String, Value>,
+    // See `WriteOptions::user_metadata`
+    pub user_metadata: Option<String>,
+    pub metrics: OperationMetrics,
+}
+
+// What a commit changed, counted from its actions
+#[derive(Debug, Clone, Default)]
+pub struct OperationMetrics {
+    pub files_added: usize,
+    pub files_removed: usize,
+    pub bytes_added: u64,
+    // `None` when an added file has no stats to count its rows from
+    pub rows_added: Option<u64>,
+}
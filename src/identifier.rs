@@ -0,0 +1,89 @@
+// Helpers for SQL identifiers. Names can be quoted with double quotes or
+// backticks so that columns and tables with spaces, mixed case, or names
+// that collide with reserved words (e.g. `select`) can be used. The quotes
+// aren't part of the name: `"My Column"` is stored as `My Column`.
+
+pub fn unquote_identifier(name: &str) -> String {
+    let name = name.trim();
+    for quote in ['"', '`'] {
+        if name.len() >= 2 && name.starts_with(quote) && name.ends_with(quote) {
+            // A quote inside a quoted name is escaped by doubling it
+            let inner = &name[1..name.len() - 1];
+            return inner.replace(&format!("{}{}", quote, quote), &quote.to_string());
+        }
+    }
+
+    name.to_owned()
+}
+
+// Split a column definition like `"first name" TEXT` into the column's
+// name, still quoted, and what follows it. Unquoted names end at the first
+// whitespace.
+pub fn split_leading_identifier(definition: &str) -> Option<(&str, &str)> {
+    let definition = definition.trim();
+    let quote = definition.chars().next().filter(|c| *c == '"' || *c == '`');
+    let Some(quote) = quote else {
+        let (name, rest) = definition.split_once(char::is_whitespace)?;
+        return Some((name, rest.trim_start()));
+    };
+
+    let mut chars = definition.char_indices().skip(1).peekable();
+    while let Some((i, c)) = chars.next() {
+        if c != quote {
+            continue;
+        }
+        // A doubled quote is part of the name
+        if chars.next_if(|(_, next)| *next == quote).is_some() {
+            continue;
+        }
+        let (name, rest) = definition.split_at(i + c.len_utf8());
+        return match rest.starts_with(char::is_whitespace) {
+            true => Some((name, rest.trim_start())),
+            false => None,
+        };
+    }
+
+    None
+}
+
+// Quote `name` so it can be safely embedded in generated SQL
+pub fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+// Split a possibly qualified name like `prod."my events"` into its
+// unquoted parts. Dots inside quotes are part of the name.
+pub fn split_qualified_name(name: &str) -> Vec<String> {
+    let mut parts = vec![];
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in name.char_indices() {
+        match (quote, c) {
+            (None, '"' | '`') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '.') => {
+                parts.push(unquote_identifier(&name[start..i]));
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(unquote_identifier(&name[start..]));
+
+    parts
+}
+
+// The inverse of `split_qualified_name`. Parts are only quoted when they
+// have to be, so simple names stay readable.
+pub fn join_qualified_name(parts: &[String]) -> String {
+    parts
+        .iter()
+        .map(
+            |part| match part.contains(['.', '"', '`']) || part.trim() != part {
+                true => quote_identifier(part),
+                false => part.clone(),
+            },
+        )
+        .collect::<Vec<String>>()
+        .join(".")
+}
@@ -0,0 +1,99 @@
+// Virtual `information_schema.tables` and `information_schema.columns`
+// tables describing the contents of a catalog, so tools can introspect it
+// with regular SELECTs.
+
+use crate::{
+    catalog::DeltaCatalog,
+    error::DeltaError,
+    identifier::{join_qualified_name, split_qualified_name},
+};
+use polars::prelude::*;
+
+// One row per table and alias. Aliases show the location of the table they
+// point to.
+pub fn tables(catalog: &DeltaCatalog) -> Result<DataFrame, DeltaError> {
+    let mut schemas = vec![];
+    let mut names = vec![];
+    let mut types = vec![];
+    let mut locations = vec![];
+
+    for (name, table_type) in entries(catalog) {
+        let (schema, table) = split_name(name);
+        schemas.push(schema);
+        names.push(table);
+        types.push(table_type);
+        locations.push(catalog.resolve(name)?.to_owned());
+    }
+
+    Ok(DataFrame::new(vec![
+        Series::new("table_schema", schemas),
+        Series::new("table_name", names),
+        Series::new("table_type", types),
+        Series::new("location", locations),
+    ])?)
+}
+
+// One row per column of every table and alias. Tables in storage we can't
+// read from are left out, since there's no way to know their columns.
+pub fn columns(catalog: &DeltaCatalog) -> Result<DataFrame, DeltaError> {
+    let mut schemas = vec![];
+    let mut names = vec![];
+    let mut columns = vec![];
+    let mut positions = vec![];
+    let mut types = vec![];
+    let mut nullable = vec![];
+    let mut partitions = vec![];
+
+    for (name, _) in entries(catalog) {
+        let table = match catalog.open_table(name) {
+            Ok(table) => table,
+            Err(DeltaError::UnsupportedStorage(_)) => continue,
+            Err(e) => return Err(e),
+        };
+        let metadata = table.metadata();
+
+        let (schema, table_name) = split_name(name);
+        for (i, field) in metadata.schema()?.fields().iter().enumerate() {
+            schemas.push(schema.clone());
+            names.push(table_name.clone());
+            columns.push(field.name.clone());
+            positions.push(i as i64 + 1);
+            types.push(field.typ.to_sql_type());
+            nullable.push(if field.nullable { "YES" } else { "NO" });
+            partitions.push(metadata.partition_columns().contains(&field.name));
+        }
+    }
+
+    Ok(DataFrame::new(vec![
+        Series::new("table_schema", schemas),
+        Series::new("table_name", names),
+        Series::new("column_name", columns),
+        Series::new("ordinal_position", positions),
+        Series::new("data_type", types),
+        Series::new("is_nullable", nullable),
+        Series::new("is_partition_column", partitions),
+    ])?)
+}
+
+fn entries(catalog: &DeltaCatalog) -> Vec<(&str, &str)> {
+    let tables = catalog
+        .tables()
+        .into_iter()
+        .map(|(name, _)| (name, "BASE TABLE"));
+    let aliases = catalog
+        .aliases()
+        .into_iter()
+        .map(|(alias, _)| (alias, "ALIAS"));
+    tables.chain(aliases).collect()
+}
+
+// `prod.events` is table `events` in schema `prod`. Unqualified names have
+// no schema.
+fn split_name(name: &str) -> (Option<String>, String) {
+    let mut parts = split_qualified_name(name);
+    let table = parts.pop().unwrap_or_default();
+    match parts.is_empty() {
+        true => (None, table),
+        false => (Some(join_qualified_name(&parts)), table),
+    }
+}
@@ -0,0 +1,123 @@
+// Exactly-once ingestion of a stream of record batches from a remote
+// producer, without the producer touching the table's files. With the
+// `flight` feature, `flight::FlightIngest` feeds Arrow Flight DoPut
+// streams into it; other transports can push batches themselves.
+//
+// Batches are buffered and committed together once there are enough rows,
+// so small batches don't each become a commit and a data file. Every
+// commit is numbered within the stream and carries the number as the txn
+// version of the stream's id, see `DeltaTable::insert_df_with_txn`. A
+// producer that lost its connection or didn't hear back can resend the
+// whole stream under the same id: the batches are split into the same
+// commits, and the ones the table already has are skipped rather than
+// written twice.
+
+use crate::{
+    error::DeltaError,
+    metrics::{IngestResult, InsertResult},
+    table::DeltaTable,
+};
+use polars::prelude::*;
+
+pub struct StreamIngest {
+    table: DeltaTable,
+    stream_id: String,
+    batch_rows: usize,
+    pending: Vec<DataFrame>,
+    pending_rows: usize,
+    result: IngestResult,
+}
+
+impl StreamIngest {
+    // Ingest into `table` as stream `stream_id`, which has to be unique to
+    // the stream's contents, committing about every `batch_rows` rows
+    pub fn new(table: DeltaTable, stream_id: &str, batch_rows: usize) -> StreamIngest {
+        StreamIngest {
+            table,
+            stream_id: stream_id.to_owned(),
+            batch_rows: batch_rows.max(1),
+            pending: vec![],
+            pending_rows: 0,
+            result: IngestResult::default(),
+        }
+    }
+
+    // Take the next batch of the stream, committing the buffered batches
+    // once they add up to `batch_rows` rows. Returns the insert when one
+    // was committed or skipped.
+    pub fn push(&mut self, batch: DataFrame) -> Result<Option<InsertResult>, DeltaError> {
+        self.result.batches_received += 1;
+        self.result.rows_received += batch.height() as u64;
+        self.pending_rows += batch.height();
+        self.pending.push(batch);
+
+        match self.pending_rows >= self.batch_rows {
+            true => self.flush().map(Some),
+            false => Ok(None),
+        }
+    }
+
+    // Commit what's left of the stream once it ended
+    pub fn finish(mut self) -> Result<IngestResult, DeltaError> {
+        if !self.pending.is_empty() {
+            self.flush()?;
+        }
+        if self.result.commits == 0 {
+            self.result.version = self.table.version()?;
+        }
+        Ok(self.result)
+    }
+
+    fn flush(&mut self) -> Result<InsertResult, DeltaError> {
+        let mut df = self.pending.remove(0);
+        for batch in self.pending.drain(..) {
+            df.vstack_mut(&batch)?;
+        }
+        self.pending_rows = 0;
+
+        let sequence = self.result.commits as i64;
+        let insert = self
+            .table
+            .insert_df_with_txn(df, &self.stream_id, sequence)?;
+        self.result.commits += 1;
+        if insert.skipped {
+            self.result.commits_skipped += 1;
+        }
+        self.result.rows_written += insert.rows_written;
+        self.result.version = insert.version;
+        Ok(insert)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{assert_log_invariants, TempTable};
+
+    fn ingest(temp: &TempTable, batches: &[&[i32]]) -> IngestResult {
+        let mut ingest = StreamIngest::new(temp.table().unwrap(), "stream-1", 3);
+        for batch in batches {
+            ingest.push(df!("id" => batch).unwrap()).unwrap();
+        }
+        ingest.finish().unwrap()
+    }
+
+    #[test]
+    fn resent_stream_is_skipped() {
+        let temp = TempTable::new(vec![("id", "int")]).unwrap();
+        let batches: &[&[i32]] = &[&[1, 2], &[3, 4], &[5]];
+
+        let first = ingest(&temp, batches);
+        assert_eq!(first.commits, 2);
+        assert_eq!(first.commits_skipped, 0);
+        assert_eq!(first.rows_written, 5);
+
+        let resent = ingest(&temp, batches);
+        assert_eq!(resent.commits, 2);
+        assert_eq!(resent.commits_skipped, 2);
+        assert_eq!(resent.rows_written, 0);
+        assert_eq!(resent.version, first.version);
+        assert_eq!(temp.table().unwrap().count().unwrap(), 5);
+        assert_log_invariants(temp.path());
+    }
+}
@@ -1,7 +1,50 @@
+pub mod actions;
+pub mod cache;
+pub mod catalog;
+pub mod context;
+pub mod csv;
+pub mod definition;
+pub mod derive;
+pub mod description;
+pub mod diff;
 pub mod error;
+pub mod file_info;
+#[cfg(feature = "flight")]
+pub mod flight;
+pub mod history;
+pub mod identifier;
+pub mod ingest;
+pub mod metrics;
+pub mod ndjson;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+pub mod options;
+pub mod output;
+pub mod plan;
+pub mod policy;
+pub mod storage;
 pub mod table;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod value;
 
-mod actions;
+mod batches;
+mod bloom;
+mod checkpoint;
+mod checksum;
 mod data_file;
+mod information_schema;
+mod manifest;
 mod metadata;
+mod partition;
+mod portable;
+mod predicate;
+mod row_groups;
 mod schema;
+mod sql;
+mod statement;
+mod stats;
+
+pub use delta_derive::DeltaSchema;
+pub use derive::DeltaSchema;
+pub use statement::sql;
@@ -1,19 +1,1202 @@
-use delta::{error::DeltaError, table::DeltaTable};
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
+use delta::{
+    csv::{BadRowPolicy, CsvLoadResult, CsvOptions},
+    description::TableDescription,
+    diff::{DiffFile, SchemaChange},
+    error::DeltaError,
+    identifier::split_leading_identifier,
+    metrics::SqlResult,
+    ndjson::{MissingField, NdjsonOptions, UnknownField},
+    options::{CompactionPolicy, CreateOptions, VacuumOptions},
+    output::{OutputFormat, ResultWriter},
+    plan::{Explain, PruneReason},
+    sql,
+    table::DeltaTable,
+};
+use polars::export::chrono::NaiveDateTime;
+use rustyline::{error::ReadlineError, DefaultEditor};
+use serde_json::{Map, Value};
+use std::{collections::BTreeMap, env, fs, io, path::PathBuf, time::Duration};
+
+#[derive(Parser)]
+#[command(name = "delta", about = "Inspect and manage Delta tables")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    #[arg(
+        long,
+        short,
+        global = true,
+        help = "Log what the table does to stderr, e.g. how many files each scan pruned"
+    )]
+    verbose: bool,
+}
+
+// Writes the library's debug logs to stderr, see `Cli::verbose`
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.target().starts_with("delta")
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{}] {}", record.target(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Create a table")]
+    Create {
+        #[arg(help = "Path to the table")]
+        table: String,
+        #[arg(
+            required_unless_present = "from_schema",
+            help = "Columns like 'id BIGINT' or 'name TEXT NULL', see `DeltaTable::create`"
+        )]
+        columns: Vec<String>,
+        #[arg(
+            long,
+            value_delimiter = ',',
+            help = "Columns to partition the table by"
+        )]
+        partition_by: Vec<String>,
+        #[arg(long, value_delimiter = ',', help = "Columns identifying a row")]
+        unique_key: Vec<String>,
+        #[arg(
+            long = "property",
+            value_parser = parse_property,
+            help = "A table property like portableParquet=true, can be repeated"
+        )]
+        properties: Vec<(String, String)>,
+        #[arg(
+            long,
+            conflicts_with_all = ["columns", "partition_by", "unique_key", "properties"],
+            help = "Create the table from a file written by export-schema"
+        )]
+        from_schema: Option<PathBuf>,
+    },
+    #[command(about = "Print the table's columns and settings as JSON, see create --from-schema")]
+    ExportSchema {
+        #[arg(help = "Path to the table")]
+        table: String,
+    },
+    #[command(about = "List the tables under a directory")]
+    Ls {
+        #[arg(default_value = "tables", help = "Directory to look for tables in")]
+        root: String,
+    },
+    #[command(about = "Print a table's columns and settings")]
+    Describe {
+        #[arg(help = "Path to the table")]
+        table: String,
+        #[arg(long, help = "Print the description as JSON")]
+        json: bool,
+    },
+    #[command(about = "List a partitioned table's partitions with their files and sizes")]
+    Partitions {
+        #[arg(help = "Path to the table")]
+        table: String,
+    },
+    #[command(about = "Append the rows of a CSV file, or of stdin, to a table")]
+    Insert {
+        #[arg(help = "Path to the table")]
+        table: String,
+        #[arg(required_unless_present = "stdin", help = "Path to the CSV file")]
+        file: Option<String>,
+        #[arg(
+            long,
+            conflicts_with = "file",
+            help = "Read rows from stdin, committing them a batch at a time"
+        )]
+        stdin: bool,
+        #[arg(long, value_enum, default_value_t = InputFormat::Csv, help = "Format of stdin")]
+        format: InputFormat,
+        #[arg(
+            long,
+            default_value_t = 128,
+            help = "MiB of stdin to write to each data file"
+        )]
+        batch_size: usize,
+        #[arg(long, help = "The file has no header, columns are matched by position")]
+        no_header: bool,
+        #[arg(long, default_value_t = ',', help = "Character separating the values")]
+        delimiter: char,
+    },
+    #[command(about = "Load a file into a table, checking its rows against the schema")]
+    #[command(group(ArgGroup::new("file").required(true).args(["csv", "ndjson"])))]
+    Load {
+        #[arg(help = "Path to the table")]
+        table: String,
+        #[arg(long, help = "Path to the CSV file")]
+        csv: Option<String>,
+        #[arg(long, help = "Path to a file with a JSON object per line")]
+        ndjson: Option<String>,
+        #[arg(long, help = "The file has no header, columns are matched by position")]
+        no_header: bool,
+        #[arg(long, default_value_t = ',', help = "Character separating the values")]
+        delimiter: char,
+        #[arg(long, help = "Value to read as null, besides empty fields")]
+        null_token: Option<String>,
+        #[arg(
+            long,
+            help = "Leave out rows that don't fit the schema instead of failing"
+        )]
+        skip_bad_rows: bool,
+        #[arg(
+            long,
+            help = "Fail on JSON lines lacking one of the columns instead of using null"
+        )]
+        require_all_fields: bool,
+        #[arg(long, help = "Ignore JSON fields that aren't columns of the table")]
+        ignore_unknown_fields: bool,
+    },
+    #[command(about = "Show the files a SELECT or DELETE would read and its logical plan")]
+    Explain {
+        #[arg(help = "Path to the table")]
+        table: String,
+        #[arg(help = "The statement, referring to the table by its name")]
+        sql: String,
+    },
+    #[command(about = "Delete the rows of a table matching a predicate")]
+    Delete {
+        #[arg(help = "Path to the table")]
+        table: String,
+        #[arg(help = "A SQL predicate like \"id < 10\"")]
+        predicate: String,
+    },
+    #[command(about = "Print a line per commit, latest first")]
+    History {
+        #[arg(help = "Path to the table")]
+        table: String,
+        #[arg(long, help = "Only print this many commits")]
+        limit: Option<usize>,
+    },
+    #[command(about = "Compact small data files into bigger ones")]
+    Optimize {
+        #[arg(help = "Path to the table")]
+        table: String,
+        #[arg(
+            long,
+            help = "Merge files of similar size in tiers instead of packing small files together"
+        )]
+        size_tiered: bool,
+        #[arg(
+            long,
+            default_value_t = 128,
+            conflicts_with = "size_tiered",
+            help = "MiB to pack files up to"
+        )]
+        target_size: u64,
+        #[arg(
+            long,
+            default_value_t = 8,
+            requires = "size_tiered",
+            help = "MiB below which files are in the lowest tier"
+        )]
+        min_size: u64,
+        #[arg(
+            long,
+            default_value_t = 4,
+            requires = "size_tiered",
+            help = "How many times bigger the files of each tier are than the one below"
+        )]
+        tier_factor: u64,
+        #[arg(
+            long,
+            default_value_t = 4,
+            requires = "size_tiered",
+            help = "Files a tier needs before they're merged"
+        )]
+        min_files: usize,
+    },
+    #[command(about = "Delete data files the table no longer refers to")]
+    Vacuum {
+        #[arg(help = "Path to the table")]
+        table: String,
+        #[arg(
+            long,
+            default_value_t = 168,
+            help = "Keep files that were in use less than this many hours ago"
+        )]
+        retention_hours: u64,
+        #[arg(
+            long,
+            help = "Print the files that would be deleted without deleting them"
+        )]
+        dry_run: bool,
+        #[arg(
+            long,
+            help = "Record the deleted files in the table's vacuum inventory, under _delta_vacuum/"
+        )]
+        inventory: bool,
+    },
+    #[command(
+        about = "Run SQL statements interactively against the tables under tables/",
+        long_about = "Run SQL statements interactively against the tables under tables/ \
+            in the current directory. Statements end with a semicolon and can span several \
+            lines. Exit with Ctrl-D or .quit."
+    )]
+    Shell {
+        #[arg(
+            long,
+            help = "Where to keep the command history [default: ~/.delta_history]"
+        )]
+        history: Option<PathBuf>,
+    },
+    #[command(about = "Work with a table's transaction log")]
+    Log {
+        #[command(subcommand)]
+        command: LogCommand,
+    },
+    #[command(about = "Show how a table changed between two versions")]
+    Diff {
+        #[arg(help = "Path to the table")]
+        table: String,
+        #[arg(long, help = "Version to compare from [default: the one before --to]")]
+        from: Option<i64>,
+        #[arg(long, help = "Version to compare to [default: latest]")]
+        to: Option<i64>,
+    },
+    #[command(about = "Run a SELECT against a table and print the result")]
+    Query {
+        #[arg(help = "Path to the table")]
+        table: String,
+        #[arg(help = "The query, referring to the table by its name")]
+        sql: String,
+        #[arg(long, value_enum, default_value_t = Format::Csv, help = "How rows are printed")]
+        format: Format,
+        #[arg(
+            long,
+            value_parser = parse_interval,
+            help = "Run the query again on every new commit, checking this often, e.g. 5s or 500ms"
+        )]
+        watch: Option<Duration>,
+    },
+    #[cfg(feature = "flight")]
+    #[command(
+        about = "Serve Arrow Flight DoPut streams into the tables under a directory",
+        long_about = "Serve Arrow Flight DoPut streams into the tables under a directory. \
+            A stream's descriptor is a path of the table's name and the stream's id, and \
+            resending a stream under the same id doesn't write its rows twice."
+    )]
+    Serve {
+        #[arg(long, default_value = "127.0.0.1:50051", help = "Address to listen on")]
+        addr: std::net::SocketAddr,
+        #[arg(
+            long,
+            default_value = "tables",
+            help = "Directory to look for tables in"
+        )]
+        root: String,
+        #[arg(
+            long,
+            default_value_t = 100_000,
+            help = "Rows of a stream to buffer before committing them"
+        )]
+        batch_rows: usize,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Table,
+    Csv,
+    // One JSON object per row
+    Json,
+    Markdown,
+}
+
+impl From<Format> for OutputFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Table => OutputFormat::Table,
+            Format::Csv => OutputFormat::Csv,
+            Format::Json => OutputFormat::JsonLines,
+            Format::Markdown => OutputFormat::Markdown,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum InputFormat {
+    Csv,
+    // One JSON object per line
+    Ndjson,
+}
+
+#[derive(Subcommand)]
+enum LogCommand {
+    #[command(about = "Print commits with their actions grouped by type")]
+    Show {
+        #[arg(help = "Path to the table")]
+        table: String,
+        #[arg(
+            help = "A version like 3, or an inclusive range like 2..5, 2.. or ..5 [default: latest]"
+        )]
+        versions: Option<String>,
+        #[arg(long, help = "Print the commits' actions as JSON, one per line")]
+        raw: bool,
+    },
+}
 
 fn main() -> Result<(), DeltaError> {
-    let table = DeltaTable::create_table("my-table", vec![("foo", "int"), ("bar", "TEXT")])?;
+    let cli = Cli::parse();
+    if cli.verbose {
+        log::set_logger(&StderrLogger).expect("no logger is set yet");
+        log::set_max_level(log::LevelFilter::Debug);
+    }
+
+    match cli.command {
+        Command::Create {
+            table,
+            columns,
+            partition_by,
+            unique_key,
+            properties,
+            from_schema,
+        } => match from_schema {
+            Some(file) => {
+                let definition = serde_json::from_str(&fs::read_to_string(file)?)?;
+                DeltaTable::create_from_definition(&table, &definition)?;
+                println!("created {}", table);
+                Ok(())
+            }
+            None => create(&table, &columns, partition_by, unique_key, properties),
+        },
+        Command::ExportSchema { table } => {
+            let definition = DeltaTable::open(&table)?.definition()?;
+            println!("{}", serde_json::to_string_pretty(&definition)?);
+            Ok(())
+        }
+        Command::Ls { root } => {
+            for table in DeltaTable::list_tables(&root)? {
+                let created = NaiveDateTime::from_timestamp_millis(table.created_time)
+                    .map_or("-".to_owned(), |ts| {
+                        ts.format("%Y-%m-%d %H:%M:%S").to_string()
+                    });
+                println!("{:>6}  {:19}  {}", table.version, created, table.name);
+            }
+            Ok(())
+        }
+        Command::Describe { table, json } => {
+            let description = DeltaTable::open(&table)?.describe()?;
+            match json {
+                true => println!("{}", serde_json::to_string_pretty(&description)?),
+                false => print_description(&description),
+            }
+            Ok(())
+        }
+        Command::Partitions { table } => {
+            for partition in DeltaTable::open(&table)?.partitions()? {
+                let values = partition
+                    .values
+                    .iter()
+                    .map(|(column, value)| {
+                        format!("{}={}", column, value.as_deref().unwrap_or("null"))
+                    })
+                    .collect::<Vec<String>>()
+                    .join("/");
+                let rows = partition
+                    .num_records
+                    .map_or("-".to_owned(), |rows| rows.to_string());
+                println!(
+                    "{:>6} files  {:>10}  {:>10} rows  {}",
+                    partition.num_files,
+                    format_size(partition.size),
+                    rows,
+                    values
+                );
+            }
+            Ok(())
+        }
+        Command::Insert {
+            table,
+            file,
+            stdin: _,
+            format,
+            batch_size,
+            no_header,
+            delimiter,
+        } => {
+            let options = CsvOptions {
+                delimiter: ascii_delimiter(delimiter)?,
+                has_header: !no_header,
+                ..Default::default()
+            };
+            match file {
+                Some(file) => load(&table, &file, &options),
+                None => insert_stdin(&table, format, &options, batch_size * 1024 * 1024),
+            }
+        }
+        Command::Load {
+            table,
+            csv,
+            ndjson,
+            no_header,
+            delimiter,
+            null_token,
+            skip_bad_rows,
+            require_all_fields,
+            ignore_unknown_fields,
+        } => {
+            if let Some(ndjson) = ndjson {
+                let options = NdjsonOptions {
+                    on_missing_field: match require_all_fields {
+                        true => MissingField::Fail,
+                        false => MissingField::Null,
+                    },
+                    on_unknown_field: match ignore_unknown_fields {
+                        true => UnknownField::Ignore,
+                        false => UnknownField::Fail,
+                    },
+                };
+                let result = DeltaTable::open(&table)?.copy_from_ndjson(&ndjson, &options)?;
+                println!(
+                    "inserted {} rows in {} files, version {}",
+                    result.rows_written, result.files_added, result.version
+                );
+                return Ok(());
+            }
+
+            let options = CsvOptions {
+                delimiter: ascii_delimiter(delimiter)?,
+                has_header: !no_header,
+                null_token,
+                on_bad_row: if skip_bad_rows {
+                    BadRowPolicy::Skip
+                } else {
+                    BadRowPolicy::Fail
+                },
+                ..Default::default()
+            };
+            load(&table, &csv.unwrap_or_default(), &options)
+        }
+        Command::Explain { table, sql } => {
+            print_explain(&DeltaTable::open(&table)?.explain(&sql)?);
+            Ok(())
+        }
+        Command::Delete { table, predicate } => {
+            let metrics = DeltaTable::open(&table)?.delete(&predicate)?;
+            println!(
+                "deleted {} rows, version {}",
+                metrics.rows_deleted, metrics.version
+            );
+            Ok(())
+        }
+        Command::History { table, limit } => show_history(&table, limit),
+        Command::Optimize {
+            table,
+            size_tiered,
+            target_size,
+            min_size,
+            tier_factor,
+            min_files,
+        } => {
+            let policy = match size_tiered {
+                true => CompactionPolicy::SizeTiered {
+                    min_size: min_size * 1024 * 1024,
+                    tier_factor,
+                    min_files,
+                },
+                false => CompactionPolicy::BinPack {
+                    target_size: target_size * 1024 * 1024,
+                },
+            };
+            let metrics = DeltaTable::open(&table)?.optimize(&policy)?;
+            println!(
+                "compacted {} files ({}) into {} ({}), version {}",
+                metrics.files_removed,
+                format_size(metrics.bytes_removed),
+                metrics.files_added,
+                format_size(metrics.bytes_added),
+                metrics.version
+            );
+            Ok(())
+        }
+        Command::Vacuum {
+            table,
+            retention_hours,
+            dry_run,
+            inventory,
+        } => {
+            let options = VacuumOptions {
+                retention: Duration::from_secs(retention_hours * 60 * 60),
+                dry_run,
+                inventory,
+            };
+            let metrics = DeltaTable::open(&table)?.vacuum(&options)?;
+            for file in &metrics.files_deleted {
+                println!("{}", file.path);
+            }
+            eprintln!(
+                "{} {} files ({}), kept {} live files and {} within the retention period",
+                if dry_run { "would delete" } else { "deleted" },
+                metrics.files_deleted.len(),
+                format_size(metrics.bytes_reclaimed),
+                metrics.files_live,
+                metrics.files_within_retention.len()
+            );
+            Ok(())
+        }
+        Command::Shell { history } => shell(history),
+        Command::Log {
+            command:
+                LogCommand::Show {
+                    table,
+                    versions,
+                    raw,
+                },
+        } => show_log(&table, versions.as_deref(), raw),
+        Command::Diff { table, from, to } => show_diff(&table, from, to),
+        Command::Query {
+            table,
+            sql,
+            format,
+            watch,
+        } => query(&table, &sql, format, watch),
+        #[cfg(feature = "flight")]
+        Command::Serve {
+            addr,
+            root,
+            batch_rows,
+        } => {
+            let runtime = tokio::runtime::Runtime::new()?;
+            eprintln!("serving the tables under {} on {}", root, addr);
+            runtime.block_on(delta::flight::FlightIngest::new(&root, batch_rows).serve(addr))
+        }
+    }
+}
+
+// Columns are given the way `DeltaTable::create` takes them, with the name
+// followed by the type
+fn create(
+    path: &str,
+    columns: &[String],
+    partition_by: Vec<String>,
+    unique_key: Vec<String>,
+    properties: Vec<(String, String)>,
+) -> Result<(), DeltaError> {
+    let schema = columns
+        .iter()
+        .map(|column| {
+            split_leading_identifier(column)
+                .ok_or_else(|| DeltaError::InvalidData(format!("column '{}' has no type", column)))
+        })
+        .collect::<Result<Vec<(&str, &str)>, DeltaError>>()?;
+    let options = CreateOptions {
+        partition_by,
+        unique_key,
+        properties: properties.into_iter().collect(),
+    };
+    DeltaTable::create_with(path, schema, options)?;
+    println!("created {}", path);
+    Ok(())
+}
+
+fn parse_property(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_owned(), value.trim().to_owned()))
+        }
+        _ => Err(format!("expected key=value, got '{}'", s)),
+    }
+}
+
+fn print_description(description: &TableDescription) {
+    println!(
+        "table {} ({}), version {}",
+        description.name, description.id, description.version
+    );
+
+    let width = description
+        .columns
+        .iter()
+        .map(|column| column.name.len())
+        .max()
+        .unwrap_or(0);
+    for column in &description.columns {
+        let mut notes = vec![];
+        if column.nullable {
+            notes.push("nullable");
+        }
+        if column.partition_column {
+            notes.push("partition");
+        }
+        let line = format!(
+            "  {:width$}  {:9}  {}",
+            column.name,
+            column.typ,
+            notes.join(", "),
+            width = width
+        );
+        println!("{}", line.trim_end());
+        for (key, value) in &column.metadata {
+            println!("    {}: {}", key, value);
+        }
+    }
+
+    if !description.configuration.is_empty() {
+        println!("configuration:");
+    }
+    for (key, value) in &description.configuration {
+        println!("  {} = {}", key, value);
+    }
+}
+
+fn ascii_delimiter(delimiter: char) -> Result<u8, DeltaError> {
+    if !delimiter.is_ascii() {
+        return Err(DeltaError::InvalidData(format!(
+            "delimiter '{}' isn't ASCII",
+            delimiter
+        )));
+    }
+    Ok(delimiter as u8)
+}
+
+fn load(path: &str, file: &str, options: &CsvOptions) -> Result<(), DeltaError> {
+    let result = DeltaTable::open(path)?.copy_from_csv(file, options)?;
+    print_load_result(&result);
+    Ok(())
+}
+
+fn insert_stdin(
+    path: &str,
+    format: InputFormat,
+    options: &CsvOptions,
+    batch_bytes: usize,
+) -> Result<(), DeltaError> {
+    let table = DeltaTable::open(path)?;
+    let stdin = io::stdin().lock();
+    match format {
+        InputFormat::Csv => {
+            print_load_result(&table.copy_from_csv_reader(stdin, options, batch_bytes)?)
+        }
+        InputFormat::Ndjson => {
+            let result =
+                table.copy_from_ndjson_reader(stdin, &NdjsonOptions::default(), batch_bytes)?;
+            println!(
+                "inserted {} rows in {} files, version {}",
+                result.rows_written, result.files_added, result.version
+            );
+        }
+    }
+    Ok(())
+}
+
+fn print_load_result(result: &CsvLoadResult) {
+    // Rows are numbered from 1 here, like an editor would
+    for rejected in &result.rejected {
+        eprintln!(
+            "skipped row {}: {}: {}",
+            rejected.row + 1,
+            rejected.column,
+            rejected.message
+        );
+    }
+    println!(
+        "inserted {} rows in {} files, version {}",
+        result.insert.rows_written, result.insert.files_added, result.insert.version
+    );
+    if !result.rejected.is_empty() {
+        println!("skipped {} rows", result.rejected.len());
+    }
+}
+
+// Rows are written out a batch at a time as the query produces them,
+// except as a table, so something like `delta query ... | head` prints its
+// rows straight away instead of waiting for the whole result.
+fn query(path: &str, sql: &str, format: Format, watch: Option<Duration>) -> Result<(), DeltaError> {
+    let table = DeltaTable::open(path)?;
+    let result = match watch {
+        None => print_query(&table, sql, format),
+        Some(interval) => {
+            let mut version = -1;
+            loop {
+                version = table.wait_for_commit(version, interval)?;
+                // On stderr, so the rows can still be piped somewhere
+                eprintln!("-- version {}", version);
+                if let Err(e) = print_query(&table, sql, format) {
+                    break Err(e);
+                }
+            }
+        }
+    };
+
+    // Whoever was reading the output is gone, e.g. `head` has all the rows
+    // it wanted
+    match result {
+        Err(DeltaError::IOError(e)) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
+
+fn print_query(table: &DeltaTable, sql: &str, format: Format) -> Result<(), DeltaError> {
+    let mut writer = ResultWriter::new(io::stdout().lock(), format.into());
+    for batch in table.query_batches(sql)? {
+        writer.write(&batch?)?;
+    }
+    writer.finish().map(|_| ())
+}
+
+// Durations like 5s, 500ms, 2m or 1h. A bare number is in seconds.
+fn parse_interval(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("'{}' doesn't start with a number", s))?;
+    if number == 0 {
+        return Err("the interval has to be more than 0".to_owned());
+    }
+    match unit {
+        "ms" => Ok(Duration::from_millis(number)),
+        "" | "s" => Ok(Duration::from_secs(number)),
+        "m" => Ok(Duration::from_secs(number * 60)),
+        "h" => Ok(Duration::from_secs(number * 60 * 60)),
+        _ => Err(format!("unknown unit '{}', expected ms, s, m or h", unit)),
+    }
+}
+
+fn shell(history: Option<PathBuf>) -> Result<(), DeltaError> {
+    let history = history
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".delta_history")));
+    let mut editor = DefaultEditor::new().map_err(readline_error)?;
+    if let Some(history) = &history {
+        // There's no history yet the first time
+        let _ = editor.load_history(history);
+    }
+
+    let mut statement = String::new();
+    loop {
+        let prompt = if statement.is_empty() {
+            "delta> "
+        } else {
+            "   ..> "
+        };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            // Ctrl-C drops the statement being typed, like in other shells
+            Err(ReadlineError::Interrupted) => {
+                statement.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(readline_error(e)),
+        };
 
-    table.insert(vec![
-        vec!["1", "test row"],
-        vec!["2", "another test row"],
-        vec!["3", "the final row"],
-    ])?;
+        if statement.is_empty() && matches!(line.trim(), ".quit" | ".exit") {
+            break;
+        }
+        if statement.is_empty() && line.trim().is_empty() {
+            continue;
+        }
+        if !statement.is_empty() {
+            statement.push('\n');
+        }
+        statement.push_str(&line);
+        if !statement.trim_end().ends_with(';') {
+            continue;
+        }
 
-    let table = DeltaTable::read_table("my-table")?;
+        let _ = editor.add_history_entry(statement.as_str());
+        if let Err(e) = sql(statement.trim_end().trim_end_matches(';')).and_then(print_sql_result) {
+            eprintln!("error: {:?}", e);
+        }
+        statement.clear();
+    }
 
-    table.insert(vec![vec!["4", "second insert"]])?;
+    if let Some(history) = &history {
+        editor.save_history(history).map_err(readline_error)?;
+    }
+    Ok(())
+}
+
+fn print_sql_result(result: SqlResult) -> Result<(), DeltaError> {
+    match result {
+        SqlResult::Created => println!("created"),
+        SqlResult::Inserted(result) => println!(
+            "inserted {} rows, version {}",
+            result.rows_written, result.version
+        ),
+        SqlResult::Deleted(metrics) => println!(
+            "deleted {} rows, version {}",
+            metrics.rows_deleted, metrics.version
+        ),
+        SqlResult::Updated(metrics) => println!(
+            "updated {} rows, version {}",
+            metrics.rows_updated, metrics.version
+        ),
+        SqlResult::Rows(df) => {
+            let mut writer = ResultWriter::new(io::stdout().lock(), OutputFormat::Table);
+            writer.write(&df)?;
+            let _ = writer.finish()?;
+        }
+        SqlResult::Explained(explain) => print_explain(&explain),
+    }
+    Ok(())
+}
+
+fn print_explain(explain: &Explain) {
+    for table in &explain.tables {
+        println!("table {}, version {}", table.table, table.version);
+        println!("  scanned: {} files", table.files_scanned.len());
+        for path in &table.files_scanned {
+            println!("    {}", path);
+        }
+        println!("  pruned: {} files", table.files_pruned.len());
+        for file in &table.files_pruned {
+            let reason = match file.reason {
+                PruneReason::Partition => "partition values",
+                PruneReason::Stats => "stats",
+                PruneReason::BloomFilter => "bloom filter",
+                PruneReason::Limit => "limit",
+            };
+            println!("    {} ({})", file.path, reason);
+        }
+    }
+
+    println!("plan:");
+    for line in explain.logical_plan.lines() {
+        println!("  {}", line);
+    }
+}
+
+fn readline_error(e: ReadlineError) -> DeltaError {
+    match e {
+        ReadlineError::Io(e) => DeltaError::IOError(e),
+        e => DeltaError::IOError(io::Error::other(e)),
+    }
+}
+
+fn show_diff(path: &str, from: Option<i64>, to: Option<i64>) -> Result<(), DeltaError> {
+    let table = DeltaTable::open(path)?;
+    let to = match to {
+        Some(to) => to,
+        None => table.versions()?.last().copied().unwrap_or(0),
+    };
+    let from = from.unwrap_or((to - 1).max(0));
+    let diff = table.diff(from, to)?;
+
+    let size = |files: &[DiffFile]| format_size(files.iter().map(|f| f.size).sum());
+    println!("diff {}..{}", diff.from, diff.to);
+    println!(
+        "  files: +{} ({}), -{} ({})",
+        diff.files_added.len(),
+        size(&diff.files_added),
+        diff.files_removed.len(),
+        size(&diff.files_removed)
+    );
+    println!(
+        "  rows: {}",
+        diff.rows_delta
+            .map_or("unknown".to_owned(), |rows| format!("{:+}", rows))
+    );
 
-    table.delete("bar = 'test row' OR foo = 2")?; // Expect to delete 1 and 2
+    let rows = |file: &DiffFile| file.rows.map_or("unknown".to_owned(), |r| r.to_string());
+    for file in &diff.files_added {
+        println!(
+            "    + {} ({}, {} rows)",
+            file.path,
+            format_size(file.size),
+            rows(file)
+        );
+    }
+    for file in &diff.files_removed {
+        println!(
+            "    - {} ({}, {} rows)",
+            file.path,
+            format_size(file.size),
+            rows(file)
+        );
+    }
+
+    if !diff.schema_changes.is_empty() {
+        println!("  schema:");
+    }
+    for change in &diff.schema_changes {
+        match change {
+            SchemaChange::ColumnAdded { name, typ } => println!("    + {} {}", name, typ),
+            SchemaChange::ColumnRemoved { name } => println!("    - {}", name),
+            SchemaChange::TypeChanged { name, from, to } => {
+                println!("    ~ {} {} -> {}", name, from, to)
+            }
+            SchemaChange::NullabilityChanged { name, nullable } => println!(
+                "    ~ {} {}",
+                name,
+                if *nullable {
+                    "nullable"
+                } else {
+                    "not nullable"
+                }
+            ),
+            SchemaChange::PartitionColumnsChanged { from, to } => println!(
+                "    ~ partitioned by [{}] -> [{}]",
+                from.join(", "),
+                to.join(", ")
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+fn show_history(path: &str, limit: Option<usize>) -> Result<(), DeltaError> {
+    for entry in DeltaTable::open(path)?.history(limit)? {
+        let timestamp = NaiveDateTime::from_timestamp_millis(entry.timestamp)
+            .map_or("-".to_owned(), |ts| {
+                ts.format("%Y-%m-%d %H:%M:%S").to_string()
+            });
+        let operation = entry.operation.as_deref().unwrap_or("-");
+        let parameters = entry
+            .operation_parameters
+            .iter()
+            .collect::<BTreeMap<_, _>>()
+            .into_iter()
+            .map(|(name, value)| format!("{}={}", name, string(Some(value))))
+            .collect::<Vec<String>>()
+            .join(" ");
+        let parameters = match &entry.user_metadata {
+            Some(user_metadata) => format!("{}  {:?}", parameters, user_metadata),
+            None => parameters,
+        };
+        let rows = entry
+            .metrics
+            .rows_added
+            .map_or("-".to_owned(), |rows| rows.to_string());
+        let line = format!(
+            "{:>6}  {:19}  {:12}  +{} -{} files  {} rows  {}  {}",
+            entry.version,
+            timestamp,
+            operation,
+            entry.metrics.files_added,
+            entry.metrics.files_removed,
+            rows,
+            format_size(entry.metrics.bytes_added),
+            parameters
+        );
+        println!("{}", line.trim_end());
+    }
+
+    Ok(())
+}
+
+fn show_log(path: &str, versions: Option<&str>, raw: bool) -> Result<(), DeltaError> {
+    let table = DeltaTable::open(path)?;
+    let all = table.versions()?;
+    let latest = all.last().copied().unwrap_or(-1);
+
+    let (start, end) = parse_versions(versions, latest)?;
+    for version in all.into_iter().filter(|v| *v >= start && *v <= end) {
+        let actions = table.read_commit(version)?;
+        if raw {
+            for action in &actions {
+                println!("{}", action);
+            }
+        } else {
+            print_commit(version, &actions);
+        }
+    }
 
     Ok(())
 }
+
+fn parse_versions(versions: Option<&str>, latest: i64) -> Result<(i64, i64), DeltaError> {
+    let parse = |s: &str, default: i64| match s.trim() {
+        "" => Ok(default),
+        s => s
+            .parse::<i64>()
+            .map_err(|_| DeltaError::InvalidData(format!("invalid version '{}'", s))),
+    };
+
+    let (start, end) = match versions {
+        None => (latest, latest),
+        Some(versions) => match versions.split_once("..") {
+            Some((start, end)) => (parse(start, 0)?, parse(end, latest)?),
+            None => {
+                let version = parse(versions, latest)?;
+                (version, version)
+            }
+        },
+    };
+
+    if end > latest || start > end {
+        return Err(DeltaError::VersionNotFound(end.max(start)));
+    }
+    Ok((start, end))
+}
+
+fn print_commit(version: i64, actions: &[Value]) {
+    println!("version {}", version);
+
+    let mut adds = vec![];
+    let mut removes = vec![];
+    let mut other = vec![];
+    for action in actions {
+        let Some((kind, body)) = action.as_object().and_then(|a| a.iter().next()) else {
+            continue;
+        };
+        match (kind.as_str(), body.as_object()) {
+            ("add", Some(add)) => adds.push(add),
+            ("remove", Some(remove)) => removes.push(remove),
+            ("metaData", Some(metadata)) => print_metadata(metadata),
+            _ => other.push(kind.as_str()),
+        }
+    }
+
+    if !adds.is_empty() {
+        let stats = adds.iter().map(|add| stats(add)).collect::<Vec<_>>();
+        let rows = stats
+            .iter()
+            .map(|stats| stats.get("numRecords").and_then(Value::as_u64))
+            .sum::<Option<u64>>();
+        let bytes = adds
+            .iter()
+            .filter_map(|add| add.get("size").and_then(Value::as_u64))
+            .sum::<u64>();
+
+        println!(
+            "  add: {} files, {} rows, {}",
+            adds.len(),
+            rows.map_or("unknown".to_owned(), |rows| rows.to_string()),
+            format_size(bytes)
+        );
+        for (add, stats) in adds.iter().zip(&stats) {
+            print_add(add, stats);
+        }
+    }
+
+    if !removes.is_empty() {
+        println!("  remove: {} files", removes.len());
+        for remove in removes {
+            println!("    {}", string(remove.get("path")));
+        }
+    }
+
+    if !other.is_empty() {
+        println!("  other: {}", other.join(", "));
+    }
+}
+
+fn print_metadata(metadata: &Map<String, Value>) {
+    println!("  metaData: {}", string(metadata.get("name")));
+
+    let schema = metadata
+        .get("schemaString")
+        .and_then(Value::as_str)
+        .and_then(|schema| serde_json::from_str::<Value>(schema).ok());
+    let fields = schema
+        .as_ref()
+        .and_then(|schema| schema.get("fields"))
+        .and_then(Value::as_array);
+    for field in fields.into_iter().flatten() {
+        println!(
+            "    {} {}{}",
+            string(field.get("name")),
+            string(field.get("type")),
+            match field.get("nullable").and_then(Value::as_bool) {
+                Some(true) => " (nullable)",
+                _ => "",
+            }
+        );
+    }
+
+    if let Some(Value::Array(columns)) = metadata.get("partitionColumns") {
+        if !columns.is_empty() {
+            let columns = columns.iter().map(|c| string(Some(c))).collect::<Vec<_>>();
+            println!("    partitioned by {}", columns.join(", "));
+        }
+    }
+}
+
+fn print_add(add: &Map<String, Value>, stats: &Map<String, Value>) {
+    let modified = add
+        .get("modificationTime")
+        .and_then(Value::as_i64)
+        .and_then(NaiveDateTime::from_timestamp_millis)
+        .map(|ts| ts.format(" at %Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default();
+    println!(
+        "    {} ({}, {} rows{})",
+        string(add.get("path")),
+        format_size(add.get("size").and_then(Value::as_u64).unwrap_or(0)),
+        stats
+            .get("numRecords")
+            .map_or("unknown".to_owned(), |rows| rows.to_string()),
+        modified
+    );
+
+    if let Some(Value::Object(values)) = add.get("partitionValues") {
+        let mut values = values
+            .iter()
+            .map(|(column, value)| format!("{}={}", column, string(Some(value))))
+            .collect::<Vec<_>>();
+        values.sort();
+        if !values.is_empty() {
+            println!("      partition {}", values.join(", "));
+        }
+    }
+
+    let (Some(Value::Object(min)), Some(Value::Object(max))) =
+        (stats.get("minValues"), stats.get("maxValues"))
+    else {
+        return;
+    };
+    let nulls = stats.get("nullCount").and_then(Value::as_object);
+    for (column, min) in min {
+        let nulls = nulls
+            .and_then(|nulls| nulls.get(column))
+            .and_then(Value::as_u64)
+            .filter(|nulls| *nulls > 0)
+            .map(|nulls| format!(", {} nulls", nulls))
+            .unwrap_or_default();
+        println!(
+            "      {}: {} .. {}{}",
+            column,
+            string(Some(min)),
+            string(max.get(column)),
+            nulls
+        );
+    }
+}
+
+// Stats are stored as a JSON string inside the add action
+fn stats(add: &Map<String, Value>) -> Map<String, Value> {
+    match add
+        .get("stats")
+        .and_then(Value::as_str)
+        .and_then(|stats| serde_json::from_str(stats).ok())
+    {
+        Some(Value::Object(stats)) => stats,
+        _ => Map::new(),
+    }
+}
+
+fn string(value: Option<&Value>) -> String {
+    match value {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Null) | None => "null".to_owned(),
+        Some(value) => value.to_string(),
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.1} {}", size, units[unit]),
+    }
+}
@@ -0,0 +1,97 @@
+use crate::{actions::Add, error::DeltaError, metrics::ManifestInfo, partition};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+// Where the manifests go, relative to the table's directory. Engines set up
+// for symlink tables, like Presto, Trino or Athena, are pointed here.
+const MANIFEST_DIR: &str = "_symlink_format_manifest";
+const MANIFEST_FILE: &str = "manifest";
+
+// Write a symlink format manifest for the table in `base_dir` as of
+// `version`: a `manifest` file per partition, in the same Hive style
+// directories as the data files, listing the partition's data files one
+// full path per line. Unpartitioned tables get a single manifest, which is
+// written even when the table is empty. Manifests of partitions that no
+// longer have any files are removed.
+pub fn write_symlink_manifests(
+    base_dir: &str,
+    version: i64,
+    partition_columns: &[String],
+    files: &[Add],
+) -> Result<ManifestInfo, DeltaError> {
+    // Paths in the log are already URI encoded, so the table's directory
+    // is too
+    let base_uri = format!(
+        "file:{}",
+        partition::encode_path(&fs::canonicalize(base_dir)?.to_string_lossy())
+    );
+
+    let mut manifests: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    if partition_columns.is_empty() {
+        manifests.insert(String::new(), vec![]);
+    }
+    for add in files {
+        let directory = match partition_columns.is_empty() {
+            true => String::new(),
+            false => partition::directory(partition_columns, &add.partition_values),
+        };
+        let path = match partition::is_absolute_uri(&add.path) {
+            true => add.path.clone(),
+            false => format!("{}/{}", base_uri, add.path),
+        };
+        manifests.entry(directory).or_default().push(path);
+    }
+
+    let root = Path::new(base_dir).join(MANIFEST_DIR);
+    let mut written = vec![];
+    for (directory, paths) in &manifests {
+        let dir = root.join(directory);
+        fs::create_dir_all(&dir)?;
+
+        // Renamed into place so engines never read a partial manifest
+        let tmp = dir.join(format!(".{}.{}.tmp", MANIFEST_FILE, Uuid::new_v4()));
+        let mut contents = paths.join("\n");
+        if !contents.is_empty() {
+            contents.push('\n');
+        }
+        fs::write(&tmp, contents)?;
+        fs::rename(&tmp, dir.join(MANIFEST_FILE))?;
+        written.push(dir.join(MANIFEST_FILE));
+    }
+    remove_stale_manifests(&root, &written)?;
+
+    Ok(ManifestInfo {
+        version,
+        path: MANIFEST_DIR.to_owned(),
+        manifests: manifests.len(),
+        files: files.len(),
+    })
+}
+
+// Remove manifests under `dir` that aren't in `keep`, along with any
+// directories left empty. Returns whether `dir` itself is now empty.
+fn remove_stale_manifests(dir: &Path, keep: &[PathBuf]) -> Result<bool, DeltaError> {
+    let mut empty = true;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if remove_stale_manifests(&path, keep)? {
+                fs::remove_dir(&path)?;
+            } else {
+                empty = false;
+            }
+        } else if path.file_name().is_some_and(|name| name == MANIFEST_FILE)
+            && !keep.contains(&path)
+        {
+            fs::remove_file(&path)?;
+        } else {
+            empty = false;
+        }
+    }
+
+    Ok(empty)
+}
@@ -1,8 +1,21 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use uuid::Uuid;
 
-use crate::{error::DeltaError, schema::DeltaTableSchema};
+use crate::{actions::sorted, error::DeltaError, schema::DeltaTableSchema};
+
+// CHECK constraints are stored in the table's configuration under this
+// prefix followed by their name, like Spark does
+const CONSTRAINT_PREFIX: &str = "delta.constraints.";
+// The table's unique key as a JSON list of column names. Not a Delta
+// property, so other writers won't enforce it.
+const UNIQUE_KEY: &str = "uniqueKey";
+// Set to "true" to write data files any parquet reader can read, see
+// `portable_parquet`
+pub const PORTABLE_PARQUET: &str = "portableParquet";
+// Set to "true" by other writers to record changed rows, see
+// `check_writable`
+const CHANGE_DATA_FEED: &str = "delta.enableChangeDataFeed";
 
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -15,6 +28,7 @@ pub struct DeltaTableMetadata {
     // schema: DeltaTableSchema,
     schema_string: String,
     partition_columns: Vec<String>,
+    #[serde(serialize_with = "sorted")]
     configuration: HashMap<String, String>,
 }
 
@@ -40,8 +54,197 @@ impl DeltaTableMetadata {
     pub fn is_valid(&self) -> bool {
         self.format.is_valid()
             // && self.schema.is_valid() // TODO: add back
-            && self.partition_columns.is_empty()
-            && self.configuration.is_empty()
+            && self.partition_columns_are_valid()
+            && self.unique_key_is_valid()
+            && self.constraints().is_empty()
+    }
+
+    // Key columns have to be distinct NOT NULL columns of the schema
+    fn unique_key_is_valid(&self) -> bool {
+        let Ok(schema) = self.schema() else {
+            return false;
+        };
+        let Ok(key) = self.try_unique_key() else {
+            return false;
+        };
+
+        let mut seen: HashSet<&str> = HashSet::new();
+        key.iter().all(|column| {
+            seen.insert(column)
+                && schema
+                    .fields()
+                    .iter()
+                    .any(|field| &field.name == column && !field.nullable)
+        })
+    }
+
+    // Partition columns have to be distinct columns of the schema, and
+    // at least one column has to be left to write to the data files.
+    fn partition_columns_are_valid(&self) -> bool {
+        let Ok(schema) = self.schema() else {
+            return false;
+        };
+
+        let mut seen: HashSet<&str> = HashSet::new();
+        self.partition_columns.len() < schema.fields().len()
+            && self.partition_columns.iter().all(|column| {
+                seen.insert(column) && schema.fields().iter().any(|field| &field.name == column)
+            })
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn schema_string(&self) -> &str {
+        &self.schema_string
+    }
+
+    pub fn partition_columns(&self) -> &Vec<String> {
+        &self.partition_columns
+    }
+
+    pub fn configuration(&self) -> &HashMap<String, String> {
+        &self.configuration
+    }
+
+    // The table's CHECK constraints as (name, SQL expression), sorted by name
+    pub fn constraints(&self) -> Vec<(&str, &str)> {
+        let mut constraints = self
+            .configuration
+            .iter()
+            .filter_map(|(key, expr)| {
+                let name = key.strip_prefix(CONSTRAINT_PREFIX)?;
+                Some((name, expr.as_str()))
+            })
+            .collect::<Vec<_>>();
+        constraints.sort();
+        constraints
+    }
+
+    // The columns of the table's unique key, empty when it has none
+    pub fn unique_key(&self) -> Vec<String> {
+        self.try_unique_key().unwrap_or_default()
+    }
+
+    fn try_unique_key(&self) -> Result<Vec<String>, DeltaError> {
+        match self.configuration.get(UNIQUE_KEY) {
+            Some(key) => Ok(serde_json::from_str(key)?),
+            None => Ok(vec![]),
+        }
+    }
+
+    pub fn with_unique_key(&self, columns: &[String]) -> Result<DeltaTableMetadata, DeltaError> {
+        let mut metadata = self.clone();
+        if !columns.is_empty() {
+            metadata
+                .configuration
+                .insert(UNIQUE_KEY.to_owned(), serde_json::to_string(columns)?);
+        }
+        Ok(metadata)
+    }
+
+    // Table properties, i.e. the configuration besides the unique key and
+    // constraints. They're kept for other tools and not interpreted here.
+    // Whether data files are written for the widest range of readers, like
+    // DuckDB, Spark and older Hive, rather than as polars writes them by
+    // default. It's a regular property, so it's set with
+    // `CreateOptions::properties` and can be turned on for a table that
+    // already has files, which are left as they are.
+    pub fn portable_parquet(&self) -> bool {
+        self.configuration
+            .get(PORTABLE_PARQUET)
+            .is_some_and(|value| value.eq_ignore_ascii_case("true"))
+    }
+
+    pub fn properties(&self) -> HashMap<&str, &str> {
+        self.configuration
+            .iter()
+            .filter(|(key, _)| *key != UNIQUE_KEY && !key.starts_with(CONSTRAINT_PREFIX))
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect()
+    }
+
+    pub fn with_properties(
+        &self,
+        properties: &HashMap<String, String>,
+    ) -> Result<DeltaTableMetadata, DeltaError> {
+        if let Some(key) = properties
+            .keys()
+            .find(|key| *key == UNIQUE_KEY || key.starts_with(CONSTRAINT_PREFIX))
+        {
+            return Err(DeltaError::InvalidData(format!(
+                "'{}' can't be set as a property",
+                key
+            )));
+        }
+
+        let mut metadata = self.clone();
+        metadata.configuration.extend(properties.clone());
+        Ok(metadata)
+    }
+
+    pub fn with_constraint(&self, name: &str, expr: &str) -> DeltaTableMetadata {
+        let mut metadata = self.clone();
+        metadata
+            .configuration
+            .insert(format!("{}{}", CONSTRAINT_PREFIX, name), expr.to_owned());
+        metadata
+    }
+
+    // Writes to a table with the change data feed turned on would have to
+    // record the rows they change as well, which this crate doesn't
+    pub(crate) fn check_writable(&self) -> Result<(), DeltaError> {
+        match self.configuration.get(CHANGE_DATA_FEED) {
+            Some(value) if value.eq_ignore_ascii_case("true") => {
+                Err(DeltaError::UnsupportedProtocol(format!(
+                    "writing tables with {} isn't supported",
+                    CHANGE_DATA_FEED
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Check that `schemaString` is a schema this crate can read, without
+    // repeated column names, and that it has the partition columns. Other
+    // writers can put anything there, and it's better to find out when the
+    // table is opened than from a serde error halfway through a write.
+    pub(crate) fn check_schema(&self, version: i64) -> Result<(), DeltaError> {
+        let corrupt = |detail: String| DeltaError::CorruptMetadata { version, detail };
+
+        let schema = serde_json::from_str::<DeltaTableSchema>(&self.schema_string)
+            .map_err(|e| corrupt(format!("schemaString doesn't parse: {}", e)))?;
+        if schema.fields().is_empty() {
+            return Err(corrupt("schemaString has no columns".to_owned()));
+        }
+        let mut seen: HashSet<&str> = HashSet::new();
+        if let Some(field) = schema
+            .fields()
+            .iter()
+            .find(|field| !seen.insert(&field.name))
+        {
+            return Err(corrupt(format!(
+                "schemaString has column '{}' more than once",
+                field.name
+            )));
+        }
+        if let Some(column) = self
+            .partition_columns
+            .iter()
+            .find(|column| !seen.contains(column.as_str()))
+        {
+            return Err(corrupt(format!(
+                "partition column '{}' isn't in schemaString",
+                column
+            )));
+        }
+
+        Ok(())
     }
 
     pub fn schema(&self) -> Result<DeltaTableSchema, DeltaError> {
@@ -54,6 +257,7 @@ impl DeltaTableMetadata {
 #[serde(rename_all = "camelCase")]
 pub struct DeltaTableFormat {
     provider: String,
+    #[serde(serialize_with = "sorted")]
     options: HashMap<String, String>,
 }
 
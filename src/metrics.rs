@@ -0,0 +1,283 @@
+use crate::plan::{Explain, PruneReason};
+use polars::frame::DataFrame;
+use serde::{Deserialize, Serialize};
+use std::{fmt, time::Duration};
+
+#[derive(Debug, Clone)]
+pub struct DeleteMetrics {
+    pub rows_deleted: usize,
+    // Files that were read because their stats couldn't rule them out
+    pub files_scanned: usize,
+    // Files replaced by a copy without the deleted rows
+    pub files_rewritten: usize,
+    // Files where every row was deleted, so no copy was written
+    pub files_removed: usize,
+    // Row groups carried over into rewritten files without re-encoding
+    pub row_groups_copied: usize,
+    // Row groups re-encoded because some of their rows were deleted
+    pub row_groups_rewritten: usize,
+    pub pruning: FilePruning,
+    pub duration: Duration,
+    // The version committed by the delete. When nothing matched no commit
+    // is made and this is the version the delete ran against.
+    pub version: i64,
+}
+
+// How many of a table's live files an operation read and why it skipped
+// the others, to see how well the table's partitioning and file layout
+// serve it. Every scan, delete, update and key check logs this at debug
+// level with the `delta::pruning` target.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FilePruning {
+    pub files_scanned: usize,
+    pub pruned_by_partition: usize,
+    pub pruned_by_stats: usize,
+    pub pruned_by_bloom_filter: usize,
+    pub pruned_by_limit: usize,
+}
+
+impl FilePruning {
+    pub fn files_pruned(&self) -> usize {
+        self.pruned_by_partition
+            + self.pruned_by_stats
+            + self.pruned_by_bloom_filter
+            + self.pruned_by_limit
+    }
+
+    // Count a file as scanned when `reason` is `None`, or as pruned for it
+    pub(crate) fn record(&mut self, reason: Option<PruneReason>) {
+        match reason {
+            None => self.files_scanned += 1,
+            Some(PruneReason::Partition) => self.pruned_by_partition += 1,
+            Some(PruneReason::Stats) => self.pruned_by_stats += 1,
+            Some(PruneReason::BloomFilter) => self.pruned_by_bloom_filter += 1,
+            Some(PruneReason::Limit) => self.pruned_by_limit += 1,
+        }
+    }
+}
+
+// As key=value pairs, for logs
+impl fmt::Display for FilePruning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "files={} scanned={} pruned_by_partition={} pruned_by_stats={} \
+             pruned_by_bloom_filter={} pruned_by_limit={}",
+            self.files_scanned + self.files_pruned(),
+            self.files_scanned,
+            self.pruned_by_partition,
+            self.pruned_by_stats,
+            self.pruned_by_bloom_filter,
+            self.pruned_by_limit
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UpdateMetrics {
+    pub rows_updated: usize,
+    // Files that were read because their stats couldn't rule them out
+    pub files_scanned: usize,
+    // Files replaced by a copy with the updated rows
+    pub files_rewritten: usize,
+    pub pruning: FilePruning,
+    pub duration: Duration,
+    // The version committed by the update. When nothing matched no commit
+    // is made and this is the version the update ran against.
+    pub version: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct MergeMetrics {
+    // Rows of the table replaced by a row of the source with the same key
+    pub rows_replaced: usize,
+    // Rows of the source written, both replacing rows and new ones
+    pub rows_written: usize,
+    // Files that were read because their stats and bloom filter couldn't
+    // rule them out
+    pub files_scanned: usize,
+    // Files replaced by a copy without the replaced rows, or removed when
+    // every row was replaced
+    pub files_rewritten: usize,
+    pub pruning: FilePruning,
+    pub duration: Duration,
+    // The version committed by the merge. When the source was empty no
+    // commit is made and this is the version the merge ran against.
+    pub version: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct InsertResult {
+    // The version committed by the insert. When there were no rows to
+    // insert, or the table already had the insert's txn version, no commit
+    // is made and this is the version the insert ran against.
+    pub version: i64,
+    pub files_added: usize,
+    pub rows_written: u64,
+    pub bytes_written: u64,
+    // Whether the insert was skipped because the table already had its
+    // txn version
+    pub skipped: bool,
+}
+
+// What `ingest::StreamIngest` did with a stream
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IngestResult {
+    pub batches_received: usize,
+    pub rows_received: u64,
+    // The stream's commits, including the skipped ones
+    pub commits: usize,
+    // Commits the table already had from an earlier attempt at the stream
+    pub commits_skipped: usize,
+    pub rows_written: u64,
+    // The version of the stream's last commit. When it made none, the
+    // table's version when it ended.
+    pub version: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct DedupeMetrics {
+    pub rows_removed: usize,
+    // Files replaced by a copy without their duplicate rows
+    pub files_rewritten: usize,
+    // Files where every row was a duplicate, so no copy was written
+    pub files_removed: usize,
+    pub duration: Duration,
+    // The version committed by the dedupe. When there were no duplicates
+    // no commit is made and this is the version the dedupe ran against.
+    pub version: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct OptimizeMetrics {
+    // Files compacted into others
+    pub files_removed: usize,
+    pub files_added: usize,
+    pub bytes_removed: u64,
+    pub bytes_added: u64,
+    pub duration: Duration,
+    // The version committed by the optimize. When there was nothing to
+    // compact no commit is made and this is the version it ran against.
+    pub version: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ArchiveMetrics {
+    // The paths the moved files had, relative to the table's directory.
+    // Sorted.
+    pub files_archived: Vec<String>,
+    pub bytes_archived: u64,
+    pub duration: Duration,
+    // The version committed by the archive. When no file qualified no
+    // commit is made and this is the version the archive ran against.
+    pub version: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct VacuumMetrics {
+    // Sorted by path. With `VacuumOptions::dry_run`, the files that would
+    // have been deleted.
+    pub files_deleted: Vec<DeletedFile>,
+    pub bytes_reclaimed: u64,
+    // Files kept because the table still refers to them
+    pub files_live: usize,
+    // Files the table no longer refers to, kept because they were referred
+    // to within the retention period. Sorted.
+    pub files_within_retention: Vec<String>,
+    pub duration: Duration,
+}
+
+// A data file deleted by `vacuum`, as recorded in the table's vacuum
+// inventory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeletedFile {
+    // Relative to the table's directory
+    pub path: String,
+    pub size: u64,
+    // When the table last referred to the file, in milliseconds since the
+    // unix epoch
+    pub last_used: i64,
+    // When the vacuum ran, in milliseconds since the unix epoch
+    pub deleted_at: i64,
+}
+
+// How often a live data file was read, see `DeltaTable::file_usage`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileUsage {
+    // Relative to the table's directory
+    pub path: String,
+    pub size: u64,
+    pub reads: u64,
+    // Milliseconds since the unix epoch, `None` when it was never read
+    pub last_read: Option<i64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CheckpointInfo {
+    // The version of the table the checkpoint holds
+    pub version: i64,
+    // Relative to the table's directory
+    pub path: String,
+    // Size of the checkpoint file in bytes
+    pub size: u64,
+    // The protocol, the metadata, a txn action per application and an Add
+    // action per live file
+    pub num_actions: usize,
+    pub num_add_files: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct ManifestInfo {
+    // The version of the table the manifests list the files of
+    pub version: i64,
+    // The directory holding the manifests, relative to the table's
+    pub path: String,
+    // One per partition, or a single one for an unpartitioned table
+    pub manifests: usize,
+    pub files: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct VerifyReport {
+    // Files whose contents were checked against their checksum
+    pub files_verified: usize,
+    // Files without a checksum we can check, e.g. written by another tool.
+    // Only their size is checked.
+    pub files_unverified: usize,
+    // Files whose size or contents no longer match what was committed
+    pub corrupted: Vec<String>,
+    // Files in the log that don't exist
+    pub missing: Vec<String>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.corrupted.is_empty() && self.missing.is_empty()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CacheStats {
+    // Tables with a cached snapshot
+    pub tables: usize,
+    // Estimated memory taken up by the cached snapshots, in bytes
+    pub size: usize,
+    pub hits: u64,
+    pub misses: u64,
+    // Snapshots dropped to stay under the memory limit
+    pub evictions: u64,
+}
+
+// What a statement run by `delta::sql` did
+#[derive(Debug, Clone)]
+pub enum SqlResult {
+    Created,
+    Inserted(InsertResult),
+    Deleted(DeleteMetrics),
+    Updated(UpdateMetrics),
+    // The result of a SELECT
+    Rows(DataFrame),
+    Explained(Explain),
+}
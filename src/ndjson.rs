@@ -0,0 +1,156 @@
+use crate::{error::DeltaError, schema::DeltaTableSchema, value::DeltaValue};
+use serde_json::Value;
+use std::{
+    fs,
+    io::{BufRead, BufReader},
+};
+
+// What a load does with a line lacking one of the table's columns
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MissingField {
+    // The column is null for that row, like JSON writers that leave out
+    // null fields expect
+    #[default]
+    Null,
+    // Fail the load with `DeltaError::InvalidValue`. Generated columns are
+    // never expected in the file.
+    Fail,
+}
+
+// What a load does with fields that aren't columns of the table
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnknownField {
+    // Fail the load with `DeltaError::SchemaMismatch`
+    #[default]
+    Fail,
+    Ignore,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct NdjsonOptions {
+    pub on_missing_field: MissingField,
+    pub on_unknown_field: UnknownField,
+}
+
+// Read a file with a JSON object per line into rows matching the table
+// schema, in schema order. Blank lines are skipped. Errors name the row by
+// its line in the file, counting from 0.
+pub fn read_ndjson(
+    path: &str,
+    options: &NdjsonOptions,
+    schema: &DeltaTableSchema,
+) -> Result<Vec<Vec<DeltaValue>>, DeltaError> {
+    let reader = BufReader::new(fs::File::open(path)?);
+
+    let mut rows = vec![];
+    for (row, line) in reader.lines().enumerate() {
+        if let Some(values) = parse_line(&line?, row, options, schema)? {
+            rows.push(values);
+        }
+    }
+
+    Ok(rows)
+}
+
+// Convert a line to a row matching the table schema, or `None` for a blank
+// line. `row` is the line's position, used in errors.
+pub(crate) fn parse_line(
+    line: &str,
+    row: usize,
+    options: &NdjsonOptions,
+    schema: &DeltaTableSchema,
+) -> Result<Option<Vec<DeltaValue>>, DeltaError> {
+    if line.trim().is_empty() {
+        return Ok(None);
+    }
+
+    let value = serde_json::from_str(line)
+        .map_err(|e| DeltaError::InvalidData(format!("line {} is not valid JSON: {}", row, e)))?;
+    let Value::Object(mut fields) = value else {
+        return Err(DeltaError::InvalidData(format!(
+            "line {} is not a JSON object",
+            row
+        )));
+    };
+
+    let mut values = vec![];
+    for field in schema.fields() {
+        let value = match fields.remove(&field.name) {
+            Some(value) => value,
+            None if options.on_missing_field == MissingField::Fail
+                && field.generation_expression().is_none() =>
+            {
+                return Err(DeltaError::InvalidValue {
+                    row,
+                    column: field.name.clone(),
+                    message: "missing field".to_owned(),
+                });
+            }
+            None => Value::Null,
+        };
+        values.push(DeltaValue::from_json(value, &field.typ).map_err(|message| {
+            DeltaError::InvalidValue {
+                row,
+                column: field.name.clone(),
+                message,
+            }
+        })?);
+    }
+
+    if let (UnknownField::Fail, Some(name)) = (options.on_unknown_field, fields.keys().next()) {
+        return Err(DeltaError::SchemaMismatch(format!(
+            "unknown column '{}' on line {}",
+            name, row
+        )));
+    }
+
+    Ok(Some(values))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema() -> DeltaTableSchema {
+        DeltaTableSchema::from_sql(vec![("id", "int"), ("name", "text NULL")]).unwrap()
+    }
+
+    #[test]
+    fn field_options_control_missing_and_unknown_fields() {
+        let schema = schema();
+        let defaults = NdjsonOptions::default();
+        let strict = NdjsonOptions {
+            on_missing_field: MissingField::Fail,
+            on_unknown_field: UnknownField::Fail,
+        };
+        let lenient = NdjsonOptions {
+            on_missing_field: MissingField::Null,
+            on_unknown_field: UnknownField::Ignore,
+        };
+
+        let row = parse_line(r#"{"name": "a", "id": 1}"#, 0, &strict, &schema).unwrap();
+        assert_eq!(
+            row,
+            Some(vec![DeltaValue::Long(1), DeltaValue::Str("a".to_owned())])
+        );
+        assert_eq!(parse_line("  ", 1, &strict, &schema).unwrap(), None);
+
+        let missing = r#"{"id": 2}"#;
+        let row = parse_line(missing, 2, &defaults, &schema).unwrap();
+        assert_eq!(row, Some(vec![DeltaValue::Long(2), DeltaValue::Null]));
+        match parse_line(missing, 2, &strict, &schema) {
+            Err(DeltaError::InvalidValue { row, column, .. }) => {
+                assert_eq!((row, column.as_str()), (2, "name"))
+            }
+            other => panic!("expected a missing field error, got {:?}", other),
+        }
+
+        let unknown = r#"{"id": 3, "name": null, "extra": true}"#;
+        assert!(matches!(
+            parse_line(unknown, 3, &defaults, &schema),
+            Err(DeltaError::SchemaMismatch(_))
+        ));
+        let row = parse_line(unknown, 3, &lenient, &schema).unwrap();
+        assert_eq!(row, Some(vec![DeltaValue::Long(3), DeltaValue::Null]));
+    }
+}
@@ -0,0 +1,210 @@
+// Adapters for using tables from async code. Everything in `DeltaTable`
+// blocks on file IO, and replaying the log or scanning data files can take
+// a while, so calling it from a task stalls the executor thread it runs on
+// and every other task scheduled there. `AsyncDeltaTable` runs each call on
+// a dedicated IO thread pool instead and returns a future for its result.
+//
+// The pool is the blocking pool of a private tokio runtime, so it doesn't
+// compete with the caller's runtime for its blocking threads. The futures
+// only need a waker, so they can be awaited from any executor, not just
+// tokio's. Only available with the `async` feature.
+
+use crate::{
+    error::DeltaError,
+    metrics::{
+        CheckpointInfo, DeleteMetrics, InsertResult, OptimizeMetrics, UpdateMetrics, VacuumMetrics,
+    },
+    options::{CompactionPolicy, VacuumOptions},
+    table::DeltaTable,
+};
+use polars::frame::DataFrame;
+use std::{cell::Cell, io, panic, sync::OnceLock, time::Duration};
+use tokio::runtime::{Builder, Handle, Runtime};
+
+// Most of the pool's threads spend their time waiting on the disk rather
+// than the CPU, so there can be many more of them than cores
+const IO_THREADS: usize = 32;
+
+thread_local! {
+    // Set on the IO thread pool's threads, which are inside a runtime but
+    // are meant to block
+    static IO_THREAD: Cell<bool> = const { Cell::new(false) };
+}
+
+// Whether the calling thread is running a tokio runtime, e.g. is inside an
+// async fn driven by one, where the blocking methods of `DeltaTable`
+// shouldn't be called. Other executors can't be detected, so this is
+// false on their threads, as it is on the IO thread pool's.
+pub fn in_async_runtime() -> bool {
+    Handle::try_current().is_ok() && !IO_THREAD.with(Cell::get)
+}
+
+fn io_runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        Builder::new_current_thread()
+            .thread_name("delta-io")
+            .max_blocking_threads(IO_THREADS)
+            .on_thread_start(|| IO_THREAD.with(|io_thread| io_thread.set(true)))
+            .build()
+            .expect("failed to start the delta IO thread pool")
+    })
+}
+
+// Run `f` on the IO thread pool. A panic in `f` is resumed in the caller,
+// as if it had been called directly.
+pub(crate) async fn run_blocking<T, F>(f: F) -> Result<T, DeltaError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, DeltaError> + Send + 'static,
+{
+    match io_runtime().spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(e) if e.is_panic() => panic::resume_unwind(e.into_panic()),
+        Err(e) => Err(DeltaError::IOError(io::Error::other(e))),
+    }
+}
+
+// A table handle whose methods run on the IO thread pool. Clones keep the
+// wrapped handle's settings, e.g. its write options or pinned version.
+#[derive(Clone)]
+pub struct AsyncDeltaTable {
+    table: DeltaTable,
+}
+
+impl AsyncDeltaTable {
+    // Wrap a handle set up with its blocking builder methods, e.g.
+    // `with_write_options`
+    pub fn new(table: DeltaTable) -> AsyncDeltaTable {
+        AsyncDeltaTable { table }
+    }
+
+    pub async fn open(path: &str) -> Result<AsyncDeltaTable, DeltaError> {
+        let path = path.to_owned();
+        run_blocking(move || DeltaTable::open(&path))
+            .await
+            .map(AsyncDeltaTable::new)
+    }
+
+    pub async fn read_table(name: &str) -> Result<AsyncDeltaTable, DeltaError> {
+        let name = name.to_owned();
+        run_blocking(move || DeltaTable::read_table(&name))
+            .await
+            .map(AsyncDeltaTable::new)
+    }
+
+    pub async fn read_table_at_version(
+        name: &str,
+        version: i64,
+    ) -> Result<AsyncDeltaTable, DeltaError> {
+        let name = name.to_owned();
+        run_blocking(move || DeltaTable::read_table_at_version(&name, version))
+            .await
+            .map(AsyncDeltaTable::new)
+    }
+
+    // The wrapped handle, e.g. for the builder methods that don't touch
+    // the table
+    pub fn table(&self) -> &DeltaTable {
+        &self.table
+    }
+
+    pub fn into_table(self) -> DeltaTable {
+        self.table
+    }
+
+    // Run `f` against the wrapped handle on the IO thread pool, for the
+    // methods without an async counterpart here
+    pub async fn run<T, F>(&self, f: F) -> Result<T, DeltaError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&DeltaTable) -> Result<T, DeltaError> + Send + 'static,
+    {
+        let table = self.table.clone();
+        run_blocking(move || f(&table)).await
+    }
+
+    pub async fn insert(&self, data: Vec<Vec<Option<String>>>) -> Result<InsertResult, DeltaError> {
+        self.run(move |table| {
+            let data = data
+                .iter()
+                .map(|row| row.iter().map(Option::as_deref).collect())
+                .collect::<Vec<Vec<Option<&str>>>>();
+            table.insert(data)
+        })
+        .await
+    }
+
+    pub async fn insert_df(&self, df: DataFrame) -> Result<InsertResult, DeltaError> {
+        self.run(move |table| table.insert_df(df)).await
+    }
+
+    pub async fn to_polars(&self) -> Result<DataFrame, DeltaError> {
+        self.run(DeltaTable::to_polars).await
+    }
+
+    pub async fn head(&self, n: usize) -> Result<DataFrame, DeltaError> {
+        self.run(move |table| table.head(n)).await
+    }
+
+    pub async fn count(&self) -> Result<u64, DeltaError> {
+        self.run(DeltaTable::count).await
+    }
+
+    pub async fn query(&self, sql: &str) -> Result<DataFrame, DeltaError> {
+        let sql = sql.to_owned();
+        self.run(move |table| table.query(&sql)).await
+    }
+
+    pub async fn delete(&self, expr: &str) -> Result<DeleteMetrics, DeltaError> {
+        let expr = expr.to_owned();
+        self.run(move |table| table.delete(&expr)).await
+    }
+
+    pub async fn update(
+        &self,
+        assignments: &[(&str, &str)],
+        expr: &str,
+    ) -> Result<UpdateMetrics, DeltaError> {
+        let assignments = assignments
+            .iter()
+            .map(|(column, value)| (column.to_string(), value.to_string()))
+            .collect::<Vec<(String, String)>>();
+        let expr = expr.to_owned();
+        self.run(move |table| {
+            let assignments = assignments
+                .iter()
+                .map(|(column, value)| (column.as_str(), value.as_str()))
+                .collect::<Vec<(&str, &str)>>();
+            table.update(&assignments, &expr)
+        })
+        .await
+    }
+
+    pub async fn optimize(&self, policy: CompactionPolicy) -> Result<OptimizeMetrics, DeltaError> {
+        self.run(move |table| table.optimize(&policy)).await
+    }
+
+    pub async fn vacuum(&self, options: VacuumOptions) -> Result<VacuumMetrics, DeltaError> {
+        self.run(move |table| table.vacuum(&options)).await
+    }
+
+    pub async fn checkpoint(&self) -> Result<CheckpointInfo, DeltaError> {
+        self.run(DeltaTable::checkpoint).await
+    }
+
+    pub async fn version(&self) -> Result<i64, DeltaError> {
+        self.run(DeltaTable::version).await
+    }
+
+    // Like `DeltaTable::wait_for_commit`, holding one of the pool's
+    // threads rather than the caller's while it waits
+    pub async fn wait_for_commit(
+        &self,
+        version: i64,
+        poll_interval: Duration,
+    ) -> Result<i64, DeltaError> {
+        self.run(move |table| table.wait_for_commit(version, poll_interval))
+            .await
+    }
+}
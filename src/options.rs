@@ -0,0 +1,181 @@
+use polars::prelude::TimeUnit;
+use std::{collections::HashMap, time::Duration};
+
+// Settings applied to everything a table handle writes, see
+// `DeltaTable::with_write_options`
+#[derive(Clone)]
+pub struct WriteOptions {
+    // Free-form tags put on the Add and Remove actions of every commit,
+    // e.g. the batch or pipeline run that produced the data. Files
+    // rewritten by a delete keep the tags they had, with these on top.
+    // The `checksum` tag is reserved for the file's checksum and always
+    // overrides a tag with that name here.
+    pub tags: HashMap<String, String>,
+    // Precision TIMESTAMP columns are stored with in data files. Delta
+    // timestamps are microseconds, but some readers only handle
+    // milliseconds. Whatever a file was written with, reads convert back
+    // to microseconds.
+    pub timestamp_unit: TimeUnit,
+    // When set, inserted rows whose values in these columns match a row
+    // already in the table, or an earlier row of the same insert, are
+    // dropped, so retrying an ingestion doesn't duplicate its rows. Only
+    // rows committed before the insert started are checked against, see
+    // `DeltaTable::dedupe` for cleaning up after concurrent inserts.
+    pub dedupe_keys: Vec<String>,
+    // When set, `dedupe_keys` are only checked against rows added in the
+    // last this many versions, which bounds how many files an insert reads
+    // on a large table while still catching retries of recent batches.
+    // Rows rewritten by a delete, update or optimize count as added in the
+    // version that rewrote them.
+    pub dedupe_window: Option<u64>,
+    // What inserts do with rows whose unique key, see
+    // `CreateOptions::unique_key`, is already taken
+    pub on_key_conflict: KeyConflict,
+    // Written rows are sorted by these columns, in ascending order, so
+    // each data file holds narrow ranges of them. That makes file stats
+    // more selective and the files compress better. Partition columns
+    // aren't worth sorting by, since a data file only holds one value.
+    pub sort_by: Vec<String>,
+    // Stored as the `userMetadata` of every commit's commitInfo, e.g. the
+    // id of the job or ticket behind the change, and returned by
+    // `DeltaTable::history`
+    pub user_metadata: Option<String>,
+    // chrono style formats, e.g. `%d/%m/%Y`, for DATE and TIMESTAMP values
+    // given as text to `DeltaTable::insert`. When unset, dates are
+    // `YYYY-MM-DD` and timestamps are ISO-8601 with a `T` or a space
+    // between date and time, with or without an offset, or a date. Either
+    // way, integers are taken as milliseconds since the unix epoch.
+    pub date_format: Option<String>,
+    pub timestamp_format: Option<String>,
+    // BOOL values given as text to `DeltaTable::insert` are `true`, `t` or
+    // `1`, and `false`, `f` or `0`, in any case. When set, only `true` and
+    // `false` are, e.g. so a stray number in a feed is caught rather than
+    // stored as a bool.
+    pub strict_booleans: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        WriteOptions {
+            tags: HashMap::new(),
+            timestamp_unit: TimeUnit::Microseconds,
+            dedupe_keys: vec![],
+            dedupe_window: None,
+            on_key_conflict: KeyConflict::default(),
+            sort_by: vec![],
+            user_metadata: None,
+            date_format: None,
+            timestamp_format: None,
+            strict_booleans: false,
+        }
+    }
+}
+
+// How an insert handles rows whose unique key is already in the table or
+// repeats an earlier row of the same insert
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyConflict {
+    // Fail the whole insert with `DeltaError::UniqueKeyViolation`
+    #[default]
+    Reject,
+    // Drop those rows and insert the rest
+    Skip,
+}
+
+// Settings fixed when a table is created, see `DeltaTable::create_with`
+#[derive(Clone, Default)]
+pub struct CreateOptions {
+    // See `DeltaTable::create_partitioned`
+    pub partition_by: Vec<String>,
+    // Columns whose values together identify a row. No two rows of the
+    // table can have the same key, which inserts check against the rows
+    // already in the table. Key columns have to be NOT NULL.
+    pub unique_key: Vec<String>,
+    // Stored in the table's configuration for other tools to read, see
+    // `DeltaTableMetadata::properties`. Can't be `uniqueKey` or start with
+    // `delta.constraints.`, which are set through their own options.
+    pub properties: HashMap<String, String>,
+}
+
+// How `DeltaTable::optimize` picks the files it compacts. Files are only
+// ever compacted with files of the same partition.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompactionPolicy {
+    // Files smaller than `target_size` bytes are packed together into
+    // files of about that size, in the order they were added
+    BinPack {
+        target_size: u64,
+    },
+    // Files are put in tiers by size: below `min_size` bytes, then each
+    // tier holding files up to `tier_factor` times bigger than the one
+    // below it. Once a tier has `min_files` files, they're merged into one,
+    // which lands in a higher tier. Unlike bin-packing, a large file is
+    // only rewritten once enough files of about its size have piled up,
+    // not every time small appends come in.
+    SizeTiered {
+        min_size: u64,
+        tier_factor: u64,
+        min_files: usize,
+    },
+}
+
+impl Default for CompactionPolicy {
+    fn default() -> Self {
+        CompactionPolicy::BinPack {
+            target_size: 128 * 1024 * 1024,
+        }
+    }
+}
+
+// How `DeltaTable::vacuum` decides what to delete, and what it keeps of
+// it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VacuumOptions {
+    // Files are only deleted once they haven't been referred to for this
+    // long. Versions from before then can't be read anymore, and a write
+    // that takes longer than this to commit loses its files, so it should
+    // be well above how long writes take.
+    pub retention: Duration,
+    // Report what would be deleted without deleting anything
+    pub dry_run: bool,
+    // Record the deleted files in the table's vacuum inventory, see
+    // `DeltaTable::vacuum_inventory`
+    pub inventory: bool,
+}
+
+// A week, like Spark
+impl Default for VacuumOptions {
+    fn default() -> Self {
+        VacuumOptions {
+            retention: Duration::from_secs(7 * 24 * 60 * 60),
+            dry_run: false,
+            inventory: false,
+        }
+    }
+}
+
+// Which data files `DeltaTable::archive` moves to cheaper storage, and
+// where
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArchiveOptions {
+    // The directory files are copied into, as a path or a `file://` URI,
+    // e.g. where an infrequent access bucket is mounted. Files keep their
+    // path relative to the table's directory under it.
+    pub destination: String,
+    // Only files added at least this long ago are moved
+    pub min_age: Duration,
+    // When set, only files read at most this many times are moved, as
+    // recorded by handles with `DeltaTable::with_usage_tracking`
+    pub max_reads: Option<u64>,
+}
+
+// Files untouched for 30 days, however often they were read
+impl ArchiveOptions {
+    pub fn new(destination: impl Into<String>) -> ArchiveOptions {
+        ArchiveOptions {
+            destination: destination.into(),
+            min_age: Duration::from_secs(30 * 24 * 60 * 60),
+            max_reads: None,
+        }
+    }
+}
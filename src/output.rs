@@ -0,0 +1,175 @@
+use crate::{error::DeltaError, value::to_json};
+use polars::prelude::*;
+use serde_json::{Map, Value};
+use std::io::Write;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    // Aligned columns between ASCII borders, for people to read
+    Table,
+    Csv,
+    // One JSON object per row
+    JsonLines,
+    // A GitHub flavored markdown table
+    Markdown,
+}
+
+// Renders query results, e.g. the batches of `DeltaTable::query_batches`,
+// one frame at a time. CSV, JSON lines and markdown are written out as
+// each frame comes in. A table has to know how wide its columns get, so
+// its rows are held until `finish`.
+pub struct ResultWriter<W: Write> {
+    out: W,
+    format: OutputFormat,
+    header_written: bool,
+    // Rows held for `OutputFormat::Table`
+    pending: Option<DataFrame>,
+}
+
+impl<W: Write> ResultWriter<W> {
+    pub fn new(out: W, format: OutputFormat) -> ResultWriter<W> {
+        ResultWriter {
+            out,
+            format,
+            header_written: false,
+            pending: None,
+        }
+    }
+
+    // Frames after the first are expected to have the same columns
+    pub fn write(&mut self, df: &DataFrame) -> Result<(), DeltaError> {
+        let mut buf = vec![];
+        match self.format {
+            OutputFormat::Table => {
+                match &mut self.pending {
+                    Some(pending) => {
+                        pending.vstack_mut(df)?;
+                    }
+                    None => self.pending = Some(df.clone()),
+                }
+                return Ok(());
+            }
+            OutputFormat::Csv => CsvWriter::new(&mut buf)
+                .include_header(!self.header_written)
+                .finish(&mut df.clone())?,
+            OutputFormat::JsonLines => {
+                for row in 0..df.height() {
+                    let mut record = Map::new();
+                    for series in df.get_columns() {
+                        record.insert(series.name().to_owned(), to_json(&series.get(row)?));
+                    }
+                    serde_json::to_writer(&mut buf, &record)?;
+                    buf.push(b'\n');
+                }
+            }
+            OutputFormat::Markdown => {
+                if !self.header_written {
+                    let names = df.get_column_names();
+                    writeln!(buf, "| {} |", names.join(" | "))?;
+                    writeln!(buf, "|{}", "---|".repeat(names.len()))?;
+                }
+                for row in rows(df)? {
+                    let row = row.iter().map(|cell| escape_markdown(cell));
+                    writeln!(buf, "| {} |", row.collect::<Vec<_>>().join(" | "))?;
+                }
+            }
+        }
+        self.header_written = true;
+
+        self.out.write_all(&buf)?;
+        self.out.flush()?;
+        Ok(())
+    }
+
+    // Write out whatever is still held and hand back the output
+    pub fn finish(mut self) -> Result<W, DeltaError> {
+        if let Some(df) = self.pending.take() {
+            let table = render_table(&df)?;
+            self.out.write_all(table.as_bytes())?;
+            self.out.flush()?;
+        }
+        Ok(self.out)
+    }
+}
+
+fn render_table(df: &DataFrame) -> Result<String, DeltaError> {
+    let names = df.get_column_names();
+    let rows = rows(df)?;
+    let widths = names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            rows.iter()
+                .map(|row| row[i].chars().count())
+                .chain([name.chars().count()])
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<usize>>();
+    let numeric = df
+        .get_columns()
+        .iter()
+        .map(|series| series.dtype().is_numeric())
+        .collect::<Vec<bool>>();
+
+    let border = widths
+        .iter()
+        .map(|width| "-".repeat(width + 2))
+        .collect::<Vec<_>>()
+        .join("+");
+    let border = format!("+{}+\n", border);
+    let line = |cells: &[String], align_numbers: bool| {
+        let cells = cells
+            .iter()
+            .zip(&widths)
+            .zip(&numeric)
+            .map(|((cell, width), numeric)| match align_numbers && *numeric {
+                true => format!(" {:>width$} ", cell, width = width),
+                false => format!(" {:<width$} ", cell, width = width),
+            })
+            .collect::<Vec<_>>();
+        format!("|{}|\n", cells.join("|"))
+    };
+
+    let names = names
+        .iter()
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>();
+    let mut table = border.clone();
+    table.push_str(&line(&names, false));
+    table.push_str(&border);
+    for row in &rows {
+        table.push_str(&line(row, true));
+    }
+    if !rows.is_empty() {
+        table.push_str(&border);
+    }
+    table.push_str(&format!(
+        "({} row{})\n",
+        rows.len(),
+        if rows.len() == 1 { "" } else { "s" }
+    ));
+    Ok(table)
+}
+
+// Each row's values as text, with dates and timestamps in ISO-8601 like
+// the JSON output
+fn rows(df: &DataFrame) -> Result<Vec<Vec<String>>, DeltaError> {
+    (0..df.height())
+        .map(|row| {
+            df.get_columns()
+                .iter()
+                .map(|series| {
+                    Ok(match to_json(&series.get(row)?) {
+                        Value::String(s) => s,
+                        value => value.to_string(),
+                    })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn escape_markdown(cell: &str) -> String {
+    cell.replace('|', "\\|").replace(['\r', '\n'], " ")
+}
@@ -0,0 +1,253 @@
+use crate::{error::DeltaError, schema::DeltaTableType};
+use polars::{
+    export::chrono::{Datelike, NaiveDate, NaiveDateTime},
+    prelude::*,
+};
+use serde_json::Value;
+use std::collections::HashMap;
+
+// Days between 0001-01-01 (chrono's day 1) and the unix epoch
+const UNIX_EPOCH_DAY: i32 = 719_163;
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.6f";
+// Directory name Hive (and so Spark) uses for a null partition value
+const NULL_DIRECTORY: &str = "__HIVE_DEFAULT_PARTITION__";
+
+// Partition values are stored as strings in Add actions, using the formats
+// from https://github.com/delta-io/delta/blob/master/PROTOCOL.md#partition-value-serialization
+// A null value is a JSON null rather than an empty string.
+pub type PartitionValues = HashMap<String, Option<String>>;
+
+pub fn serialize_value(value: &AnyValue) -> Option<String> {
+    match value {
+        AnyValue::Null => None,
+        AnyValue::Utf8(v) => Some((*v).to_owned()),
+        AnyValue::Boolean(v) => Some(v.to_string()),
+        AnyValue::Float32(v) => Some(serialize_float(*v as f64)),
+        AnyValue::Float64(v) => Some(serialize_float(*v)),
+        AnyValue::Date(days) => NaiveDate::from_num_days_from_ce_opt(days + UNIX_EPOCH_DAY)
+            .map(|date| date.format("%Y-%m-%d").to_string()),
+        AnyValue::Datetime(v, unit, _) => {
+            let micros = match unit {
+                TimeUnit::Nanoseconds => v / 1_000,
+                TimeUnit::Microseconds => *v,
+                TimeUnit::Milliseconds => v * 1_000,
+            };
+            NaiveDateTime::from_timestamp_micros(micros)
+                .map(|ts| ts.format(TIMESTAMP_FORMAT).to_string())
+        }
+        // Integers
+        v => Some(v.to_string()),
+    }
+}
+
+fn serialize_float(v: f64) -> String {
+    match v {
+        v if v.is_nan() => "NaN".to_owned(),
+        v if v == f64::INFINITY => "Infinity".to_owned(),
+        v if v == f64::NEG_INFINITY => "-Infinity".to_owned(),
+        v => v.to_string(),
+    }
+}
+
+// A partition value read back from the log, typed by its column
+enum Parsed {
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    String(String),
+    Date(i32),
+    Timestamp(i64),
+}
+
+// Other writers may use any of the protocol's formats, e.g. timestamps
+// without fractional seconds, so parsing is more lenient than serializing.
+fn parse_value(value: &str, typ: &DeltaTableType) -> Option<Parsed> {
+    match typ {
+        DeltaTableType::String => Some(Parsed::String(value.to_owned())),
+        DeltaTableType::Long
+        | DeltaTableType::Integer
+        | DeltaTableType::Short
+        | DeltaTableType::Byte => value.parse().ok().map(Parsed::Integer),
+        DeltaTableType::Float | DeltaTableType::Double => match value {
+            "Infinity" => Some(Parsed::Float(f64::INFINITY)),
+            "-Infinity" => Some(Parsed::Float(f64::NEG_INFINITY)),
+            value => value.parse().ok().map(Parsed::Float),
+        },
+        DeltaTableType::Boolean => match value.to_lowercase().as_str() {
+            "true" => Some(Parsed::Boolean(true)),
+            "false" => Some(Parsed::Boolean(false)),
+            _ => None,
+        },
+        DeltaTableType::Date => NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .ok()
+            .map(|date| Parsed::Date(date.num_days_from_ce() - UNIX_EPOCH_DAY)),
+        DeltaTableType::Timestamp => ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"]
+            .iter()
+            .find_map(|format| {
+                NaiveDateTime::parse_from_str(value.trim_end_matches('Z'), format).ok()
+            })
+            .map(|ts| Parsed::Timestamp(ts.timestamp_micros())),
+    }
+}
+
+// A literal holding the partition value, for adding the column back onto
+// rows read from a data file
+pub fn literal(name: &str, value: Option<&str>, typ: &DeltaTableType) -> Result<Expr, DeltaError> {
+    let literal = match value {
+        None => lit(NULL),
+        Some(value) => match parse_value(value, typ) {
+            Some(Parsed::Integer(v)) => lit(v),
+            Some(Parsed::Float(v)) => lit(v),
+            Some(Parsed::Boolean(v)) => lit(v),
+            Some(Parsed::String(v)) => lit(v),
+            Some(Parsed::Date(v)) => lit(v),
+            Some(Parsed::Timestamp(v)) => lit(v),
+            None => {
+                return Err(DeltaError::InvalidData(format!(
+                    "invalid partition value '{}' for column '{}'",
+                    value, name
+                )))
+            }
+        },
+    };
+
+    Ok(literal.cast(typ.to_polars_type()).alias(name))
+}
+
+// The partition value in the form used by per-file stats, so partitions
+// can be pruned the same way as min/max values. `None` for values that
+// can't be represented there, which just means they won't be pruned.
+pub fn to_stat(value: &str, typ: &DeltaTableType) -> Option<Value> {
+    match parse_value(value, typ)? {
+        Parsed::Integer(v) => Some(v.into()),
+        Parsed::Float(v) => serde_json::Number::from_f64(v).map(Value::Number),
+        Parsed::Boolean(v) => Some(v.into()),
+        Parsed::String(v) => Some(Value::String(v)),
+        // Dates are already in the stats format
+        Parsed::Date(_) => Some(Value::String(value.to_owned())),
+        Parsed::Timestamp(v) => NaiveDateTime::from_timestamp_micros(v)
+            .map(|ts| Value::String(ts.format("%Y-%m-%dT%H:%M:%S%.6fZ").to_string())),
+    }
+}
+
+// The directory data files for a partition are written to, Hive style:
+// `a=1/b=x`, in partition column order
+pub fn directory(partition_columns: &[String], values: &PartitionValues) -> String {
+    partition_columns
+        .iter()
+        .map(|column| {
+            let value = match values.get(column) {
+                Some(Some(value)) if !value.is_empty() => escape_path_name(value),
+                _ => NULL_DIRECTORY.to_owned(),
+            };
+            format!("{}={}", escape_path_name(column), value)
+        })
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+// Hive escapes characters that would be ambiguous in a `column=value`
+// directory name
+fn escape_path_name(name: &str) -> String {
+    let mut escaped = String::new();
+    for c in name.chars() {
+        if c.is_ascii_control() || "\"#%'*/:=?\\{[]^".contains(c) {
+            escaped.push_str(&format!("%{:02X}", c as u32));
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+// Paths in Add and Remove actions are URIs, so anything outside of the
+// unreserved characters has to be percent-encoded
+pub fn encode_path(path: &str) -> String {
+    let mut encoded = String::new();
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/=".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+// Paths are relative to the table's directory unless they're absolute
+// URIs, e.g. of files moved by `DeltaTable::archive`. Relative paths always
+// have `:` encoded, so only absolute ones start with a scheme.
+pub fn is_absolute_uri(path: &str) -> bool {
+    path.split_once(':').is_some_and(|(scheme, _)| {
+        !scheme.is_empty()
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+    })
+}
+
+pub fn decode_path(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match (bytes[i], bytes.get(i + 1..i + 3)) {
+            (b'%', Some(hex)) => std::str::from_utf8(hex)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_round_trip_through_the_protocol_formats() {
+        let date = NaiveDate::from_ymd_opt(2024, 2, 29).unwrap();
+        let days = date.num_days_from_ce() - UNIX_EPOCH_DAY;
+        let millis = date
+            .and_hms_milli_opt(3, 4, 5, 600)
+            .unwrap()
+            .timestamp_millis();
+
+        assert_eq!(serialize_value(&AnyValue::Null), None);
+        assert_eq!(
+            serialize_value(&AnyValue::Date(days)).as_deref(),
+            Some("2024-02-29")
+        );
+        assert_eq!(
+            serialize_value(&AnyValue::Datetime(millis, TimeUnit::Milliseconds, &None)).as_deref(),
+            Some("2024-02-29 03:04:05.600000")
+        );
+        assert_eq!(
+            serialize_value(&AnyValue::Float64(f64::NEG_INFINITY)).as_deref(),
+            Some("-Infinity")
+        );
+
+        // Other writers' timestamps may be ISO 8601 and lack fractions
+        let stat = |value| to_stat(value, &DeltaTableType::Timestamp);
+        let expected = Value::String("2024-02-29T03:04:05.000000Z".to_owned());
+        assert_eq!(stat("2024-02-29 03:04:05"), Some(expected.clone()));
+        assert_eq!(stat("2024-02-29T03:04:05Z"), Some(expected));
+        assert_eq!(stat("yesterday"), None);
+        // JSON can't hold infinities, so they aren't pruned on
+        assert_eq!(to_stat("Infinity", &DeltaTableType::Double), None);
+        assert!(literal("n", Some("x"), &DeltaTableType::Integer).is_err());
+    }
+}
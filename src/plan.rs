@@ -0,0 +1,101 @@
+use crate::{file_info::FileInfo, stats::DeltaFileStats};
+use serde_json::Value;
+use std::collections::HashMap;
+
+// What a scan filtered by a predicate has to read, as worked out by
+// `DeltaTable::plan_scan`. Meant for planners that split the reads up
+// themselves, so they don't have to open the files to find their row
+// groups.
+#[derive(Debug, Clone)]
+pub struct ScanPlan {
+    // The version of the table the plan was made against
+    pub version: i64,
+    // Files that may hold matching rows, in the order they were added
+    pub files: Vec<PlannedFile>,
+    // Files whose stats or partition values show no row can match
+    pub files_skipped: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct PlannedFile {
+    pub file: FileInfo,
+    // The file's stats from the log, `None` when it was added without any
+    pub stats: Option<ColumnStats>,
+    // Read from the file's footer, in the order they're stored in
+    pub row_groups: Vec<RowGroupInfo>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RowGroupInfo {
+    // Compressed size in bytes
+    pub size: u64,
+    pub stats: ColumnStats,
+    // Whether the row group's stats allow any of its rows to match. Reads
+    // can skip row groups where this is false.
+    pub may_match: bool,
+}
+
+// Column statistics keyed by column name, in the same form as the log's
+// per-file stats: dates and timestamps are strings, and columns without
+// stats are left out. Partition columns have their partition value as both
+// the min and the max.
+#[derive(Debug, Clone, Default)]
+pub struct ColumnStats {
+    pub num_records: u64,
+    pub min_values: HashMap<String, Value>,
+    pub max_values: HashMap<String, Value>,
+    pub null_count: HashMap<String, u64>,
+}
+
+impl From<DeltaFileStats> for ColumnStats {
+    fn from(stats: DeltaFileStats) -> Self {
+        ColumnStats {
+            num_records: stats.num_records,
+            min_values: stats.min_values,
+            max_values: stats.max_values,
+            null_count: stats.null_count,
+        }
+    }
+}
+
+// What a query or delete would read, as worked out by `DeltaTable::explain`
+// or an EXPLAIN through `delta::sql`, without running it
+#[derive(Debug, Clone)]
+pub struct Explain {
+    // One per table the statement reads, in the order it first refers to
+    // them
+    pub tables: Vec<TableScan>,
+    // Polars' optimized logical plan. For a delete, it's the plan finding
+    // the rows to delete in the files scanned.
+    pub logical_plan: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct TableScan {
+    pub table: String,
+    // The version of the table the files are from
+    pub version: i64,
+    // Paths of the files that would be read, relative to the table, in the
+    // order they were added
+    pub files_scanned: Vec<String>,
+    pub files_pruned: Vec<PrunedFile>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PrunedFile {
+    pub path: String,
+    pub reason: PruneReason,
+}
+
+// Why a file isn't read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PruneReason {
+    // Its partition values don't satisfy the predicate
+    Partition,
+    // Its stats show no row satisfies the predicate
+    Stats,
+    // Its bloom filter shows it holds none of the keys looked for
+    BloomFilter,
+    // The files before it hold enough rows for the query's LIMIT
+    Limit,
+}
@@ -0,0 +1,72 @@
+use polars::prelude::*;
+use std::collections::HashMap;
+use xxhash_rust::xxh3::xxh3_64;
+
+// Decides what the readers of a table handle see of each column, e.g. to
+// hide personal data from some of them while others read it as stored.
+// See `DeltaTable::with_read_policy`.
+pub trait ReadPolicy: Send + Sync {
+    // The expression to read `column` as, in terms of `col(column)`, or
+    // `None` to read it as it's stored. Called for each of the table's
+    // columns on every read. The result is named after the column, but
+    // doesn't have to keep its type.
+    fn mask(&self, column: &str) -> Option<Expr>;
+}
+
+// How `ColumnMasks` masks a column
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Mask {
+    // Every value reads as null, keeping the column's type
+    Null,
+    // Values read as the hex digits of a hash of their text, so rows can
+    // still be counted, grouped and joined by the column without its
+    // values being seen. Nulls stay null.
+    Hash,
+    // Values that aren't null read as this text
+    Redact(String),
+}
+
+// A `ReadPolicy` masking a fixed set of columns
+#[derive(Clone, Debug, Default)]
+pub struct ColumnMasks {
+    masks: HashMap<String, Mask>,
+}
+
+impl ColumnMasks {
+    pub fn new() -> ColumnMasks {
+        ColumnMasks::default()
+    }
+
+    // Mask `column` with `mask`, replacing any mask set for it before
+    pub fn with(mut self, column: &str, mask: Mask) -> ColumnMasks {
+        self.masks.insert(column.to_owned(), mask);
+        self
+    }
+}
+
+impl ReadPolicy for ColumnMasks {
+    fn mask(&self, column: &str) -> Option<Expr> {
+        let value = col(column);
+        match self.masks.get(column)? {
+            Mask::Null => Some(value.map(
+                |s| Ok(Some(Series::full_null(s.name(), s.len(), s.dtype()))),
+                GetOutput::same_type(),
+            )),
+            Mask::Hash => Some(value.cast(DataType::Utf8).map(
+                |s| {
+                    Ok(Some(
+                        s.utf8()?
+                            .apply_values(|v| format!("{:016x}", xxh3_64(v.as_bytes())).into())
+                            .into_series(),
+                    ))
+                },
+                GetOutput::from_type(DataType::Utf8),
+            )),
+            Mask::Redact(text) => Some(
+                when(value.is_null())
+                    .then(lit(NULL).cast(DataType::Utf8))
+                    .otherwise(lit(text.clone())),
+            ),
+        }
+    }
+}
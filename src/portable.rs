@@ -0,0 +1,55 @@
+use crate::error::DeltaError;
+use polars::{io::parquet::ParquetCompression, prelude::*};
+use polars_parquet::write::{
+    transverse, Encoding, FileWriter, RowGroupIterator, Version, WriteOptions,
+};
+use std::io::Write;
+
+// Same as polars' default
+const ROW_GROUP_ROWS: usize = 512 * 512;
+
+// Options for writing the row groups of a table's data files. Portable
+// files, see `DeltaTableMetadata::portable_parquet`, use v1 data pages and
+// snappy, which every reader handles, where polars writes v2 pages and
+// zstd by default. Both always have statistics.
+pub fn write_options(portable: bool) -> WriteOptions {
+    match portable {
+        true => WriteOptions {
+            write_statistics: true,
+            version: Version::V1,
+            compression: ParquetCompression::Snappy.into(),
+            data_pagesize_limit: None,
+        },
+        false => WriteOptions {
+            write_statistics: true,
+            version: Version::V2,
+            compression: ParquetCompression::default().into(),
+            data_pagesize_limit: None,
+        },
+    }
+}
+
+// Write `df` as a portable parquet file with plain encoded columns and a
+// row group per `ROW_GROUP_ROWS` rows, returning the file's size. Like
+// polars, timestamps are int64 with their unit and dates are int32 days,
+// never the legacy int96 timestamps.
+pub fn write_portable<W: Write>(writer: W, df: &DataFrame) -> Result<u64, DeltaError> {
+    let df = df.agg_chunks();
+    let schema = df.schema().to_arrow();
+    let options = write_options(true);
+    let encodings = schema
+        .fields
+        .iter()
+        .map(|field| transverse(&field.data_type, |_| Encoding::Plain))
+        .collect::<Vec<_>>();
+
+    let mut writer = FileWriter::try_new(writer, schema.clone(), options)?;
+    for offset in (0..df.height()).step_by(ROW_GROUP_ROWS) {
+        let group = df.slice(offset as i64, ROW_GROUP_ROWS);
+        let chunks = group.iter_chunks().map(Ok);
+        for row_group in RowGroupIterator::try_new(chunks, &schema, options, encodings.clone())? {
+            writer.write(row_group?)?;
+        }
+    }
+    Ok(writer.end(None)?)
+}
@@ -0,0 +1,130 @@
+use crate::error::DeltaError;
+use polars::{prelude::*, sql::sql_expr};
+use sqlparser::{
+    ast::{visit_expressions_mut, BinaryOperator, Expr as SqlExpr, VisitMut},
+    dialect::GenericDialect,
+    parser::Parser,
+    tokenizer::{Token, Tokenizer},
+};
+use std::ops::ControlFlow;
+
+// On top of what polars' SQL dialect supports, this understands
+// `x IS NAN` and `x IS NOT NAN` for float columns.
+pub fn parse_predicate(sql: &str) -> Result<Expr, DeltaError> {
+    let marked = mark_is_nan(sql).map_err(DeltaError::InvalidPredicate)?;
+    if marked == sql {
+        return parse_expr(sql);
+    }
+
+    let mut expr = parse_sql_expr(&marked)?;
+    rewrite_is_nan(&mut expr);
+    Ok(sql_expr(expr.to_string())?)
+}
+
+// Parse a single SQL expression. Polars parses the first expression it
+// finds and ignores whatever follows, so `a = 'x') OR (1 = 1` would be
+// taken as `a = 'x'` and `a = 'x'; DELETE FROM t` as well. Anything left
+// after the expression is an error instead, so a predicate with a stray
+// quote or parenthesis, e.g. built by pasting values into SQL, is never
+// quietly run as something else. See `sql::bind_params` for passing
+// values safely.
+pub fn parse_expr(sql: &str) -> Result<Expr, DeltaError> {
+    parse_sql_expr(sql)?;
+    Ok(sql_expr(sql)?)
+}
+
+fn parse_sql_expr(sql: &str) -> Result<SqlExpr, DeltaError> {
+    let dialect = GenericDialect {};
+    let invalid = |e: sqlparser::parser::ParserError| DeltaError::InvalidPredicate(e.to_string());
+    let mut parser = Parser::new(&dialect).try_with_sql(sql).map_err(invalid)?;
+    let expr = parser.parse_expr().map_err(invalid)?;
+    let next = parser.next_token();
+    if next.token != Token::EOF {
+        return Err(DeltaError::InvalidPredicate(format!(
+            "unexpected '{}'{} after the expression",
+            next.token, next.location
+        )));
+    }
+
+    Ok(expr)
+}
+
+// The SQL parser doesn't know `IS [NOT] NAN`, so the NAN of each one is
+// replaced by UNKNOWN, which parses the same way, for `rewrite_is_nan` to
+// turn into a comparison. Polars doesn't support `IS [NOT] UNKNOWN`, so
+// one in `sql` is refused rather than taken for NaN. Only NAN tokens are
+// replaced, never a NAN inside a string or a quoted identifier. Works on
+// whole statements too, see `sql::parse_statement`.
+pub fn mark_is_nan(sql: &str) -> Result<String, String> {
+    let tokens = Tokenizer::new(&GenericDialect {}, sql)
+        .tokenize_with_location()
+        .map_err(|e| e.to_string())?;
+    let words = tokens
+        .iter()
+        .filter(|token| !matches!(token.token, Token::Whitespace(_)))
+        .collect::<Vec<_>>();
+
+    let is_word = |token: &Token, word: &str| match token {
+        Token::Word(w) => w.quote_style.is_none() && w.value.eq_ignore_ascii_case(word),
+        _ => false,
+    };
+    let mut offsets = vec![];
+    for (i, token) in words.iter().enumerate() {
+        let is = match words[..i] {
+            [.., is, not] if is_word(&not.token, "NOT") => is,
+            [.., is] => is,
+            [] => continue,
+        };
+        if !is_word(&is.token, "IS") {
+            continue;
+        }
+        if is_word(&token.token, "UNKNOWN") {
+            return Err("IS [NOT] UNKNOWN isn't supported".to_owned());
+        }
+        if is_word(&token.token, "NAN") {
+            offsets.push(byte_offset(sql, token.location.line, token.location.column));
+        }
+    }
+
+    let mut marked = sql.to_owned();
+    for offset in offsets.into_iter().rev() {
+        marked.replace_range(offset..offset + "NAN".len(), "UNKNOWN");
+    }
+    Ok(marked)
+}
+
+// Where the 1-based `line` and `column`, in characters, are in `sql`
+fn byte_offset(sql: &str, line: u64, column: u64) -> usize {
+    let line_start = (line as usize)
+        .checked_sub(2)
+        .and_then(|n| sql.match_indices('\n').nth(n))
+        .map_or(0, |(i, _)| i + 1);
+    sql[line_start..]
+        .char_indices()
+        .nth(column as usize - 1)
+        .map_or(sql.len(), |(i, _)| line_start + i)
+}
+
+// Turn the `IS [NOT] NAN`s `mark_is_nan` marked in `node`, an expression
+// or a whole statement, into comparisons polars understands
+pub fn rewrite_is_nan<V: VisitMut>(node: &mut V) {
+    let _ = visit_expressions_mut(node, |expr| {
+        rewrite_is_nan_expr(expr);
+        ControlFlow::<()>::Continue(())
+    });
+}
+
+// NaN is the only value that isn't equal to itself, so `x IS NAN` becomes
+// `x <> x`. Nulls stay null either way, which SQL treats as not matching.
+fn rewrite_is_nan_expr(expr: &mut SqlExpr) {
+    let (operand, op) = match expr {
+        SqlExpr::IsUnknown(operand) => (operand, BinaryOperator::NotEq),
+        SqlExpr::IsNotUnknown(operand) => (operand, BinaryOperator::Eq),
+        _ => return,
+    };
+    *expr = SqlExpr::Nested(Box::new(SqlExpr::BinaryOp {
+        left: operand.clone(),
+        op,
+        right: operand.clone(),
+    }));
+}
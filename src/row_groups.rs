@@ -0,0 +1,389 @@
+use crate::{checksum::ChecksumWriter, error::DeltaError, portable, stats::DeltaFileStats};
+use polars::{
+    export::arrow::{array::Array, chunk::Chunk},
+    prelude::*,
+};
+use polars_parquet::{
+    parquet::{indexes::Interval, read::get_page_iterator},
+    read::{self, fallible_streaming_iterator, RowGroupMetaData},
+    write::{
+        transverse, CompressedPage, DynIter, DynStreamingIterator, Encoding, FileWriter,
+        RowGroupIterator,
+    },
+};
+use std::{fs::File, path::Path};
+
+// Helper column marking rows that matched one of the keys in `Keep::NotIn`
+const MATCHED_KEY: &str = "__delta_matched_key";
+
+// Which rows of a file a rewrite keeps
+pub enum Keep {
+    // Rows for which the expression is true
+    Where(Expr),
+    // Rows whose values in the frame's columns don't equal any of the
+    // frame's rows, or for which the expression, if any, isn't true. The
+    // frame's rows have to be distinct and without nulls.
+    NotIn(DataFrame, Option<Expr>),
+}
+
+impl Keep {
+    fn filter(&self, rows: LazyFrame) -> Result<LazyFrame, DeltaError> {
+        match self {
+            Keep::Where(keep) => Ok(rows.filter(keep.clone())),
+            Keep::NotIn(keys, within) => {
+                // Keys have the table's types, while the file may store a
+                // column differently, e.g. timestamps in milliseconds
+                let schema = rows.schema()?;
+                let columns = keys
+                    .get_columns()
+                    .iter()
+                    .map(|key| match schema.get(key.name()) {
+                        Some(dtype) => col(key.name()).cast(dtype.clone()),
+                        None => col(key.name()),
+                    })
+                    .collect::<Vec<Expr>>();
+                let on = keys
+                    .get_column_names()
+                    .into_iter()
+                    .map(col)
+                    .collect::<Vec<Expr>>();
+                let keys = keys
+                    .clone()
+                    .lazy()
+                    .select(&columns)
+                    .with_column(lit(true).alias(MATCHED_KEY));
+
+                let mut kept = col(MATCHED_KEY).is_null();
+                if let Some(within) = within {
+                    kept = kept.or(within.clone().fill_null(lit(false)).not());
+                }
+
+                // A left join keeps the rows in order, and since the keys
+                // are distinct it doesn't duplicate any of them
+                Ok(rows
+                    .join(keys, &on, &on, JoinArgs::new(JoinType::Left))
+                    .filter(kept)
+                    .drop_columns([MATCHED_KEY]))
+            }
+        }
+    }
+
+    fn columns(&self) -> Vec<String> {
+        match self {
+            Keep::Where(keep) => expr_columns(keep),
+            Keep::NotIn(keys, within) => keys
+                .get_column_names()
+                .into_iter()
+                .map(|name| name.to_owned())
+                .chain(within.iter().flat_map(expr_columns))
+                .collect(),
+        }
+    }
+}
+
+fn expr_columns(expr: &Expr) -> Vec<String> {
+    expr.into_iter()
+        .filter_map(|expr| match expr {
+            Expr::Column(name) => Some(name.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+// What was left of a file after rewriting it one row group at a time
+pub struct RowGroupRewrite {
+    pub rows_removed: usize,
+    // Row groups copied page by page without being decoded and re-encoded
+    pub row_groups_copied: usize,
+    pub row_groups_rewritten: usize,
+    // Size of the new file, 0 when every row was removed
+    pub size: u64,
+    // Checksum of the new file, `None` when every row was removed
+    pub checksum: Option<String>,
+    // The rows that were kept, used to compute stats for the new file
+    pub remaining: DataFrame,
+}
+
+// Write the rows of `source` matching `keep` to `dest`. `partition` holds
+// the file's partition values as literal columns, added to the rows before
+// they're filtered so `keep` can refer to them. Row groups where
+// every row is kept have their compressed pages copied over as they are,
+// unless the pages lack statistics, so deleting a few rows from a large file only re-encodes the row groups
+// those rows were in. Returns `None` without creating `dest` when every row
+// would be kept, and doesn't create `dest` when no rows would be.
+//
+// Which rows to keep is first worked out from only the columns `keep`
+// refers to, so files and row groups with nothing to delete are never fully
+// decoded. Re-encoded row groups are written as `portable::write_options`
+// says.
+pub fn rewrite_row_groups(
+    source: &Path,
+    dest: &Path,
+    partition: &[Expr],
+    keep: &Keep,
+    portable: bool,
+) -> Result<Option<RowGroupRewrite>, DeltaError> {
+    let mut file = File::open(source)?;
+    let metadata = read::read_metadata(&mut file)?;
+    let schema = read::infer_schema(&metadata)?;
+
+    let projected = predicate_schema(&schema, keep);
+    let mut groups = vec![];
+    for group in &metadata.row_groups {
+        if group.num_rows() == 0 {
+            continue;
+        }
+        let rows = read_row_group(&file, group, &projected)?
+            .lazy()
+            .with_columns(partition);
+        let kept = keep.filter(rows)?.collect()?.height();
+        groups.push((group, kept));
+    }
+
+    let rows_removed = groups
+        .iter()
+        .map(|(group, kept)| group.num_rows() - kept)
+        .sum::<usize>();
+    if rows_removed == 0 {
+        return Ok(None);
+    }
+
+    let mut rewrite = RowGroupRewrite {
+        rows_removed,
+        row_groups_copied: 0,
+        row_groups_rewritten: 0,
+        size: 0,
+        checksum: None,
+        remaining: DataFrame::empty(),
+    };
+    if groups.iter().all(|(_, kept)| *kept == 0) {
+        return Ok(Some(rewrite));
+    }
+
+    let options = portable::write_options(portable);
+    let mut writer = FileWriter::try_new(
+        ChecksumWriter::new(File::create(dest)?),
+        schema.clone(),
+        options,
+    )?;
+
+    for (group, kept) in groups {
+        if kept == 0 {
+            continue;
+        }
+
+        // The kept rows are needed either way for the new file's stats
+        let df = read_row_group(&file, group, &schema)?;
+        let columns = df
+            .get_column_names()
+            .into_iter()
+            .map(col)
+            .collect::<Vec<Expr>>();
+        let kept = keep
+            .filter(df.lazy().with_columns(partition))?
+            .select(columns)
+            .collect()?;
+
+        let pages = match kept.height() == group.num_rows() {
+            true => Some(read_pages(&file, group)?).filter(|pages| has_page_statistics(pages)),
+            false => None,
+        };
+        if let Some(pages) = pages {
+            let columns = pages.iter().map(|pages| {
+                Ok(DynStreamingIterator::new(
+                    fallible_streaming_iterator::convert(pages.iter().map(Ok)),
+                ))
+            });
+            writer.write(DynIter::new(columns))?;
+            rewrite.row_groups_copied += 1;
+        } else {
+            let encodings = schema
+                .fields
+                .iter()
+                .map(|field| transverse(&field.data_type, |_| Encoding::Plain))
+                .collect();
+            let chunks = kept.iter_chunks().map(Ok);
+            for row_group in RowGroupIterator::try_new(chunks, &schema, options, encodings)? {
+                writer.write(row_group?)?;
+            }
+            rewrite.row_groups_rewritten += 1;
+        }
+
+        if rewrite.remaining.width() == 0 {
+            rewrite.remaining = kept;
+        } else {
+            rewrite.remaining.vstack_mut(&kept)?;
+        }
+    }
+    rewrite.size = writer.end(None)?;
+    rewrite.checksum = Some(writer.into_inner().checksum());
+
+    Ok(Some(rewrite))
+}
+
+// The columns of `schema` that `keep` refers to. Partition columns aren't
+// in the file and there's no need to read them. At least one column is
+// always read, so the rows can still be counted when `keep` only refers to
+// partition columns.
+fn predicate_schema(schema: &ArrowSchema, keep: &Keep) -> ArrowSchema {
+    let columns = keep.columns();
+
+    let fields = schema
+        .fields
+        .iter()
+        .filter(|field| columns.contains(&field.name))
+        .cloned()
+        .collect::<Vec<_>>();
+    match fields.is_empty() {
+        true => ArrowSchema::from(schema.fields.iter().take(1).cloned().collect::<Vec<_>>()),
+        false => ArrowSchema::from(fields),
+    }
+}
+
+// The compressed size and stats of each of the file's row groups, read
+// from its footer
+pub fn row_group_stats(path: &Path) -> Result<Vec<(u64, DeltaFileStats)>, DeltaError> {
+    let metadata = read::read_metadata(&mut File::open(path)?)?;
+    let schema = read::infer_schema(&metadata)?;
+
+    Ok(metadata
+        .row_groups
+        .iter()
+        .map(|group| {
+            let stats = DeltaFileStats::from_row_group(group, &schema)?;
+            Ok((group.compressed_size() as u64, stats))
+        })
+        .collect::<PolarsResult<Vec<_>>>()?)
+}
+
+fn read_row_group(
+    file: &File,
+    group: &RowGroupMetaData,
+    schema: &ArrowSchema,
+) -> Result<DataFrame, DeltaError> {
+    let reader = read::FileReader::new(
+        file.try_clone()?,
+        vec![group.clone()],
+        schema.clone(),
+        None,
+        None,
+        None,
+    );
+
+    let mut df = DataFrame::empty();
+    for chunk in reader {
+        let chunk = chunk_to_frame(chunk?, schema)?;
+        if df.width() == 0 {
+            df = chunk;
+        } else {
+            df.vstack_mut(&chunk)?;
+        }
+    }
+
+    Ok(df)
+}
+
+// A chunk of arrays read from a parquet file as a frame, with its columns
+// named after `schema`'s fields
+pub fn chunk_to_frame(
+    chunk: Chunk<Box<dyn Array>>,
+    schema: &ArrowSchema,
+) -> Result<DataFrame, DeltaError> {
+    let columns = chunk
+        .into_arrays()
+        .into_iter()
+        .zip(&schema.fields)
+        .map(|(array, field)| Series::try_from((field.name.as_str(), array)))
+        .collect::<PolarsResult<Vec<Series>>>()?;
+
+    Ok(DataFrame::new(columns)?)
+}
+
+// The compressed pages of each column in the row group. Pages read from a
+// file don't know how many rows they hold, which the writer needs to fill
+// in the row group's metadata. Our columns aren't nested, so that's the
+// number of values in the page.
+fn read_pages(
+    file: &File,
+    group: &RowGroupMetaData,
+) -> Result<Vec<Vec<CompressedPage>>, DeltaError> {
+    group
+        .columns()
+        .iter()
+        .map(|column| {
+            get_page_iterator(column, file.try_clone()?, None, vec![], usize::MAX)
+                .map_err(PolarsError::from)?
+                .map(|page| {
+                    let mut page = page.map_err(PolarsError::from)?;
+                    if let CompressedPage::Data(data) = &mut page {
+                        data.select_rows(vec![Interval::new(0, data.num_values())]);
+                    }
+                    Ok(page)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// The new file's page indexes are built from its pages' statistics, so
+// pages from writers that leave them out can't be copied
+fn has_page_statistics(pages: &[Vec<CompressedPage>]) -> bool {
+    pages.iter().flatten().all(|page| match page {
+        CompressedPage::Data(data) => {
+            matches!(data.statistics(), Some(Ok(stats)) if stats.null_count().is_some())
+        }
+        CompressedPage::Dict(_) => true,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+    use uuid::Uuid;
+
+    #[test]
+    fn only_row_groups_with_removed_rows_are_re_encoded() {
+        let dir = env::temp_dir().join(format!("delta-row-groups-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let (source, dest) = (dir.join("source.parquet"), dir.join("dest.parquet"));
+        let mut df =
+            df!("id" => [1, 2, 3, 4, 5, 6], "name" => ["a", "b", "c", "d", "e", "f"]).unwrap();
+
+        // Files from other writers may lack page statistics, and have every
+        // kept row group re-encoded instead
+        for statistics in [true, false] {
+            ParquetWriter::new(File::create(&source).unwrap())
+                .with_row_group_size(Some(2))
+                .with_statistics(statistics)
+                .finish(&mut df)
+                .unwrap();
+
+            let keep_all = Keep::Where(col("id").gt(lit(0)));
+            assert!(rewrite_row_groups(&source, &dest, &[], &keep_all, false)
+                .unwrap()
+                .is_none());
+            assert!(!dest.exists());
+
+            let keep = Keep::Where(col("id").neq(lit(3)));
+            let rewrite = rewrite_row_groups(&source, &dest, &[], &keep, false)
+                .unwrap()
+                .unwrap();
+            assert_eq!(rewrite.rows_removed, 1);
+            let copied = if statistics { 2 } else { 0 };
+            assert_eq!(rewrite.row_groups_copied, copied);
+            assert_eq!(rewrite.row_groups_rewritten, 3 - copied);
+            assert_eq!(rewrite.size, fs::metadata(&dest).unwrap().len());
+
+            let rows = ParquetReader::new(File::open(&dest).unwrap())
+                .finish()
+                .unwrap();
+            assert_eq!(rows, df.filter(&df["id"].not_equal(3).unwrap()).unwrap());
+            let groups = row_group_stats(&dest).unwrap();
+            let sizes = groups.iter().map(|(_, stats)| stats.num_records);
+            assert_eq!(sizes.collect::<Vec<u64>>(), [2, 1, 2]);
+            fs::remove_file(&dest).unwrap();
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,5 +1,11 @@
-use crate::error::DeltaError;
-use polars::datatypes::{DataType, TimeUnit};
+use crate::{error::DeltaError, identifier::unquote_identifier, predicate::parse_expr};
+use polars::{
+    datatypes::{DataType, TimeUnit},
+    frame::DataFrame,
+    lazy::{dsl::Expr, frame::IntoLazy},
+    prelude::ChunkCompare,
+    series::Series,
+};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 
@@ -12,16 +18,38 @@ pub struct DeltaTableSchema {
 }
 
 impl DeltaTableSchema {
+    // Column types can be followed by `NULL` or `NOT NULL`, e.g. `TEXT NULL`.
+    // Columns are NOT NULL unless declared otherwise, as every column was
+    // before nullable columns were supported. A column declared with
+    // `GENERATED ALWAYS AS (<expr>)` at the end is a generated column, see
+    // `align`.
     pub fn from_sql(sql_schema: Vec<(&str, &str)>) -> Result<Self, DeltaError> {
         let mut fields = vec![];
         for sql_col in sql_schema {
-            let typ = DeltaTableType::from_sql_type(sql_col.1)?;
+            let (typ, generation_expression) = split_generated(sql_col.1)?;
+            let mut metadata = HashMap::new();
+            if let Some(expr) = generation_expression {
+                metadata.insert(GENERATION_EXPRESSION.to_owned(), expr.to_owned());
+            }
+
+            let words = typ.split_whitespace().collect::<Vec<&str>>();
+            let (typ, nullable) = match words.as_slice() {
+                [typ] => (typ, false),
+                [typ, null] if null.eq_ignore_ascii_case("NULL") => (typ, true),
+                [typ, not, null]
+                    if not.eq_ignore_ascii_case("NOT") && null.eq_ignore_ascii_case("NULL") =>
+                {
+                    (typ, false)
+                }
+                _ => return Err(DeltaError::InvalidType),
+            };
+            let typ = DeltaTableType::from_sql_type(typ)?;
 
             fields.push(DeltaTableColumnDefinition {
-                name: sql_col.0.to_owned(),
+                name: unquote_identifier(sql_col.0),
                 typ,
-                nullable: false,
-                metadata: HashMap::new(),
+                nullable,
+                metadata,
             })
         }
 
@@ -40,13 +68,249 @@ impl DeltaTableSchema {
             }
 
             seen.insert(field.name.as_str());
-            return field.is_valid();
-        })
+            field.is_valid()
+        }) && self.generated_columns_are_valid()
+    }
+
+    // Generation expressions have to parse, and can only refer to columns
+    // that aren't generated themselves
+    fn generated_columns_are_valid(&self) -> bool {
+        let Ok(generated) = self.generated_columns() else {
+            return false;
+        };
+        if generated.is_empty() {
+            return true;
+        }
+
+        let cols = self
+            .fields
+            .iter()
+            .filter(|field| field.generation_expression().is_none())
+            .map(|field| Series::new_empty(&field.name, &field.typ.to_polars_type()))
+            .collect::<Vec<Series>>();
+        let Ok(df) = DataFrame::new(cols) else {
+            return false;
+        };
+        df.lazy()
+            .select(
+                generated
+                    .into_iter()
+                    .map(|(_, expr)| expr)
+                    .collect::<Vec<_>>(),
+            )
+            .collect()
+            .is_ok()
+    }
+
+    // The generated columns with the expressions computing them, cast to
+    // the column's type
+    fn generated_columns(&self) -> Result<Vec<(&DeltaTableColumnDefinition, Expr)>, DeltaError> {
+        self.fields
+            .iter()
+            .filter_map(|field| {
+                let expr = field.generation_expression()?;
+                Some(parse_expr(expr).map(|expr| {
+                    let expr = expr.cast(field.typ.to_polars_type()).alias(&field.name);
+                    (field, expr)
+                }))
+            })
+            .collect()
+    }
+
+    // The fields positional rows line up with. Rows with a value for every
+    // column line up with all of them, shorter rows leave out the
+    // generated columns.
+    pub fn positional_fields(&self, width: usize) -> Vec<&DeltaTableColumnDefinition> {
+        match width == self.fields.len() {
+            true => self.fields.iter().collect(),
+            false => self
+                .fields
+                .iter()
+                .filter(|field| field.generation_expression().is_none())
+                .collect(),
+        }
     }
 
     pub fn fields(&self) -> &Vec<DeltaTableColumnDefinition> {
         &self.fields
     }
+
+    pub fn empty_frame(&self) -> DataFrame {
+        let cols = self
+            .fields
+            .iter()
+            .map(|field| Series::new_empty(&field.name, &field.typ.to_polars_type()))
+            .collect::<Vec<Series>>();
+
+        // Field names are unique for a valid schema
+        DataFrame::new(cols).unwrap()
+    }
+
+    // Select the schema's columns out of `df` by name, in schema order,
+    // casting each one to its table type. Missing or unknown columns are
+    // an error rather than being matched up positionally.
+    //
+    // Generated columns are computed from the other columns when they're
+    // missing, and fill in nulls when they're not. Values given for a
+    // generated column have to be the ones it would have been computed as.
+    pub fn align(&self, df: &DataFrame) -> Result<DataFrame, DeltaError> {
+        for name in df.get_column_names() {
+            if !self.fields.iter().any(|field| field.name == name) {
+                return Err(DeltaError::SchemaMismatch(format!(
+                    "unknown column '{}'",
+                    name
+                )));
+            }
+        }
+
+        let cols = self
+            .fields
+            .iter()
+            .filter(|field| field.generation_expression().is_none())
+            .map(|field| {
+                let col = df.column(&field.name).map_err(|_| {
+                    DeltaError::SchemaMismatch(format!("missing column '{}'", field.name))
+                })?;
+                Ok(col.cast(&field.typ.to_polars_type())?)
+            })
+            .collect::<Result<Vec<Series>, DeltaError>>()?;
+        let mut aligned = DataFrame::new(cols)?;
+
+        let generated = self.generated_columns()?;
+        if !generated.is_empty() {
+            let computed = aligned
+                .clone()
+                .lazy()
+                .select(
+                    generated
+                        .iter()
+                        .map(|(_, expr)| expr.clone())
+                        .collect::<Vec<_>>(),
+                )
+                .collect()?;
+            for (field, _) in generated {
+                let value = computed.column(&field.name)?;
+                let value = match df.column(&field.name) {
+                    Ok(given) => {
+                        let given = given.cast(&field.typ.to_polars_type())?;
+                        let mismatch = given.is_not_null() & given.not_equal_missing(value)?;
+                        if let Some(row) = mismatch.into_iter().position(|m| m == Some(true)) {
+                            return Err(DeltaError::InvalidValue {
+                                row,
+                                column: field.name.clone(),
+                                message: format!(
+                                    "value does not match the generation expression '{}'",
+                                    field.generation_expression().unwrap_or_default()
+                                ),
+                            });
+                        }
+                        given.zip_with(&given.is_not_null(), value)?
+                    }
+                    Err(_) => value.clone(),
+                };
+                aligned.with_column(value)?;
+            }
+        }
+
+        Ok(aligned.select(self.fields.iter().map(|field| field.name.as_str()))?)
+    }
+
+    // Make sure no column of `df` that isn't nullable holds a null. The
+    // error names the first row with one.
+    pub fn check_nulls(&self, df: &DataFrame) -> Result<(), DeltaError> {
+        for field in self.fields.iter().filter(|field| !field.nullable) {
+            let Ok(col) = df.column(&field.name) else {
+                continue;
+            };
+
+            if col.null_count() > 0 {
+                let row = col
+                    .is_null()
+                    .into_iter()
+                    .position(|null| null == Some(true));
+                return Err(DeltaError::InvalidValue {
+                    row: row.unwrap_or_default(),
+                    column: field.name.clone(),
+                    message: "null value is not allowed in a non-nullable column".to_owned(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    // Make sure every column of `df` that belongs to the schema can be
+    // stored in its field without losing data. `align` casts blindly, which
+    // is what we want for text input but would silently turn bad values
+    // into nulls for a frame that is already typed.
+    pub fn check_types(&self, df: &DataFrame) -> Result<(), DeltaError> {
+        for field in &self.fields {
+            let Ok(col) = df.column(&field.name) else {
+                continue;
+            };
+
+            if !field.typ.accepts(col.dtype()) {
+                return Err(DeltaError::SchemaMismatch(format!(
+                    "column '{}' has type {} but the table expects {}",
+                    field.name,
+                    col.dtype(),
+                    field.typ.to_polars_type()
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Make sure the text values of `df`'s numeric columns are numbers that
+    // fit their field, e.g. that 40000 isn't given for a SMALLINT. Casting
+    // text in `align` turns those into nulls, and for floats turns values
+    // too large for the type into infinity. The error names the first row
+    // with one.
+    pub fn check_numbers(&self, df: &DataFrame) -> Result<(), DeltaError> {
+        for field in &self.fields {
+            let Ok(col) = df.column(&field.name) else {
+                continue;
+            };
+            if col.dtype() != &DataType::Utf8 || !field.typ.is_numeric() {
+                continue;
+            }
+
+            let sql_type = field.typ.to_sql_type();
+            let cast = col
+                .cast(&field.typ.to_polars_type())?
+                .cast(&DataType::Float64)?;
+            let bad = col
+                .utf8()?
+                .into_iter()
+                .zip(cast.f64()?)
+                .enumerate()
+                .find_map(|(row, (text, value))| {
+                    let text = text?;
+                    let message = match value {
+                        Some(value)
+                            if !value.is_infinite() || text.to_lowercase().contains("inf") =>
+                        {
+                            return None
+                        }
+                        None if !field.typ.is_number(text) => {
+                            format!("'{}' is not a valid {}", text, sql_type)
+                        }
+                        _ => format!("value {} is out of range for {}", text, sql_type),
+                    };
+                    Some((row, message))
+                });
+            if let Some((row, message)) = bad {
+                return Err(DeltaError::InvalidValue {
+                    row,
+                    column: field.name.clone(),
+                    message,
+                });
+            }
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -61,10 +325,51 @@ pub struct DeltaTableColumnDefinition {
 
 impl DeltaTableColumnDefinition {
     fn is_valid(&self) -> bool {
-        // Don't know how to handle null values yet.
         // Will just ignore the metadata field so we
         // don't need to enforce that it's empty.
-        !self.nullable
+        !self.name.is_empty()
+    }
+
+    pub fn metadata(&self) -> &HashMap<String, String> {
+        &self.metadata
+    }
+
+    // The SQL expression the column's values are computed from, when it's
+    // a generated column
+    pub fn generation_expression(&self) -> Option<&str> {
+        self.metadata.get(GENERATION_EXPRESSION).map(String::as_str)
+    }
+}
+
+// Column metadata key holding a generated column's expression, as Spark
+// stores it
+const GENERATION_EXPRESSION: &str = "delta.generationExpression";
+
+// Split a column's SQL type from its `GENERATED ALWAYS AS (<expr>)`
+// clause, if it has one
+fn split_generated(sql_type: &str) -> Result<(&str, Option<&str>), DeltaError> {
+    let upper = sql_type.to_ascii_uppercase();
+    let Some(start) = upper.find("GENERATED") else {
+        return Ok((sql_type, None));
+    };
+
+    let clause = upper[start..]
+        .split_whitespace()
+        .take(3)
+        .collect::<Vec<&str>>();
+    let rest = sql_type[start..]
+        .splitn(4, char::is_whitespace)
+        .nth(3)
+        .map(str::trim)
+        .unwrap_or_default();
+    match (
+        clause.as_slice(),
+        rest.strip_prefix('(').and_then(|r| r.strip_suffix(')')),
+    ) {
+        (["GENERATED", "ALWAYS", "AS"], Some(expr)) if !expr.trim().is_empty() => {
+            Ok((&sql_type[..start], Some(expr.trim())))
+        }
+        _ => Err(DeltaError::InvalidType),
     }
 }
 
@@ -110,6 +415,22 @@ impl DeltaTableType {
         }
     }
 
+    // The SQL type `from_sql_type` maps to this type
+    pub fn to_sql_type(&self) -> &'static str {
+        match self {
+            Self::String => "TEXT",
+            Self::Long => "BIGINT",
+            Self::Integer => "INT",
+            Self::Short => "SMALLINT",
+            Self::Byte => "TINYINT",
+            Self::Float => "FLOAT",
+            Self::Double => "DOUBLE",
+            Self::Boolean => "BOOL",
+            Self::Date => "DATE",
+            Self::Timestamp => "TIMESTAMP",
+        }
+    }
+
     pub fn to_polars_type(&self) -> DataType {
         match self {
             Self::String => DataType::Utf8,
@@ -124,4 +445,46 @@ impl DeltaTableType {
             Self::Timestamp => DataType::Datetime(TimeUnit::Microseconds, None),
         }
     }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            Self::Long | Self::Integer | Self::Short | Self::Byte | Self::Float | Self::Double
+        )
+    }
+
+    // Whether `text` is written like a value of this numeric type, whether
+    // or not it fits, e.g. "3.5" is a number but not an integer. Casting
+    // doesn't take a leading '+' on integers.
+    fn is_number(&self, text: &str) -> bool {
+        match self {
+            Self::Float | Self::Double => text.parse::<f64>().is_ok_and(f64::is_finite),
+            _ => !text.starts_with('+') && text.parse::<i128>().is_ok(),
+        }
+    }
+
+    // Whether values of `dtype` can be cast to this type without loss:
+    // the same type, or a narrower one of the same kind.
+    pub fn accepts(&self, dtype: &DataType) -> bool {
+        use DataType::*;
+
+        match self {
+            Self::String => matches!(dtype, Utf8),
+            Self::Long => matches!(
+                dtype,
+                Int8 | Int16 | Int32 | Int64 | UInt8 | UInt16 | UInt32
+            ),
+            Self::Integer => matches!(dtype, Int8 | Int16 | Int32 | UInt8 | UInt16),
+            Self::Short => matches!(dtype, Int8 | Int16 | UInt8),
+            Self::Byte => matches!(dtype, Int8),
+            Self::Float => matches!(dtype, Float32),
+            Self::Double => matches!(dtype, Float32 | Float64),
+            Self::Boolean => matches!(dtype, Boolean),
+            Self::Date => matches!(dtype, Date),
+            Self::Timestamp => matches!(
+                dtype,
+                Date | Datetime(TimeUnit::Milliseconds | TimeUnit::Microseconds, _)
+            ),
+        }
+    }
 }
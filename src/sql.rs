@@ -0,0 +1,636 @@
+use crate::{
+    error::DeltaError,
+    identifier::join_qualified_name,
+    predicate::{mark_is_nan, rewrite_is_nan},
+    value::DeltaValue,
+};
+use polars::{prelude::*, sql::SQLContext};
+use sqlparser::{
+    ast::{
+        ExcludeSelectItem, Expr, FunctionArg, FunctionArgExpr, GroupByExpr, Ident, Query,
+        SelectItem, SetExpr, Statement, TableFactor, TableWithJoins, Value,
+    },
+    dialect::GenericDialect,
+    parser::Parser,
+    tokenizer::{Token, Tokenizer},
+};
+use std::collections::HashMap;
+
+// Virtual columns any table can be queried with, to trace rows back to the
+// data file and commit they came from: the file's path relative to the
+// table, the version that added the file and the row's position in it.
+// Tables only have them when a query refers to them, so `SELECT *` doesn't
+// return them.
+pub const FILE_NAME_COLUMN: &str = "_file_name";
+pub const COMMIT_VERSION_COLUMN: &str = "_commit_version";
+pub const ROW_INDEX_COLUMN: &str = "_row_index";
+const METADATA_COLUMNS: &[&str] = &[FILE_NAME_COLUMN, COMMIT_VERSION_COLUMN, ROW_INDEX_COLUMN];
+
+// What a query reads from each of its tables
+pub struct QueryScan {
+    // The most rows the query can read from the table, when that's known,
+    // see `row_limit`
+    pub limit: Option<usize>,
+    // The metadata columns the query refers to, see `METADATA_COLUMNS`
+    pub metadata_columns: Vec<&'static str>,
+}
+
+// Parse a single SQL statement. Polars' SQL support only ever looks at the
+// first part of a table name, so statements are parsed here first and
+// qualified names like `prod.events` rewritten before polars sees them.
+// `x IS [NOT] NAN` is understood anywhere in the statement, like in
+// predicates, see `predicate::parse_predicate`.
+pub fn parse_statement(sql: &str) -> Result<Statement, DeltaError> {
+    let marked = mark_is_nan(sql).map_err(DeltaError::InvalidQuery)?;
+    let mut statements = Parser::parse_sql(&GenericDialect {}, &marked)
+        .map_err(|e| DeltaError::InvalidQuery(e.to_string()))?;
+
+    match statements.len() {
+        1 => {
+            let mut statement = statements.remove(0);
+            rewrite_is_nan(&mut statement);
+            Ok(statement)
+        }
+        n => Err(DeltaError::InvalidQuery(format!(
+            "expected a single statement, got {}",
+            n
+        ))),
+    }
+}
+
+// Replace each `?` placeholder in `sql` with the next of `params`, as a
+// SQL literal of the value's type. Strings are quoted and escaped, so
+// callers never build statements out of values by hand. Placeholders are
+// found by tokenizing `sql`, so a `?` in a string or quoted identifier
+// isn't one.
+pub fn bind_params(sql: &str, params: &[DeltaValue]) -> Result<String, DeltaError> {
+    let tokens = Tokenizer::new(&GenericDialect {}, sql)
+        .tokenize_with_location()
+        .map_err(|e| DeltaError::InvalidQuery(e.to_string()))?;
+
+    // Token locations are lines and columns of characters
+    let mut offsets = HashMap::new();
+    let (mut line, mut column) = (1, 1);
+    for (offset, c) in sql.char_indices() {
+        offsets.insert((line, column), offset);
+        match c {
+            '\n' => (line, column) = (line + 1, 1),
+            _ => column += 1,
+        }
+    }
+
+    let mut bound = String::with_capacity(sql.len());
+    let mut params = params.iter();
+    let mut end = 0;
+    for token in tokens {
+        let Token::Placeholder(placeholder) = &token.token else {
+            continue;
+        };
+        if placeholder != "?" {
+            return Err(DeltaError::InvalidQuery(format!(
+                "unsupported placeholder {}, only ? is",
+                placeholder
+            )));
+        }
+        let start = offsets[&(token.location.line, token.location.column)];
+        let param = params.next().ok_or_else(|| {
+            DeltaError::InvalidQuery("more placeholders than parameters".to_owned())
+        })?;
+        bound.push_str(&sql[end..start]);
+        bound.push_str(&param.to_sql()?);
+        end = start + 1;
+    }
+    if params.next().is_some() {
+        return Err(DeltaError::InvalidQuery(
+            "more parameters than placeholders".to_owned(),
+        ));
+    }
+    bound.push_str(&sql[end..]);
+
+    Ok(bound)
+}
+
+// Run a query with polars. `resolve` is called once for each table the
+// query references, with the table's name in its canonical form, and
+// returns the table's rows, with what the query needs from them.
+pub fn run_query(
+    query: Box<Query>,
+    resolve: impl FnMut(&str, &QueryScan) -> Result<LazyFrame, DeltaError>,
+) -> Result<DataFrame, DeltaError> {
+    plan_query(query, resolve)?
+        .collect()
+        .map_err(|e| DeltaError::InvalidQuery(e.to_string()))
+}
+
+// Polars' optimized logical plan for a query, see `run_query`
+pub fn describe_query(
+    query: Box<Query>,
+    resolve: impl FnMut(&str, &QueryScan) -> Result<LazyFrame, DeltaError>,
+) -> Result<String, DeltaError> {
+    plan_query(query, resolve)?
+        .describe_optimized_plan()
+        .map_err(|e| DeltaError::InvalidQuery(e.to_string()))
+}
+
+fn plan_query(
+    mut query: Box<Query>,
+    mut resolve: impl FnMut(&str, &QueryScan) -> Result<LazyFrame, DeltaError>,
+) -> Result<LazyFrame, DeltaError> {
+    let scan = QueryScan {
+        limit: row_limit(&query),
+        metadata_columns: metadata_columns(&query),
+    };
+    hide_metadata_columns(&mut query, &scan.metadata_columns, &mut vec![]);
+    let mut frames = PlHashMap::new();
+    rewrite_tables(&mut query, &mut |parts: &[Ident]| {
+        let name = join_qualified_name(
+            &parts
+                .iter()
+                .map(|part| part.value.clone())
+                .collect::<Vec<String>>(),
+        );
+        if !frames.contains_key(&name) {
+            frames.insert(name.clone(), resolve(&name, &scan)?);
+        }
+        // Polars only looks at the first part of a table name, so register
+        // the whole name as a single identifier
+        Ok::<Ident, DeltaError>(Ident::with_quote('"', name))
+    })?;
+    resolve_having(&mut query)?;
+
+    let mut context = SQLContext::new();
+    for (name, frame) in frames {
+        context.register(&name, frame);
+    }
+
+    context
+        .execute(&query.to_string())
+        .map_err(|e| DeltaError::InvalidQuery(e.to_string()))
+}
+
+// The metadata columns a query refers to. Any identifier with their name
+// counts, wherever it is in the query.
+fn metadata_columns(query: &Query) -> Vec<&'static str> {
+    let sql = query.to_string();
+    let tokens = Tokenizer::new(&GenericDialect {}, &sql)
+        .tokenize()
+        .unwrap_or_default();
+
+    METADATA_COLUMNS
+        .iter()
+        .copied()
+        .filter(|column| {
+            tokens
+                .iter()
+                .any(|token| matches!(token, Token::Word(word) if word.value == *column))
+        })
+        .collect()
+}
+
+// Metadata columns are only selected by name, so `SELECT *, _file_name`
+// works. Wildcards over tables have them excluded, while wildcards over
+// subqueries and CTEs keep whatever those selected.
+fn hide_metadata_columns(query: &mut Query, columns: &[&str], ctes: &mut Vec<String>) {
+    if columns.is_empty() {
+        return;
+    }
+
+    let scope = ctes.len();
+    if let Some(with) = &mut query.with {
+        for cte in &mut with.cte_tables {
+            hide_metadata_columns(&mut cte.query, columns, ctes);
+            ctes.push(cte.alias.name.value.clone());
+        }
+    }
+    hide_metadata_columns_in(&mut query.body, columns, ctes);
+    ctes.truncate(scope);
+}
+
+fn hide_metadata_columns_in(body: &mut SetExpr, columns: &[&str], ctes: &mut Vec<String>) {
+    let select = match body {
+        SetExpr::Select(select) => select,
+        SetExpr::Query(query) => return hide_metadata_columns(query, columns, ctes),
+        SetExpr::SetOperation { left, right, .. } => {
+            hide_metadata_columns_in(left, columns, ctes);
+            return hide_metadata_columns_in(right, columns, ctes);
+        }
+        _ => return,
+    };
+
+    let mut only_tables = true;
+    for table in &mut select.from {
+        let relations = std::iter::once(&mut table.relation)
+            .chain(table.joins.iter_mut().map(|join| &mut join.relation));
+        for relation in relations {
+            match relation {
+                TableFactor::Table { name, .. } => {
+                    only_tables &=
+                        !matches!(name.0.as_slice(), [ident] if ctes.contains(&ident.value));
+                }
+                TableFactor::Derived { subquery, .. } => {
+                    hide_metadata_columns(subquery, columns, ctes);
+                    only_tables = false;
+                }
+                _ => only_tables = false,
+            }
+        }
+    }
+    if !only_tables {
+        return;
+    }
+
+    // Polars drops the columns a wildcard excludes from the whole result,
+    // and refuses a column selected twice, so a metadata column selected by
+    // name next to `*` is left to the wildcard instead
+    let mut selected = vec![];
+    if select
+        .projection
+        .iter()
+        .any(|item| matches!(item, SelectItem::Wildcard(_)))
+    {
+        select.projection.retain(|item| match item {
+            SelectItem::UnnamedExpr(Expr::Identifier(ident))
+                if columns.contains(&ident.value.as_str()) =>
+            {
+                selected.push(ident.value.clone());
+                false
+            }
+            _ => true,
+        });
+    }
+    for item in &mut select.projection {
+        let (options, selected) = match item {
+            SelectItem::Wildcard(options) => (options, selected.as_slice()),
+            SelectItem::QualifiedWildcard(_, options) => (options, &[][..]),
+            _ => continue,
+        };
+        let mut excluded = match options.opt_exclude.take() {
+            Some(ExcludeSelectItem::Single(ident)) => vec![ident],
+            Some(ExcludeSelectItem::Multiple(idents)) => idents,
+            None => vec![],
+        };
+        for column in columns {
+            if !selected.iter().any(|name| name == column)
+                && !excluded.iter().any(|ident| ident.value == *column)
+            {
+                excluded.push(Ident::new(*column));
+            }
+        }
+        if !excluded.is_empty() {
+            options.opt_exclude = Some(ExcludeSelectItem::Multiple(excluded));
+        }
+    }
+}
+
+// The most rows a query can read from its table, for a plain
+// `SELECT a, b FROM t LIMIT n` without filters, joins, grouping or
+// ordering, all of which could need rows past the first `n`.
+fn row_limit(query: &Query) -> Option<usize> {
+    if query.with.is_some()
+        || !query.order_by.is_empty()
+        || !query.limit_by.is_empty()
+        || query.fetch.is_some()
+    {
+        return None;
+    }
+    let SetExpr::Select(select) = query.body.as_ref() else {
+        return None;
+    };
+
+    let plain = select.distinct.is_none()
+        && select.top.is_none()
+        && select.into.is_none()
+        && matches!(
+            select.from.as_slice(),
+            [from] if from.joins.is_empty() && matches!(from.relation, TableFactor::Table { .. })
+        )
+        && select.lateral_views.is_empty()
+        && select.selection.is_none()
+        && matches!(&select.group_by, GroupByExpr::Expressions(exprs) if exprs.is_empty())
+        && select.cluster_by.is_empty()
+        && select.distribute_by.is_empty()
+        && select.sort_by.is_empty()
+        && select.having.is_none()
+        && select.named_window.is_empty()
+        && select.qualify.is_none()
+        && select.projection.iter().all(|item| match item {
+            SelectItem::Wildcard(_) | SelectItem::QualifiedWildcard(..) => true,
+            SelectItem::UnnamedExpr(expr) | SelectItem::ExprWithAlias { expr, .. } => {
+                matches!(expr, Expr::Identifier(_) | Expr::CompoundIdentifier(_))
+            }
+        });
+    if !plain {
+        return None;
+    }
+
+    let number = |expr: &Expr| match expr {
+        Expr::Value(Value::Number(n, _)) => n.parse::<usize>().ok(),
+        _ => None,
+    };
+    let offset = match &query.offset {
+        Some(offset) => number(&offset.value)?,
+        None => 0,
+    };
+    number(query.limit.as_ref()?)?.checked_add(offset)
+}
+
+// Aggregate functions polars' SQL knows. Everything else it supports works
+// on one row at a time.
+const AGGREGATES: &[&str] = &[
+    "avg",
+    "count",
+    "first",
+    "last",
+    "max",
+    "min",
+    "stddev",
+    "stddev_samp",
+    "sum",
+    "variance",
+    "var_samp",
+];
+
+// Whether a query works on each row of its table on its own, like
+// `SELECT a, upper(b) FROM t WHERE c > 1`. Running such a query over each
+// of the table's files and concatenating the results gives the same rows
+// as running it over the whole table, so results can be streamed file by
+// file. Anything that looks at several rows at once, like aggregates,
+// DISTINCT, ORDER BY, LIMIT, joins or subqueries, isn't.
+pub fn is_row_wise(query: &Query) -> bool {
+    if query.with.is_some()
+        || !query.order_by.is_empty()
+        || query.limit.is_some()
+        || !query.limit_by.is_empty()
+        || query.offset.is_some()
+        || query.fetch.is_some()
+    {
+        return false;
+    }
+    let SetExpr::Select(select) = query.body.as_ref() else {
+        return false;
+    };
+
+    select.distinct.is_none()
+        && select.top.is_none()
+        && select.into.is_none()
+        && matches!(
+            select.from.as_slice(),
+            [from] if from.joins.is_empty() && matches!(from.relation, TableFactor::Table { .. })
+        )
+        && select.lateral_views.is_empty()
+        && select.selection.as_ref().is_none_or(is_scalar)
+        && matches!(&select.group_by, GroupByExpr::Expressions(exprs) if exprs.is_empty())
+        && select.cluster_by.is_empty()
+        && select.distribute_by.is_empty()
+        && select.sort_by.is_empty()
+        && select.having.is_none()
+        && select.named_window.is_empty()
+        && select.qualify.is_none()
+        && select.projection.iter().all(|item| match item {
+            SelectItem::Wildcard(_) | SelectItem::QualifiedWildcard(..) => true,
+            SelectItem::UnnamedExpr(expr) | SelectItem::ExprWithAlias { expr, .. } => {
+                is_scalar(expr)
+            }
+        })
+}
+
+// Whether an expression only looks at the row it's evaluated for.
+// Expressions we don't know are assumed not to.
+fn is_scalar(expr: &Expr) -> bool {
+    match expr {
+        Expr::Identifier(_) | Expr::CompoundIdentifier(_) | Expr::Value(_) => true,
+        Expr::BinaryOp { left, right, .. } => is_scalar(left) && is_scalar(right),
+        Expr::UnaryOp { expr, .. }
+        | Expr::Nested(expr)
+        | Expr::IsNull(expr)
+        | Expr::IsNotNull(expr)
+        | Expr::IsTrue(expr)
+        | Expr::IsFalse(expr)
+        | Expr::Cast { expr, .. } => is_scalar(expr),
+        Expr::Between {
+            expr, low, high, ..
+        } => is_scalar(expr) && is_scalar(low) && is_scalar(high),
+        Expr::InList { expr, list, .. } => is_scalar(expr) && list.iter().all(is_scalar),
+        Expr::Like { expr, pattern, .. } | Expr::ILike { expr, pattern, .. } => {
+            is_scalar(expr) && is_scalar(pattern)
+        }
+        Expr::Case {
+            operand,
+            conditions,
+            results,
+            else_result,
+        } => {
+            operand.as_deref().is_none_or(is_scalar)
+                && conditions.iter().all(is_scalar)
+                && results.iter().all(is_scalar)
+                && else_result.as_deref().is_none_or(is_scalar)
+        }
+        Expr::Function(function) => {
+            let name = function.name.to_string().to_lowercase();
+            function.over.is_none()
+                && function.filter.is_none()
+                && !function.distinct
+                && !AGGREGATES.contains(&name.as_str())
+                && function.args.iter().all(|arg| match arg {
+                    FunctionArg::Named { arg, .. } | FunctionArg::Unnamed(arg) => match arg {
+                        FunctionArgExpr::Expr(expr) => is_scalar(expr),
+                        _ => false,
+                    },
+                })
+        }
+        _ => false,
+    }
+}
+
+// Replace every table referenced by `query` with the single identifier
+// returned by `f`, given the parts of the table's name. Names of CTEs
+// defined by the query aren't tables and are left alone.
+pub fn rewrite_tables<E>(
+    query: &mut Query,
+    f: &mut impl FnMut(&[Ident]) -> Result<Ident, E>,
+) -> Result<(), E> {
+    rewrite_query(query, &mut vec![], f)
+}
+
+fn rewrite_query<E>(
+    query: &mut Query,
+    ctes: &mut Vec<String>,
+    f: &mut impl FnMut(&[Ident]) -> Result<Ident, E>,
+) -> Result<(), E> {
+    let scope = ctes.len();
+    if let Some(with) = &mut query.with {
+        for cte in &mut with.cte_tables {
+            rewrite_query(&mut cte.query, ctes, f)?;
+            ctes.push(cte.alias.name.value.clone());
+        }
+    }
+
+    rewrite_set_expr(&mut query.body, ctes, f)?;
+    ctes.truncate(scope);
+
+    Ok(())
+}
+
+fn rewrite_set_expr<E>(
+    body: &mut SetExpr,
+    ctes: &mut Vec<String>,
+    f: &mut impl FnMut(&[Ident]) -> Result<Ident, E>,
+) -> Result<(), E> {
+    match body {
+        SetExpr::Select(select) => {
+            for table in &mut select.from {
+                rewrite_table_with_joins(table, ctes, f)?;
+            }
+            if let Some(selection) = &mut select.selection {
+                rewrite_expr(selection, ctes, f)?;
+            }
+            Ok(())
+        }
+        SetExpr::Query(query) => rewrite_query(query, ctes, f),
+        SetExpr::SetOperation { left, right, .. } => {
+            rewrite_set_expr(left, ctes, f)?;
+            rewrite_set_expr(right, ctes, f)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn rewrite_table_with_joins<E>(
+    table: &mut TableWithJoins,
+    ctes: &mut Vec<String>,
+    f: &mut impl FnMut(&[Ident]) -> Result<Ident, E>,
+) -> Result<(), E> {
+    rewrite_table_factor(&mut table.relation, ctes, f)?;
+    for join in &mut table.joins {
+        rewrite_table_factor(&mut join.relation, ctes, f)?;
+    }
+
+    Ok(())
+}
+
+fn rewrite_table_factor<E>(
+    factor: &mut TableFactor,
+    ctes: &mut Vec<String>,
+    f: &mut impl FnMut(&[Ident]) -> Result<Ident, E>,
+) -> Result<(), E> {
+    match factor {
+        TableFactor::Table { name, args, .. } if args.is_none() => {
+            let is_cte = matches!(name.0.as_slice(), [ident] if ctes.contains(&ident.value));
+            if !is_cte {
+                name.0 = vec![f(&name.0)?];
+            }
+            Ok(())
+        }
+        TableFactor::Derived { subquery, .. } => rewrite_query(subquery, ctes, f),
+        TableFactor::NestedJoin {
+            table_with_joins, ..
+        } => rewrite_table_with_joins(table_with_joins, ctes, f),
+        _ => Ok(()),
+    }
+}
+
+// Subqueries can appear anywhere in an expression, but in practice they're
+// found in WHERE clauses, combined with AND/OR/NOT.
+fn rewrite_expr<E>(
+    expr: &mut Expr,
+    ctes: &mut Vec<String>,
+    f: &mut impl FnMut(&[Ident]) -> Result<Ident, E>,
+) -> Result<(), E> {
+    match expr {
+        Expr::InSubquery { expr, subquery, .. } => {
+            rewrite_expr(expr, ctes, f)?;
+            rewrite_query(subquery, ctes, f)
+        }
+        Expr::Exists { subquery, .. } | Expr::Subquery(subquery) => {
+            rewrite_query(subquery, ctes, f)
+        }
+        Expr::BinaryOp { left, right, .. } => {
+            rewrite_expr(left, ctes, f)?;
+            rewrite_expr(right, ctes, f)
+        }
+        Expr::UnaryOp { expr, .. } | Expr::Nested(expr) => rewrite_expr(expr, ctes, f),
+        _ => Ok(()),
+    }
+}
+
+// Polars applies HAVING to the already aggregated rows, so an aggregate in
+// it like `count(*) > 1` would be computed over the groups rather than
+// within each one. Aggregates that are also selected with an alias are
+// replaced by that alias, which refers to the per-group value. Any other
+// function call in HAVING is rejected rather than giving wrong results.
+fn resolve_having(query: &mut Query) -> Result<(), DeltaError> {
+    if let Some(with) = &mut query.with {
+        for cte in &mut with.cte_tables {
+            resolve_having(&mut cte.query)?;
+        }
+    }
+    resolve_having_in(&mut query.body)
+}
+
+fn resolve_having_in(body: &mut SetExpr) -> Result<(), DeltaError> {
+    match body {
+        SetExpr::Select(select) => {
+            let aliases = select
+                .projection
+                .iter()
+                .filter_map(|item| match item {
+                    SelectItem::ExprWithAlias { expr, alias } => {
+                        Some((expr.to_string(), alias.clone()))
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<(String, Ident)>>();
+
+            match &mut select.having {
+                Some(having) => replace_aggregates(having, &aliases),
+                None => Ok(()),
+            }
+        }
+        SetExpr::Query(query) => resolve_having(query),
+        SetExpr::SetOperation { left, right, .. } => {
+            resolve_having_in(left)?;
+            resolve_having_in(right)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn replace_aggregates(expr: &mut Expr, aliases: &[(String, Ident)]) -> Result<(), DeltaError> {
+    match expr {
+        Expr::Function(function) => {
+            let sql = function.to_string();
+            match aliases.iter().find(|(expr, _)| *expr == sql) {
+                Some((_, alias)) => {
+                    *expr = Expr::Identifier(alias.clone());
+                    Ok(())
+                }
+                None => Err(DeltaError::InvalidQuery(format!(
+                    "{} in HAVING has to be selected with an alias, e.g. SELECT {} AS n ... HAVING n > 1",
+                    sql, sql
+                ))),
+            }
+        }
+        Expr::BinaryOp { left, right, .. } => {
+            replace_aggregates(left, aliases)?;
+            replace_aggregates(right, aliases)
+        }
+        Expr::Between {
+            expr, low, high, ..
+        } => {
+            replace_aggregates(expr, aliases)?;
+            replace_aggregates(low, aliases)?;
+            replace_aggregates(high, aliases)
+        }
+        Expr::InList { expr, list, .. } => {
+            replace_aggregates(expr, aliases)?;
+            list.iter_mut()
+                .try_for_each(|item| replace_aggregates(item, aliases))
+        }
+        Expr::UnaryOp { expr, .. }
+        | Expr::Nested(expr)
+        | Expr::IsNull(expr)
+        | Expr::IsNotNull(expr)
+        | Expr::Cast { expr, .. } => replace_aggregates(expr, aliases),
+        _ => Ok(()),
+    }
+}
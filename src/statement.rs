@@ -0,0 +1,393 @@
+use crate::{
+    error::DeltaError,
+    identifier::join_qualified_name,
+    metrics::SqlResult,
+    options::CreateOptions,
+    plan::Explain,
+    sql::{describe_query, parse_statement, run_query},
+    table::DeltaTable,
+};
+use polars::prelude::*;
+use sqlparser::ast::{
+    Assignment, ColumnDef, ColumnOption, DataType as SqlType, Expr, GeneratedAs, ObjectName, Query,
+    SetExpr, Statement, TableConstraint, TableFactor, TableWithJoins, TimezoneInfo, UnaryOperator,
+    Value,
+};
+use std::io;
+
+// Run a single SQL statement against the tables under `tables/`, the ones
+// `DeltaTable::create_table` and `DeltaTable::read_table` work with:
+//
+// - CREATE TABLE [IF NOT EXISTS] with NULL, NOT NULL, PRIMARY KEY, UNIQUE
+//   and GENERATED ALWAYS AS (<expr>) column options, and a PRIMARY KEY or
+//   UNIQUE table constraint, which becomes the table's unique key
+// - INSERT INTO, with or without a column list, from VALUES or a SELECT
+// - DELETE FROM, where no WHERE clause deletes every row
+// - UPDATE ... SET ... [WHERE ...]
+// - SELECT, which can refer to any number of tables
+// - EXPLAIN of a SELECT or DELETE, see `DeltaTable::explain`
+// - SHOW TABLES, see `DeltaTable::list_tables`
+pub fn sql(statement: &str) -> Result<SqlResult, DeltaError> {
+    match parse_statement(statement)? {
+        Statement::CreateTable {
+            name,
+            columns,
+            constraints,
+            if_not_exists,
+            ..
+        } => create_table(&name, &columns, &constraints, if_not_exists),
+        Statement::Insert {
+            table_name,
+            columns,
+            source,
+            ..
+        } => insert(&table_name, &columns, source),
+        Statement::Delete {
+            from, selection, ..
+        } => {
+            let (name, predicate) = delete_target(&from, selection)?;
+            let table = DeltaTable::read_table(&name)?;
+            Ok(SqlResult::Deleted(table.delete(&predicate)?))
+        }
+        Statement::Update {
+            table,
+            assignments,
+            from: None,
+            selection,
+            ..
+        } => update(&table, &assignments, selection),
+        Statement::Query(query) => Ok(SqlResult::Rows(run_query(query, |name, scan| {
+            DeltaTable::read_table(name)?.scan_for_query(scan)
+        })?)),
+        Statement::Explain { statement, .. } => explain(*statement),
+        Statement::ShowTables {
+            db_name: None,
+            filter: None,
+            ..
+        } => show_tables(),
+        statement => Err(unsupported(&format!("statement: {}", statement))),
+    }
+}
+
+// One row per table under `tables/`, with its latest version and when it
+// was created
+fn show_tables() -> Result<SqlResult, DeltaError> {
+    let tables = match DeltaTable::list_tables("tables") {
+        Err(DeltaError::IOError(e)) if e.kind() == io::ErrorKind::NotFound => vec![],
+        result => result?,
+    };
+
+    let created = Series::new(
+        "created_time",
+        tables.iter().map(|t| t.created_time).collect::<Vec<i64>>(),
+    )
+    .cast(&DataType::Datetime(TimeUnit::Milliseconds, None))?;
+    Ok(SqlResult::Rows(DataFrame::new(vec![
+        Series::new(
+            "name",
+            tables
+                .iter()
+                .map(|t| t.name.as_str())
+                .collect::<Vec<&str>>(),
+        ),
+        Series::new(
+            "version",
+            tables.iter().map(|t| t.version).collect::<Vec<i64>>(),
+        ),
+        created,
+    ])?))
+}
+
+fn explain(statement: Statement) -> Result<SqlResult, DeltaError> {
+    match statement {
+        Statement::Query(query) => {
+            let mut tables = vec![];
+            let logical_plan = describe_query(query, |name, scan| {
+                let table = DeltaTable::read_table(name)?;
+                tables.push(table.explain_scan(name, scan)?);
+                table.scan_for_query(scan)
+            })?;
+            Ok(SqlResult::Explained(Explain {
+                tables,
+                logical_plan,
+            }))
+        }
+        Statement::Delete {
+            from, selection, ..
+        } => {
+            let (name, predicate) = delete_target(&from, selection)?;
+            let table = DeltaTable::read_table(&name)?;
+            Ok(SqlResult::Explained(
+                table.explain_delete(&name, &predicate)?,
+            ))
+        }
+        statement => Err(unsupported(&format!("EXPLAIN of: {}", statement))),
+    }
+}
+
+// The table a DELETE is from and its predicate, which is TRUE without a
+// WHERE clause
+pub(crate) fn delete_target(
+    from: &[TableWithJoins],
+    selection: Option<Expr>,
+) -> Result<(String, String), DeltaError> {
+    let [from] = from else {
+        return Err(unsupported("DELETE from more than one table"));
+    };
+    let predicate = selection.map_or("TRUE".to_owned(), |expr| expr.to_string());
+    Ok((table_name(from)?, predicate))
+}
+
+fn create_table(
+    name: &ObjectName,
+    columns: &[ColumnDef],
+    constraints: &[TableConstraint],
+    if_not_exists: bool,
+) -> Result<SqlResult, DeltaError> {
+    let mut schema = vec![];
+    let mut unique_key = vec![];
+    for column in columns {
+        let mut typ = column_type(&column.data_type)?.to_owned();
+        for option in &column.options {
+            match &option.option {
+                ColumnOption::Null => typ.push_str(" NULL"),
+                ColumnOption::NotNull => typ.push_str(" NOT NULL"),
+                ColumnOption::Unique { .. } => unique_key.push(column.name.value.clone()),
+                ColumnOption::Generated {
+                    generated_as: GeneratedAs::ExpStored,
+                    generation_expr: Some(expr),
+                    ..
+                } => typ.push_str(&format!(" GENERATED ALWAYS AS ({})", expr)),
+                option => return Err(unsupported(&format!("column option: {}", option))),
+            }
+        }
+        schema.push((column.name.value.clone(), typ));
+    }
+    for constraint in constraints {
+        match constraint {
+            TableConstraint::Unique { columns, .. } if unique_key.is_empty() => {
+                unique_key = columns.iter().map(|c| c.value.clone()).collect();
+            }
+            constraint => return Err(unsupported(&format!("table constraint: {}", constraint))),
+        }
+    }
+
+    let path = format!("tables/{}", object_name(name));
+    let schema = schema
+        .iter()
+        .map(|(name, typ)| (name.as_str(), typ.as_str()))
+        .collect();
+    let options = CreateOptions {
+        unique_key,
+        ..Default::default()
+    };
+    match DeltaTable::create_with(&path, schema, options) {
+        Err(DeltaError::TableAlreadyExists) if if_not_exists => Ok(SqlResult::Created),
+        result => result.map(|_| SqlResult::Created),
+    }
+}
+
+// The type `DeltaTableType::from_sql_type` takes for a column declared
+// as `data_type`, accepting the usual SQL spellings, e.g. INTEGER or
+// VARCHAR(n). Lengths aren't enforced, as text columns have none.
+fn column_type(data_type: &SqlType) -> Result<&'static str, DeltaError> {
+    match data_type {
+        SqlType::Text
+        | SqlType::String(_)
+        | SqlType::Varchar(_)
+        | SqlType::CharacterVarying(_)
+        | SqlType::CharVarying(_)
+        | SqlType::Nvarchar(_)
+        | SqlType::Char(_)
+        | SqlType::Character(_) => Ok("TEXT"),
+        SqlType::BigInt(_) | SqlType::Int8(_) | SqlType::Int64 => Ok("BIGINT"),
+        SqlType::Int(_) | SqlType::Integer(_) | SqlType::Int4(_) => Ok("INT"),
+        SqlType::SmallInt(_) | SqlType::Int2(_) => Ok("SMALLINT"),
+        SqlType::TinyInt(_) => Ok("TINYINT"),
+        // FLOAT(p) is double precision past 24 bits, like in Postgres
+        SqlType::Float(Some(precision)) if *precision > 24 => Ok("DOUBLE"),
+        SqlType::Float(_) | SqlType::Real | SqlType::Float4 => Ok("FLOAT"),
+        SqlType::Double | SqlType::DoublePrecision | SqlType::Float8 | SqlType::Float64 => {
+            Ok("DOUBLE")
+        }
+        SqlType::Bool | SqlType::Boolean => Ok("BOOL"),
+        SqlType::Date => Ok("DATE"),
+        SqlType::Timestamp(_, TimezoneInfo::None) => Ok("TIMESTAMP"),
+        data_type => Err(unsupported(&format!("column type: {}", data_type))),
+    }
+}
+
+fn insert(
+    name: &ObjectName,
+    columns: &[sqlparser::ast::Ident],
+    source: Box<Query>,
+) -> Result<SqlResult, DeltaError> {
+    let table = DeltaTable::read_table(&object_name(name))?;
+
+    let SetExpr::Values(values) = source.body.as_ref() else {
+        if !columns.is_empty() {
+            return Err(unsupported("a column list with INSERT ... SELECT"));
+        }
+        let rows = run_query(source, |name, scan| {
+            DeltaTable::read_table(name)?.scan_for_query(scan)
+        })?;
+        return Ok(SqlResult::Inserted(table.insert_df(rows)?));
+    };
+
+    let mut rows = values
+        .rows
+        .iter()
+        .map(|row| row.iter().map(literal).collect())
+        .collect::<Result<Vec<Vec<Option<String>>>, DeltaError>>()?;
+
+    // With a column list, rows are spread out over every column, with null
+    // for the ones left out
+    if !columns.is_empty() {
+        let fields = table.metadata().schema()?.fields().clone();
+        let positions = columns
+            .iter()
+            .map(|column| {
+                fields
+                    .iter()
+                    .position(|field| field.name == column.value)
+                    .ok_or_else(|| {
+                        DeltaError::SchemaMismatch(format!("unknown column '{}'", column.value))
+                    })
+            })
+            .collect::<Result<Vec<usize>, DeltaError>>()?;
+        for row in rows.iter_mut() {
+            if row.len() != positions.len() {
+                return Err(width_mismatch(positions.len(), row.len()));
+            }
+            let mut full = vec![None; fields.len()];
+            for (value, position) in row.drain(..).zip(&positions) {
+                full[*position] = value;
+            }
+            *row = full;
+        }
+    } else {
+        let schema = table.metadata().schema()?;
+        let width = rows.first().map_or(0, Vec::len);
+        let expected = schema.positional_fields(width).len();
+        if let Some(row) = rows.iter().find(|row| row.len() != expected) {
+            return Err(width_mismatch(expected, row.len()));
+        }
+    }
+
+    let rows = rows
+        .iter()
+        .map(|row| row.iter().map(Option::as_deref).collect())
+        .collect::<Vec<Vec<Option<&str>>>>();
+    Ok(SqlResult::Inserted(table.insert(rows)?))
+}
+
+fn update(
+    table: &TableWithJoins,
+    assignments: &[Assignment],
+    selection: Option<Expr>,
+) -> Result<SqlResult, DeltaError> {
+    let table = DeltaTable::read_table(&table_name(table)?)?;
+    let assignments = assignments
+        .iter()
+        .map(|assignment| {
+            let [column] = assignment.id.as_slice() else {
+                return Err(unsupported("qualified columns in SET"));
+            };
+            Ok((column.value.clone(), assignment.value.to_string()))
+        })
+        .collect::<Result<Vec<(String, String)>, DeltaError>>()?;
+    let assignments = assignments
+        .iter()
+        .map(|(column, value)| (column.as_str(), value.as_str()))
+        .collect::<Vec<(&str, &str)>>();
+
+    let predicate = selection.map_or("TRUE".to_owned(), |expr| expr.to_string());
+    Ok(SqlResult::Updated(table.update(&assignments, &predicate)?))
+}
+
+// The value of a literal in VALUES as text, which is how `insert` takes
+// them
+fn literal(expr: &Expr) -> Result<Option<String>, DeltaError> {
+    match expr {
+        Expr::Value(Value::Null) => Ok(None),
+        Expr::Value(Value::Number(n, _)) => Ok(Some(n.clone())),
+        Expr::Value(Value::SingleQuotedString(s)) => Ok(Some(s.clone())),
+        Expr::Value(Value::Boolean(b)) => Ok(Some(b.to_string())),
+        Expr::UnaryOp {
+            op: UnaryOperator::Minus,
+            expr,
+        } => match expr.as_ref() {
+            Expr::Value(Value::Number(n, _)) => Ok(Some(format!("-{}", n))),
+            _ => Err(unsupported(&format!("value: {}", expr))),
+        },
+        expr => Err(unsupported(&format!("value: {}", expr))),
+    }
+}
+
+fn table_name(table: &TableWithJoins) -> Result<String, DeltaError> {
+    match &table.relation {
+        TableFactor::Table { name, .. } if table.joins.is_empty() => Ok(object_name(name)),
+        _ => Err(unsupported("joins in DELETE or UPDATE")),
+    }
+}
+
+fn object_name(name: &ObjectName) -> String {
+    join_qualified_name(
+        &name
+            .0
+            .iter()
+            .map(|part| part.value.clone())
+            .collect::<Vec<String>>(),
+    )
+}
+
+fn width_mismatch(expected: usize, got: usize) -> DeltaError {
+    DeltaError::SchemaMismatch(format!("expected {} values, got {}", expected, got))
+}
+
+fn unsupported(what: &str) -> DeltaError {
+    DeltaError::InvalidQuery(format!("unsupported {}", what))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempTable;
+    use std::env;
+
+    #[test]
+    fn statements_are_dispatched_to_the_named_table() {
+        // Statements work on `tables/` in the working directory, which no
+        // other test relies on
+        let temp = TempTable::new(vec![("id", "int")]).unwrap();
+        env::set_current_dir(temp.path().strip_suffix("/table").unwrap()).unwrap();
+
+        let created = sql("CREATE TABLE people (id INT PRIMARY KEY, name TEXT NULL)").unwrap();
+        assert!(matches!(created, SqlResult::Created));
+        match sql("INSERT INTO people VALUES (1, 'a'), (2, NULL), (3, 'c')").unwrap() {
+            SqlResult::Inserted(result) => assert_eq!(result.rows_written, 3),
+            other => panic!("expected an insert, got {:?}", other),
+        }
+        assert!(matches!(
+            sql("INSERT INTO people (id) VALUES (1)"),
+            Err(DeltaError::UniqueKeyViolation { .. })
+        ));
+        match sql("UPDATE people SET name = 'b' WHERE name IS NULL").unwrap() {
+            SqlResult::Updated(metrics) => assert_eq!(metrics.rows_updated, 1),
+            other => panic!("expected an update, got {:?}", other),
+        }
+        match sql("DELETE FROM people WHERE id = 3").unwrap() {
+            SqlResult::Deleted(metrics) => assert_eq!(metrics.rows_deleted, 1),
+            other => panic!("expected a delete, got {:?}", other),
+        }
+
+        let SqlResult::Rows(rows) = sql("SELECT id, name FROM people ORDER BY id").unwrap() else {
+            panic!("expected rows");
+        };
+        assert_eq!(rows, df!("id" => [1, 2], "name" => ["a", "b"]).unwrap());
+        assert!(matches!(
+            sql("DROP TABLE people"),
+            Err(DeltaError::InvalidQuery(_))
+        ));
+    }
+}
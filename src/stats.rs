@@ -0,0 +1,436 @@
+use crate::{
+    partition::{self, PartitionValues},
+    schema::{DeltaTableSchema, DeltaTableType},
+};
+use polars::export::chrono::{Datelike, NaiveDate, NaiveDateTime};
+use polars::prelude::*;
+use polars_parquet::read::{statistics, RowGroupMetaData};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{cmp::Ordering, collections::HashMap};
+
+// Days between 0001-01-01 (chrono's day 1) and the unix epoch
+const UNIX_EPOCH_DAY: i32 = 719_163;
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.6fZ";
+
+// Per-file column statistics, stored in the `stats` field of Add actions.
+// https://github.com/delta-io/delta/blob/master/PROTOCOL.md#per-file-statistics
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DeltaFileStats {
+    pub num_records: u64,
+    #[serde(default)]
+    pub min_values: HashMap<String, Value>,
+    #[serde(default)]
+    pub max_values: HashMap<String, Value>,
+    #[serde(default)]
+    pub null_count: HashMap<String, u64>,
+}
+
+impl DeltaFileStats {
+    pub fn from_frame(df: &DataFrame) -> Self {
+        let mut stats = DeltaFileStats {
+            num_records: df.height() as u64,
+            ..Default::default()
+        };
+
+        for series in df.get_columns() {
+            let name = series.name().to_owned();
+            stats
+                .null_count
+                .insert(name.clone(), series.null_count() as u64);
+
+            // NaN is left out of min/max. Since every comparison with NaN
+            // is false it can't affect which files a predicate matches.
+            let values = match series.dtype() {
+                DataType::Float32 | DataType::Float64 => match series.is_not_nan() {
+                    Ok(mask) => series.filter(&mask).unwrap_or_else(|_| series.clone()),
+                    Err(_) => series.clone(),
+                },
+                _ => series.clone(),
+            };
+
+            if let Some(min) = values.min_as_series().get(0).ok().and_then(to_stat) {
+                stats.min_values.insert(name.clone(), min);
+            }
+            if let Some(max) = values.max_as_series().get(0).ok().and_then(to_stat) {
+                stats.max_values.insert(name, max);
+            }
+        }
+
+        stats
+    }
+
+    // Stats of a row group, from the min/max and null counts the writer put
+    // in the file's footer. Columns the writer didn't keep stats for are
+    // left out.
+    pub fn from_row_group(group: &RowGroupMetaData, schema: &ArrowSchema) -> PolarsResult<Self> {
+        let mut stats = DeltaFileStats {
+            num_records: group.num_rows() as u64,
+            ..Default::default()
+        };
+
+        for field in &schema.fields {
+            let column = statistics::deserialize(field, group)?;
+            let name = field.name.clone();
+
+            let null_count = Series::try_from((name.as_str(), column.null_count))?;
+            if let Ok(AnyValue::UInt64(n)) = null_count.get(0) {
+                stats.null_count.insert(name.clone(), n);
+            }
+            let min = Series::try_from((name.as_str(), column.min_value))?;
+            if let Some(min) = min.get(0).ok().and_then(to_stat) {
+                stats.min_values.insert(name.clone(), min);
+            }
+            let max = Series::try_from((name.as_str(), column.max_value))?;
+            if let Some(max) = max.get(0).ok().and_then(to_stat) {
+                stats.max_values.insert(name, max);
+            }
+        }
+
+        Ok(stats)
+    }
+
+    // Stats covering the rows of both, e.g. of a file from those of its row
+    // groups. A column only keeps a min, max or null count both have.
+    pub fn merge(self, other: &DeltaFileStats) -> Self {
+        let bound =
+            |values: HashMap<String, Value>, others: &HashMap<String, Value>, keep: Ordering| {
+                values
+                    .into_iter()
+                    .filter_map(|(name, value)| {
+                        let other = others.get(&name)?;
+                        match compare(&value, other)? {
+                            ordering if ordering == keep => Some((name, value)),
+                            Ordering::Equal => Some((name, value)),
+                            _ => Some((name, other.clone())),
+                        }
+                    })
+                    .collect()
+            };
+
+        DeltaFileStats {
+            num_records: self.num_records + other.num_records,
+            min_values: bound(self.min_values, &other.min_values, Ordering::Less),
+            max_values: bound(self.max_values, &other.max_values, Ordering::Greater),
+            null_count: self
+                .null_count
+                .into_iter()
+                .filter_map(|(name, n)| Some((name.clone(), n + other.null_count.get(&name)?)))
+                .collect(),
+        }
+    }
+
+    // Partition columns aren't stored in data files, so they have no stats.
+    // Every row in the file has the same value for them though, which is
+    // both the min and the max.
+    pub fn with_partition_values(
+        mut self,
+        values: &PartitionValues,
+        schema: &DeltaTableSchema,
+    ) -> Self {
+        for (name, value) in values {
+            let Some(field) = schema.fields().iter().find(|f| &f.name == name) else {
+                continue;
+            };
+
+            match value {
+                None => {
+                    self.null_count.insert(name.clone(), self.num_records);
+                }
+                Some(value) => {
+                    self.null_count.insert(name.clone(), 0);
+                    if let Some(value) = partition::to_stat(value, &field.typ) {
+                        self.min_values.insert(name.clone(), value.clone());
+                        self.max_values.insert(name.clone(), value);
+                    }
+                }
+            }
+        }
+
+        self
+    }
+
+    // Conservatively decide whether any row in the file could satisfy
+    // `predicate`. Anything we can't reason about is assumed to match, so
+    // a `false` here is always safe to act on.
+    pub fn can_match(&self, predicate: &Expr, schema: &DeltaTableSchema) -> bool {
+        match predicate {
+            Expr::Alias(expr, _) => self.can_match(expr, schema),
+            Expr::BinaryExpr { left, op, right } => match op {
+                Operator::And => self.can_match(left, schema) && self.can_match(right, schema),
+                Operator::Or => self.can_match(left, schema) || self.can_match(right, schema),
+                _ => self.comparison_can_match(left, *op, right, schema),
+            },
+            Expr::Function {
+                input,
+                function: FunctionExpr::Boolean(function),
+                ..
+            } => match (function, input.as_slice()) {
+                (BooleanFunction::IsNull, [Expr::Column(name)]) => {
+                    self.null_count.get(name.as_ref()).is_none_or(|n| *n > 0)
+                }
+                (BooleanFunction::IsNotNull, [Expr::Column(name)]) => !self.all_null(name),
+                (BooleanFunction::IsIn, [column, Expr::Literal(LiteralValue::Series(list))]) => {
+                    self.in_list_can_match(column, list, schema)
+                }
+                _ => true,
+            },
+            _ => true,
+        }
+    }
+
+    fn comparison_can_match(
+        &self,
+        left: &Expr,
+        op: Operator,
+        right: &Expr,
+        schema: &DeltaTableSchema,
+    ) -> bool {
+        let (name, op, literal) = match (unwrap_cast(left), unwrap_cast(right)) {
+            (Expr::Column(name), Expr::Literal(literal)) => (name, op, literal),
+            (Expr::Literal(literal), Expr::Column(name)) => (name, flip(op), literal),
+            _ => return true,
+        };
+
+        let Some(field) = schema.fields().iter().find(|f| f.name == name.as_ref()) else {
+            return true;
+        };
+
+        // Null never compares equal (or unequal) to anything, so a column
+        // that is entirely null can't satisfy a plain comparison.
+        match op {
+            Operator::EqValidity | Operator::NotEqValidity => {}
+            _ if self.all_null(name) => return false,
+            _ => {}
+        }
+
+        let Some(value) = Scalar::from_literal(literal, &field.typ) else {
+            return true;
+        };
+        let min = self
+            .min_values
+            .get(name.as_ref())
+            .and_then(|v| Scalar::from_stat(v, &field.typ));
+        let max = self
+            .max_values
+            .get(name.as_ref())
+            .and_then(|v| Scalar::from_stat(v, &field.typ));
+
+        match op {
+            Operator::Eq | Operator::EqValidity => {
+                min.is_none_or(|min| min <= value) && max.is_none_or(|max| value <= max)
+            }
+            Operator::NotEq => {
+                !matches!((min, max), (Some(min), Some(max)) if min == value && max == value)
+            }
+            Operator::Lt => min.is_none_or(|min| min < value),
+            Operator::LtEq => min.is_none_or(|min| min <= value),
+            Operator::Gt => max.is_none_or(|max| max > value),
+            Operator::GtEq => max.is_none_or(|max| max >= value),
+            _ => true,
+        }
+    }
+
+    // A file can only hold one of the list's values if the range between
+    // the list's smallest and largest value overlaps the file's. That's
+    // checked once, rather than for each value, so long lists stay cheap.
+    fn in_list_can_match(&self, column: &Expr, list: &Series, schema: &DeltaTableSchema) -> bool {
+        let Expr::Column(name) = unwrap_cast(column) else {
+            return true;
+        };
+        let Some(field) = schema.fields().iter().find(|f| f.name == name.as_ref()) else {
+            return true;
+        };
+
+        // A null in the list matches null rows
+        if list.null_count() > 0 {
+            return true;
+        }
+        if self.all_null(name) {
+            return false;
+        }
+
+        let bound = |value: PolarsResult<AnyValue>| {
+            value
+                .ok()
+                .and_then(to_stat)
+                .and_then(|value| Scalar::from_stat(&value, &field.typ))
+        };
+        let (Some(low), Some(high)) = (
+            bound(list.min_as_series().get(0)),
+            bound(list.max_as_series().get(0)),
+        ) else {
+            return true;
+        };
+        let min = self
+            .min_values
+            .get(name.as_ref())
+            .and_then(|v| Scalar::from_stat(v, &field.typ));
+        let max = self
+            .max_values
+            .get(name.as_ref())
+            .and_then(|v| Scalar::from_stat(v, &field.typ));
+
+        min.is_none_or(|min| min <= high) && max.is_none_or(|max| low <= max)
+    }
+
+    fn all_null(&self, name: &str) -> bool {
+        self.num_records > 0 && self.null_count.get(name) == Some(&self.num_records)
+    }
+}
+
+// A stats value or literal, normalized so the two can be compared. Only
+// values of the same variant are ever compared with each other, since both
+// sides are converted using the column's type.
+#[derive(PartialEq, PartialOrd)]
+enum Scalar {
+    Number(f64),
+    String(String),
+    Date(i32),
+    Timestamp(i64),
+}
+
+impl Scalar {
+    fn from_stat(value: &Value, typ: &DeltaTableType) -> Option<Scalar> {
+        match (typ, value) {
+            (DeltaTableType::String, Value::String(s)) => Some(Scalar::String(s.clone())),
+            (DeltaTableType::Date, Value::String(s)) => parse_date(s).map(Scalar::Date),
+            (DeltaTableType::Timestamp, Value::String(s)) => {
+                parse_timestamp(s).map(Scalar::Timestamp)
+            }
+            (
+                DeltaTableType::Long
+                | DeltaTableType::Integer
+                | DeltaTableType::Short
+                | DeltaTableType::Byte
+                | DeltaTableType::Float
+                | DeltaTableType::Double,
+                Value::Number(n),
+            ) => n.as_f64().map(Scalar::Number),
+            _ => None,
+        }
+    }
+
+    fn from_literal(literal: &LiteralValue, typ: &DeltaTableType) -> Option<Scalar> {
+        match typ {
+            DeltaTableType::String => match literal {
+                LiteralValue::Utf8(s) => Some(Scalar::String(s.clone())),
+                _ => None,
+            },
+            DeltaTableType::Date => match literal {
+                LiteralValue::Date(days) => Some(Scalar::Date(*days)),
+                LiteralValue::Utf8(s) => parse_date(s).map(Scalar::Date),
+                _ => None,
+            },
+            DeltaTableType::Timestamp => match literal {
+                LiteralValue::DateTime(v, unit, _) => Some(Scalar::Timestamp(to_micros(*v, *unit))),
+                LiteralValue::Date(days) => Some(Scalar::Timestamp(*days as i64 * 86_400_000_000)),
+                LiteralValue::Utf8(s) => parse_timestamp(s).map(Scalar::Timestamp),
+                _ => None,
+            },
+            DeltaTableType::Boolean => None,
+            _ => match literal.to_anyvalue()? {
+                AnyValue::Float32(v) => Some(Scalar::Number(v as f64)),
+                AnyValue::Float64(v) => Some(Scalar::Number(v)),
+                v @ (AnyValue::Int8(_)
+                | AnyValue::Int16(_)
+                | AnyValue::Int32(_)
+                | AnyValue::Int64(_)
+                | AnyValue::UInt8(_)
+                | AnyValue::UInt16(_)
+                | AnyValue::UInt32(_)
+                | AnyValue::UInt64(_)) => v.extract::<f64>().map(Scalar::Number),
+                _ => None,
+            },
+        }
+    }
+}
+
+fn to_stat(value: AnyValue) -> Option<Value> {
+    match value {
+        AnyValue::Int8(v) => Some(v.into()),
+        AnyValue::Int16(v) => Some(v.into()),
+        AnyValue::Int32(v) => Some(v.into()),
+        AnyValue::Int64(v) => Some(v.into()),
+        AnyValue::UInt8(v) => Some(v.into()),
+        AnyValue::UInt16(v) => Some(v.into()),
+        AnyValue::UInt32(v) => Some(v.into()),
+        AnyValue::UInt64(v) => Some(v.into()),
+        // JSON can't represent NaN or ±Inf, so those are left out
+        AnyValue::Float32(v) => serde_json::Number::from_f64(v as f64).map(Value::Number),
+        AnyValue::Float64(v) => serde_json::Number::from_f64(v).map(Value::Number),
+        AnyValue::Utf8(v) => Some(Value::String(v.to_owned())),
+        AnyValue::Date(days) => format_date(days).map(Value::String),
+        AnyValue::Datetime(v, unit, _) => format_timestamp(to_micros(v, unit)).map(Value::String),
+        _ => None,
+    }
+}
+
+// Orders two stats of the same column. Dates and timestamps are stored in
+// formats that sort as strings.
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => match (a.as_i64(), b.as_i64()) {
+            (Some(a), Some(b)) => Some(a.cmp(&b)),
+            _ => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        },
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+fn unwrap_cast(expr: &Expr) -> &Expr {
+    match expr {
+        Expr::Cast { expr, .. } => unwrap_cast(expr),
+        _ => expr,
+    }
+}
+
+// `lit op col` is the same as `col flip(op) lit`
+fn flip(op: Operator) -> Operator {
+    match op {
+        Operator::Lt => Operator::Gt,
+        Operator::LtEq => Operator::GtEq,
+        Operator::Gt => Operator::Lt,
+        Operator::GtEq => Operator::LtEq,
+        op => op,
+    }
+}
+
+fn to_micros(v: i64, unit: TimeUnit) -> i64 {
+    match unit {
+        TimeUnit::Nanoseconds => v / 1_000,
+        TimeUnit::Microseconds => v,
+        TimeUnit::Milliseconds => v * 1_000,
+    }
+}
+
+fn format_date(days: i32) -> Option<String> {
+    NaiveDate::from_num_days_from_ce_opt(days + UNIX_EPOCH_DAY)
+        .map(|date| date.format("%Y-%m-%d").to_string())
+}
+
+fn parse_date(s: &str) -> Option<i32> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .ok()
+        .map(|date| date.num_days_from_ce() - UNIX_EPOCH_DAY)
+}
+
+fn format_timestamp(micros: i64) -> Option<String> {
+    NaiveDateTime::from_timestamp_micros(micros).map(|ts| ts.format(TIMESTAMP_FORMAT).to_string())
+}
+
+fn parse_timestamp(s: &str) -> Option<i64> {
+    let s = s.trim_end_matches('Z');
+    ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+        .map(|ts| ts.timestamp_micros())
+}
@@ -0,0 +1,117 @@
+// Where a table's log is kept. Everything about a table besides its rows is
+// in the log, so replaying it, planning scans from the files' stats,
+// committing and checkpointing only go through this trait. A host without
+// a local filesystem, e.g. one fetching the log over HTTP, can supply its
+// own and open tables with `DeltaTable::open_with_log_store`. Data files
+// are still read and written through the filesystem, so the crate as a
+// whole doesn't build for targets without one, like wasm32.
+//
+// Files are named relative to the log directory, e.g.
+// `00000000000000000000.json`.
+
+use crate::error::DeltaError;
+use std::{fs, io, path::PathBuf, time::SystemTime};
+use uuid::Uuid;
+
+#[cfg(feature = "testing")]
+use crate::testing::{self, FaultPoint};
+
+pub trait LogStore: Send + Sync {
+    // Identifies where the log is, e.g. its URL. Handles whose logs are in
+    // the same place share cached snapshots, see `cache`.
+    fn location(&self) -> String;
+
+    // Names of the files in the log, in any order
+    fn list(&self) -> Result<Vec<String>, DeltaError>;
+
+    // The contents of a file. A missing file is an `io::ErrorKind::NotFound`
+    // IO error.
+    fn read(&self, name: &str) -> Result<Vec<u8>, DeltaError>;
+
+    // When a file was written
+    fn modified(&self, name: &str) -> Result<SystemTime, DeltaError>;
+
+    // Write a file unless one with its name already exists, returning
+    // whether it was written. Committing relies on exactly one of several
+    // writers racing for a name succeeding, and readers must never see a
+    // partially written file.
+    fn put_if_absent(&self, name: &str, contents: &[u8]) -> Result<bool, DeltaError>;
+
+    // Write a file, replacing the one with its name if there is one, e.g.
+    // `_last_checkpoint`. Readers must see either the old or the new
+    // contents, never a partially written file.
+    fn put(&self, name: &str, contents: &[u8]) -> Result<(), DeltaError>;
+}
+
+// A log in a local directory
+pub struct LocalLogStore {
+    dir: PathBuf,
+}
+
+impl LocalLogStore {
+    pub fn new(dir: impl Into<PathBuf>) -> LocalLogStore {
+        LocalLogStore { dir: dir.into() }
+    }
+}
+
+impl LogStore for LocalLogStore {
+    fn location(&self) -> String {
+        fs::canonicalize(&self.dir)
+            .unwrap_or_else(|_| self.dir.clone())
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn list(&self) -> Result<Vec<String>, DeltaError> {
+        let mut names = vec![];
+        for entry in fs::read_dir(&self.dir)? {
+            if let Some(name) = entry?.file_name().to_str() {
+                names.push(name.to_owned());
+            }
+        }
+        Ok(names)
+    }
+
+    fn read(&self, name: &str) -> Result<Vec<u8>, DeltaError> {
+        Ok(fs::read(self.dir.join(name))?)
+    }
+
+    fn modified(&self, name: &str) -> Result<SystemTime, DeltaError> {
+        Ok(fs::metadata(self.dir.join(name))?.modified()?)
+    }
+
+    // The file is written under a temporary name and linked into place, so
+    // a crash never leaves a partially written file behind. Linking fails
+    // when the target exists, which is what makes exactly one of several
+    // writers racing for a name win.
+    fn put_if_absent(&self, name: &str, contents: &[u8]) -> Result<bool, DeltaError> {
+        let tmp = self.dir.join(format!(".{}.{}.tmp", name, Uuid::new_v4()));
+        fs::write(&tmp, contents)?;
+
+        #[cfg(feature = "testing")]
+        testing::fail_point(
+            self.dir.parent().unwrap_or(&self.dir),
+            FaultPoint::DuringRename,
+        )?;
+
+        let result = fs::hard_link(&tmp, self.dir.join(name));
+        let _ = fs::remove_file(&tmp);
+        match result {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(false),
+            Err(e) => Err(DeltaError::IOError(e)),
+        }
+    }
+
+    // Written under a temporary name and renamed into place, which
+    // replaces the old file in one step
+    fn put(&self, name: &str, contents: &[u8]) -> Result<(), DeltaError> {
+        let tmp = self.dir.join(format!(".{}.{}.tmp", name, Uuid::new_v4()));
+        fs::write(&tmp, contents)?;
+        if let Err(e) = fs::rename(&tmp, self.dir.join(name)) {
+            let _ = fs::remove_file(&tmp);
+            return Err(DeltaError::IOError(e));
+        }
+        Ok(())
+    }
+}
@@ -2,247 +2,5460 @@
 //  [X] Create a new deltatable with fixed schema -- CREATE TABLE <TABLE_NAME> (<COLUMN_NAME> <TYPE>, ...)
 //  [X] Insert into a table -- INSERT INTO <TABLE_NAME> VALUES (<VALUE1>, <VALUE2>, ...), ...
 //  [X] Delete from table -- DELETE FROM <TABLE_NAME> WHERE expr
-//  [ ] Query a table -- SELECT expr FROM <TABLE_NAME> WHERE expr
-//  [ ] Update a table -- UPDATE <TABLE_NAME> SET col1=val1, col2=val2, ... WHERE expr
-//  [ ] SQL query parser and command line tool
+//  [X] Query a table -- SELECT expr FROM <TABLE_NAME> WHERE expr
+//  [X] Update a table -- UPDATE <TABLE_NAME> SET col1=val1, col2=val2, ... WHERE expr
+//  [X] SQL query parser and command line tool
 
 use crate::{
-    actions::Action,
+    actions::{parse_action, Action, Add, CommitInfo, Protocol, Remove, Txn},
+    batches::{Batches, PendingFile},
+    bloom::{BloomFilter, BLOOM_FILTER_TAG},
+    cache,
+    checkpoint::{complete_checkpoints, read_checkpoint, write_checkpoint},
+    checksum::{self, ChecksumWriter, CHECKSUM_TAG},
+    csv::{parse_csv, read_csv, CsvChunks, CsvLoadResult, CsvOptions, RejectedRow},
     data_file::DataFile,
+    definition::{ColumnDefinition, TableDefinition},
+    derive::DeltaSchema,
+    description::{ColumnDescription, TableDescription, TableListing},
+    diff::{self, DiffSide, TableDiff},
     error::DeltaError,
+    file_info::{FileInfo, PartitionInfo},
+    history::{HistoryEntry, OperationMetrics},
+    identifier::unquote_identifier,
+    manifest::write_symlink_manifests,
     metadata::{DeltaTableFormat, DeltaTableMetadata},
+    metrics::{
+        ArchiveMetrics, CheckpointInfo, DedupeMetrics, DeleteMetrics, DeletedFile, FilePruning,
+        FileUsage, InsertResult, ManifestInfo, MergeMetrics, OptimizeMetrics, UpdateMetrics,
+        VacuumMetrics, VerifyReport,
+    },
+    ndjson::{self, read_ndjson, NdjsonOptions},
+    options::{
+        ArchiveOptions, CompactionPolicy, CreateOptions, KeyConflict, VacuumOptions, WriteOptions,
+    },
+    partition::{self, PartitionValues},
+    plan::{Explain, PlannedFile, PruneReason, PrunedFile, RowGroupInfo, ScanPlan, TableScan},
+    policy::ReadPolicy,
+    portable,
+    predicate::{parse_expr, parse_predicate},
+    row_groups::{rewrite_row_groups, row_group_stats, Keep},
     schema::DeltaTableSchema,
+    sql::{
+        bind_params, describe_query, is_row_wise, parse_statement, run_query, QueryScan,
+        COMMIT_VERSION_COLUMN, FILE_NAME_COLUMN, ROW_INDEX_COLUMN,
+    },
+    statement::delete_target,
+    stats::DeltaFileStats,
+    storage::{LocalLogStore, LogStore},
+    value::{build_series, deserialize_rows, text_column, DeltaValue},
 };
-use polars::{prelude::*, series::Series, sql::SQLContext};
+use log::{debug, warn};
+use polars::{
+    export::{arrow::chunk::Chunk, rayon::prelude::*},
+    io::RowCount,
+    prelude::*,
+    series::Series,
+};
+use polars_core::POOL;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+use sqlparser::ast::{Query, Statement};
 use std::collections::HashMap;
-use std::{collections::HashSet, fs, time::SystemTime};
+use std::{
+    collections::{hash_map::RandomState, BTreeMap, BTreeSet, HashSet},
+    fs,
+    hash::{BuildHasher, Hasher},
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
 use uuid::Uuid;
 
+#[cfg(feature = "testing")]
+use crate::testing::{self, FaultPoint};
+
+// How many times a commit is retried after losing a race with another
+// writer, unless changed with `with_max_commit_retries`
+const DEFAULT_MAX_COMMIT_RETRIES: u32 = 10;
+
+// Recorded in every commit's commitInfo as the writer
+const ENGINE_INFO: &str = concat!("delta/", env!("CARGO_PKG_VERSION"));
+
+// Helper columns, named so they won't clash with the table's own
+const EXISTING_KEY: &str = "__delta_existing_key";
+const DEDUPE_FILE: &str = "__delta_file";
+const DEDUPE_ROW: &str = "__delta_row";
+
+// Data files smaller than this are read together, see `scan_files`
+const COALESCE_FILE_SIZE: u64 = 4 * 1024 * 1024;
+
+#[derive(Clone)]
 pub struct DeltaTable {
     metadata: DeltaTableMetadata,
     base_dir: String,
-    logs_dir: String,
+    max_commit_retries: u32,
+    write_options: WriteOptions,
+    // Whether the log is parsed leniently, see `open_lenient`
+    lenient: bool,
+    read_policy: Option<Arc<dyn ReadPolicy>>,
+    // Rows outside of it are invisible to this handle, see `with_row_filter`
+    row_filter: Option<Expr>,
+    // Whether reads are recorded for `file_usage`, see `with_usage_tracking`
+    track_usage: bool,
+    // The version every read through this handle sees, see `at_version`
+    pinned_version: Option<i64>,
+    log_store: Arc<dyn LogStore>,
 }
 
 impl DeltaTable {
     pub fn read_table(name: &str) -> Result<DeltaTable, DeltaError> {
-        let base_dir = format!("tables/{}", name);
-        let logs_dir = format!("tables/{}/_delta_log", name);
-
-        let contents = fs::read_to_string(format!("{}/{}", logs_dir, DeltaTable::log_file(0)))?;
-        if let Ok(Action::Metadata(metadata)) = serde_json::from_str(&contents) {
-            return Ok(DeltaTable {
-                metadata,
-                base_dir,
-                logs_dir,
-            });
+        DeltaTable::open(&format!("tables/{}", DeltaTable::table_name(name)?))
+    }
+
+    // The table named `name` as of `version`, see `at_version`
+    pub fn read_table_at_version(name: &str, version: i64) -> Result<DeltaTable, DeltaError> {
+        DeltaTable::read_table(name)?.at_version(version)
+    }
+
+    // Open the table stored at `path`, rather than looking it up by name
+    // under `tables/`.
+    pub fn open(path: &str) -> Result<DeltaTable, DeltaError> {
+        let logs_dir = format!("{}/_delta_log", path.trim_end_matches('/'));
+        DeltaTable::open_with(path, Arc::new(LocalLogStore::new(logs_dir)), false)
+    }
+
+    // Open the table whose data files are under `path` and whose log is
+    // kept in `log_store`, see `storage`
+    pub fn open_with_log_store(
+        path: &str,
+        log_store: impl LogStore + 'static,
+    ) -> Result<DeltaTable, DeltaError> {
+        DeltaTable::open_with(path, Arc::new(log_store), false)
+    }
+
+    // Open a table written by another or an older Delta writer. Its log is
+    // parsed leniently, see `parse_action`: variant spellings of action and
+    // field names are accepted, missing legacy fields are defaulted and
+    // unknown actions are skipped, as is a commitInfo that doesn't parse.
+    // The first commit can hold other actions besides the table's metadata.
+    pub fn open_lenient(path: &str) -> Result<DeltaTable, DeltaError> {
+        let logs_dir = format!("{}/_delta_log", path.trim_end_matches('/'));
+        DeltaTable::open_with(path, Arc::new(LocalLogStore::new(logs_dir)), true)
+    }
+
+    fn open_with(
+        path: &str,
+        log_store: Arc<dyn LogStore>,
+        lenient: bool,
+    ) -> Result<DeltaTable, DeltaError> {
+        // The first commit holds the table's metadata, and its protocol
+        // unless it was created before we wrote one, see `first_actions`
+        let contents = DeltaTable::first_actions(&*log_store)?;
+        let mut metadata = None;
+        let mut protocol = Protocol::default();
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            match DeltaTable::parse_commit_action(0, line, lenient) {
+                Ok(Some(Action::Metadata(m))) => metadata = Some(m),
+                Ok(Some(Action::Protocol(p))) => protocol = p,
+                Ok(Some(Action::CommitInfo(_) | Action::Txn(_)) | None) => {}
+                Ok(Some(_)) if lenient => {}
+                Err(e) if lenient => return Err(e),
+                Err(e @ DeltaError::CorruptMetadata { .. }) => return Err(e),
+                _ => return Err(DeltaError::InvalidTable),
+            }
         }
+        let Some(metadata) = metadata else {
+            return Err(DeltaError::InvalidTable);
+        };
+        metadata.check_schema(0)?;
+        protocol.check_readable()?;
+
+        let mut table = DeltaTable::new(metadata, path, log_store);
+        table.lenient = lenient;
+        Ok(table)
+    }
 
-        return Err(DeltaError::InvalidTable);
+    // A handle with the default settings
+    fn new(metadata: DeltaTableMetadata, path: &str, log_store: Arc<dyn LogStore>) -> DeltaTable {
+        let base_dir = path.trim_end_matches('/').to_owned();
+        DeltaTable {
+            metadata,
+            base_dir,
+            max_commit_retries: DEFAULT_MAX_COMMIT_RETRIES,
+            write_options: WriteOptions::default(),
+            lenient: false,
+            read_policy: None,
+            row_filter: None,
+            track_usage: false,
+            pinned_version: None,
+            log_store,
+        }
     }
 
     pub fn create_table(name: &str, schema: Vec<(&str, &str)>) -> Result<DeltaTable, DeltaError> {
+        DeltaTable::create(&format!("tables/{}", DeltaTable::table_name(name)?), schema)
+    }
+
+    // Create a table whose schema is generated from `T`'s fields, e.g. by
+    // `#[derive(DeltaSchema)]`
+    pub fn create_table_for<T: DeltaSchema>(name: &str) -> Result<DeltaTable, DeltaError> {
+        DeltaTable::create_table(name, T::delta_schema())
+    }
+
+    pub fn create_for<T: DeltaSchema>(path: &str) -> Result<DeltaTable, DeltaError> {
+        DeltaTable::create(path, T::delta_schema())
+    }
+
+    // Create a table at `path`. The table is named after the last component
+    // of the path, and missing parent directories are created.
+    pub fn create(path: &str, schema: Vec<(&str, &str)>) -> Result<DeltaTable, DeltaError> {
+        DeltaTable::create_partitioned(path, schema, vec![])
+    }
+
+    // Like `create`, but data files are split up by the values of
+    // `partition_by` and written to a directory per partition. Partition
+    // columns aren't stored in the data files themselves.
+    pub fn create_partitioned(
+        path: &str,
+        schema: Vec<(&str, &str)>,
+        partition_by: Vec<&str>,
+    ) -> Result<DeltaTable, DeltaError> {
+        DeltaTable::create_with(
+            path,
+            schema,
+            CreateOptions {
+                partition_by: partition_by.into_iter().map(str::to_owned).collect(),
+                ..Default::default()
+            },
+        )
+    }
+
+    // Like `create`, with the table set up by `options`, e.g. partitioned
+    // or with a unique key
+    pub fn create_with(
+        path: &str,
+        schema: Vec<(&str, &str)>,
+        options: CreateOptions,
+    ) -> Result<DeltaTable, DeltaError> {
+        let base_dir = path.trim_end_matches('/').to_owned();
+        let name = match base_dir.rsplit_once('/') {
+            Some((_, name)) => name,
+            None => &base_dir,
+        };
+        let name = DeltaTable::table_name(name)?;
+
         let schema = DeltaTableSchema::from_sql(schema)?;
         if !schema.is_valid() {
             return Err(DeltaError::InvalidTable);
         }
 
+        let unquote = |columns: Vec<String>| {
+            columns
+                .iter()
+                .map(|column| unquote_identifier(column))
+                .collect::<Vec<String>>()
+        };
         let metadata = DeltaTableMetadata::new(
             Uuid::new_v4(),
-            name.to_owned(),
+            name,
             DeltaTableFormat::new("parquet".to_owned(), HashMap::new()),
             serde_json::to_string(&schema)?,
-            vec![],
+            unquote(options.partition_by),
             HashMap::new(),
-        );
+        )
+        .with_unique_key(&unquote(options.unique_key))?
+        .with_properties(&options.properties)?;
         if !metadata.is_valid() {
             return Err(DeltaError::InvalidTable);
         }
 
-        let table = DeltaTable {
-            metadata,
-            base_dir: format!("tables/{}", name),
-            logs_dir: format!("tables/{}/_delta_log", name),
-        };
+        let logs_dir = format!("{}/_delta_log", base_dir);
+        let table = DeltaTable::new(metadata, &base_dir, Arc::new(LocalLogStore::new(logs_dir)));
 
-        // Try to create a directory for the table
-        if let Err(e) = fs::create_dir(&table.base_dir) {
-            match e.kind() {
-                std::io::ErrorKind::AlreadyExists => return Err(DeltaError::TableAlreadyExists),
-                _ => return Err(DeltaError::IOError(e)),
-            }
+        let path = Path::new(&table.base_dir);
+        if path.exists() {
+            return Err(DeltaError::TableAlreadyExists);
+        }
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
         }
 
-        // Make the logs directory
-        fs::create_dir(&table.logs_dir)?;
+        // The table is set up next to where it belongs and renamed into
+        // place once its first commit is written, so a table is never seen
+        // without its metadata, and a failed create leaves nothing behind
+        let staging =
+            path.with_file_name(format!(".{}.{}.tmp", table.metadata.name(), Uuid::new_v4()));
+        if let Err(e) = table.create_from(&staging) {
+            let _ = fs::remove_dir_all(&staging);
+            return Err(e);
+        }
 
-        // Write the first log file
-        fs::write(
-            format!("{}/{}", &table.logs_dir, table.next_log_file()?),
-            serde_json::to_string(&Action::Metadata(table.metadata.clone()))?,
-        )?;
+        Ok(table)
+    }
+
+    // Create a table at `path` from a definition, e.g. one exported from
+    // another table with `definition`. Its constraints are added after the
+    // table's first commit, each in a commit of its own.
+    pub fn create_from_definition(
+        path: &str,
+        definition: &TableDefinition,
+    ) -> Result<DeltaTable, DeltaError> {
+        let types = definition
+            .columns
+            .iter()
+            .map(ColumnDefinition::sql_type)
+            .collect::<Vec<String>>();
+        let schema = definition
+            .columns
+            .iter()
+            .zip(&types)
+            .map(|(column, typ)| (column.name.as_str(), typ.as_str()))
+            .collect();
+        let options = CreateOptions {
+            partition_by: definition.partition_by.clone(),
+            unique_key: definition.unique_key.clone(),
+            properties: definition.properties.clone().into_iter().collect(),
+        };
 
+        let table = DeltaTable::create_with(path, schema, options)?;
+        for (name, expr) in &definition.constraints {
+            table.add_constraint(name, expr)?;
+        }
         Ok(table)
     }
 
-    pub fn insert(&self, data: Vec<Vec<&str>>) -> Result<(), DeltaError> {
-        let schema: DeltaTableSchema = self.metadata.schema()?;
-        let fields = schema.fields();
-        let n_cols = fields.len();
-
-        // Bad, should fix this
-        let cols = (0..n_cols)
-            .map(|i| {
-                let s = Series::new(
-                    &fields[i].name,
-                    data.iter().map(|row| row[i]).collect::<Vec<&str>>(),
-                );
-                s.cast(&fields[i].typ.to_polars_type()).unwrap()
+    // The table's columns and settings as of its latest version, see
+    // `create_from_definition`
+    pub fn definition(&self) -> Result<TableDefinition, DeltaError> {
+        let metadata = self.snapshot()?.metadata;
+        let columns = metadata
+            .schema()?
+            .fields()
+            .iter()
+            .map(|field| ColumnDefinition {
+                name: field.name.clone(),
+                typ: field.typ.to_sql_type().to_owned(),
+                nullable: field.nullable,
+                generated_as: field.generation_expression().map(str::to_owned),
             })
-            .collect::<Vec<Series>>();
+            .collect();
 
-        let mut df = DataFrame::new(cols)?;
+        Ok(TableDefinition {
+            columns,
+            partition_by: metadata.partition_columns().clone(),
+            unique_key: metadata.unique_key(),
+            constraints: metadata
+                .constraints()
+                .into_iter()
+                .map(|(name, expr)| (name.to_owned(), expr.to_owned()))
+                .collect(),
+            properties: metadata
+                .properties()
+                .into_iter()
+                .map(|(key, value)| (key.to_owned(), value.to_owned()))
+                .collect(),
+        })
+    }
 
-        let data_file = self.write_data_file(&mut df)?;
+    // The table's columns, with their types, nullability and metadata,
+    // and its settings, as of its latest version
+    pub fn describe(&self) -> Result<TableDescription, DeltaError> {
+        let snapshot = self.snapshot()?;
+        let metadata = snapshot.metadata;
+        let partition_columns = metadata.partition_columns();
 
-        fs::write(
-            format!("{}/{}", self.logs_dir, self.next_log_file()?),
-            serde_json::to_string(&Action::Add {
-                path: data_file.name,
-                partition_values: HashMap::new(),
-                size: data_file.size,
-                modification_time: SystemTime::now()
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .unwrap()
-                    .as_millis(),
-                data_change: true,
-            })?,
+        let mut columns = vec![];
+        for field in metadata.schema()?.fields() {
+            columns.push(ColumnDescription {
+                name: field.name.clone(),
+                typ: field.typ.to_sql_type().to_owned(),
+                delta_type: match serde_json::to_value(&field.typ)? {
+                    Value::String(typ) => typ,
+                    typ => typ.to_string(),
+                },
+                nullable: field.nullable,
+                partition_column: partition_columns.contains(&field.name),
+                metadata: field.metadata().clone().into_iter().collect(),
+            });
+        }
+
+        Ok(TableDescription {
+            id: metadata.id(),
+            name: metadata.name().to_owned(),
+            version: snapshot.version,
+            columns,
+            partition_columns: partition_columns.clone(),
+            configuration: metadata.configuration().clone().into_iter().collect(),
+        })
+    }
+
+    // Every table under `root`, sorted by name. Directories are tables when
+    // they have a log `open` can read; other directories are searched for
+    // tables in turn, except hidden ones like a create's staging directory.
+    pub fn list_tables(root: &str) -> Result<Vec<TableListing>, DeltaError> {
+        let root = root.trim_end_matches('/');
+        let mut tables = vec![];
+        let mut pending = vec![String::new()];
+        while let Some(dir) = pending.pop() {
+            let path = match dir.is_empty() {
+                true => root.to_owned(),
+                false => format!("{}/{}", root, dir),
+            };
+            for entry in fs::read_dir(&path)? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if name.starts_with('.') || !entry.file_type()?.is_dir() {
+                    continue;
+                }
+                let name = match dir.is_empty() {
+                    true => name,
+                    false => format!("{}/{}", dir, name),
+                };
+
+                let path = format!("{}/{}", root, name);
+                if !Path::new(&path).join("_delta_log").is_dir() {
+                    pending.push(name);
+                    continue;
+                }
+                // Not a table after all, e.g. one whose create failed
+                let Ok(table) = DeltaTable::open(&path) else {
+                    continue;
+                };
+                tables.push(TableListing {
+                    name,
+                    version: table.next_version()? - 1,
+                    created_time: table.created_time()?,
+                    path,
+                });
+            }
+        }
+
+        tables.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(tables)
+    }
+
+    // When the earliest commit or checkpoint still in the log was written.
+    // That's the first commit until log cleanup removes it.
+    fn created_time(&self) -> Result<i64, DeltaError> {
+        let (versions, checkpoints) = DeltaTable::list_log(&*self.log_store)?;
+        let commit = versions.first();
+        let checkpoint = checkpoints
+            .iter()
+            .next()
+            .and_then(|(version, files)| Some((*version, files.first()?)));
+        let earliest = match (commit, checkpoint) {
+            (Some((version, _)), Some((checkpoint_version, file)))
+                if checkpoint_version < *version =>
+            {
+                file
+            }
+            (Some((_, log)), _) => log,
+            (None, Some((_, file))) => file,
+            (None, None) => return Err(DeltaError::InvalidTable),
+        };
+        Ok(self
+            .log_store
+            .modified(earliest)?
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as i64))
+    }
+
+    // The protocol a table is created with. CHECK constraints and generated
+    // columns need writer versions 3 and 4, so other writers enforce them
+    // too.
+    fn initial_protocol(metadata: &DeltaTableMetadata) -> Result<Protocol, DeltaError> {
+        let mut protocol = Protocol::default();
+        if !metadata.constraints().is_empty() {
+            protocol = protocol.with_writer_feature("checkConstraints")?;
+        }
+        if metadata
+            .schema()?
+            .fields()
+            .iter()
+            .any(|field| field.generation_expression().is_some())
+        {
+            protocol = protocol.with_writer_feature("generatedColumns")?;
+        }
+        Ok(protocol)
+    }
+
+    // Write the table's first commit under `staging`, then move it to the
+    // table's directory. Renaming a directory fails when the target is
+    // already a table, so when creates race exactly one of them wins.
+    fn create_from(&self, staging: &Path) -> Result<(), DeltaError> {
+        self.metadata.check_writable()?;
+        let logs_dir = format!("{}/_delta_log", staging.to_string_lossy());
+        let staged = DeltaTable {
+            base_dir: staging.to_string_lossy().into_owned(),
+            log_store: Arc::new(LocalLogStore::new(&logs_dir)),
+            ..self.clone()
+        };
+        fs::create_dir(&staged.base_dir)?;
+        fs::create_dir(&logs_dir)?;
+        staged.commit_version(
+            0,
+            &[
+                Action::CommitInfo(self.create_commit_info()?),
+                Action::Protocol(DeltaTable::initial_protocol(&self.metadata)?),
+                Action::Metadata(self.metadata.clone()),
+            ],
         )?;
 
-        Ok(())
+        let path = Path::new(&self.base_dir);
+        match fs::rename(staging, path) {
+            Ok(()) => Ok(()),
+            Err(e) if path.exists() => match e.kind() {
+                std::io::ErrorKind::AlreadyExists | std::io::ErrorKind::DirectoryNotEmpty => {
+                    Err(DeltaError::TableAlreadyExists)
+                }
+                _ => Err(DeltaError::IOError(e)),
+            },
+            Err(e) => Err(DeltaError::IOError(e)),
+        }
     }
 
-    // For now delete assumes single writer, meaning no race conditions
-    // where a new log file is added during the deletion. Should look into
-    // how to handle that long term.
-    pub fn delete(&self, expr: &str) -> Result<(), DeltaError> {
-        let query = format!("SELECT * FROM df WHERE NOT ({});", expr);
+    // When another writer commits first, a commit is retried on top of
+    // theirs as long as the two don't conflict, up to `retries` times.
+    // Zero disables retrying.
+    pub fn with_max_commit_retries(mut self, retries: u32) -> DeltaTable {
+        self.max_commit_retries = retries;
+        self
+    }
 
-        let mut created_files: Vec<DataFile> = vec![];
-        let mut deleted_files: Vec<String> = vec![];
+    // Options used by every write through this handle. Clone the table
+    // first to write with different options from the same table.
+    pub fn with_write_options(mut self, options: WriteOptions) -> DeltaTable {
+        self.write_options = options;
+        self
+    }
 
-        let files = self.get_datafiles()?;
-        for file in files {
-            let df = LazyFrame::scan_parquet(
-                format!("{}/{}", &self.base_dir, &file),
-                Default::default(),
-            )?
-            .collect()?;
+    // Masks applied to every read of the table's rows through this handle,
+    // so one table can serve restricted and privileged readers: rows read
+    // with `to_polars`, `scan`, `query`, `iter_batches` and the like go
+    // through `policy`. Writes, and the log with its per-file stats, aren't
+    // affected.
+    pub fn with_read_policy(mut self, policy: impl ReadPolicy + 'static) -> DeltaTable {
+        self.read_policy = Some(Arc::new(policy));
+        self
+    }
 
-            let original_rows = df.height();
+    // Restrict this handle to the rows matching `expr`, e.g.
+    // `tenant_id = 'acme'`, for sharing a table between tenants. Every
+    // read, query, delete and update through the handle only sees those
+    // rows, as if the predicate was AND-ed into it, and `overwrite_where`
+    // only replaces them. Rows are filtered before the read policy masks
+    // them. Calling this again narrows the filter rather than replacing
+    // it, so a restricted handle can be passed on without being widened.
+    // Inserts, the log and per-file stats aren't affected, and `dedupe`,
+    // which works on the whole table, is refused.
+    pub fn with_row_filter(mut self, expr: &str) -> Result<DeltaTable, DeltaError> {
+        let predicate = parse_predicate(expr)?;
+        self.validate_predicate(&predicate)?;
+        self.row_filter = Some(self.within_filter(predicate));
+        Ok(self)
+    }
 
-            let mut ctx = SQLContext::new();
-            ctx.register("df", df.lazy());
-            let mut updated = ctx.execute(&query)?.collect()?;
+    // Record which data files this handle reads, and when, in a sidecar
+    // next to the log, for `file_usage`. Every read, query, key check,
+    // delete and update counts once for each file it scans. Scans are
+    // counted when they're set up, whether or not their rows end up
+    // collected. The sidecar is shared by every handle tracking the table
+    // and grows until `clear_usage`.
+    pub fn with_usage_tracking(mut self) -> DeltaTable {
+        self.track_usage = true;
+        self
+    }
 
-            if updated.height() == original_rows {
-                continue; // No rows deleted
-            }
+    // Pin this handle to `version` of the table, for time travel: reads,
+    // queries, `get_datafiles` and the schema are those of the table as of
+    // that commit, however much was committed since. The log is only
+    // replayed up to it. Anything that would change the table, including
+    // vacuum and checkpoints, is refused with `PinnedVersion`.
+    pub fn at_version(mut self, version: i64) -> Result<DeltaTable, DeltaError> {
+        self.metadata = self.snapshot_at(Some(version))?.metadata;
+        self.pinned_version = Some(version);
+        Ok(self)
+    }
 
-            created_files.push(self.write_data_file(&mut updated)?);
-            deleted_files.push(file)
+    // Changing the table through a pinned handle would be based on rows
+    // that may have changed since
+    fn check_not_pinned(&self) -> Result<(), DeltaError> {
+        match self.pinned_version {
+            Some(version) => Err(DeltaError::PinnedVersion(version)),
+            None => Ok(()),
         }
+    }
 
-        let modification_time = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_millis();
-
-        let mut actions: Vec<String> = vec![];
-        for created in created_files {
-            let action = Action::Add {
-                path: created.name,
-                partition_values: HashMap::new(),
-                size: created.size,
-                modification_time,
-                data_change: true,
-            };
+    // `predicate` AND-ed with the handle's row filter, if it has one
+    fn within_filter(&self, predicate: Expr) -> Expr {
+        match &self.row_filter {
+            Some(filter) => predicate.and(filter.clone()),
+            None => predicate,
+        }
+    }
+
+    // Values are given as text, and cast to their column's type. Rows can
+    // be `&str`s, or `Option<&str>`s with `None` for a null.
+    pub fn insert<'a, V>(&self, data: Vec<Vec<V>>) -> Result<InsertResult, DeltaError>
+    where
+        V: Into<Option<&'a str>> + Copy,
+    {
+        self.append(self.text_rows(data)?)
+    }
+
+    // Like `insert`, as version `version` of application `app_id`'s writes,
+    // e.g. its job and batch number. When the table already has that
+    // version or a later one of `app_id`'s, nothing is written and the
+    // table's current version is returned, so a job that failed without
+    // knowing whether its write was committed can simply retry it. The
+    // version is committed with the rows as a txn action, see
+    // `app_version`.
+    pub fn insert_with_txn<'a, V>(
+        &self,
+        data: Vec<Vec<V>>,
+        app_id: &str,
+        version: i64,
+    ) -> Result<InsertResult, DeltaError>
+    where
+        V: Into<Option<&'a str>> + Copy,
+    {
+        let txn = DeltaTable::txn(app_id, version);
+        self.append_with_txn(self.text_rows(data)?, Some(txn))
+    }
+
+    // `insert_df` as `insert_with_txn` does `insert`
+    pub fn insert_df_with_txn(
+        &self,
+        df: DataFrame,
+        app_id: &str,
+        version: i64,
+    ) -> Result<InsertResult, DeltaError> {
+        self.metadata.schema()?.check_types(&df)?;
+        self.append_with_txn(df, Some(DeltaTable::txn(app_id, version)))
+    }
+
+    // The latest version of `app_id`'s writes in the table, see
+    // `insert_with_txn`, or `None` when it has none
+    pub fn app_version(&self, app_id: &str) -> Result<Option<i64>, DeltaError> {
+        Ok(self.snapshot()?.app_versions.get(app_id).copied())
+    }
 
-            actions.push(serde_json::to_string(&action)?);
+    fn txn(app_id: &str, version: i64) -> Txn {
+        Txn {
+            app_id: app_id.to_owned(),
+            version,
+            last_updated: Some(DeltaTable::modification_time()),
         }
+    }
 
-        for deleted in deleted_files {
-            let action = Action::Remove {
-                path: deleted,
-                data_change: true,
+    // Rows given as text, see `insert`, as a frame to append
+    fn text_rows<'a, V>(&self, data: Vec<Vec<V>>) -> Result<DataFrame, DeltaError>
+    where
+        V: Into<Option<&'a str>> + Copy,
+    {
+        let schema: DeltaTableSchema = self.metadata.schema()?;
+        let fields = schema.positional_fields(data.first().map_or(0, |row| row.len()));
+        check_row_widths(&data, fields.len())?;
+
+        // Rows are positional, so name each column after the schema field
+        // it lines up with. Other than dates and timestamps, casting
+        // happens when the frame is aligned, so numbers are checked here,
+        // while the rows still line up with `data`.
+        let cols = fields
+            .iter()
+            .enumerate()
+            .map(|(i, field)| {
+                let values = data
+                    .iter()
+                    .map(|row| row[i].into())
+                    .collect::<Vec<Option<&str>>>();
+                text_column(field, &values, &self.write_options)
+            })
+            .collect::<Result<Vec<Series>, DeltaError>>()?;
+        let df = DataFrame::new(cols)?;
+        schema.check_numbers(&df)?;
+
+        Ok(df)
+    }
+
+    // Like `insert`, with `metadata` stored as the commit's `userMetadata`
+    // in place of `WriteOptions::user_metadata`, e.g. to correlate the
+    // commit with a job run: `json!({"job_id": "abc"})`. A JSON string is
+    // stored as its text, anything else as JSON.
+    pub fn insert_with_metadata<'a, V>(
+        &self,
+        data: Vec<Vec<V>>,
+        metadata: Value,
+    ) -> Result<InsertResult, DeltaError>
+    where
+        V: Into<Option<&'a str>> + Copy,
+    {
+        let user_metadata = match metadata {
+            Value::String(text) => text,
+            metadata => metadata.to_string(),
+        };
+        let options = WriteOptions {
+            user_metadata: Some(user_metadata),
+            ..self.write_options.clone()
+        };
+        self.clone().with_write_options(options).insert(data)
+    }
+
+    // Insert typed rows. Each cell is checked against its column's type, and
+    // the error for a bad cell names its row and column.
+    pub fn insert_rows(&self, rows: Vec<Vec<DeltaValue>>) -> Result<InsertResult, DeltaError> {
+        let schema: DeltaTableSchema = self.metadata.schema()?;
+        let fields = schema.positional_fields(rows.first().map_or(0, Vec::len));
+        check_row_widths(&rows, fields.len())?;
+
+        let cols = fields
+            .iter()
+            .enumerate()
+            .map(|(i, field)| build_series(field, i, &rows))
+            .collect::<Result<Vec<Series>, DeltaError>>()?;
+
+        self.append(DataFrame::new(cols)?)
+    }
+
+    // Insert anything that serializes to a map or struct, e.g. application
+    // events. Fields are matched to columns by name, and missing fields are
+    // null.
+    pub fn insert_records<T: Serialize>(&self, records: &[T]) -> Result<InsertResult, DeltaError> {
+        let schema: DeltaTableSchema = self.metadata.schema()?;
+
+        let mut rows = vec![];
+        for (row, record) in records.iter().enumerate() {
+            let Value::Object(mut fields) = serde_json::to_value(record)? else {
+                return Err(DeltaError::InvalidData(
+                    "records must serialize to a map or struct".to_owned(),
+                ));
             };
 
-            actions.push(serde_json::to_string(&action)?);
+            let values = schema
+                .fields()
+                .iter()
+                .map(|field| {
+                    let value = fields.remove(&field.name).unwrap_or(Value::Null);
+                    DeltaValue::from_json(value, &field.typ).map_err(|message| {
+                        DeltaError::InvalidValue {
+                            row,
+                            column: field.name.clone(),
+                            message,
+                        }
+                    })
+                })
+                .collect::<Result<Vec<DeltaValue>, DeltaError>>()?;
+
+            if let Some(name) = fields.keys().next() {
+                return Err(DeltaError::SchemaMismatch(format!(
+                    "unknown column '{}'",
+                    name
+                )));
+            }
+            rows.push(values);
         }
 
-        let contents = actions.join("\n");
-        fs::write(
-            format!("{}/{}", self.logs_dir, self.next_log_file()?),
-            contents,
-        )?;
+        self.insert_rows(rows)
+    }
 
-        Ok(())
+    // Columns are matched to the schema by name, so the frame can have
+    // them in any order. The data file is always written in schema order.
+    // Column types have to match the table's, though narrower types of the
+    // same kind are widened, e.g. an Int32 column into a BIGINT field.
+    pub fn insert_df(&self, df: DataFrame) -> Result<InsertResult, DeltaError> {
+        self.metadata.schema()?.check_types(&df)?;
+        self.append(df)
     }
 
-    pub fn get_datafiles(&self) -> Result<HashSet<String>, DeltaError> {
-        let mut logs: Vec<_> = fs::read_dir(&self.logs_dir)?
-            .filter_map(|entry| entry.ok())
-            .collect();
+    // Append Arrow record batches, e.g. from a Flight client or a parquet
+    // reader, without going through rows. `schema` names the batches'
+    // columns, which are then matched to the table like in `insert_df`.
+    pub fn insert_arrow(
+        &self,
+        schema: &ArrowSchema,
+        batches: Vec<Chunk<ArrayRef>>,
+    ) -> Result<InsertResult, DeltaError> {
+        self.insert_df(DeltaTable::arrow_frame(schema, batches)?)
+    }
+
+    // The rows of Arrow record batches whose columns `schema` names, see
+    // `insert_arrow`
+    pub(crate) fn arrow_frame(
+        schema: &ArrowSchema,
+        batches: Vec<Chunk<ArrayRef>>,
+    ) -> Result<DataFrame, DeltaError> {
+        let mut columns: Vec<Vec<ArrayRef>> = vec![vec![]; schema.fields.len()];
+        for batch in batches {
+            if batch.arrays().len() != schema.fields.len() {
+                return Err(DeltaError::SchemaMismatch(format!(
+                    "expected batches with {} columns, got {}",
+                    schema.fields.len(),
+                    batch.arrays().len()
+                )));
+            }
+
+            for (i, array) in batch.into_arrays().into_iter().enumerate() {
+                if array.data_type() != &schema.fields[i].data_type {
+                    return Err(DeltaError::SchemaMismatch(format!(
+                        "column '{}' has type {:?} but the batch schema says {:?}",
+                        schema.fields[i].name,
+                        array.data_type(),
+                        schema.fields[i].data_type
+                    )));
+                }
+                columns[i].push(array);
+            }
+        }
 
-        logs.sort_by(|a, b| b.file_name().cmp(&a.file_name()));
+        let cols = schema
+            .fields
+            .iter()
+            .zip(columns)
+            .map(|(field, arrays)| {
+                if arrays.is_empty() {
+                    Ok(Series::new_empty(&field.name, &(&field.data_type).into()))
+                } else {
+                    Series::try_from((field.name.as_str(), arrays))
+                }
+            })
+            .collect::<PolarsResult<Vec<Series>>>()?;
 
-        let mut removed_files: HashSet<String> = HashSet::new();
-        let mut data_files: HashSet<String> = HashSet::new();
-        for log in logs {
-            for line in fs::read_to_string(log.path())?.lines() {
-                let action = serde_json::from_str::<Action>(line)?;
+        Ok(DataFrame::new(cols)?)
+    }
 
-                match action {
-                    Action::Add { path, .. } => {
-                        if !removed_files.contains(&path) {
-                            data_files.insert(path);
-                        }
-                    }
-                    Action::Remove { path, .. } => {
-                        removed_files.insert(path);
+    // Add parquet files to the table as they are, copying them into the
+    // table's directory instead of decoding and re-encoding their rows.
+    // Their stats come from the row group stats in their footers. With
+    // `validate_schema`, each file has to have exactly the table's columns,
+    // with types `insert_df` would accept; without it, a file that doesn't
+    // match makes reads fail. NOT NULL columns, CHECK constraints and the
+    // unique key are enforced either way, though the last two take reading
+    // the rows. Since rows can't be dropped from a file, a conflicting key
+    // always fails the add. Partitioned tables aren't supported, as their
+    // data files don't hold the partition columns.
+    pub fn add_parquet_files(
+        &self,
+        paths: &[&str],
+        validate_schema: bool,
+    ) -> Result<InsertResult, DeltaError> {
+        self.check_not_pinned()?;
+        // Everything is checked against the table as of one version, and
+        // committed against it, so a change to the schema, key or
+        // constraints since is a conflict
+        let snapshot = self.snapshot()?;
+        let read_version = snapshot.version;
+        let metadata = snapshot.metadata;
+        if !metadata.partition_columns().is_empty() {
+            return Err(DeltaError::InvalidData(
+                "parquet files can't be added to a partitioned table".to_owned(),
+            ));
+        }
+        let schema = metadata.schema()?;
+        let unique_key = metadata.unique_key();
+        let read_rows = !unique_key.is_empty() || !metadata.constraints().is_empty();
+
+        // Everything is checked before the first file is copied
+        let mut files = vec![];
+        let mut rows = vec![];
+        for path in paths {
+            let footer = polars_parquet::read::read_metadata(&mut fs::File::open(path)?)?;
+            let file_schema = polars_parquet::read::infer_schema(&footer)?;
+            if validate_schema {
+                DeltaTable::check_file_schema(path, &file_schema, &schema)?;
+            }
+
+            let stats = footer
+                .row_groups
+                .iter()
+                .map(|group| DeltaFileStats::from_row_group(group, &file_schema))
+                .collect::<PolarsResult<Vec<DeltaFileStats>>>()?
+                .into_iter()
+                .reduce(|all, group| all.merge(&group))
+                .unwrap_or_default();
+            if stats.num_records == 0 {
+                continue;
+            }
+
+            // Footers without null counts leave no choice but to count
+            let nulls_unknown = schema
+                .fields()
+                .iter()
+                .any(|field| !field.nullable && !stats.null_count.contains_key(&field.name));
+            let mut bloom_filter = None;
+            if read_rows || nulls_unknown {
+                let df = LazyFrame::scan_parquet(path, ScanArgsParquet::default())?
+                    .select(DeltaTable::table_columns(&schema))
+                    .collect()?;
+                schema.check_nulls(&df)?;
+                DeltaTable::check_constraints_of(&metadata, &df)?;
+                bloom_filter = DeltaTable::key_bloom_filter(&df, &unique_key)?;
+                rows.push(df);
+            } else {
+                for field in schema.fields().iter().filter(|field| !field.nullable) {
+                    if stats.null_count.get(&field.name) != Some(&0) {
+                        return Err(DeltaError::InvalidData(format!(
+                            "'{}' has nulls in non-nullable column '{}'",
+                            path, field.name
+                        )));
                     }
-                    Action::Metadata { .. } => {}
                 }
             }
+            files.push((path, stats, bloom_filter));
+        }
+
+        if !unique_key.is_empty() {
+            let mut all = schema.empty_frame();
+            for df in &rows {
+                all.vstack_mut(df)?;
+            }
+            let kept = self.drop_existing_keys(all.clone(), &unique_key, None)?;
+            if kept.height() < all.height() {
+                return Err(DeltaError::UniqueKeyViolation {
+                    columns: unique_key,
+                    rows: all.height() - kept.height(),
+                });
+            }
+        }
+
+        if files.is_empty() {
+            return Ok(InsertResult {
+                version: read_version,
+                files_added: 0,
+                rows_written: 0,
+                bytes_written: 0,
+                skipped: false,
+            });
+        }
+
+        // Copied rather than hard linked, so changes to the original can't
+        // change the table
+        let mut data_files = vec![];
+        for (path, stats, bloom_filter) in files {
+            let name = self.next_data_file(&PartitionValues::new());
+            let mut file =
+                ChecksumWriter::new(fs::File::create(format!("{}/{}", self.base_dir, name))?);
+            let size = std::io::copy(&mut fs::File::open(path)?, &mut file)?;
+            data_files.push(DataFile {
+                name,
+                size,
+                stats,
+                partition_values: PartitionValues::new(),
+                checksum: Some(file.checksum()),
+                bloom_filter,
+                tags: self.write_options.tags.clone(),
+            });
         }
 
-        Ok(data_files)
+        let files_added = data_files.len();
+        let rows_written = data_files.iter().map(|f| f.stats.num_records).sum();
+        let bytes_written = data_files.iter().map(|f| f.size).sum();
+
+        let modification_time = DeltaTable::modification_time();
+        let actions = data_files
+            .into_iter()
+            .map(|data_file| DeltaTable::add_action(data_file, modification_time))
+            .collect::<Result<Vec<Action>, DeltaError>>()?;
+        let info = self.write_commit_info("Append", None)?;
+        let version = self.commit_new_rows(read_version, info, actions)?;
+
+        Ok(InsertResult {
+            version,
+            files_added,
+            rows_written,
+            bytes_written,
+            skipped: false,
+        })
     }
 
-    fn next_data_file(&self) -> Result<String, DeltaError> {
-        // Hacky, but do `n-1` instead of `n` for data files because
-        // one of the entries in the base dir is the logs directory.
-        let n = fs::read_dir(&self.base_dir)?.collect::<Vec<_>>().len();
-        return Ok(format!("{:0>20}.parquet", n - 1));
+    // A parquet file has to have exactly the table's columns to be added
+    // to it, with types the table accepts
+    fn check_file_schema(
+        path: &str,
+        file_schema: &ArrowSchema,
+        schema: &DeltaTableSchema,
+    ) -> Result<(), DeltaError> {
+        for field in schema.fields() {
+            if !file_schema.fields.iter().any(|f| f.name == field.name) {
+                return Err(DeltaError::SchemaMismatch(format!(
+                    "'{}' has no column '{}'",
+                    path, field.name
+                )));
+            }
+        }
+        let columns = file_schema
+            .fields
+            .iter()
+            .map(|field| {
+                if !schema.fields().iter().any(|f| f.name == field.name) {
+                    return Err(DeltaError::SchemaMismatch(format!(
+                        "unknown column '{}' in '{}'",
+                        field.name, path
+                    )));
+                }
+                Ok(Series::new_empty(&field.name, &(&field.data_type).into()))
+            })
+            .collect::<Result<Vec<Series>, DeltaError>>()?;
+
+        schema.check_types(&DataFrame::new(columns)?)
     }
 
-    fn next_log_file(&self) -> Result<String, DeltaError> {
-        let n = fs::read_dir(&self.logs_dir)?.collect::<Vec<_>>().len();
-        return Ok(format!("{:0>20}.json", n));
+    // Write `df` as a new data file and commit it. Columns are cast to the
+    // table's types, so callers with untyped data go through here directly.
+    // Appending no rows is a no-op rather than an empty commit.
+    fn append(&self, df: DataFrame) -> Result<InsertResult, DeltaError> {
+        self.append_with_txn(df, None)
     }
 
-    fn write_data_file(&self, df: &mut DataFrame) -> Result<DataFile, DeltaError> {
-        let data_file = self.next_data_file()?;
-        let file = fs::File::create(format!("{}/{}", self.base_dir, data_file))?;
-        let data_file_size = ParquetWriter::new(file).finish(df)?;
+    // Like `append`, skipping the write when the table already has `txn`'s
+    // version of its application, see `insert_with_txn`
+    fn append_with_txn(&self, df: DataFrame, txn: Option<Txn>) -> Result<InsertResult, DeltaError> {
+        self.check_not_pinned()?;
+        let read_version = self.next_version()? - 1;
+        if let Some(txn) = &txn {
+            // Read after `read_version`, so a txn committed in between is
+            // either seen here or a conflict when committing
+            let snapshot = self.snapshot()?;
+            if snapshot
+                .app_versions
+                .get(&txn.app_id)
+                .is_some_and(|version| *version >= txn.version)
+            {
+                return Ok(InsertResult {
+                    version: snapshot.version,
+                    files_added: 0,
+                    rows_written: 0,
+                    bytes_written: 0,
+                    skipped: true,
+                });
+            }
+        }
+        let df = self.truncate_timestamps(self.metadata.schema()?.align(&df)?)?;
+        let df = self.resolve_key_conflicts(df, None)?;
+        let df = match self.write_options.dedupe_keys.is_empty() {
+            true => df,
+            false => {
+                let since = self
+                    .write_options
+                    .dedupe_window
+                    .map(|window| read_version + 1 - window as i64);
+                self.drop_existing_keys_since(df, &self.write_options.dedupe_keys, None, since)?
+            }
+        };
+        let data_files = self.write_data_files(&df)?;
+        if data_files.is_empty() {
+            return Ok(InsertResult {
+                version: read_version,
+                files_added: 0,
+                rows_written: 0,
+                bytes_written: 0,
+                skipped: false,
+            });
+        }
 
-        return Ok(DataFile {
-            name: data_file,
-            size: data_file_size,
-        });
+        let files_added = data_files.len();
+        let rows_written = data_files.iter().map(|f| f.stats.num_records).sum();
+        let bytes_written = data_files.iter().map(|f| f.size).sum();
+
+        let modification_time = DeltaTable::modification_time();
+        let mut actions = data_files
+            .into_iter()
+            .map(|data_file| DeltaTable::add_action(data_file, modification_time))
+            .collect::<Result<Vec<Action>, DeltaError>>()?;
+        actions.extend(txn.map(Action::Txn));
+        let info = self.write_commit_info("Append", None)?;
+        let version = self.commit_new_rows(read_version, info, actions)?;
+
+        Ok(InsertResult {
+            version,
+            files_added,
+            rows_written,
+            bytes_written,
+            skipped: false,
+        })
     }
 
-    fn log_file(idx: usize) -> String {
-        format!("{:0>20}.json", idx)
+    // Check the rows about to be written against the table's unique key,
+    // rejecting or skipping conflicting rows as configured by
+    // `WriteOptions::on_key_conflict`. Only existing rows matching `within`,
+    // or all of them when it's `None`, are checked against.
+    fn resolve_key_conflicts(
+        &self,
+        df: DataFrame,
+        within: Option<&Expr>,
+    ) -> Result<DataFrame, DeltaError> {
+        let unique_key = self.metadata.unique_key();
+        if unique_key.is_empty() {
+            return Ok(df);
+        }
+
+        let rows = df.height();
+        let df = self.drop_existing_keys(df, &unique_key, within)?;
+        if self.write_options.on_key_conflict == KeyConflict::Reject && df.height() < rows {
+            return Err(DeltaError::UniqueKeyViolation {
+                columns: unique_key,
+                rows: rows - df.height(),
+            });
+        }
+
+        Ok(df)
+    }
+
+    // Drop the rows of `df` whose `keys` are already in the table or appear
+    // in an earlier row of `df`. Only files whose stats could hold one of
+    // the keys are read.
+    fn drop_existing_keys(
+        &self,
+        df: DataFrame,
+        keys: &[String],
+        within: Option<&Expr>,
+    ) -> Result<DataFrame, DeltaError> {
+        self.drop_existing_keys_since(df, keys, within, None)
+    }
+
+    // Like `drop_existing_keys`, but when `since` is set only files added
+    // in that version or later are checked against
+    fn drop_existing_keys_since(
+        &self,
+        df: DataFrame,
+        keys: &[String],
+        within: Option<&Expr>,
+        since: Option<i64>,
+    ) -> Result<DataFrame, DeltaError> {
+        self.check_key_columns(keys)?;
+
+        let schema = self.metadata.schema()?;
+        let df = schema
+            .align(&df)?
+            .unique_stable(Some(keys), UniqueKeepStrategy::First, None)?;
+        let key_columns = keys.iter().map(|key| col(key)).collect::<Vec<Expr>>();
+
+        let key_values = df.select(keys)?;
+        let predicate = DeltaTable::key_predicate(&key_values);
+        let snapshot = self.snapshot()?;
+        let mut pruning = FilePruning::default();
+        let mut scanned = vec![];
+        for add in snapshot.files {
+            if since.is_some_and(|since| snapshot.added_in[&add.path] < since) {
+                continue;
+            }
+            let reason = DeltaTable::prune_reason(&add, &predicate, Some(&key_values), &schema)?;
+            pruning.record(reason);
+            if reason.is_none() {
+                scanned.push(add);
+            }
+        }
+        let frames = self.scan_files(scanned.iter().map(|add| (add, 0)), &schema, &[])?;
+        self.log_pruning("key check", &pruning);
+        self.record_reads(scanned.iter().map(|add| add.path.as_str()));
+        let mut existing = match frames.is_empty() {
+            true => schema.empty_frame().lazy(),
+            false => concat(frames, UnionArgs::default())?,
+        };
+        if let Some(within) = within {
+            existing = existing.filter(within.clone());
+        }
+        let existing = existing
+            .select(&key_columns)
+            .unique(None, UniqueKeepStrategy::Any)
+            .with_column(lit(true).alias(EXISTING_KEY));
+
+        Ok(df
+            .lazy()
+            .join(
+                existing,
+                &key_columns,
+                &key_columns,
+                JoinArgs::new(JoinType::Left),
+            )
+            .filter(col(EXISTING_KEY).is_null())
+            .select(DeltaTable::table_columns(&schema))
+            .collect()?)
+    }
+
+    // Matches rows whose value in each of `keys`' columns is among that
+    // column's values. Used to prune files by their stats: matching each
+    // column on its own lets through rows that don't match the keys as a
+    // whole, so with several columns the exact match is left to a join.
+    fn key_predicate(keys: &DataFrame) -> Expr {
+        keys.get_columns()
+            .iter()
+            .map(|key| col(key.name()).is_in(lit(key.clone())))
+            .reduce(|all, key| all.and(key))
+            .unwrap_or(lit(false))
+    }
+
+    // Add a CHECK constraint, which the rows already in the table and every
+    // row written after have to satisfy. Like in SQL, a row only violates
+    // a constraint when it's false for the row, not when it's null. The
+    // constraint is stored in the table's configuration, see
+    // `DeltaTableMetadata::constraints`, and the protocol is raised to one
+    // with the `checkConstraints` writer feature so other writers enforce
+    // it too. Returns the version committed.
+    pub fn add_constraint(&self, name: &str, expr: &str) -> Result<i64, DeltaError> {
+        self.check_not_pinned()?;
+        // Spark lower cases constraint names too
+        let name = name.to_lowercase();
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(DeltaError::InvalidData(format!(
+                "invalid constraint name '{}'",
+                name
+            )));
+        }
+        let predicate = parse_predicate(expr)?;
+        self.validate_predicate(&predicate)?;
+
+        let snapshot = self.snapshot()?;
+        if snapshot
+            .metadata
+            .constraints()
+            .iter()
+            .any(|(n, _)| *n == name)
+        {
+            return Err(DeltaError::InvalidData(format!(
+                "constraint '{}' already exists",
+                name
+            )));
+        }
+
+        let rows = self
+            .scan_unmasked()?
+            .filter(predicate.not())
+            .collect()?
+            .height();
+        if rows > 0 {
+            return Err(DeltaError::ConstraintViolation {
+                name,
+                expression: expr.to_owned(),
+                rows,
+            });
+        }
+
+        // Appends committed after the table was checked conflict with this,
+        // so rows that violate the constraint can't slip in
+        let metadata = snapshot.metadata.with_constraint(&name, expr);
+        let info = self.commit_info(
+            "ADD CONSTRAINT",
+            HashMap::from([
+                ("name".to_owned(), Value::from(name.as_str())),
+                ("expr".to_owned(), Value::from(expr)),
+            ]),
+        );
+        let mut actions = vec![Action::Metadata(metadata)];
+        let protocol = snapshot.protocol.with_writer_feature("checkConstraints")?;
+        if protocol != snapshot.protocol {
+            actions.insert(0, Action::Protocol(protocol));
+        }
+        self.commit(snapshot.version, info, actions)
+    }
+
+    // Make sure every row of `df` satisfies the table's CHECK constraints.
+    // Constraints may have been added since this handle was opened, so
+    // they're read from the latest metadata.
+    fn check_constraints(&self, df: &DataFrame) -> Result<(), DeltaError> {
+        DeltaTable::check_constraints_of(&self.snapshot()?.metadata, df)
+    }
+
+    // Make sure every row of `df` satisfies the constraints in `metadata`
+    fn check_constraints_of(
+        metadata: &DeltaTableMetadata,
+        df: &DataFrame,
+    ) -> Result<(), DeltaError> {
+        for (name, expr) in metadata.constraints() {
+            let rows = df
+                .clone()
+                .lazy()
+                .filter(parse_predicate(expr)?.not())
+                .collect()?
+                .height();
+            if rows > 0 {
+                return Err(DeltaError::ConstraintViolation {
+                    name: name.to_owned(),
+                    expression: expr.to_owned(),
+                    rows,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_key_columns<S: AsRef<str>>(&self, keys: &[S]) -> Result<(), DeltaError> {
+        if keys.is_empty() {
+            return Err(DeltaError::InvalidData(
+                "at least one key column is needed".to_owned(),
+            ));
+        }
+
+        let schema = self.metadata.schema()?;
+        for key in keys {
+            if !schema.fields().iter().any(|f| f.name == key.as_ref()) {
+                return Err(DeltaError::SchemaMismatch(format!(
+                    "unknown column '{}'",
+                    key.as_ref()
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Remove rows with the same values in `keys` as an earlier row,
+    // keeping the first in table order, i.e. the one from the oldest file.
+    // Null keys count as equal. Only the files holding duplicates are
+    // rewritten, all in a single commit.
+    pub fn dedupe(&self, keys: &[&str]) -> Result<DedupeMetrics, DeltaError> {
+        let start = Instant::now();
+        self.check_not_pinned()?;
+        if self.row_filter.is_some() {
+            return Err(DeltaError::InvalidData(
+                "can't dedupe through a handle with a row filter".to_owned(),
+            ));
+        }
+        self.check_key_columns(keys)?;
+        let schema = self.metadata.schema()?;
+        let snapshot = self.snapshot()?;
+
+        // Every row's keys, tagged with where the row is
+        let key_columns = keys.iter().map(|key| col(key)).collect::<Vec<Expr>>();
+        let mut frames = vec![];
+        for (i, add) in snapshot.files.iter().enumerate() {
+            frames.push(
+                self.scan_file(add, &schema, None)?
+                    .select(&key_columns)
+                    .with_row_count(DEDUPE_ROW, None)
+                    .with_column(lit(i as u32).alias(DEDUPE_FILE)),
+            );
+        }
+        let keys = keys.iter().map(|key| key.to_string()).collect::<Vec<_>>();
+        let kept = match frames.is_empty() {
+            true => DataFrame::empty(),
+            false => concat(frames, UnionArgs::default())?
+                .collect()?
+                .unique_stable(Some(&keys), UniqueKeepStrategy::First, None)?,
+        };
+
+        let mut kept_rows: HashMap<u32, Vec<IdxSize>> = HashMap::new();
+        if kept.height() > 0 {
+            let files = kept.column(DEDUPE_FILE)?.u32()?;
+            let rows = kept.column(DEDUPE_ROW)?.idx()?;
+            for (file, row) in files.into_iter().zip(rows) {
+                if let (Some(file), Some(row)) = (file, row) {
+                    kept_rows.entry(file).or_default().push(row);
+                }
+            }
+        }
+
+        let mut rewrite = Rewrite {
+            read_version: snapshot.version,
+            ..Default::default()
+        };
+        for (i, add) in snapshot.files.into_iter().enumerate() {
+            let mut rows = kept_rows.remove(&(i as u32)).unwrap_or_default();
+            let count = self.file_row_count(&add)? as usize;
+            if rows.len() == count {
+                continue;
+            }
+
+            rewrite.rows_removed += count - rows.len();
+            if rows.is_empty() {
+                rewrite.files_removed += 1;
+            } else {
+                rows.sort_unstable();
+                let df = self
+                    .scan_file(&add, &schema, None)?
+                    .collect()?
+                    .take(&IdxCa::new("", rows))?
+                    .drop_many(self.metadata.partition_columns());
+                let mut data_file = self.write_data_file(df, add.partition_values.clone())?;
+                data_file.tags = self.rewritten_tags(&add);
+                rewrite.created.push(data_file);
+                rewrite.files_rewritten += 1;
+            }
+            rewrite.removed.push(add.path);
+        }
+
+        let rows_removed = rewrite.rows_removed;
+        let files_rewritten = rewrite.files_rewritten;
+        let files_removed = rewrite.files_removed;
+        let version = match rewrite.removed.is_empty() {
+            true => rewrite.read_version,
+            false => {
+                let mut info = self.commit_info(
+                    "DEDUPE",
+                    HashMap::from([(
+                        "keys".to_owned(),
+                        Value::from(serde_json::to_string(&keys)?),
+                    )]),
+                );
+                info.operation_metrics
+                    .insert("numDeletedRows".to_owned(), rows_removed.to_string());
+                self.commit(
+                    rewrite.read_version,
+                    info,
+                    rewrite
+                        .into_actions(DeltaTable::modification_time(), &self.write_options.tags)?,
+                )?
+            }
+        };
+
+        Ok(DedupeMetrics {
+            rows_removed,
+            files_rewritten,
+            files_removed,
+            duration: start.elapsed(),
+            version,
+        })
+    }
+
+    // Compact small data files into bigger ones, picking them as `policy`
+    // says, all in a single commit. Rows don't change, so the commit's
+    // actions are marked as not changing data. New files get the handle's
+    // tags rather than the tags of the files they replace.
+    pub fn optimize(&self, policy: &CompactionPolicy) -> Result<OptimizeMetrics, DeltaError> {
+        let start = Instant::now();
+        self.check_not_pinned()?;
+        let schema = self.metadata.schema()?;
+        let snapshot = self.snapshot()?;
+
+        let mut partitions: BTreeMap<Vec<(String, Option<String>)>, Vec<Add>> = BTreeMap::new();
+        for add in snapshot.files {
+            let mut key = add.partition_values.clone().into_iter().collect::<Vec<_>>();
+            key.sort();
+            partitions.entry(key).or_default().push(add);
+        }
+
+        let mut rewrite = Rewrite {
+            read_version: snapshot.version,
+            ..Default::default()
+        };
+        let mut bytes_removed = 0;
+        for files in partitions.into_values() {
+            for group in compaction_groups(files, policy) {
+                let mut df = schema.empty_frame();
+                for add in &group {
+                    df.vstack_mut(&self.scan_file(add, &schema, None)?.collect()?)?;
+                }
+                let df = df.drop_many(self.metadata.partition_columns());
+                let partition_values = group[0].partition_values.clone();
+                rewrite
+                    .created
+                    .push(self.write_data_file(df, partition_values)?);
+                for add in group {
+                    bytes_removed += add.size;
+                    rewrite.removed.push(add.path);
+                }
+            }
+        }
+
+        let files_removed = rewrite.removed.len();
+        let files_added = rewrite.created.len();
+        let bytes_added = rewrite.created.iter().map(|file| file.size).sum();
+        let version = match rewrite.removed.is_empty() {
+            true => rewrite.read_version,
+            false => {
+                let read_version = rewrite.read_version;
+                let mut actions = rewrite
+                    .into_actions(DeltaTable::modification_time(), &self.write_options.tags)?;
+                for action in &mut actions {
+                    match action {
+                        Action::Add(add) => add.data_change = false,
+                        Action::Remove(remove) => remove.data_change = false,
+                        _ => {}
+                    }
+                }
+                let mut info = self.optimize_commit_info(policy);
+                info.operation_metrics
+                    .insert("numRemovedBytes".to_owned(), bytes_removed.to_string());
+                self.commit(read_version, info, actions)?
+            }
+        };
+
+        Ok(OptimizeMetrics {
+            files_removed,
+            files_added,
+            bytes_removed,
+            bytes_added,
+            duration: start.elapsed(),
+            version,
+        })
+    }
+
+    fn optimize_commit_info(&self, policy: &CompactionPolicy) -> CommitInfo {
+        let parameters = match policy {
+            CompactionPolicy::BinPack { target_size } => HashMap::from([
+                ("policy".to_owned(), Value::from("binPack")),
+                ("targetSize".to_owned(), Value::from(*target_size)),
+            ]),
+            CompactionPolicy::SizeTiered {
+                min_size,
+                tier_factor,
+                min_files,
+            } => HashMap::from([
+                ("policy".to_owned(), Value::from("sizeTiered")),
+                ("minSize".to_owned(), Value::from(*min_size)),
+                ("tierFactor".to_owned(), Value::from(*tier_factor)),
+                ("minFiles".to_owned(), Value::from(*min_files)),
+            ]),
+        };
+
+        self.commit_info("OPTIMIZE", parameters)
+    }
+
+    // Append the contents of a CSV file. Columns are matched by name when
+    // the file has a header and by position otherwise.
+    pub fn copy_from_csv(
+        &self,
+        path: &str,
+        options: &CsvOptions,
+    ) -> Result<CsvLoadResult, DeltaError> {
+        let (df, rejected) = read_csv(path, options, &self.metadata.schema()?)?;
+        Ok(CsvLoadResult {
+            insert: self.append(df)?,
+            rejected,
+        })
+    }
+
+    // Append the contents of a file with a JSON object per line. Fields are
+    // matched to columns by name, see `NdjsonOptions` for fields that don't
+    // match. Values are converted like in `insert_records`.
+    pub fn copy_from_ndjson(
+        &self,
+        path: &str,
+        options: &NdjsonOptions,
+    ) -> Result<InsertResult, DeltaError> {
+        let rows = read_ndjson(path, options, &self.metadata.schema()?)?;
+        self.insert_rows(rows)
+    }
+
+    // Append CSV read from a stream, e.g. stdin, committing every
+    // `batch_bytes` or so of input as its own data file, so the input
+    // never has to fit in memory. A failure leaves the batches before it
+    // committed. Rows are numbered from the start of the stream, in errors
+    // and in `CsvLoadResult::rejected`.
+    pub fn copy_from_csv_reader<R: BufRead>(
+        &self,
+        reader: R,
+        options: &CsvOptions,
+        batch_bytes: usize,
+    ) -> Result<CsvLoadResult, DeltaError> {
+        let schema = self.metadata.schema()?;
+        let mut result = CsvLoadResult {
+            insert: self.empty_insert()?,
+            rejected: vec![],
+        };
+
+        let mut rows = 0;
+        for chunk in CsvChunks::new(reader, options, batch_bytes) {
+            let (df, rejected) = parse_csv(chunk?, options, &schema).map_err(|e| match e {
+                DeltaError::InvalidValue {
+                    row,
+                    column,
+                    message,
+                } => DeltaError::InvalidValue {
+                    row: rows + row,
+                    column,
+                    message,
+                },
+                e => e,
+            })?;
+            let parsed = df.height() + rejected.len();
+
+            add_insert(&mut result.insert, self.append(df)?);
+            result
+                .rejected
+                .extend(rejected.into_iter().map(|rejected| RejectedRow {
+                    row: rows + rejected.row,
+                    ..rejected
+                }));
+            rows += parsed;
+        }
+
+        Ok(result)
+    }
+
+    // Append lines of JSON read from a stream, committing in batches like
+    // `copy_from_csv_reader`
+    pub fn copy_from_ndjson_reader<R: BufRead>(
+        &self,
+        reader: R,
+        options: &NdjsonOptions,
+        batch_bytes: usize,
+    ) -> Result<InsertResult, DeltaError> {
+        let schema = self.metadata.schema()?;
+        let mut result = self.empty_insert()?;
+
+        let mut batch = vec![];
+        let mut bytes = 0;
+        for (row, line) in reader.lines().enumerate() {
+            let line = line?;
+            bytes += line.len() + 1;
+            batch.extend(ndjson::parse_line(&line, row, options, &schema)?);
+            if bytes >= batch_bytes {
+                add_insert(&mut result, self.insert_rows(std::mem::take(&mut batch))?);
+                bytes = 0;
+            }
+        }
+        if !batch.is_empty() {
+            add_insert(&mut result, self.insert_rows(batch)?);
+        }
+
+        Ok(result)
+    }
+
+    // What an insert without rows returns
+    fn empty_insert(&self) -> Result<InsertResult, DeltaError> {
+        Ok(InsertResult {
+            version: self.next_version()? - 1,
+            files_added: 0,
+            rows_written: 0,
+            bytes_written: 0,
+            skipped: false,
+        })
+    }
+
+    // If another writer removes one of the files being rewritten while the
+    // delete runs, the delete fails with `CommitConflict` rather than
+    // resurrecting rows. Concurrent appends are fine.
+    pub fn delete(&self, expr: &str) -> Result<DeleteMetrics, DeltaError> {
+        self.delete_predicate(parse_predicate(expr)?, expr.to_owned())
+    }
+
+    // Like `delete`, with each `?` in `expr` replaced by the next of
+    // `params`, e.g. `delete_with_params("name = ? AND id > ?", &params)`.
+    // See `sql::bind_params`.
+    pub fn delete_with_params(
+        &self,
+        expr: &str,
+        params: &[DeltaValue],
+    ) -> Result<DeleteMetrics, DeltaError> {
+        self.delete(&bind_params(expr, params)?)
+    }
+
+    // Same as `delete`, but with the predicate built programmatically,
+    // e.g. `col("bar").eq(lit("test row"))`, so no SQL is involved.
+    pub fn delete_where(&self, predicate: Expr) -> Result<DeleteMetrics, DeltaError> {
+        let text = predicate.to_string();
+        self.delete_predicate(predicate, text)
+    }
+
+    // `text` is how the predicate is recorded in the commit's commitInfo,
+    // the SQL it was parsed from if there is any
+    fn delete_predicate(&self, predicate: Expr, text: String) -> Result<DeleteMetrics, DeltaError> {
+        let start = Instant::now();
+        self.check_not_pinned()?;
+        self.validate_predicate(&predicate)?;
+        let predicate = self.within_filter(predicate);
+
+        let keep = Keep::Where(DeltaTable::not_matching(&predicate));
+        let parameters = HashMap::from([("predicate".to_owned(), Value::from(text))]);
+        self.delete_matching(start, &predicate, None, &keep, parameters)
+    }
+
+    // Delete every row whose values in `keys`' columns equal one of `keys`'
+    // rows, e.g. a frame of ids to delete. Much cheaper than a predicate
+    // with a huge IN list: no SQL is generated or parsed, rows are matched
+    // against the keys with a hash join, and files whose stats can't hold
+    // any of the keys aren't read. Keys with a null never match.
+    pub fn delete_keys(&self, keys: DataFrame) -> Result<DeleteMetrics, DeltaError> {
+        let start = Instant::now();
+        self.check_not_pinned()?;
+        let columns = keys
+            .get_column_names()
+            .into_iter()
+            .map(|name| name.to_owned())
+            .collect::<Vec<String>>();
+        self.check_key_columns(&columns)?;
+
+        let schema = self.metadata.schema()?;
+        schema.check_types(&keys)?;
+        let keys = keys
+            .lazy()
+            .select(
+                schema
+                    .fields()
+                    .iter()
+                    .filter(|field| columns.iter().any(|c| c == field.name.as_str()))
+                    .map(|field| col(&field.name).cast(field.typ.to_polars_type()))
+                    .collect::<Vec<Expr>>(),
+            )
+            .drop_nulls(None)
+            .unique(None, UniqueKeepStrategy::Any)
+            .collect()?;
+
+        // Rows are matched with a join rather than by `predicate`, which
+        // compares with the table's types where a file may store a column
+        // differently, e.g. timestamps in milliseconds
+        let predicate = self.within_filter(DeltaTable::key_predicate(&keys));
+        let keep = Keep::NotIn(keys.clone(), self.row_filter.clone());
+
+        let parameters = HashMap::from([(
+            "keys".to_owned(),
+            Value::from(serde_json::to_string(&columns)?),
+        )]);
+        self.delete_matching(start, &predicate, Some(&keys), &keep, parameters)
+    }
+
+    // Delete rows by where they are rather than by their values, e.g. rows
+    // flagged by a query: `file` and `row_indexes` are the `_file_name` and
+    // `_row_index` values of the rows. The file is rewritten without them,
+    // or removed if none are left. Fails if the file is no longer part of
+    // the table, since its rows may have moved to another file since.
+    pub fn delete_rows(
+        &self,
+        file: &str,
+        row_indexes: &[usize],
+    ) -> Result<DeleteMetrics, DeltaError> {
+        let start = Instant::now();
+        self.check_not_pinned()?;
+        let schema = self.metadata.schema()?;
+        let snapshot = self.snapshot()?;
+        let Some(add) = snapshot
+            .files
+            .into_iter()
+            .find(|add| add.path == file || partition::decode_path(&add.path) == file)
+        else {
+            return Err(DeltaError::InvalidData(format!(
+                "'{}' is not a data file of the table",
+                file
+            )));
+        };
+
+        let count = self.file_row_count(&add)? as usize;
+        let deleted = row_indexes.iter().copied().collect::<HashSet<usize>>();
+        if let Some(row) = deleted.iter().find(|row| **row >= count) {
+            return Err(DeltaError::InvalidData(format!(
+                "row {} is out of range, '{}' has {} rows",
+                row, file, count
+            )));
+        }
+        if let Some(filter) = &self.row_filter {
+            let mut rows = deleted
+                .iter()
+                .map(|row| *row as IdxSize)
+                .collect::<Vec<_>>();
+            rows.sort_unstable();
+            let outside = self
+                .scan_file(&add, &schema, None)?
+                .collect()?
+                .take(&IdxCa::new("", rows))?
+                .lazy()
+                .filter(DeltaTable::not_matching(filter))
+                .collect()?
+                .height();
+            if outside > 0 {
+                return Err(DeltaError::InvalidData(format!(
+                    "{} rows are outside of the handle's row filter",
+                    outside
+                )));
+            }
+        }
+
+        let mut rewrite = Rewrite {
+            read_version: snapshot.version,
+            pruning: FilePruning {
+                files_scanned: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        if !deleted.is_empty() {
+            rewrite.rows_removed = deleted.len();
+            let rows = (0..count)
+                .filter(|row| !deleted.contains(row))
+                .map(|row| row as IdxSize)
+                .collect::<Vec<IdxSize>>();
+            if rows.is_empty() {
+                rewrite.files_removed += 1;
+            } else {
+                let df = self
+                    .scan_file(&add, &schema, None)?
+                    .collect()?
+                    .take(&IdxCa::new("", rows))?
+                    .drop_many(self.metadata.partition_columns());
+                let mut data_file = self.write_data_file(df, add.partition_values.clone())?;
+                data_file.tags = self.rewritten_tags(&add);
+                rewrite.created.push(data_file);
+                rewrite.files_rewritten += 1;
+            }
+            rewrite.removed.push(add.path);
+        }
+
+        let parameters = HashMap::from([("file".to_owned(), Value::from(file))]);
+        self.commit_rewrite(start, rewrite, parameters)
+    }
+
+    fn delete_matching(
+        &self,
+        start: Instant,
+        predicate: &Expr,
+        keys: Option<&DataFrame>,
+        keep: &Keep,
+        parameters: HashMap<String, Value>,
+    ) -> Result<DeleteMetrics, DeltaError> {
+        let rewrite = self.rewrite_without(predicate, keys, keep)?;
+        self.commit_rewrite(start, rewrite, parameters)
+    }
+
+    // Commit a delete, recording `parameters` in its commitInfo
+    fn commit_rewrite(
+        &self,
+        start: Instant,
+        rewrite: Rewrite,
+        parameters: HashMap<String, Value>,
+    ) -> Result<DeleteMetrics, DeltaError> {
+        let rows_deleted = rewrite.rows_removed;
+        let pruning = rewrite.pruning;
+        self.log_pruning("delete", &pruning);
+        let files_rewritten = rewrite.files_rewritten;
+        let files_removed = rewrite.files_removed;
+        let row_groups_copied = rewrite.row_groups_copied;
+        let row_groups_rewritten = rewrite.row_groups_rewritten;
+
+        // Nothing matched, so there is nothing to commit
+        let version = if rewrite.removed.is_empty() {
+            rewrite.read_version
+        } else {
+            let read_version = rewrite.read_version;
+            let mut info = self.commit_info("DELETE", parameters);
+            info.operation_metrics
+                .insert("numDeletedRows".to_owned(), rows_deleted.to_string());
+            self.commit(
+                read_version,
+                info,
+                rewrite.into_actions(DeltaTable::modification_time(), &self.write_options.tags)?,
+            )?
+        };
+
+        Ok(DeleteMetrics {
+            rows_deleted,
+            files_scanned: pruning.files_scanned,
+            files_rewritten,
+            files_removed,
+            row_groups_copied,
+            row_groups_rewritten,
+            pruning,
+            duration: start.elapsed(),
+            version,
+        })
+    }
+
+    // Like Delta's `replaceWhere`: rows matching `expr` are replaced by
+    // `df` in a single commit. Every row in `df` has to match `expr`,
+    // otherwise the overwrite would leak data outside of the predicate.
+    // With a unique key, `df` is only checked against the rows it doesn't
+    // replace. Returns the version committed, or when there was nothing to
+    // replace and nothing to replace it with, the version it ran against.
+    pub fn overwrite_where(&self, df: DataFrame, expr: &str) -> Result<i64, DeltaError> {
+        self.check_not_pinned()?;
+        let predicate = parse_predicate(expr)?;
+        self.validate_predicate(&predicate)?;
+        let predicate = self.within_filter(predicate);
+
+        let schema = self.metadata.schema()?;
+        schema.check_types(&df)?;
+        let df = self.truncate_timestamps(schema.align(&df)?)?;
+
+        let outside = df
+            .clone()
+            .lazy()
+            .filter(DeltaTable::not_matching(&predicate))
+            .collect()?;
+        if outside.height() > 0 {
+            return Err(DeltaError::InvalidData(format!(
+                "{} rows do not match the overwrite predicate '{}'",
+                outside.height(),
+                expr
+            )));
+        }
+
+        // Key conflicts are found before anything is written, so a rejected
+        // overwrite leaves no files behind. Files added after `read_version`
+        // weren't checked against, so they're a conflict when committing.
+        let read_version = self.next_version()? - 1;
+        let df = self.resolve_key_conflicts(df, Some(&DeltaTable::not_matching(&predicate)))?;
+        let keep = Keep::Where(DeltaTable::not_matching(&predicate));
+        let mut rewrite = self.rewrite_without(&predicate, None, &keep)?;
+        rewrite.created.extend(self.write_data_files(&df)?);
+
+        // Nothing to replace and nothing to replace it with
+        if rewrite.removed.is_empty() && rewrite.created.is_empty() {
+            return Ok(rewrite.read_version);
+        }
+        let actions =
+            rewrite.into_actions(DeltaTable::modification_time(), &self.write_options.tags)?;
+        let info = self.write_commit_info("Overwrite", Some(expr))?;
+        self.commit_new_rows(read_version, info, actions)
+    }
+
+    // Upsert `source` into the table, like `MERGE INTO t USING source ON
+    // <the columns of on are equal> WHEN MATCHED THEN UPDATE SET * WHEN NOT
+    // MATCHED THEN INSERT *`: rows with the same values in `on`'s columns
+    // as a row of `source` are replaced by it, and the rest of `source` is
+    // added, in a single commit. Rows are matched like `delete_keys` does,
+    // so files whose stats or bloom filter show they hold none of
+    // `source`'s keys aren't read, and only files holding matched rows are
+    // rewritten. The rows of `source` need distinct keys. On a table with a
+    // unique key, `on` has to be that key.
+    pub fn merge(&self, source: DataFrame, on: &[&str]) -> Result<MergeMetrics, DeltaError> {
+        let start = Instant::now();
+        self.check_not_pinned()?;
+        let on = on
+            .iter()
+            .map(|column| unquote_identifier(column))
+            .collect::<Vec<String>>();
+        self.check_key_columns(&on)?;
+        let unique_key = self.metadata.unique_key();
+        if !unique_key.is_empty()
+            && (on.len() != unique_key.len() || on.iter().any(|c| !unique_key.contains(c)))
+        {
+            return Err(DeltaError::InvalidData(format!(
+                "the table's unique key is {:?}, so merges have to be on it",
+                unique_key
+            )));
+        }
+
+        let schema = self.metadata.schema()?;
+        schema.check_types(&source)?;
+        let source = self.truncate_timestamps(schema.align(&source)?)?;
+        let duplicates = source.select(&on)?.is_duplicated()?.sum().unwrap_or(0) as usize;
+        if duplicates > 0 {
+            return Err(DeltaError::InvalidData(format!(
+                "{} rows of the source have the same key as another",
+                duplicates
+            )));
+        }
+
+        // Rows outside the row filter aren't replaced, so with a unique key
+        // the source is checked against them. Files added after
+        // `read_version` weren't checked against, so they're a conflict
+        // when committing.
+        let read_version = self.next_version()? - 1;
+        let source = match &self.row_filter {
+            Some(filter) => self.resolve_key_conflicts(source, Some(&filter.clone().not()))?,
+            None => source,
+        };
+
+        let keys = source.select(&on)?.drop_nulls::<String>(None)?;
+        let predicate = self.within_filter(DeltaTable::key_predicate(&keys));
+        let keep = Keep::NotIn(keys.clone(), self.row_filter.clone());
+        let mut rewrite = self.rewrite_without(&predicate, Some(&keys), &keep)?;
+        let rows_replaced = rewrite.rows_removed;
+        let files_rewritten = rewrite.files_rewritten + rewrite.files_removed;
+        let pruning = rewrite.pruning;
+        self.log_pruning("merge", &pruning);
+        rewrite.created.extend(self.write_data_files(&source)?);
+
+        // Nothing to replace and nothing to replace it with
+        let version = if rewrite.removed.is_empty() && rewrite.created.is_empty() {
+            read_version
+        } else {
+            let parameters =
+                HashMap::from([("on".to_owned(), Value::from(serde_json::to_string(&on)?))]);
+            let mut info = self.commit_info("MERGE", parameters);
+            info.operation_metrics
+                .insert("numTargetRowsUpdated".to_owned(), rows_replaced.to_string());
+            let actions =
+                rewrite.into_actions(DeltaTable::modification_time(), &self.write_options.tags)?;
+            self.commit_new_rows(read_version, info, actions)?
+        };
+
+        Ok(MergeMetrics {
+            rows_replaced,
+            rows_written: source.height(),
+            files_scanned: pruning.files_scanned,
+            files_rewritten,
+            pruning,
+            duration: start.elapsed(),
+            version,
+        })
+    }
+
+    // Set columns to new values in the rows matching `expr`, like SQL's
+    // `UPDATE ... SET column = value WHERE expr`. Values are SQL
+    // expressions, which can refer to the row's current values. Files
+    // holding matching rows are rewritten in a single commit, and files
+    // whose stats show no row can match aren't read.
+    pub fn update(
+        &self,
+        assignments: &[(&str, &str)],
+        expr: &str,
+    ) -> Result<UpdateMetrics, DeltaError> {
+        let assignments = assignments
+            .iter()
+            .map(|(column, value)| Ok((*column, parse_expr(value)?)))
+            .collect::<Result<Vec<(&str, Expr)>, DeltaError>>()?;
+        self.update_predicate(assignments, parse_predicate(expr)?, expr.to_owned())
+    }
+
+    pub fn update_where(
+        &self,
+        assignments: Vec<(&str, Expr)>,
+        predicate: Expr,
+    ) -> Result<UpdateMetrics, DeltaError> {
+        let text = predicate.to_string();
+        self.update_predicate(assignments, predicate, text)
+    }
+
+    // Like `delete_predicate`, `text` is how the predicate is recorded
+    fn update_predicate(
+        &self,
+        assignments: Vec<(&str, Expr)>,
+        predicate: Expr,
+        text: String,
+    ) -> Result<UpdateMetrics, DeltaError> {
+        let start = Instant::now();
+        self.check_not_pinned()?;
+        self.validate_predicate(&predicate)?;
+        let predicate = self.within_filter(predicate);
+
+        let schema = self.metadata.schema()?;
+        let unique_key = self.metadata.unique_key();
+        let mut values = vec![];
+        for (column, value) in assignments {
+            let column = unquote_identifier(column);
+            let Some(field) = schema.fields().iter().find(|f| f.name == column) else {
+                return Err(DeltaError::SchemaMismatch(format!(
+                    "unknown column '{}'",
+                    column
+                )));
+            };
+            if field.generation_expression().is_some() || unique_key.contains(&column) {
+                return Err(DeltaError::InvalidData(format!(
+                    "column '{}' can't be updated",
+                    column
+                )));
+            }
+
+            values.push(
+                when(predicate.clone().fill_null(lit(false)))
+                    .then(value.cast(field.typ.to_polars_type()))
+                    .otherwise(col(&column))
+                    .alias(&column),
+            );
+        }
+        // Catch values referring to unknown columns before touching data
+        schema
+            .empty_frame()
+            .lazy()
+            .with_columns(&values)
+            .collect()
+            .map_err(|e| DeltaError::InvalidQuery(e.to_string()))?;
+
+        // Generated columns are computed again from the updated values
+        let generated = schema
+            .fields()
+            .iter()
+            .filter(|field| field.generation_expression().is_some())
+            .map(|field| field.name.as_str())
+            .collect::<Vec<&str>>();
+
+        let snapshot = self.snapshot()?;
+        let mut rewrite = Rewrite {
+            read_version: snapshot.version,
+            ..Default::default()
+        };
+        let mut rows_updated = 0;
+        for add in snapshot.files {
+            let reason = DeltaTable::prune_reason(&add, &predicate, None, &schema)?;
+            rewrite.pruning.record(reason);
+            if reason.is_some() {
+                continue;
+            }
+
+            let rows = self.scan_file(&add, &schema, None)?.collect()?;
+            self.record_reads([add.path.as_str()]);
+            let matched = rows
+                .clone()
+                .lazy()
+                .filter(predicate.clone().fill_null(lit(false)))
+                .collect()?
+                .height();
+            if matched == 0 {
+                continue;
+            }
+
+            let updated = rows
+                .lazy()
+                .with_columns(&values)
+                .collect()?
+                .drop_many(&generated);
+            for mut data_file in self.write_data_files(&updated)? {
+                data_file.tags = self.rewritten_tags(&add);
+                rewrite.created.push(data_file);
+            }
+            rows_updated += matched;
+            rewrite.files_rewritten += 1;
+            rewrite.removed.push(add.path);
+        }
+
+        let pruning = rewrite.pruning;
+        self.log_pruning("update", &pruning);
+        let files_rewritten = rewrite.files_rewritten;
+        let version = match rewrite.removed.is_empty() {
+            true => rewrite.read_version,
+            false => {
+                let read_version = rewrite.read_version;
+                let mut info = self.commit_info(
+                    "UPDATE",
+                    HashMap::from([("predicate".to_owned(), Value::from(text))]),
+                );
+                info.operation_metrics
+                    .insert("numUpdatedRows".to_owned(), rows_updated.to_string());
+                self.commit(
+                    read_version,
+                    info,
+                    rewrite
+                        .into_actions(DeltaTable::modification_time(), &self.write_options.tags)?,
+                )?
+            }
+        };
+
+        Ok(UpdateMetrics {
+            rows_updated,
+            files_scanned: pruning.files_scanned,
+            files_rewritten,
+            pruning,
+            duration: start.elapsed(),
+            version,
+        })
+    }
+
+    // The whole table as a single frame, with rows in the order their
+    // files were added. Meant for small tables, use `scan` to filter or
+    // aggregate before loading anything.
+    pub fn to_polars(&self) -> Result<DataFrame, DeltaError> {
+        Ok(self.scan()?.collect()?)
+    }
+
+    pub(crate) fn metadata(&self) -> &DeltaTableMetadata {
+        &self.metadata
+    }
+
+    // A lazy scan over all of the table's rows, with partition columns
+    // added back and the columns in schema order, cast to the table's
+    // types. Filters and projections applied to the frame are pushed down
+    // into the parquet reads, so callers can compose their own queries with
+    // polars directly.
+    pub fn scan(&self) -> Result<LazyFrame, DeltaError> {
+        self.mask(self.scan_unmasked()?)
+    }
+
+    // `scan` without the read policy, for checking the rows themselves
+    fn scan_unmasked(&self) -> Result<LazyFrame, DeltaError> {
+        let schema = self.metadata.schema()?;
+        let files = self.live_files()?;
+        let frames = self.scan_files(files.iter().map(|add| (add, 0)), &schema, &[])?;
+        self.record_reads(files.iter().map(|add| add.path.as_str()));
+        self.log_pruning(
+            "scan",
+            &FilePruning {
+                files_scanned: files.len(),
+                ..Default::default()
+            },
+        );
+
+        match frames.is_empty() {
+            true => Ok(schema.empty_frame().lazy()),
+            false => Ok(concat(frames, UnionArgs::default())?),
+        }
+    }
+
+    // Like `scan`, for when only the first `n` rows are needed. Polars
+    // can't push a limit through the partition columns into the parquet
+    // reads, so it's done here: files past the first `n` rows aren't scanned
+    // at all, and the others read no more rows than they have to.
+    pub fn scan_head(&self, n: usize) -> Result<LazyFrame, DeltaError> {
+        // Which files hold the first `n` rows depends on the filter
+        if self.row_filter.is_some() {
+            return Ok(self.scan()?.limit(n as IdxSize));
+        }
+
+        let schema = self.metadata.schema()?;
+
+        let files = self.live_files()?;
+        let mut pruning = FilePruning::default();
+        let mut frames = vec![];
+        let mut rows = 0;
+        for add in &files {
+            if rows >= n {
+                pruning.record(Some(PruneReason::Limit));
+                continue;
+            }
+            pruning.record(None);
+            frames.push(self.scan_file(add, &schema, Some(n - rows))?);
+            rows += self.file_row_count(add)? as usize;
+        }
+        self.log_pruning("scan", &pruning);
+        self.record_reads(files[..frames.len()].iter().map(|add| add.path.as_str()));
+
+        self.mask(match frames.is_empty() {
+            true => schema.empty_frame().lazy(),
+            false => concat(frames, UnionArgs::default())?.limit(n as IdxSize),
+        })
+    }
+
+    // Apply the row filter and the read policy, see `with_row_filter` and
+    // `with_read_policy`
+    fn mask(&self, frame: LazyFrame) -> Result<LazyFrame, DeltaError> {
+        let frame = match &self.row_filter {
+            Some(filter) => frame.filter(filter.clone()),
+            None => frame,
+        };
+        let masks = self.masks()?;
+        Ok(match masks.is_empty() {
+            true => frame,
+            false => frame.with_columns(masks),
+        })
+    }
+
+    fn masks(&self) -> Result<Vec<Expr>, DeltaError> {
+        let Some(policy) = &self.read_policy else {
+            return Ok(vec![]);
+        };
+        Ok(self
+            .metadata
+            .schema()?
+            .fields()
+            .iter()
+            .filter_map(|field| policy.mask(&field.name).map(|mask| mask.alias(&field.name)))
+            .collect())
+    }
+
+    // The scan to run a query over, see `run_query`. Metadata columns the
+    // query refers to are added after the table's own, unless the table has
+    // a column with that name.
+    pub(crate) fn scan_for_query(&self, scan: &QueryScan) -> Result<LazyFrame, DeltaError> {
+        let schema = self.metadata.schema()?;
+        let columns = DeltaTable::metadata_columns(&schema, scan);
+        if columns.is_empty() {
+            return match scan.limit {
+                Some(n) => self.scan_head(n),
+                None => self.scan(),
+            };
+        }
+
+        // With a row filter, the rows past the limit may be the ones left
+        let limit = scan.limit.filter(|_| self.row_filter.is_none());
+        let snapshot = self.snapshot()?;
+        let mut pruning = FilePruning::default();
+        let mut frames = vec![];
+        let mut rows = 0;
+        let mut scanned = 0;
+        for add in &snapshot.files {
+            let Some(n) = limit else {
+                break;
+            };
+            if rows >= n {
+                pruning.record(Some(PruneReason::Limit));
+                continue;
+            }
+            pruning.record(None);
+            let added_in = snapshot.added_in[&add.path];
+            frames.push(self.scan_file_with(add, added_in, &schema, Some(n - rows), &columns)?);
+            rows += self.file_row_count(add)? as usize;
+            scanned += 1;
+        }
+        if limit.is_none() {
+            let files = snapshot
+                .files
+                .iter()
+                .map(|add| (add, snapshot.added_in[&add.path]));
+            frames = self.scan_files(files, &schema, &columns)?;
+            scanned = snapshot.files.len();
+            pruning.files_scanned = scanned;
+        }
+        self.log_pruning("scan", &pruning);
+        self.record_reads(
+            snapshot.files[..scanned]
+                .iter()
+                .map(|add| add.path.as_str()),
+        );
+
+        self.mask(match frames.is_empty() {
+            true => schema
+                .empty_frame()
+                .lazy()
+                .with_columns(DeltaTable::empty_metadata_columns(&columns)),
+            false => concat(frames, UnionArgs::default())?,
+        })
+    }
+
+    fn metadata_columns(schema: &DeltaTableSchema, scan: &QueryScan) -> Vec<&'static str> {
+        scan.metadata_columns
+            .iter()
+            .copied()
+            .filter(|column| !schema.fields().iter().any(|field| field.name == *column))
+            .collect()
+    }
+
+    fn empty_metadata_columns(columns: &[&str]) -> Vec<Expr> {
+        columns
+            .iter()
+            .map(|column| {
+                let typ = match *column {
+                    FILE_NAME_COLUMN => DataType::Utf8,
+                    _ => DataType::Int64,
+                };
+                lit(NULL).cast(typ).alias(column)
+            })
+            .collect()
+    }
+
+    // Run a SELECT against the table, referred to by its name. The query
+    // runs over all of the table's files together, so aggregates, GROUP BY,
+    // HAVING, ORDER BY and LIMIT see every row. To query several tables
+    // together, register them in a `DeltaContext` or `DeltaCatalog`.
+    pub fn query(&self, sql: &str) -> Result<DataFrame, DeltaError> {
+        self.run_query(DeltaTable::parse_query(sql)?, None)
+    }
+
+    // Like `query`, with each `?` in `sql` replaced by the next of `params`,
+    // see `sql::bind_params`
+    pub fn query_with_params(
+        &self,
+        sql: &str,
+        params: &[DeltaValue],
+    ) -> Result<DataFrame, DeltaError> {
+        self.query(&bind_params(sql, params)?)
+    }
+
+    // Like `query`, with the result split into batches that can be used
+    // as soon as each is ready. Queries working on each row on its own, see
+    // `is_row_wise`, are run over one data file at a time, so the first rows
+    // come back without reading the whole table. Any other query's result
+    // is a single batch.
+    pub fn query_batches(
+        &self,
+        sql: &str,
+    ) -> Result<impl Iterator<Item = Result<DataFrame, DeltaError>> + '_, DeltaError> {
+        let query = DeltaTable::parse_query(sql)?;
+        let files = match is_row_wise(&query) {
+            true => {
+                let snapshot = self.snapshot()?;
+                snapshot
+                    .files
+                    .into_iter()
+                    .map(|add| {
+                        let added_in = snapshot.added_in[&add.path];
+                        (add, added_in)
+                    })
+                    .collect()
+            }
+            false => vec![],
+        };
+
+        // Without any files to go through, the query still runs once so
+        // the result has its columns
+        let files = match files.is_empty() {
+            true => vec![None],
+            false => files.into_iter().map(Some).collect(),
+        };
+        Ok(files
+            .into_iter()
+            .map(move |file| self.run_query(query.clone(), file.as_ref())))
+    }
+
+    // Like `query`, with each row deserialized into a `T` by column name
+    pub fn query_as<T: DeserializeOwned>(&self, sql: &str) -> Result<Vec<T>, DeltaError> {
+        deserialize_rows(&self.query(sql)?)
+    }
+
+    fn parse_query(sql: &str) -> Result<Box<Query>, DeltaError> {
+        match parse_statement(sql)? {
+            Statement::Query(query) => Ok(query),
+            _ => Err(DeltaError::InvalidQuery(
+                "only SELECT queries are supported".to_owned(),
+            )),
+        }
+    }
+
+    // Run a query over the whole table, or over just one of its files
+    fn run_query(
+        &self,
+        query: Box<Query>,
+        file: Option<&(Add, i64)>,
+    ) -> Result<DataFrame, DeltaError> {
+        run_query(query, |name, scan| {
+            if unquote_identifier(name) != self.metadata.name() {
+                return Err(DeltaError::TableNotFound(name.to_owned()));
+            }
+            match file {
+                Some((add, added_in)) => {
+                    let schema = self.metadata.schema()?;
+                    let columns = DeltaTable::metadata_columns(&schema, scan);
+                    self.mask(self.scan_file_with(add, *added_in, &schema, None, &columns)?)
+                }
+                None => self.scan_for_query(scan),
+            }
+        })
+    }
+
+    // The first `n` rows of the table, in the order their files were
+    // added. Only the files holding those rows are read, see `scan_head`.
+    pub fn head(&self, n: usize) -> Result<DataFrame, DeltaError> {
+        Ok(self.scan_head(n)?.collect()?)
+    }
+
+    // The table's rows, shaped like `scan`'s, as frames of at most
+    // `batch_size` rows each, so tables bigger than memory can be gone
+    // through without loading them whole. Files are read in the order they
+    // were added and only as the batches are asked for. The files to read
+    // are fixed when this is called, so later commits don't change what's
+    // returned.
+    pub fn iter_batches(
+        &self,
+        batch_size: usize,
+    ) -> Result<impl Iterator<Item = Result<DataFrame, DeltaError>>, DeltaError> {
+        let schema = self.metadata.schema()?;
+        let live = self.live_files()?;
+        let files = live
+            .iter()
+            .map(|add| {
+                Ok(PendingFile {
+                    path: self.data_file_path(&add.path)?,
+                    partition: self.partition_literals(&add.partition_values, &schema)?,
+                })
+            })
+            .collect::<Result<Vec<PendingFile>, DeltaError>>()?;
+        self.record_reads(live.iter().map(|add| add.path.as_str()));
+
+        // Filtered batches can come out smaller than `batch_size`
+        let filter = self.row_filter.clone();
+        let masks = self.masks()?;
+        Ok(
+            Batches::new(files, DeltaTable::table_columns(&schema), batch_size).map(move |batch| {
+                if filter.is_none() && masks.is_empty() {
+                    return batch;
+                }
+                let mut batch = batch?.lazy();
+                if let Some(filter) = &filter {
+                    batch = batch.filter(filter.clone());
+                }
+                if !masks.is_empty() {
+                    batch = batch.with_columns(masks.clone());
+                }
+                Ok(batch.collect()?)
+            }),
+        )
+    }
+
+    // `n` rows picked at random, the same ones for the same `seed` as long
+    // as the table doesn't change. Files are only read if they hold picked
+    // rows, see `file_row_count`, unless the handle has a row filter, which
+    // has to be applied to every row first.
+    // Rows are returned in table order.
+    pub fn sample(&self, n: usize, seed: u64) -> Result<DataFrame, DeltaError> {
+        if self.row_filter.is_some() {
+            let df = self.scan()?.collect()?;
+            let rows = sample_positions(df.height() as u64, n as u64, seed)
+                .into_iter()
+                .map(|position| position as IdxSize)
+                .collect::<Vec<IdxSize>>();
+            return Ok(df.take(&IdxCa::new("", rows))?);
+        }
+
+        let schema = self.metadata.schema()?;
+        let files = self.live_files()?;
+
+        let counts = files
+            .iter()
+            .map(|add| self.file_row_count(add))
+            .collect::<Result<Vec<u64>, DeltaError>>()?;
+
+        let mut picked = sample_positions(counts.iter().sum(), n as u64, seed).into_iter();
+        let mut next = picked.next();
+        let mut offset = 0;
+        let mut df = schema.empty_frame();
+        let mut read = vec![];
+        for (add, count) in files.iter().zip(counts) {
+            let mut rows = vec![];
+            while let Some(position) = next.filter(|p| *p < offset + count) {
+                rows.push((position - offset) as IdxSize);
+                next = picked.next();
+            }
+            offset += count;
+
+            if !rows.is_empty() {
+                let file = self.scan_file(add, &schema, None)?.collect()?;
+                df.vstack_mut(&file.take(&IdxCa::new("", rows))?)?;
+                read.push(add.path.as_str());
+            }
+        }
+        self.record_reads(read);
+
+        Ok(self.mask(df.lazy())?.collect()?)
+    }
+
+    // The number of rows in the table, without reading any data unless the
+    // handle has a row filter
+    pub fn count(&self) -> Result<u64, DeltaError> {
+        if self.row_filter.is_some() {
+            return Ok(self.scan()?.collect()?.height() as u64);
+        }
+
+        self.live_files()?
+            .iter()
+            .map(|add| self.file_row_count(add))
+            .sum()
+    }
+
+    // Rows in a data file, from its stats when it has them and from its
+    // parquet footer otherwise
+    fn file_row_count(&self, add: &Add) -> Result<u64, DeltaError> {
+        match add.parsed_stats() {
+            Some(stats) => Ok(stats.num_records),
+            None => {
+                let mut file = fs::File::open(self.data_file_path(&add.path)?)?;
+                Ok(polars_parquet::read::read_metadata(&mut file)?.num_rows as u64)
+            }
+        }
+    }
+
+    // The rows of a single data file, shaped like `scan`'s, reading at most
+    // `n_rows` of them
+    fn scan_file(
+        &self,
+        add: &Add,
+        schema: &DeltaTableSchema,
+        n_rows: Option<usize>,
+    ) -> Result<LazyFrame, DeltaError> {
+        self.scan_file_with(add, 0, schema, n_rows, &[])
+    }
+
+    // Like `scan_file`, with the metadata columns in `metadata` after the
+    // table's. `added_in` is the version the file was added in.
+    fn scan_file_with(
+        &self,
+        add: &Add,
+        added_in: i64,
+        schema: &DeltaTableSchema,
+        n_rows: Option<usize>,
+        metadata: &[&str],
+    ) -> Result<LazyFrame, DeltaError> {
+        let frame = LazyFrame::scan_parquet(
+            self.data_file_path(&add.path)?,
+            ScanArgsParquet {
+                n_rows,
+                row_count: DeltaTable::row_index(metadata),
+                ..Default::default()
+            },
+        )?;
+        self.shape_file(frame, add, added_in, schema, metadata)
+    }
+
+    // The rows read from a data file with its partition columns added back
+    // and the metadata columns in `metadata` after the table's
+    fn shape_file(
+        &self,
+        frame: LazyFrame,
+        add: &Add,
+        added_in: i64,
+        schema: &DeltaTableSchema,
+        metadata: &[&str],
+    ) -> Result<LazyFrame, DeltaError> {
+        let partition = self.partition_literals(&add.partition_values, schema)?;
+        let mut columns = DeltaTable::table_columns(schema);
+        for column in metadata {
+            columns.push(match *column {
+                FILE_NAME_COLUMN => lit(partition::decode_path(&add.path)).alias(FILE_NAME_COLUMN),
+                COMMIT_VERSION_COLUMN => lit(added_in).alias(COMMIT_VERSION_COLUMN),
+                _ => col(ROW_INDEX_COLUMN).cast(DataType::Int64),
+            });
+        }
+        Ok(frame.with_columns(partition).select(columns))
+    }
+
+    // Counts from the start of the file, even with `n_rows`
+    fn row_index(metadata: &[&str]) -> Option<RowCount> {
+        metadata.contains(&ROW_INDEX_COLUMN).then(|| RowCount {
+            name: ROW_INDEX_COLUMN.to_owned(),
+            offset: 0,
+        })
+    }
+
+    // The rows of `files`, each with the version it was added in, as
+    // `scan_file_with` would scan them. Planning a scan reads the file's
+    // footer, which costs about as much as reading a tiny file, so before a
+    // table is compacted most of the time would go to planning one scan
+    // after another. Instead the scans are planned on polars' thread pool,
+    // and runs of adjacent files under `COALESCE_FILE_SIZE` are unioned
+    // into one frame, which polars reads in parallel. Everything stays
+    // lazy, so projections, predicates and limits still reach every file,
+    // and rows keep their order.
+    fn scan_files<'a>(
+        &self,
+        files: impl IntoIterator<Item = (&'a Add, i64)>,
+        schema: &DeltaTableSchema,
+        metadata: &[&str],
+    ) -> Result<Vec<LazyFrame>, DeltaError> {
+        let files = files.into_iter().collect::<Vec<(&Add, i64)>>();
+        let scans = POOL.install(|| {
+            files
+                .par_iter()
+                .map(|(add, added_in)| self.scan_file_with(add, *added_in, schema, None, metadata))
+                .collect::<Result<Vec<LazyFrame>, DeltaError>>()
+        })?;
+
+        let mut frames = vec![];
+        let mut run = vec![];
+        for ((add, _), scan) in files.iter().zip(scans) {
+            if add.size < COALESCE_FILE_SIZE {
+                run.push(scan);
+                continue;
+            }
+            frames.extend(DeltaTable::union_run(std::mem::take(&mut run))?);
+            frames.push(scan);
+        }
+        frames.extend(DeltaTable::union_run(run)?);
+        Ok(frames)
+    }
+
+    // A run of small files' scans as a single frame, see `scan_files`.
+    // They're rechunked along with the rest of the scan, not on their own.
+    fn union_run(mut run: Vec<LazyFrame>) -> Result<Option<LazyFrame>, DeltaError> {
+        match run.len() {
+            0 => Ok(None),
+            1 => Ok(run.pop()),
+            _ => Ok(Some(concat(
+                run,
+                UnionArgs {
+                    rechunk: false,
+                    ..Default::default()
+                },
+            )?)),
+        }
+    }
+
+    // The table's columns in schema order, cast to the table's types
+    fn table_columns(schema: &DeltaTableSchema) -> Vec<Expr> {
+        schema
+            .fields()
+            .iter()
+            .map(|field| col(&field.name).cast(field.typ.to_polars_type()))
+            .collect()
+    }
+
+    // Re-read every live data file and check it against the size and
+    // checksum it was committed with, to catch files that were modified,
+    // truncated or lost after being written
+    pub fn verify_data(&self) -> Result<VerifyReport, DeltaError> {
+        let mut report = VerifyReport::default();
+        for add in self.live_files()? {
+            let path = self.data_file_path(&add.path)?;
+            let size = match fs::metadata(&path) {
+                Ok(metadata) => metadata.len(),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    report.missing.push(add.path);
+                    continue;
+                }
+                Err(e) => return Err(DeltaError::IOError(e)),
+            };
+            if size != add.size {
+                report.corrupted.push(add.path);
+                continue;
+            }
+
+            let expected = add.tags.as_ref().and_then(|tags| tags.get(CHECKSUM_TAG));
+            let actual = match expected {
+                Some(expected) => checksum::file_checksum(&path, expected)?,
+                None => None,
+            };
+            match (expected, actual) {
+                (Some(expected), Some(actual)) if *expected != actual => {
+                    report.corrupted.push(add.path)
+                }
+                (_, Some(_)) => report.files_verified += 1,
+                (_, None) => report.files_unverified += 1,
+            }
+        }
+
+        Ok(report)
+    }
+
+    // The table's live data files with their stats and tags, in the order
+    // they were added
+    pub fn files(&self) -> Result<Vec<FileInfo>, DeltaError> {
+        Ok(self
+            .live_files()?
+            .into_iter()
+            .map(DeltaTable::file_info)
+            .collect())
+    }
+
+    // The partitions the table's live data files are in, ordered by their
+    // values, with how many files and bytes each holds. Empty for a table
+    // that isn't partitioned.
+    pub fn partitions(&self) -> Result<Vec<PartitionInfo>, DeltaError> {
+        let partition_columns = self.metadata.partition_columns();
+        if partition_columns.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut partitions: BTreeMap<Vec<Option<String>>, PartitionInfo> = BTreeMap::new();
+        for add in self.live_files()? {
+            let values = partition_columns
+                .iter()
+                .map(|column| add.partition_values.get(column).cloned().flatten())
+                .collect::<Vec<_>>();
+            let partition = partitions
+                .entry(values.clone())
+                .or_insert_with(|| PartitionInfo {
+                    values: partition_columns.iter().cloned().zip(values).collect(),
+                    num_files: 0,
+                    size: 0,
+                    num_records: Some(0),
+                });
+            partition.num_files += 1;
+            partition.size += add.size;
+            partition.num_records = partition
+                .num_records
+                .zip(add.parsed_stats())
+                .map(|(records, stats)| records + stats.num_records);
+        }
+
+        Ok(partitions.into_values().collect())
+    }
+
+    fn file_info(add: Add) -> FileInfo {
+        let num_records = add.parsed_stats().map(|stats| stats.num_records);
+        FileInfo {
+            path: add.path,
+            size: add.size,
+            modification_time: add.modification_time,
+            partition_values: add.partition_values,
+            num_records,
+            tags: add.tags.unwrap_or_default(),
+        }
+    }
+
+    // The files a scan filtered by `expr` has to read, with the stats of
+    // each of their row groups. Files are pruned by their stats in the log
+    // like deletes are, and only the footers of the files left are read.
+    pub fn plan_scan(&self, expr: &str) -> Result<ScanPlan, DeltaError> {
+        self.plan_scan_where(parse_predicate(expr)?)
+    }
+
+    pub fn plan_scan_where(&self, predicate: Expr) -> Result<ScanPlan, DeltaError> {
+        self.validate_predicate(&predicate)?;
+        let schema = self.metadata.schema()?;
+
+        let snapshot = self.snapshot()?;
+        let mut plan = ScanPlan {
+            version: snapshot.version,
+            files: vec![],
+            files_skipped: 0,
+        };
+        for add in snapshot.files {
+            let stats = add
+                .parsed_stats()
+                .map(|stats| stats.with_partition_values(&add.partition_values, &schema));
+            if !stats
+                .clone()
+                .unwrap_or_default()
+                .with_partition_values(&add.partition_values, &schema)
+                .can_match(&predicate, &schema)
+            {
+                plan.files_skipped += 1;
+                continue;
+            }
+
+            let row_groups = row_group_stats(&self.data_file_path(&add.path)?)?
+                .into_iter()
+                .map(|(size, stats)| {
+                    let stats = stats.with_partition_values(&add.partition_values, &schema);
+                    RowGroupInfo {
+                        size,
+                        may_match: stats.can_match(&predicate, &schema),
+                        stats: stats.into(),
+                    }
+                })
+                .collect();
+            plan.files.push(PlannedFile {
+                file: DeltaTable::file_info(add),
+                stats: stats.map(Into::into),
+                row_groups,
+            });
+        }
+
+        Ok(plan)
+    }
+
+    // What `sql`, a SELECT or a DELETE against the table, would read: the
+    // files it scans, the ones it prunes and why, and polars' plan for it.
+    // Only the log and the footers of the files scanned are read. Queries
+    // only prune files past their LIMIT, see `scan_head`, while deletes
+    // prune by partition values and stats. A leading EXPLAIN is allowed.
+    pub fn explain(&self, sql: &str) -> Result<Explain, DeltaError> {
+        let statement = match parse_statement(sql)? {
+            Statement::Explain { statement, .. } => *statement,
+            statement => statement,
+        };
+        match statement {
+            Statement::Query(query) => {
+                let mut tables = vec![];
+                let logical_plan = describe_query(query, |name, scan| {
+                    if unquote_identifier(name) != self.metadata.name() {
+                        return Err(DeltaError::TableNotFound(name.to_owned()));
+                    }
+                    tables.push(self.explain_scan(name, scan)?);
+                    self.scan_for_query(scan)
+                })?;
+                Ok(Explain {
+                    tables,
+                    logical_plan,
+                })
+            }
+            Statement::Delete {
+                from, selection, ..
+            } => {
+                let (name, predicate) = delete_target(&from, selection)?;
+                if unquote_identifier(&name) != self.metadata.name() {
+                    return Err(DeltaError::TableNotFound(name));
+                }
+                self.explain_delete(&name, &predicate)
+            }
+            _ => Err(DeltaError::InvalidQuery(
+                "only SELECT and DELETE can be explained".to_owned(),
+            )),
+        }
+    }
+
+    // The files `scan_for_query` reads for `scan`
+    pub(crate) fn explain_scan(
+        &self,
+        name: &str,
+        scan: &QueryScan,
+    ) -> Result<TableScan, DeltaError> {
+        let snapshot = self.snapshot()?;
+        let mut table_scan = TableScan {
+            table: name.to_owned(),
+            version: snapshot.version,
+            files_scanned: vec![],
+            files_pruned: vec![],
+        };
+
+        let mut rows = 0;
+        for add in snapshot.files {
+            if scan.limit.is_some_and(|n| rows >= n) {
+                table_scan.files_pruned.push(PrunedFile {
+                    path: add.path,
+                    reason: PruneReason::Limit,
+                });
+                continue;
+            }
+            if scan.limit.is_some() {
+                rows += self.file_row_count(&add)? as usize;
+            }
+            table_scan.files_scanned.push(add.path);
+        }
+
+        Ok(table_scan)
+    }
+
+    // The files a delete by `expr` would rewrite or prune, see
+    // `rewrite_without`
+    pub(crate) fn explain_delete(&self, name: &str, expr: &str) -> Result<Explain, DeltaError> {
+        let predicate = parse_predicate(expr)?;
+        self.validate_predicate(&predicate)?;
+        let schema = self.metadata.schema()?;
+
+        let snapshot = self.snapshot()?;
+        let mut table_scan = TableScan {
+            table: name.to_owned(),
+            version: snapshot.version,
+            files_scanned: vec![],
+            files_pruned: vec![],
+        };
+        let mut frames = vec![];
+        for add in snapshot.files {
+            let Some(reason) = DeltaTable::prune_reason(&add, &predicate, None, &schema)? else {
+                frames.push(self.scan_file(&add, &schema, None)?);
+                table_scan.files_scanned.push(add.path);
+                continue;
+            };
+            table_scan.files_pruned.push(PrunedFile {
+                path: add.path,
+                reason,
+            });
+        }
+
+        let frame = match frames.is_empty() {
+            true => schema.empty_frame().lazy(),
+            false => concat(frames, UnionArgs::default())?,
+        };
+        Ok(Explain {
+            tables: vec![table_scan],
+            logical_plan: frame.filter(predicate).describe_optimized_plan()?,
+        })
+    }
+
+    pub fn get_datafiles(&self) -> Result<HashSet<String>, DeltaError> {
+        Ok(self.live_files()?.into_iter().map(|add| add.path).collect())
+    }
+
+    // What changed between versions `from` and `to`: the files added and
+    // removed, how many rows that adds up to, and any schema changes
+    pub fn diff(&self, from: i64, to: i64) -> Result<TableDiff, DeltaError> {
+        let from = self.snapshot_at(Some(from))?;
+        let to = self.snapshot_at(Some(to))?;
+
+        diff::diff(
+            DiffSide {
+                version: from.version,
+                metadata: &from.metadata,
+                files: &from.files,
+            },
+            DiffSide {
+                version: to.version,
+                metadata: &to.metadata,
+                files: &to.files,
+            },
+        )
+    }
+
+    // Write a checkpoint of the table as of its latest version, e.g. for
+    // readers that only bootstrap from checkpoints rather than replaying
+    // every commit. Reads replay the log from the latest checkpoint too,
+    // so on a table with a long history they only read the commits after
+    // it.
+    pub fn checkpoint(&self) -> Result<CheckpointInfo, DeltaError> {
+        self.check_not_pinned()?;
+        let snapshot = self.snapshot()?;
+        write_checkpoint(
+            &*self.log_store,
+            snapshot.version,
+            &snapshot.protocol,
+            &snapshot.metadata,
+            &snapshot.app_versions,
+            &snapshot.files,
+        )
+    }
+
+    // Write a symlink format manifest of the table's current files, for
+    // engines like Presto, Trino or Athena that read Delta tables through
+    // one. The manifest isn't kept up to date by later writes, so it has to
+    // be generated again after the table changes.
+    pub fn generate_symlink_manifest(&self) -> Result<ManifestInfo, DeltaError> {
+        self.check_not_pinned()?;
+        let snapshot = self.snapshot()?;
+        write_symlink_manifests(
+            &self.base_dir,
+            snapshot.version,
+            snapshot.metadata.partition_columns(),
+            &snapshot.files,
+        )
+    }
+
+    // Delete data files the table no longer refers to, e.g. ones replaced
+    // by a delete or left behind by a failed write, once they haven't been
+    // referred to for `VacuumOptions::retention`. Hidden files and
+    // directories, starting with `_` or `.`, like the log and symlink
+    // manifests, are left alone. Reports what was deleted and what was
+    // kept, with paths relative to the table's directory.
+    pub fn vacuum(&self, options: &VacuumOptions) -> Result<VacuumMetrics, DeltaError> {
+        self.check_not_pinned()?;
+        let start = Instant::now();
+        let snapshot = self.snapshot()?;
+        snapshot.protocol.check_writable()?;
+
+        let now = SystemTime::now();
+        let cutoff = now
+            .checked_sub(options.retention)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+
+        // A file was last referred to when the commit removing it was
+        // written. Files that were never committed, or were removed by a
+        // commit that has since been cleaned up, only have their own
+        // modification time to go by. What's live comes from the snapshot,
+        // as the commits adding some files may be gone.
+        let live = snapshot
+            .files
+            .iter()
+            .map(|add| partition::decode_path(&add.path))
+            .collect::<HashSet<String>>();
+        let mut removed = HashMap::new();
+        for (_, log) in self.log_versions()? {
+            let committed = self.log_store.modified(&log)?;
+            for line in DeltaTable::read_log(&*self.log_store, &log)?.lines() {
+                if let Some(Action::Remove(remove)) = self.parse_action(line)? {
+                    removed.insert(partition::decode_path(&remove.path), committed);
+                }
+            }
+        }
+
+        let millis = |time: SystemTime| {
+            time.duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |since| since.as_millis() as i64)
+        };
+        let mut metrics = VacuumMetrics {
+            files_deleted: vec![],
+            bytes_reclaimed: 0,
+            files_live: 0,
+            files_within_retention: vec![],
+            duration: Duration::ZERO,
+        };
+        let mut dirs = vec![PathBuf::new()];
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(Path::new(&self.base_dir).join(&dir))? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if name.starts_with('_') || name.starts_with('.') {
+                    continue;
+                }
+
+                let path = dir.join(&name);
+                let metadata = entry.metadata()?;
+                if metadata.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+
+                let path = path.to_string_lossy().into_owned();
+                let last_used = match removed.get(&path) {
+                    Some(removed) => metadata.modified()?.max(*removed),
+                    None => metadata.modified()?,
+                };
+                if live.contains(&path) {
+                    metrics.files_live += 1;
+                } else if last_used > cutoff {
+                    metrics.files_within_retention.push(path);
+                } else {
+                    metrics.bytes_reclaimed += metadata.len();
+                    metrics.files_deleted.push(DeletedFile {
+                        path,
+                        size: metadata.len(),
+                        last_used: millis(last_used),
+                        deleted_at: millis(now),
+                    });
+                }
+            }
+        }
+        metrics.files_deleted.sort_by(|a, b| a.path.cmp(&b.path));
+        metrics.files_within_retention.sort();
+
+        if !options.dry_run {
+            // Files deleted before a failure are still recorded
+            let mut result = Ok(());
+            let mut deleted = 0;
+            for file in &metrics.files_deleted {
+                result = fs::remove_file(Path::new(&self.base_dir).join(&file.path));
+                if result.is_err() {
+                    break;
+                }
+                deleted += 1;
+            }
+            if options.inventory && deleted > 0 {
+                self.write_vacuum_inventory(&metrics.files_deleted[..deleted], millis(now))?;
+            }
+            result?;
+        }
+
+        metrics.duration = start.elapsed();
+        Ok(metrics)
+    }
+
+    // Where vacuum records what it deleted, one file per run. Like the
+    // log, it's hidden from vacuum itself.
+    fn vacuum_inventory_dir(&self) -> PathBuf {
+        Path::new(&self.base_dir).join("_delta_vacuum")
+    }
+
+    fn write_vacuum_inventory(&self, files: &[DeletedFile], time: i64) -> Result<(), DeltaError> {
+        let dir = self.vacuum_inventory_dir();
+        fs::create_dir_all(&dir)?;
+        let contents = files
+            .iter()
+            .map(serde_json::to_string)
+            .collect::<Result<Vec<String>, _>>()?
+            .join("\n");
+        let name = format!("{:020}-{}.json", time, Uuid::new_v4());
+        fs::write(dir.join(name), contents)?;
+        Ok(())
+    }
+
+    // Every file deleted by a vacuum with `VacuumOptions::inventory`, in
+    // the order they were deleted
+    pub fn vacuum_inventory(&self) -> Result<Vec<DeletedFile>, DeltaError> {
+        let dir = self.vacuum_inventory_dir();
+        if !dir.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut runs = fs::read_dir(&dir)?
+            .map(|entry| Ok(entry?.path()))
+            .collect::<Result<Vec<PathBuf>, DeltaError>>()?;
+        runs.sort();
+
+        let mut files = vec![];
+        for run in runs {
+            for line in fs::read_to_string(run)?.lines() {
+                if !line.trim().is_empty() {
+                    files.push(serde_json::from_str(line)?);
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    // Where handles with `with_usage_tracking` record their reads. Like
+    // the log, it's hidden from vacuum.
+    fn usage_file(&self) -> PathBuf {
+        Path::new(&self.base_dir)
+            .join("_delta_usage")
+            .join("reads.json")
+    }
+
+    // Record a read of each of `paths` in the usage sidecar, one line each,
+    // when this handle tracks usage. The lines are appended in one write so
+    // those of concurrent readers don't interleave. Usage is only advisory,
+    // so failing to record it doesn't fail the read.
+    fn record_reads<'a>(&self, paths: impl IntoIterator<Item = &'a str>) {
+        if !self.track_usage {
+            return;
+        }
+        let read_at = DeltaTable::modification_time();
+        let contents = paths
+            .into_iter()
+            .map(|path| json!({"path": path, "readAt": read_at}).to_string() + "\n")
+            .collect::<String>();
+        if contents.is_empty() {
+            return;
+        }
+
+        let file = self.usage_file();
+        let result = file
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| {
+                fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&file)?
+                    .write_all(contents.as_bytes())
+            });
+        if let Err(e) = result {
+            warn!(target: "delta::usage", "table={} can't record reads: {}", self.base_dir, e);
+        }
+    }
+
+    // How often each of the table's live files was read by handles with
+    // `with_usage_tracking`, and when last, e.g. to pick the files worth
+    // caching or compacting, or cold enough to move to cheaper storage.
+    // Most read first, with files that were never read last.
+    pub fn file_usage(&self) -> Result<Vec<FileUsage>, DeltaError> {
+        let mut reads: HashMap<String, (u64, i64)> = HashMap::new();
+        match fs::read_to_string(self.usage_file()) {
+            Ok(contents) => {
+                // A line cut short by a crashed reader is skipped
+                for read in contents
+                    .lines()
+                    .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+                {
+                    let (Some(path), Some(read_at)) =
+                        (read["path"].as_str(), read["readAt"].as_i64())
+                    else {
+                        continue;
+                    };
+                    let entry = reads.entry(path.to_owned()).or_default();
+                    entry.0 += 1;
+                    entry.1 = entry.1.max(read_at);
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(DeltaError::IOError(e)),
+        }
+
+        let mut usage = self
+            .live_files()?
+            .into_iter()
+            .map(|add| {
+                let read = reads.get(&add.path);
+                FileUsage {
+                    reads: read.map_or(0, |(reads, _)| *reads),
+                    last_read: read.map(|(_, read_at)| *read_at),
+                    path: add.path,
+                    size: add.size,
+                }
+            })
+            .collect::<Vec<FileUsage>>();
+        usage.sort_by(|a, b| b.reads.cmp(&a.reads).then_with(|| a.path.cmp(&b.path)));
+        Ok(usage)
+    }
+
+    // Forget every read recorded for `file_usage`
+    pub fn clear_usage(&self) -> Result<(), DeltaError> {
+        match fs::remove_file(self.usage_file()) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(DeltaError::IOError(e)),
+            _ => Ok(()),
+        }
+    }
+
+    // Move data files that are old and rarely read to cheaper storage, see
+    // `ArchiveOptions`. Each file is copied to the destination, then a
+    // single commit swaps its Add for one with the copy's absolute URI,
+    // keeping its stats and tags. Rows don't change, so the commit's
+    // actions are marked as not changing data. The originals are left for
+    // `vacuum` to delete once past retention, so older versions stay
+    // readable until then. Copies are never vacuumed.
+    pub fn archive(&self, options: &ArchiveOptions) -> Result<ArchiveMetrics, DeltaError> {
+        let start = Instant::now();
+        self.check_not_pinned()?;
+        let destination = match options.destination.split_once("://") {
+            Some(("file", path)) => path,
+            Some(_) => return Err(DeltaError::UnsupportedStorage(options.destination.clone())),
+            None => options.destination.as_str(),
+        };
+        fs::create_dir_all(destination)?;
+        let destination = fs::canonicalize(destination)?;
+
+        let snapshot = self.snapshot()?;
+        let reads = match options.max_reads {
+            Some(_) => self
+                .file_usage()?
+                .into_iter()
+                .map(|usage| (usage.path, usage.reads))
+                .collect::<HashMap<String, u64>>(),
+            None => HashMap::new(),
+        };
+        let cutoff =
+            DeltaTable::modification_time().saturating_sub(options.min_age.as_millis() as i64);
+
+        let mut files = snapshot
+            .files
+            .into_iter()
+            .filter(|add| {
+                !partition::is_absolute_uri(&add.path)
+                    && add.modification_time <= cutoff
+                    && options.max_reads.is_none_or(|max_reads| {
+                        reads.get(&add.path).copied().unwrap_or(0) <= max_reads
+                    })
+            })
+            .collect::<Vec<Add>>();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut actions = vec![];
+        for add in &files {
+            let relative = partition::decode_path(&add.path);
+            let target = destination.join(&relative);
+            if let Some(dir) = target.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::copy(self.data_file_path(&add.path)?, &target)?;
+
+            actions.push(Action::Add(Add {
+                path: format!(
+                    "file://{}",
+                    partition::encode_path(&target.to_string_lossy())
+                ),
+                data_change: false,
+                ..add.clone()
+            }));
+            actions.push(Action::Remove(Remove {
+                path: add.path.clone(),
+                data_change: false,
+                tags: add.tags.clone(),
+            }));
+        }
+
+        let bytes_archived: u64 = files.iter().map(|add| add.size).sum();
+        let version = match actions.is_empty() {
+            true => snapshot.version,
+            false => {
+                let mut parameters = HashMap::from([
+                    (
+                        "destination".to_owned(),
+                        Value::from(destination.to_string_lossy()),
+                    ),
+                    (
+                        "minAge".to_owned(),
+                        Value::from(options.min_age.as_millis() as u64),
+                    ),
+                ]);
+                if let Some(max_reads) = options.max_reads {
+                    parameters.insert("maxReads".to_owned(), Value::from(max_reads));
+                }
+                let mut info = self.commit_info("ARCHIVE", parameters);
+                info.operation_metrics
+                    .insert("numArchivedBytes".to_owned(), bytes_archived.to_string());
+                self.commit(snapshot.version, info, actions)?
+            }
+        };
+
+        Ok(ArchiveMetrics {
+            files_archived: files.into_iter().map(|add| add.path).collect(),
+            bytes_archived,
+            duration: start.elapsed(),
+            version,
+        })
+    }
+
+    // Raise the table's protocol to reader version `min_reader` and writer
+    // version `min_writer`, and add `features` to it, e.g. to move to
+    // table features. Like Spark, lowering either version is an error, and
+    // so is asking for a protocol this crate can't read or write, e.g. one
+    // with `deletionVectors`. Returns the version committed, or the
+    // table's current version when the protocol already has everything
+    // asked for.
+    pub fn upgrade_protocol(
+        &self,
+        min_reader: i32,
+        min_writer: i32,
+        features: &[&str],
+    ) -> Result<i64, DeltaError> {
+        let snapshot = self.snapshot()?;
+        let protocol = snapshot
+            .protocol
+            .upgrade(min_reader, min_writer, features)?;
+        if protocol == snapshot.protocol {
+            return Ok(snapshot.version);
+        }
+        // Committing a protocol this crate can't read or write would lock
+        // it out of the table
+        protocol.check_readable()?;
+        protocol.check_writable()?;
+
+        let info = self.commit_info(
+            "UPGRADE PROTOCOL",
+            HashMap::from([(
+                "newProtocol".to_owned(),
+                Value::from(serde_json::to_string(&protocol)?),
+            )]),
+        );
+        self.commit(snapshot.version, info, vec![Action::Protocol(protocol)])
+    }
+
+    fn live_files(&self) -> Result<Vec<Add>, DeltaError> {
+        Ok(self.snapshot()?.files)
+    }
+
+    pub(crate) fn snapshot(&self) -> Result<Snapshot, DeltaError> {
+        self.snapshot_at(self.pinned_version)
+    }
+
+    // Replay the log forward up to `version`, or all of it, and return the
+    // Add actions for every file that is still part of the table, in the
+    // order they were added. Replaying starts from the table's cached
+    // snapshot when it isn't past `version`, see `cache`, and is skipped
+    // when that's the version asked for.
+    fn snapshot_at(&self, version: Option<i64>) -> Result<Snapshot, DeltaError> {
+        let (versions, checkpoints) = DeltaTable::list_log(&*self.log_store)?;
+        if let Some(version) = version {
+            if !versions.iter().any(|(v, _)| *v == version) && !checkpoints.contains_key(&version) {
+                return Err(DeltaError::VersionNotFound(version));
+            }
+        }
+
+        let cache_key = self.cache_key();
+        let cached = cache::get(&cache_key)
+            .filter(|cached| version.is_none_or(|version| cached.version <= version));
+
+        // Most reads find nothing new in the log, and the cached snapshot
+        // is already the table as of the version asked for
+        let target = version.or(versions.last().map(|(version, _)| *version));
+        if let Some(cached) = cached
+            .as_ref()
+            .filter(|cached| Some(cached.version) == target)
+        {
+            return Ok(Snapshot::clone(cached));
+        }
+
+        let mut seq = 0;
+        let mut read_version = -1;
+        let mut metadata = self.metadata.clone();
+        let mut protocol = Protocol::default();
+        let mut app_versions = HashMap::new();
+        let mut data_files: HashMap<String, (usize, i64, Add)> = HashMap::new();
+        if let Some(cached) = &cached {
+            read_version = cached.version;
+            metadata = cached.metadata.clone();
+            protocol = cached.protocol.clone();
+            app_versions = cached.app_versions.clone();
+            for add in &cached.files {
+                let added_in = cached.added_in[&add.path];
+                data_files.insert(add.path.clone(), (seq, added_in, add.clone()));
+                seq += 1;
+            }
+        }
+        let cached_version = read_version;
+
+        // Start from the latest checkpoint there's anything to replay after,
+        // rather than from the first commit. Which version its files were
+        // added in is lost, so they count as added in the checkpoint's.
+        let checkpoint = checkpoints
+            .range(..=version.unwrap_or(i64::MAX))
+            .next_back()
+            .filter(|(checkpoint_version, _)| **checkpoint_version > cached_version);
+        if let Some((checkpoint_version, files)) = checkpoint {
+            read_version = *checkpoint_version;
+            data_files.clear();
+            app_versions.clear();
+            protocol = Protocol::default();
+            for action in read_checkpoint(&*self.log_store, files)? {
+                match action {
+                    Action::Add(add) => {
+                        data_files.insert(add.path.clone(), (seq, read_version, add));
+                        seq += 1;
+                    }
+                    Action::Metadata(m) => {
+                        m.check_schema(read_version)?;
+                        metadata = m;
+                    }
+                    Action::Protocol(p) => protocol = p,
+                    Action::Txn(txn) => {
+                        app_versions.insert(txn.app_id, txn.version);
+                    }
+                    Action::Remove(_) | Action::CommitInfo(_) => {}
+                }
+            }
+        }
+        let replay_from = read_version;
+
+        for (log_version, log) in versions {
+            if log_version <= replay_from {
+                continue;
+            }
+            if version.is_some_and(|version| log_version > version) {
+                break;
+            }
+
+            read_version = log_version;
+            for line in DeltaTable::read_log(&*self.log_store, &log)?.lines() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let Some(action) =
+                    DeltaTable::parse_commit_action(log_version, line, self.lenient)?
+                else {
+                    continue;
+                };
+
+                match action {
+                    Action::Add(add) => {
+                        data_files.insert(add.path.clone(), (seq, log_version, add));
+                        seq += 1;
+                    }
+                    Action::Remove(remove) => {
+                        data_files.remove(&remove.path);
+                    }
+                    Action::Metadata(m) => {
+                        m.check_schema(log_version)?;
+                        metadata = m;
+                    }
+                    Action::Protocol(p) => protocol = p,
+                    Action::Txn(txn) => {
+                        app_versions.insert(txn.app_id, txn.version);
+                    }
+                    Action::CommitInfo(_) => {}
+                }
+            }
+        }
+
+        protocol.check_readable()?;
+
+        let mut data_files: Vec<(usize, i64, Add)> = data_files.into_values().collect();
+        data_files.sort_by_key(|(seq, _, _)| *seq);
+
+        let snapshot = Snapshot {
+            version: read_version,
+            metadata,
+            protocol,
+            app_versions,
+            added_in: data_files
+                .iter()
+                .map(|(_, version, add)| (add.path.clone(), *version))
+                .collect(),
+            files: data_files.into_iter().map(|(_, _, add)| add).collect(),
+        };
+        if read_version > cached_version {
+            cache::put(&cache_key, snapshot.clone());
+        }
+
+        Ok(snapshot)
+    }
+
+    // Identifies the table in the snapshot cache. A table deleted and
+    // created again at the same path gets a new id, so it never picks up
+    // the old table's snapshot.
+    fn cache_key(&self) -> String {
+        format!(
+            "{}#{}#{}",
+            self.log_store.location(),
+            self.metadata.id(),
+            self.lenient
+        )
+    }
+
+    // A file rewritten without some of its rows keeps the original's tags,
+    // except for its checksum, with this handle's tags on top
+    fn rewritten_tags(&self, add: &Add) -> HashMap<String, String> {
+        let mut tags = add.tags.clone().unwrap_or_default();
+        tags.remove(CHECKSUM_TAG);
+        tags.extend(self.write_options.tags.clone());
+        tags
+    }
+
+    // Data files get random names so concurrent writers never collide.
+    // The name is relative to the table's directory.
+    fn next_data_file(&self, partition_values: &PartitionValues) -> String {
+        let name = format!("part-{}.parquet", Uuid::new_v4());
+        match self.metadata.partition_columns().is_empty() {
+            true => name,
+            false => format!(
+                "{}/{}",
+                partition::directory(self.metadata.partition_columns(), partition_values),
+                name
+            ),
+        }
+    }
+
+    // Paths in the log are URI encoded, and relative to the table's
+    // directory unless the file was archived somewhere else. Only local
+    // files can be read for now.
+    fn data_file_path(&self, path: &str) -> Result<PathBuf, DeltaError> {
+        if !partition::is_absolute_uri(path) {
+            return Ok(PathBuf::from(format!(
+                "{}/{}",
+                self.base_dir,
+                partition::decode_path(path)
+            )));
+        }
+
+        match path.strip_prefix("file:") {
+            Some(local) => Ok(PathBuf::from(partition::decode_path(
+                local.trim_start_matches("//"),
+            ))),
+            None => Err(DeltaError::UnsupportedStorage(path.to_owned())),
+        }
+    }
+
+    // The partition columns of a data file as literals, to add back onto
+    // the rows read from it
+    fn partition_literals(
+        &self,
+        partition_values: &PartitionValues,
+        schema: &DeltaTableSchema,
+    ) -> Result<Vec<Expr>, DeltaError> {
+        schema
+            .fields()
+            .iter()
+            .filter(|field| self.metadata.partition_columns().contains(&field.name))
+            .map(|field| {
+                let value = partition_values.get(&field.name).cloned().flatten();
+                partition::literal(&field.name, value.as_deref(), &field.typ)
+            })
+            .collect()
+    }
+
+    // The version of the latest commit in the log, i.e. the version reads
+    // through this handle see until something else is committed, or the
+    // version the handle is pinned to. Every write returns the version it
+    // committed, so a job processing the table incrementally can remember
+    // where it got to, see `diff` and `wait_for_commit`.
+    pub fn version(&self) -> Result<i64, DeltaError> {
+        match self.pinned_version {
+            Some(version) => Ok(version),
+            None => Ok(self.next_version()? - 1),
+        }
+    }
+
+    // Versions of every commit in the log, oldest first
+    pub fn versions(&self) -> Result<Vec<i64>, DeltaError> {
+        Ok(self
+            .log_versions()?
+            .into_iter()
+            .map(|(version, _)| version)
+            .collect())
+    }
+
+    // Block until there's a commit later than `version` in the log, e.g. to
+    // pick up what a writer in another process committed, and return the
+    // latest version. The log is checked every `poll_interval`.
+    pub fn wait_for_commit(
+        &self,
+        version: i64,
+        poll_interval: Duration,
+    ) -> Result<i64, DeltaError> {
+        #[cfg(feature = "async")]
+        if crate::nonblocking::in_async_runtime() {
+            warn!(
+                target: "delta::async",
+                "table={} waiting for a commit blocks the async runtime, see `AsyncDeltaTable`",
+                self.base_dir
+            );
+        }
+        loop {
+            let latest = self.next_version()? - 1;
+            if latest > version {
+                return Ok(latest);
+            }
+            thread::sleep(poll_interval);
+        }
+    }
+
+    // One entry per version of the table, latest first, up to `limit`
+    // entries when given
+    pub fn history(&self, limit: Option<usize>) -> Result<Vec<HistoryEntry>, DeltaError> {
+        let mut history = vec![];
+        for (version, log) in self
+            .log_versions()?
+            .into_iter()
+            .rev()
+            .take(limit.unwrap_or(usize::MAX))
+        {
+            let mut info = None;
+            let mut metrics = OperationMetrics {
+                rows_added: Some(0),
+                ..OperationMetrics::default()
+            };
+            for line in DeltaTable::read_log(&*self.log_store, &log)?.lines() {
+                match self.parse_action(line)? {
+                    Some(Action::CommitInfo(commit_info)) => info = Some(commit_info),
+                    Some(Action::Add(add)) => {
+                        metrics.files_added += 1;
+                        metrics.bytes_added += add.size;
+                        metrics.rows_added = metrics
+                            .rows_added
+                            .zip(add.parsed_stats())
+                            .map(|(rows, stats)| rows + stats.num_records);
+                    }
+                    Some(Action::Remove(_)) => metrics.files_removed += 1,
+                    _ => {}
+                }
+            }
+
+            let timestamp = match &info {
+                Some(info) => info.timestamp,
+                None => self
+                    .log_store
+                    .modified(&log)?
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .map_or(0, |since| since.as_millis() as i64),
+            };
+            let (operation, operation_parameters, user_metadata) = match info {
+                Some(info) => (
+                    Some(info.operation),
+                    info.operation_parameters,
+                    info.user_metadata,
+                ),
+                None if version == 0 => (Some("CREATE TABLE".to_owned()), HashMap::new(), None),
+                None => (None, HashMap::new(), None),
+            };
+            history.push(HistoryEntry {
+                version,
+                timestamp,
+                operation,
+                operation_parameters,
+                user_metadata,
+                metrics,
+            });
+        }
+
+        Ok(history)
+    }
+
+    // The actions of commit `version` as they're stored in the log, one
+    // JSON object per action. Actions this crate doesn't know about are
+    // included as well.
+    pub fn read_commit(&self, version: i64) -> Result<Vec<Value>, DeltaError> {
+        let name = DeltaTable::log_file(version as usize);
+        let contents = match DeltaTable::read_log(&*self.log_store, &name) {
+            Ok(contents) => contents,
+            Err(DeltaError::IOError(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(DeltaError::VersionNotFound(version))
+            }
+            Err(e) => return Err(e),
+        };
+
+        contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| Ok(serde_json::from_str(line)?))
+            .collect()
+    }
+
+    // `parse_action` for a line of the commit with `version`. A metaData
+    // action that doesn't parse leaves the table without a schema from that
+    // version on, so it's reported as corrupt metadata rather than as the
+    // JSON error.
+    fn parse_commit_action(
+        version: i64,
+        line: &str,
+        lenient: bool,
+    ) -> Result<Option<Action>, DeltaError> {
+        parse_action(line, lenient).map_err(|e| {
+            let is_metadata = serde_json::from_str::<Value>(line).is_ok_and(|action| {
+                action.as_object().is_some_and(|action| {
+                    action
+                        .keys()
+                        .any(|kind| kind.eq_ignore_ascii_case("metaData"))
+                })
+            });
+            match is_metadata {
+                true => DeltaError::CorruptMetadata {
+                    version,
+                    detail: match e {
+                        DeltaError::JsonError(e) => e.to_string(),
+                        e => format!("{:?}", e),
+                    },
+                },
+                false => e,
+            }
+        })
+    }
+
+    // Blank lines are skipped even when parsing strictly, as some writers
+    // end commits with an empty line
+    fn parse_action(&self, line: &str) -> Result<Option<Action>, DeltaError> {
+        match line.trim().is_empty() {
+            true => Ok(None),
+            false => parse_action(line, self.lenient),
+        }
+    }
+
+    fn next_version(&self) -> Result<i64, DeltaError> {
+        Ok(self
+            .log_versions()?
+            .last()
+            .map_or(0, |(version, _)| version + 1))
+    }
+
+    // The commits in the log, sorted by version. Anything in the log
+    // directory that isn't a commit file is ignored.
+    fn log_versions(&self) -> Result<Vec<(i64, String)>, DeltaError> {
+        Ok(DeltaTable::list_log(&*self.log_store)?.0)
+    }
+
+    // The commits in the log, like `log_versions`, and its complete
+    // checkpoints, see `complete_checkpoints`
+    fn list_log(log_store: &dyn LogStore) -> Result<LogListing, DeltaError> {
+        let names = log_store.list()?;
+        let mut versions = vec![];
+        for name in &names {
+            let version = name
+                .strip_suffix(".json")
+                .and_then(|version| version.parse::<i64>().ok());
+
+            if let Some(version) = version {
+                versions.push((version, name.clone()));
+            }
+        }
+
+        versions.sort_by_key(|(version, _)| *version);
+        Ok((versions, complete_checkpoints(&names)))
+    }
+
+    // The actions a table is opened from: its first commit, or when the
+    // commits up to its latest checkpoint were cleaned up, like Spark does
+    // once they're older than the log's retention, the protocol and
+    // metadata of that checkpoint as commit lines
+    fn first_actions(log_store: &dyn LogStore) -> Result<String, DeltaError> {
+        let error = match DeltaTable::read_log(log_store, &DeltaTable::log_file(0)) {
+            Err(DeltaError::IOError(e)) if e.kind() == std::io::ErrorKind::NotFound => e,
+            result => return result,
+        };
+        let Some((_, files)) = DeltaTable::list_log(log_store)?.1.pop_last() else {
+            return Err(DeltaError::IOError(error));
+        };
+        let actions = read_checkpoint(log_store, &files)?
+            .into_iter()
+            .filter(|action| matches!(action, Action::Protocol(_) | Action::Metadata(_)))
+            .collect::<Vec<Action>>();
+        Action::serialize_commit(&actions)
+    }
+
+    fn read_log(log_store: &dyn LogStore, name: &str) -> Result<String, DeltaError> {
+        String::from_utf8(log_store.read(name)?)
+            .map_err(|e| DeltaError::InvalidData(format!("'{}' isn't UTF-8: {}", name, e)))
+    }
+
+    // Why a read of the rows matching `predicate` can skip `add`, or `None`
+    // when it has to read it. With `keys`, only rows with one of them are
+    // looked for, so files whose bloom filter rules them all out are
+    // skipped too.
+    fn prune_reason(
+        add: &Add,
+        predicate: &Expr,
+        keys: Option<&DataFrame>,
+        schema: &DeltaTableSchema,
+    ) -> Result<Option<PruneReason>, DeltaError> {
+        let stats = add.parsed_stats().unwrap_or_default();
+        let partition = DeltaFileStats {
+            num_records: stats.num_records,
+            ..Default::default()
+        }
+        .with_partition_values(&add.partition_values, schema);
+        if !partition.can_match(predicate, schema) {
+            return Ok(Some(PruneReason::Partition));
+        }
+        let stats = stats.with_partition_values(&add.partition_values, schema);
+        if !stats.can_match(predicate, schema) {
+            return Ok(Some(PruneReason::Stats));
+        }
+        if let Some(keys) = keys {
+            if !DeltaTable::may_hold_keys(add, keys)? {
+                return Ok(Some(PruneReason::BloomFilter));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn log_pruning(&self, operation: &str, pruning: &FilePruning) {
+        debug!(target: "delta::pruning", "table={} operation={:?} {}", self.base_dir, operation, pruning);
+    }
+
+    // Rewrite every data file holding rows that `keep` doesn't keep. Files
+    // where every row is kept are left alone, and files where no row is
+    // are dropped without a replacement. Only the row groups holding
+    // dropped rows are re-encoded, the rest are copied into the new file as
+    // is. Files whose stats show no row can match `predicate` aren't read,
+    // so every row `keep` drops has to match it. Neither are files whose
+    // bloom filter shows they hold none of `keys`, when `keep` drops rows
+    // by key.
+    fn rewrite_without(
+        &self,
+        predicate: &Expr,
+        keys: Option<&DataFrame>,
+        keep: &Keep,
+    ) -> Result<Rewrite, DeltaError> {
+        let schema = self.metadata.schema()?;
+
+        let snapshot = self.snapshot()?;
+        let mut rewrite = Rewrite {
+            read_version: snapshot.version,
+            ..Default::default()
+        };
+        for add in snapshot.files {
+            let reason = DeltaTable::prune_reason(&add, predicate, keys, &schema)?;
+            rewrite.pruning.record(reason);
+            if reason.is_some() {
+                continue;
+            }
+
+            let partition = self.partition_literals(&add.partition_values, &schema)?;
+            let name = self.next_data_file(&add.partition_values);
+            self.record_reads([add.path.as_str()]);
+            let outcome = rewrite_row_groups(
+                &self.data_file_path(&add.path)?,
+                Path::new(&format!("{}/{}", &self.base_dir, &name)),
+                &partition,
+                keep,
+                self.metadata.portable_parquet(),
+            )?;
+
+            let Some(outcome) = outcome else {
+                continue; // No rows deleted
+            };
+
+            rewrite.rows_removed += outcome.rows_removed;
+            rewrite.row_groups_copied += outcome.row_groups_copied;
+            rewrite.row_groups_rewritten += outcome.row_groups_rewritten;
+            if outcome.remaining.height() > 0 {
+                rewrite.created.push(DataFile {
+                    name,
+                    size: outcome.size,
+                    stats: DeltaFileStats::from_frame(&outcome.remaining),
+                    partition_values: add.partition_values.clone(),
+                    checksum: outcome.checksum,
+                    bloom_filter: None,
+                    tags: self.rewritten_tags(&add),
+                });
+                rewrite.files_rewritten += 1;
+            } else {
+                rewrite.files_removed += 1;
+            }
+            rewrite.removed.push(add.path)
+        }
+
+        Ok(rewrite)
+    }
+
+    // Run the predicate against an empty frame with the table's schema, so
+    // unknown columns and type mismatches are caught before touching data.
+    fn validate_predicate(&self, predicate: &Expr) -> Result<(), DeltaError> {
+        self.metadata
+            .schema()?
+            .empty_frame()
+            .lazy()
+            .filter(predicate.clone())
+            .collect()
+            .map_err(|e| DeltaError::InvalidPredicate(e.to_string()))?;
+
+        Ok(())
+    }
+
+    // Rows where the predicate is null don't match it, so they are kept.
+    fn not_matching(predicate: &Expr) -> Expr {
+        predicate.clone().fill_null(lit(false)).not()
+    }
+
+    // Write `actions`, which were worked out from the table as of
+    // `read_version`, as the next commit and return its version. If other
+    // writers got there first, their commits are checked for conflicts and
+    // the commit is retried after them with a jittered backoff. Tables
+    // whose protocol this crate can't write are refused, see
+    // `Protocol::check_writable`. `info` goes first in the commit, with
+    // the counts of files added and removed added to its metrics.
+    fn commit(
+        &self,
+        read_version: i64,
+        info: CommitInfo,
+        actions: Vec<Action>,
+    ) -> Result<i64, DeltaError> {
+        self.commit_with(read_version, info, actions, false)
+    }
+
+    // Like `commit`, for commits writing new rows. On a table with a unique
+    // key the rows were only checked against the files that were there at
+    // `read_version`, so files added since are a conflict.
+    fn commit_new_rows(
+        &self,
+        read_version: i64,
+        info: CommitInfo,
+        actions: Vec<Action>,
+    ) -> Result<i64, DeltaError> {
+        let check_added = !self.metadata.unique_key().is_empty();
+        self.commit_with(read_version, info, actions, check_added)
+    }
+
+    fn commit_with(
+        &self,
+        read_version: i64,
+        mut info: CommitInfo,
+        mut actions: Vec<Action>,
+        check_added: bool,
+    ) -> Result<i64, DeltaError> {
+        self.check_not_pinned()?;
+        let snapshot = self.snapshot()?;
+        snapshot.protocol.check_writable()?;
+        snapshot.metadata.check_writable()?;
+
+        for (metric, value) in DeltaTable::file_metrics(&actions) {
+            info.operation_metrics.entry(metric).or_insert(value);
+        }
+        actions.insert(0, Action::CommitInfo(info));
+
+        let mut version = read_version + 1;
+        let mut checked = read_version;
+        let mut attempt = 0;
+        loop {
+            // Commits that landed between reading the table and now
+            let latest = self.next_version()? - 1;
+            if latest > checked {
+                self.check_conflicts(&actions, checked + 1, latest, check_added)?;
+                checked = latest;
+                version = latest + 1;
+            }
+
+            match self.commit_version(version, &actions) {
+                Err(DeltaError::VersionAlreadyExists(_)) if attempt < self.max_commit_retries => {
+                    attempt += 1;
+                    thread::sleep(DeltaTable::commit_backoff(attempt));
+                }
+                result => return result,
+            }
+        }
+    }
+
+    // Appends never conflict with anything, since they don't depend on
+    // what's already in the table. Removing a file conflicts with another
+    // commit that already removed it, as the rows we meant to keep or
+    // delete may have been changed in the meantime. Metadata and protocol
+    // changes conflict with everything, and changing the metadata, e.g. to
+    // add a constraint, conflicts with files added in the meantime, as
+    // does anything committed with `check_added`.
+    fn check_conflicts(
+        &self,
+        actions: &[Action],
+        from: i64,
+        to: i64,
+        check_added: bool,
+    ) -> Result<(), DeltaError> {
+        let check_added = check_added
+            || actions
+                .iter()
+                .any(|action| matches!(action, Action::Metadata(_)));
+        let removing = actions
+            .iter()
+            .filter_map(|action| match action {
+                Action::Remove(remove) => Some(remove.path.as_str()),
+                _ => None,
+            })
+            .collect::<HashSet<&str>>();
+        let apps = actions
+            .iter()
+            .filter_map(|action| match action {
+                Action::Txn(txn) => Some(txn.app_id.as_str()),
+                _ => None,
+            })
+            .collect::<HashSet<&str>>();
+
+        for (version, log) in self.log_versions()? {
+            if version < from || version > to {
+                continue;
+            }
+
+            for line in DeltaTable::read_log(&*self.log_store, &log)?.lines() {
+                match self.parse_action(line)? {
+                    Some(Action::Add(add)) if check_added => {
+                        return Err(DeltaError::CommitConflict(format!(
+                            "file '{}' was added by version {}",
+                            add.path, version
+                        )))
+                    }
+                    Some(Action::Remove(remove)) if removing.contains(remove.path.as_str()) => {
+                        return Err(DeltaError::CommitConflict(format!(
+                            "file '{}' was removed by version {}",
+                            remove.path, version
+                        )))
+                    }
+                    Some(Action::Metadata(_)) => {
+                        return Err(DeltaError::CommitConflict(format!(
+                            "table metadata was changed by version {}",
+                            version
+                        )))
+                    }
+                    Some(Action::Protocol(_)) => {
+                        return Err(DeltaError::CommitConflict(format!(
+                            "table protocol was changed by version {}",
+                            version
+                        )))
+                    }
+                    Some(Action::Txn(txn)) if apps.contains(txn.app_id.as_str()) => {
+                        return Err(DeltaError::CommitConflict(format!(
+                            "application '{}' committed version {} of its writes in version {}",
+                            txn.app_id, txn.version, version
+                        )))
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Exponential backoff with full jitter, so writers that collided don't
+    // keep colliding
+    fn commit_backoff(attempt: u32) -> Duration {
+        let max = Duration::from_millis(10 * 2u64.pow(attempt.min(7))).as_nanos() as u64;
+        let random = RandomState::new().build_hasher().finish();
+        Duration::from_nanos(random % max)
+    }
+
+    // Commit files are created exclusively, see `LogStore::put_if_absent`,
+    // so if another writer already committed `version` this fails with
+    // `VersionAlreadyExists` instead of overwriting their commit.
+    fn commit_version(&self, version: i64, actions: &[Action]) -> Result<i64, DeltaError> {
+        let contents = Action::serialize_commit(actions)?;
+
+        #[cfg(feature = "testing")]
+        testing::fail_point(Path::new(&self.base_dir), FaultPoint::BeforeCommit)?;
+
+        let name = DeltaTable::log_file(version as usize);
+        match self.log_store.put_if_absent(&name, contents.as_bytes())? {
+            true => Ok(version),
+            false => Err(DeltaError::VersionAlreadyExists(version)),
+        }
+    }
+
+    fn add_action(data_file: DataFile, modification_time: i64) -> Result<Action, DeltaError> {
+        Ok(Action::Add(Add {
+            path: partition::encode_path(&data_file.name),
+            partition_values: data_file.partition_values,
+            size: data_file.size,
+            modification_time,
+            data_change: true,
+            stats: Some(serde_json::to_string(&data_file.stats)?),
+            tags: {
+                let mut tags = data_file.tags;
+                if let Some(checksum) = data_file.checksum {
+                    tags.insert(CHECKSUM_TAG.to_owned(), checksum);
+                }
+                if let Some(bloom_filter) = data_file.bloom_filter {
+                    tags.insert(BLOOM_FILTER_TAG.to_owned(), bloom_filter);
+                }
+                (!tags.is_empty()).then_some(tags)
+            },
+        }))
+    }
+
+    // The commitInfo of a commit doing `operation`, named like Spark's
+    // operations, e.g. DELETE. Metrics specific to the operation are added
+    // by the caller, see `commit`.
+    fn commit_info(&self, operation: &str, parameters: HashMap<String, Value>) -> CommitInfo {
+        CommitInfo {
+            timestamp: DeltaTable::modification_time(),
+            operation: operation.to_owned(),
+            operation_parameters: parameters,
+            user_metadata: self.write_options.user_metadata.clone(),
+            operation_metrics: HashMap::new(),
+            engine_info: Some(ENGINE_INFO.to_owned()),
+        }
+    }
+
+    // The counts every commit adding or removing files records. Rows are
+    // only counted when every added file has stats.
+    fn file_metrics(actions: &[Action]) -> HashMap<String, String> {
+        let added = actions
+            .iter()
+            .filter_map(|action| match action {
+                Action::Add(add) => Some(add),
+                _ => None,
+            })
+            .collect::<Vec<&Add>>();
+        let removed = actions
+            .iter()
+            .filter(|action| matches!(action, Action::Remove(_)))
+            .count();
+        if added.is_empty() && removed == 0 {
+            return HashMap::new();
+        }
+
+        let mut metrics = HashMap::from([
+            ("numAddedFiles".to_owned(), added.len().to_string()),
+            ("numRemovedFiles".to_owned(), removed.to_string()),
+            (
+                "numAddedBytes".to_owned(),
+                added.iter().map(|add| add.size).sum::<u64>().to_string(),
+            ),
+        ]);
+        let rows = added
+            .iter()
+            .map(|add| Some(add.parsed_stats()?.num_records))
+            .sum::<Option<u64>>();
+        if let Some(rows) = rows {
+            metrics.insert("numOutputRows".to_owned(), rows.to_string());
+        }
+        metrics
+    }
+
+    // The commitInfo of the table's first commit, with the parameters Spark
+    // records for a CREATE TABLE
+    fn create_commit_info(&self) -> Result<CommitInfo, DeltaError> {
+        let properties = self
+            .metadata
+            .properties()
+            .into_iter()
+            .collect::<BTreeMap<&str, &str>>();
+        Ok(self.commit_info(
+            "CREATE TABLE",
+            HashMap::from([
+                ("isManaged".to_owned(), Value::from("false")),
+                (
+                    "partitionBy".to_owned(),
+                    Value::from(serde_json::to_string(self.metadata.partition_columns())?),
+                ),
+                (
+                    "properties".to_owned(),
+                    Value::from(serde_json::to_string(&properties)?),
+                ),
+            ]),
+        ))
+    }
+
+    // The commitInfo of a write, with the parameters Spark records for one
+    // plus how the rows were sorted
+    fn write_commit_info(
+        &self,
+        mode: &str,
+        predicate: Option<&str>,
+    ) -> Result<CommitInfo, DeltaError> {
+        let mut parameters = HashMap::from([
+            ("mode".to_owned(), Value::from(mode)),
+            (
+                "partitionBy".to_owned(),
+                Value::from(serde_json::to_string(self.metadata.partition_columns())?),
+            ),
+        ]);
+        if let Some(predicate) = predicate {
+            parameters.insert("predicate".to_owned(), Value::from(predicate));
+        }
+        if !self.write_options.sort_by.is_empty() {
+            parameters.insert(
+                "sortBy".to_owned(),
+                Value::from(serde_json::to_string(&self.write_options.sort_by)?),
+            );
+        }
+
+        Ok(self.commit_info("WRITE", parameters))
+    }
+
+    // The protocol stores modification times as i64 milliseconds
+    fn modification_time() -> i64 {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64
+    }
+
+    // Write `df` out in schema order, as one data file per partition
+    fn write_data_files(&self, df: &DataFrame) -> Result<Vec<DataFile>, DeltaError> {
+        let schema = self.metadata.schema()?;
+        let mut df = self.truncate_timestamps(schema.align(df)?)?;
+        schema.check_nulls(&df)?;
+        self.check_constraints(&df)?;
+
+        let sort_by = &self.write_options.sort_by;
+        if !sort_by.is_empty() {
+            self.check_key_columns(sort_by)?;
+            df = df.sort(sort_by, vec![false; sort_by.len()], true)?;
+        }
+
+        // An empty frame still has to match the schema, but there's no
+        // point writing a data file without rows
+        if df.height() == 0 {
+            return Ok(vec![]);
+        }
+
+        let partition_columns = self.metadata.partition_columns();
+        if partition_columns.is_empty() {
+            return Ok(vec![self.write_data_file(df, PartitionValues::new())?]);
+        }
+
+        df.partition_by_stable(partition_columns, true)?
+            .into_iter()
+            .map(|df| {
+                let mut partition_values = PartitionValues::new();
+                for column in partition_columns {
+                    let value = df.column(column)?.get(0)?;
+                    partition_values.insert(column.clone(), partition::serialize_value(&value));
+                }
+
+                self.write_data_file(df.drop_many(partition_columns), partition_values)
+            })
+            .collect()
+    }
+
+    fn write_data_file(
+        &self,
+        mut df: DataFrame,
+        partition_values: PartitionValues,
+    ) -> Result<DataFile, DeltaError> {
+        let data_file = self.next_data_file(&partition_values);
+        let path = PathBuf::from(format!("{}/{}", self.base_dir, data_file));
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        // Built before timestamps are converted to the stored unit, since
+        // keys are matched against it with the table's types
+        let bloom_filter = self.bloom_filter(&df)?;
+
+        let unit = self.write_options.timestamp_unit;
+        for name in df.get_column_names_owned() {
+            if let DataType::Datetime(from, tz) = df.column(&name)?.dtype().clone() {
+                if from != unit {
+                    let col = df.column(&name)?.cast(&DataType::Datetime(unit, tz))?;
+                    df.with_column(col)?;
+                }
+            }
+        }
+
+        // Row group stats in the footer are what `plan_scan` reports
+        let mut file = ChecksumWriter::new(fs::File::create(path)?);
+        let data_file_size = match self.metadata.portable_parquet() {
+            true => portable::write_portable(&mut file, &df)?,
+            false => ParquetWriter::new(&mut file)
+                .with_statistics(true)
+                .finish(&mut df)?,
+        };
+
+        #[cfg(feature = "testing")]
+        testing::fail_point(Path::new(&self.base_dir), FaultPoint::AfterDataWrite)?;
+
+        Ok(DataFile {
+            name: data_file,
+            size: data_file_size,
+            stats: DeltaFileStats::from_frame(&df),
+            partition_values,
+            checksum: Some(file.checksum()),
+            bloom_filter,
+            tags: self.write_options.tags.clone(),
+        })
+    }
+
+    // Timestamps are kept in microseconds in memory, but can be stored with
+    // less precision. Truncating them to the stored unit before anything
+    // looks at them means key checks, constraints and bloom filters see the
+    // values that will be read back.
+    fn truncate_timestamps(&self, mut df: DataFrame) -> Result<DataFrame, DeltaError> {
+        let unit = self.write_options.timestamp_unit;
+        for name in df.get_column_names_owned() {
+            if let DataType::Datetime(from, tz) = df.column(&name)?.dtype().clone() {
+                if from != unit {
+                    let col = df
+                        .column(&name)?
+                        .cast(&DataType::Datetime(unit, tz.clone()))?
+                        .cast(&DataType::Datetime(from, tz))?;
+                    df.with_column(col)?;
+                }
+            }
+        }
+        Ok(df)
+    }
+
+    // A bloom filter over the unique key columns in `df`, i.e. those that
+    // aren't partition columns, when the table has a unique key
+    fn bloom_filter(&self, df: &DataFrame) -> Result<Option<String>, DeltaError> {
+        DeltaTable::key_bloom_filter(df, &self.metadata.unique_key())
+    }
+
+    // A bloom filter over the columns of `unique_key` in `df`, if any
+    fn key_bloom_filter(
+        df: &DataFrame,
+        unique_key: &[String],
+    ) -> Result<Option<String>, DeltaError> {
+        let columns = unique_key
+            .iter()
+            .filter(|column| df.column(column).is_ok())
+            .cloned()
+            .collect::<Vec<String>>();
+        if columns.is_empty() {
+            return Ok(None);
+        }
+
+        match BloomFilter::build(df, &columns)? {
+            Some(filter) => Ok(Some(filter.to_tag()?)),
+            None => Ok(None),
+        }
+    }
+
+    // Whether a data file could hold one of `keys`' rows as far as its
+    // bloom filter can tell. Files without one could hold anything.
+    fn may_hold_keys(add: &Add, keys: &DataFrame) -> Result<bool, DeltaError> {
+        match BloomFilter::from_tags(&add.tags) {
+            Some(filter) => filter.may_contain_any(keys),
+            None => Ok(true),
+        }
+    }
+
+    // Table names may be quoted, but since they become a directory name
+    // they can't be empty or contain path separators.
+    pub(crate) fn table_name(name: &str) -> Result<String, DeltaError> {
+        let name = unquote_identifier(name);
+        if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+            return Err(DeltaError::InvalidTable);
+        }
+
+        Ok(name)
+    }
+
+    fn log_file(idx: usize) -> String {
+        format!("{:0>20}.json", idx)
+    }
+}
+
+// The commits in a log and its complete checkpoints, see `list_log`
+type LogListing = (Vec<(i64, String)>, BTreeMap<i64, Vec<String>>);
+
+// The table as of a version of the log
+#[derive(Clone)]
+pub(crate) struct Snapshot {
+    pub(crate) version: i64,
+    pub(crate) metadata: DeltaTableMetadata,
+    pub(crate) protocol: Protocol,
+    // The latest txn version of each application, see `insert_with_txn`
+    pub(crate) app_versions: HashMap<String, i64>,
+    pub(crate) files: Vec<Add>,
+    // The version each of `files` was added in, by path
+    pub(crate) added_in: HashMap<String, i64>,
+}
+
+// The result of rewriting data files to drop matching rows
+#[derive(Default)]
+struct Rewrite {
+    // The version the rewritten files were read at
+    read_version: i64,
+    created: Vec<DataFile>,
+    removed: Vec<String>,
+    rows_removed: usize,
+    pruning: FilePruning,
+    files_rewritten: usize,
+    files_removed: usize,
+    row_groups_copied: usize,
+    row_groups_rewritten: usize,
+}
+
+impl Rewrite {
+    fn into_actions(
+        self,
+        modification_time: i64,
+        tags: &HashMap<String, String>,
+    ) -> Result<Vec<Action>, DeltaError> {
+        let mut actions: Vec<Action> = vec![];
+        for created in self.created {
+            actions.push(DeltaTable::add_action(created, modification_time)?);
+        }
+
+        for removed in self.removed {
+            actions.push(Action::Remove(Remove {
+                path: removed,
+                data_change: true,
+                tags: (!tags.is_empty()).then(|| tags.clone()),
+            }));
+        }
+
+        Ok(actions)
+    }
+}
+
+// The groups of a partition's files to compact into one file each, see
+// `CompactionPolicy`. Groups always have more than one file.
+fn compaction_groups(files: Vec<Add>, policy: &CompactionPolicy) -> Vec<Vec<Add>> {
+    let mut groups = vec![];
+    match *policy {
+        CompactionPolicy::BinPack { target_size } => {
+            let mut bin: Vec<Add> = vec![];
+            let mut size = 0;
+            for add in files.into_iter().filter(|add| add.size < target_size) {
+                if size + add.size > target_size {
+                    groups.push(std::mem::take(&mut bin));
+                    size = 0;
+                }
+                size += add.size;
+                bin.push(add);
+            }
+            groups.push(bin);
+        }
+        CompactionPolicy::SizeTiered {
+            min_size,
+            tier_factor,
+            min_files,
+        } => {
+            let mut tiers: BTreeMap<u32, Vec<Add>> = BTreeMap::new();
+            for add in files {
+                let mut tier = 0;
+                let mut bound = min_size.max(1);
+                while add.size >= bound {
+                    tier += 1;
+                    bound = bound.saturating_mul(tier_factor.max(2));
+                }
+                tiers.entry(tier).or_default().push(add);
+            }
+            groups.extend(tiers.into_values().filter(|tier| tier.len() >= min_files));
+        }
+    }
+
+    groups.retain(|group| group.len() > 1);
+    groups
+}
+
+// Positional rows all have to have `width` values
+fn check_row_widths<T>(rows: &[Vec<T>], width: usize) -> Result<(), DeltaError> {
+    for (row, values) in rows.iter().enumerate() {
+        if values.len() != width {
+            return Err(DeltaError::InvalidValue {
+                row,
+                column: String::new(),
+                message: format!("expected {} values, got {}", width, values.len()),
+            });
+        }
+    }
+    Ok(())
+}
+
+// Fold an insert into the running total of a load made of several
+fn add_insert(total: &mut InsertResult, insert: InsertResult) {
+    total.version = insert.version;
+    total.files_added += insert.files_added;
+    total.rows_written += insert.rows_written;
+    total.bytes_written += insert.bytes_written;
+}
+
+// `n` distinct positions out of `0..total`, sorted, picked with Floyd's
+// algorithm so only `n` random numbers are needed. Everything is picked if
+// there aren't more than `n` positions.
+fn sample_positions(total: u64, n: u64, seed: u64) -> BTreeSet<u64> {
+    if n >= total {
+        return (0..total).collect();
+    }
+
+    // splitmix64, which is plenty for picking rows
+    let mut state = seed;
+    let mut random = move || {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    };
+
+    let mut picked = BTreeSet::new();
+    for j in total - n..total {
+        let position = random() % (j + 1);
+        if !picked.insert(position) {
+            picked.insert(j);
+        }
+    }
+
+    picked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        policy::{ColumnMasks, Mask},
+        testing::{assert_log_invariants, run_concurrent_writers, TempTable},
+    };
+    use polars::export::chrono::NaiveDate;
+    use std::{io, sync::Mutex};
+
+    fn table_with_rows(rows: &[&str]) -> (TempTable, DeltaTable) {
+        let temp = TempTable::new(vec![("id", "int")]).unwrap();
+        let table = temp.table().unwrap();
+        table
+            .insert(rows.iter().map(|row| vec![*row]).collect())
+            .unwrap();
+        (temp, table)
+    }
+
+    // The table's ids in order
+    fn ids(table: &DeltaTable) -> Vec<i32> {
+        let mut ids = table.to_polars().unwrap()["id"]
+            .i32()
+            .unwrap()
+            .into_no_null_iter()
+            .collect::<Vec<i32>>();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn removing_a_file_removed_concurrently_conflicts() {
+        let (temp, table) = table_with_rows(&["1", "2"]);
+        let read_version = table.version().unwrap();
+        let path = table.snapshot().unwrap().files[0].path.clone();
+
+        temp.table().unwrap().delete("id = 1").unwrap();
+
+        let remove = Action::Remove(Remove {
+            path,
+            data_change: true,
+            tags: None,
+        });
+        let info = table.write_commit_info("Delete", Some("id = 2")).unwrap();
+        match table.commit(read_version, info, vec![remove]) {
+            Err(DeltaError::CommitConflict(_)) => {}
+            result => panic!("expected a conflict, got {:?}", result),
+        }
+        assert_eq!(table.count().unwrap(), 1);
+        assert_log_invariants(temp.path());
+    }
+
+    #[test]
+    fn concurrent_appends_all_commit() {
+        let (temp, _) = table_with_rows(&[]);
+        let results = run_concurrent_writers(temp.path(), 8, |i, table| {
+            table
+                .with_max_commit_retries(100)
+                .insert(vec![vec![i.to_string().as_str()]])
+        });
+
+        let mut versions = results
+            .into_iter()
+            .map(|result| result.unwrap().version)
+            .collect::<Vec<i64>>();
+        versions.sort();
+        assert_eq!(versions, (1..=8).collect::<Vec<i64>>());
+        assert_eq!(temp.table().unwrap().count().unwrap(), 8);
+        assert_log_invariants(temp.path());
+    }
+
+    #[test]
+    fn vacuum_keeps_files_within_retention() {
+        let (temp, table) = table_with_rows(&["1", "2"]);
+        table.delete("id = 1").unwrap();
+
+        let metrics = table.vacuum(&VacuumOptions::default()).unwrap();
+        assert!(metrics.files_deleted.is_empty());
+        assert_eq!(metrics.files_within_retention.len(), 1);
+        assert_eq!(metrics.files_live, 1);
+
+        let no_retention = VacuumOptions {
+            retention: Duration::ZERO,
+            ..Default::default()
+        };
+        let dry_run = table
+            .vacuum(&VacuumOptions {
+                dry_run: true,
+                ..no_retention
+            })
+            .unwrap();
+        assert_eq!(dry_run.files_deleted.len(), 1);
+        let removed = Path::new(temp.path()).join(&dry_run.files_deleted[0].path);
+        assert!(removed.exists());
+
+        let metrics = table.vacuum(&no_retention).unwrap();
+        assert_eq!(metrics.files_deleted.len(), 1);
+        assert!(!removed.exists());
+        assert_eq!(table.count().unwrap(), 1);
+        assert_log_invariants(temp.path());
+    }
+
+    #[test]
+    fn at_version_reads_the_past_and_refuses_writes() {
+        let (temp, table) = table_with_rows(&["1"]);
+        table.insert(vec![vec!["2"], vec!["3"]]).unwrap();
+        table.delete("id = 1").unwrap();
+
+        let pinned = table.clone().at_version(2).unwrap();
+        assert_eq!(pinned.version().unwrap(), 2);
+        assert_eq!(pinned.count().unwrap(), 3);
+        assert_eq!(table.clone().at_version(1).unwrap().count().unwrap(), 1);
+        assert_eq!(table.count().unwrap(), 2);
+
+        assert!(matches!(
+            pinned.insert(vec![vec!["4"]]),
+            Err(DeltaError::PinnedVersion(2))
+        ));
+        assert!(matches!(
+            pinned.delete("id = 2"),
+            Err(DeltaError::PinnedVersion(2))
+        ));
+        assert!(matches!(
+            table.clone().at_version(10),
+            Err(DeltaError::VersionNotFound(10))
+        ));
+        assert_eq!(table.version().unwrap(), 3);
+        assert_log_invariants(temp.path());
+    }
+
+    #[test]
+    fn commit_info_from_other_writers_is_tolerated() {
+        let (temp, _) = table_with_rows(&["1"]);
+        fs::write(
+            format!("{}/_delta_log/{:020}.json", temp.path(), 2),
+            r#"{"commitInfo":{"timestamp":1}}"#,
+        )
+        .unwrap();
+
+        let table = DeltaTable::open(temp.path()).unwrap();
+        assert_eq!(table.version().unwrap(), 2);
+        assert_eq!(table.count().unwrap(), 1);
+        assert_eq!(table.history(None).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn added_parquet_files_are_checked_against_the_latest_table() {
+        let (temp, stale) = table_with_rows(&["1"]);
+        temp.table()
+            .unwrap()
+            .add_constraint("positive", "id > 0")
+            .unwrap();
+
+        let write = |name: &str, ids: &[i32]| {
+            let path = format!("{}/../{}", temp.path(), name);
+            let mut df = df!("id" => ids).unwrap();
+            ParquetWriter::new(fs::File::create(&path).unwrap())
+                .finish(&mut df)
+                .unwrap();
+            path
+        };
+        let bad = write("bad.parquet", &[2, -1]);
+        assert!(matches!(
+            stale.add_parquet_files(&[&bad], true),
+            Err(DeltaError::ConstraintViolation { rows: 1, .. })
+        ));
+
+        let good = write("good.parquet", &[2, 3]);
+        let result = stale.add_parquet_files(&[&good], true).unwrap();
+        assert_eq!(result.rows_written, 2);
+        assert_eq!(stale.count().unwrap(), 3);
+        assert_log_invariants(temp.path());
+    }
+
+    #[test]
+    fn keys_are_matched_at_the_stored_timestamp_unit() {
+        let temp = TempTable::new(vec![("id", "int")]).unwrap();
+        let table = DeltaTable::create_with(
+            &format!("{}/../events", temp.path()),
+            vec![("at", "timestamp")],
+            CreateOptions {
+                unique_key: vec!["at".to_owned()],
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .with_write_options(WriteOptions {
+            timestamp_unit: TimeUnit::Milliseconds,
+            ..Default::default()
+        });
+        table
+            .insert(vec![vec!["2024-01-01 00:00:00.001234"]])
+            .unwrap();
+
+        // Stored in the same millisecond, so the same key
+        assert!(matches!(
+            table.insert(vec![vec!["2024-01-01 00:00:00.001999"]]),
+            Err(DeltaError::UniqueKeyViolation { rows: 1, .. })
+        ));
+
+        let keys = table.scan().unwrap().collect().unwrap();
+        let metrics = table.delete_keys(keys).unwrap();
+        assert_eq!(metrics.rows_deleted, 1);
+        assert_eq!(metrics.pruning.pruned_by_bloom_filter, 0);
+        assert_eq!(table.count().unwrap(), 0);
+    }
+
+    #[test]
+    fn merge_only_rewrites_files_holding_source_keys() {
+        let temp = TempTable::new(vec![("id", "int")]).unwrap();
+        let table = DeltaTable::create_with(
+            &format!("{}/../people", temp.path()),
+            vec![("id", "int"), ("name", "text")],
+            CreateOptions {
+                unique_key: vec!["id".to_owned()],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        table.insert(vec![vec!["1", "a"], vec!["3", "c"]]).unwrap();
+        table.insert(vec![vec!["2", "b"], vec!["4", "d"]]).unwrap();
+        table.insert(vec![vec!["10", "j"]]).unwrap();
+
+        let source = df!("id" => [2, 5], "name" => ["B", "e"]).unwrap();
+        let metrics = table.merge(source, &["id"]).unwrap();
+        assert_eq!(metrics.rows_replaced, 1);
+        assert_eq!(metrics.rows_written, 2);
+        assert_eq!(metrics.files_rewritten, 1);
+        assert_eq!(metrics.pruning.files_scanned, 1);
+        assert_eq!(metrics.pruning.pruned_by_stats, 1);
+        assert_eq!(metrics.pruning.pruned_by_bloom_filter, 1);
+
+        let rows = table
+            .query("SELECT id, name FROM people ORDER BY id")
+            .unwrap();
+        assert_eq!(
+            rows,
+            df!("id" => [1, 2, 3, 4, 5, 10], "name" => ["a", "B", "c", "d", "e", "j"]).unwrap()
+        );
+        assert!(matches!(
+            table.merge(df!("id" => [6, 6], "name" => ["f", "g"]).unwrap(), &["id"]),
+            Err(DeltaError::InvalidData(_))
+        ));
+        assert!(matches!(
+            table.merge(df!("id" => [6], "name" => ["f"]).unwrap(), &["name"]),
+            Err(DeltaError::InvalidData(_))
+        ));
+    }
+
+    #[test]
+    fn queries_understand_is_nan() {
+        let temp = TempTable::new(vec![("id", "int")]).unwrap();
+        let table = DeltaTable::create(
+            &format!("{}/../readings", temp.path()),
+            vec![("id", "int"), ("x", "double NULL")],
+        )
+        .unwrap();
+        let x = Series::new("x", [Some(1.5), Some(f64::NAN), None]);
+        table
+            .insert_df(DataFrame::new(vec![Series::new("id", [1, 2, 3]), x]).unwrap())
+            .unwrap();
+
+        let ids = |sql: &str| {
+            let rows = table.query(sql).unwrap();
+            rows.column("id")
+                .unwrap()
+                .i32()
+                .unwrap()
+                .into_no_null_iter()
+                .collect::<Vec<i32>>()
+        };
+        assert_eq!(ids("SELECT id FROM readings WHERE x IS NAN"), [2]);
+        assert_eq!(
+            ids("SELECT id FROM readings WHERE x is not nan ORDER BY id"),
+            [1]
+        );
+        assert_eq!(
+            ids("SELECT id, count(*) AS n FROM readings GROUP BY id HAVING n IS NOT NAN ORDER BY id"),
+            [1, 2, 3]
+        );
+        assert!(matches!(
+            table.query("SELECT id FROM readings WHERE x IS UNKNOWN"),
+            Err(DeltaError::InvalidQuery(_))
+        ));
+    }
+
+    // A log kept in memory, like a host without a filesystem would supply
+    #[derive(Clone, Default)]
+    struct MemoryLogStore(Arc<Mutex<HashMap<String, Vec<u8>>>>);
+
+    impl LogStore for MemoryLogStore {
+        fn location(&self) -> String {
+            format!("memory://{:p}", Arc::as_ptr(&self.0))
+        }
+
+        fn list(&self) -> Result<Vec<String>, DeltaError> {
+            Ok(self.0.lock().unwrap().keys().cloned().collect())
+        }
+
+        fn read(&self, name: &str) -> Result<Vec<u8>, DeltaError> {
+            let files = self.0.lock().unwrap();
+            match files.get(name) {
+                Some(contents) => Ok(contents.clone()),
+                None => Err(io::Error::from(io::ErrorKind::NotFound).into()),
+            }
+        }
+
+        fn modified(&self, _: &str) -> Result<SystemTime, DeltaError> {
+            Ok(SystemTime::now())
+        }
+
+        fn put_if_absent(&self, name: &str, contents: &[u8]) -> Result<bool, DeltaError> {
+            let mut files = self.0.lock().unwrap();
+            if files.contains_key(name) {
+                return Ok(false);
+            }
+            files.insert(name.to_owned(), contents.to_vec());
+            Ok(true)
+        }
+
+        fn put(&self, name: &str, contents: &[u8]) -> Result<(), DeltaError> {
+            self.0
+                .lock()
+                .unwrap()
+                .insert(name.to_owned(), contents.to_vec());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn checkpoints_are_written_to_the_log_store() {
+        let (temp, _) = table_with_rows(&["1"]);
+        let store = MemoryLogStore::default();
+        for entry in fs::read_dir(format!("{}/_delta_log", temp.path())).unwrap() {
+            let entry = entry.unwrap();
+            let name = entry.file_name().into_string().unwrap();
+            store.put(&name, &fs::read(entry.path()).unwrap()).unwrap();
+        }
+
+        let table = DeltaTable::open_with_log_store(temp.path(), store.clone()).unwrap();
+        table.insert(vec![vec!["2"]]).unwrap();
+        let info = table.checkpoint().unwrap();
+        assert_eq!(info.version, 2);
+        assert!(store
+            .read("00000000000000000002.checkpoint.parquet")
+            .is_ok());
+        let last = serde_json::from_slice::<Value>(&store.read("_last_checkpoint").unwrap());
+        assert_eq!(last.unwrap()["version"], 2);
+        assert!(fs::read_dir(format!("{}/_delta_log", temp.path()))
+            .unwrap()
+            .all(|entry| !entry
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .contains("checkpoint")));
+
+        let table = DeltaTable::open_with_log_store(temp.path(), store).unwrap();
+        assert_eq!(table.version().unwrap(), 2);
+        assert_eq!(table.count().unwrap(), 2);
+    }
+
+    #[test]
+    fn checkpoint_holds_the_live_files_and_txns() {
+        let (temp, table) = table_with_rows(&["1"]);
+        table.insert_with_txn(vec![vec!["2"]], "job", 0).unwrap();
+
+        let info = table.checkpoint().unwrap();
+        assert_eq!(info.version, 2);
+        assert_eq!(info.num_add_files, 2);
+        // The protocol, the metadata, the job's txn and an Add per file
+        assert_eq!(info.num_actions, 5);
+        let file = fs::metadata(format!("{}/{}", temp.path(), info.path)).unwrap();
+        assert_eq!(file.len(), info.size);
+        let last = fs::read(format!("{}/_delta_log/_last_checkpoint", temp.path())).unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Value>(&last).unwrap()["version"],
+            2
+        );
+
+        let reopened = temp.table().unwrap();
+        assert_eq!(ids(&reopened), vec![1, 2]);
+        assert_eq!(reopened.app_version("job").unwrap(), Some(0));
+    }
+
+    #[test]
+    fn opening_replays_only_commits_after_the_checkpoint() {
+        let (temp, table) = table_with_rows(&["1", "2"]);
+        table.delete("id = 1").unwrap();
+        table.checkpoint().unwrap();
+        table.insert(vec![vec!["3"]]).unwrap();
+
+        // Commits the checkpoint covers aren't needed to open the table
+        let log_dir = format!("{}/_delta_log", temp.path());
+        for version in 0..=2 {
+            fs::remove_file(format!("{}/{:020}.json", log_dir, version)).unwrap();
+        }
+
+        let reopened = temp.table().unwrap();
+        assert_eq!(reopened.version().unwrap(), 3);
+        assert_eq!(ids(&reopened), vec![2, 3]);
+    }
+
+    #[test]
+    fn check_constraints_are_enforced_on_writes() {
+        let temp = TempTable::new(vec![("id", "int"), ("amount", "double NULL")]).unwrap();
+        let table = temp.table().unwrap();
+        table
+            .insert(vec![vec![Some("1"), Some("-5")], vec![Some("2"), None]])
+            .unwrap();
+
+        // Existing rows have to satisfy a new constraint
+        assert!(matches!(
+            table.add_constraint("positive_amount", "amount > 0"),
+            Err(DeltaError::ConstraintViolation { rows: 1, .. })
+        ));
+        table.delete("id = 1").unwrap();
+        table
+            .add_constraint("Positive_Amount", "amount > 0")
+            .unwrap();
+        assert!(matches!(
+            table.add_constraint("positive_amount", "amount > 1"),
+            Err(DeltaError::InvalidData(_))
+        ));
+
+        // Null amounts don't violate it, like in SQL
+        table.insert(vec![vec![Some("3"), None]]).unwrap();
+        table.insert(vec![vec![Some("4"), Some("1.5")]]).unwrap();
+        match table.insert(vec![vec![Some("5"), Some("0")], vec![Some("6"), Some("2")]]) {
+            Err(DeltaError::ConstraintViolation { name, rows, .. }) => {
+                assert_eq!((name.as_str(), rows), ("positive_amount", 1))
+            }
+            other => panic!("expected a constraint violation, got {:?}", other),
+        }
+        assert!(matches!(
+            table.update(&[("amount", "amount - 2")], "id = 4"),
+            Err(DeltaError::ConstraintViolation { .. })
+        ));
+
+        // Other handles on the table see it too
+        let other = temp.table().unwrap();
+        assert!(matches!(
+            other.insert(vec![vec![Some("7"), Some("-1")]]),
+            Err(DeltaError::ConstraintViolation { .. })
+        ));
+        assert_eq!(ids(&table), vec![2, 3, 4]);
+        assert_log_invariants(temp.path());
+    }
+
+    #[test]
+    fn generated_columns_are_computed_on_writes() {
+        let temp = TempTable::new(vec![("id", "int")]).unwrap();
+        let table = DeltaTable::create(
+            &format!("{}/../events", temp.path()),
+            vec![
+                ("id", "int"),
+                ("ts", "timestamp"),
+                ("day", "date GENERATED ALWAYS AS (CAST(ts AS DATE))"),
+            ],
+        )
+        .unwrap();
+        let day = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        let days = |table: &DeltaTable| {
+            let df = table
+                .to_polars()
+                .unwrap()
+                .sort(["id"], false, false)
+                .unwrap();
+            df["day"]
+                .cast(&DataType::Date)
+                .unwrap()
+                .date()
+                .unwrap()
+                .as_date_iter()
+                .map(Option::unwrap)
+                .collect::<Vec<NaiveDate>>()
+        };
+
+        table
+            .insert(vec![vec!["1", "2024-01-02 03:04:05"]])
+            .unwrap();
+        // A given value is kept when it's the one that would be computed
+        table
+            .insert(vec![vec!["2", "2024-03-04 23:59:59", "2024-03-04"]])
+            .unwrap();
+        match table.insert(vec![vec!["3", "2024-03-04 00:00:00", "2024-03-05"]]) {
+            Err(DeltaError::InvalidValue { column, .. }) => assert_eq!(column, "day"),
+            other => panic!("expected a mismatched generated value, got {:?}", other),
+        }
+        assert_eq!(days(&table), vec![day(2024, 1, 2), day(2024, 3, 4)]);
+
+        // Updates recompute it, and can't set it directly
+        table
+            .update(&[("ts", "CAST(DATE('2025-06-07') AS TIMESTAMP)")], "id = 1")
+            .unwrap();
+        assert!(matches!(
+            table.update(&[("day", "'2025-01-01'")], "id = 2"),
+            Err(DeltaError::InvalidData(_))
+        ));
+        assert_eq!(days(&table), vec![day(2025, 6, 7), day(2024, 3, 4)]);
+    }
+
+    #[test]
+    fn unique_keys_reject_or_skip_conflicting_rows() {
+        let temp = TempTable::new(vec![("id", "int")]).unwrap();
+        let path = format!("{}/../people", temp.path());
+        let key = CreateOptions {
+            unique_key: vec!["id".to_owned(), "name".to_owned()],
+            ..Default::default()
+        };
+        assert!(DeltaTable::create_with(
+            &path,
+            vec![("id", "int"), ("name", "text NULL")],
+            key.clone()
+        )
+        .is_err());
+        let table =
+            DeltaTable::create_with(&path, vec![("id", "int"), ("name", "text")], key).unwrap();
+        table.insert(vec![vec!["1", "a"], vec!["1", "b"]]).unwrap();
+
+        // Conflicts with the table and within the insert are both counted
+        let rows = vec![vec!["1", "a"], vec!["2", "a"], vec!["2", "a"]];
+        assert!(matches!(
+            table.insert(rows.clone()),
+            Err(DeltaError::UniqueKeyViolation { rows: 2, .. })
+        ));
+        assert_eq!(table.count().unwrap(), 2);
+
+        let skipping = table.with_write_options(WriteOptions {
+            on_key_conflict: KeyConflict::Skip,
+            ..Default::default()
+        });
+        let result = skipping.insert(rows).unwrap();
+        assert_eq!(result.rows_written, 1);
+        assert_eq!(ids(&skipping), vec![1, 1, 2]);
+        assert_log_invariants(&path);
+    }
+
+    #[test]
+    fn params_are_bound_as_literals() {
+        let temp = TempTable::new(vec![("id", "int")]).unwrap();
+        let table = DeltaTable::create(
+            &format!("{}/../people", temp.path()),
+            vec![("id", "int"), ("name", "text")],
+        )
+        .unwrap();
+        table
+            .insert(vec![vec!["1", "o'brien"], vec!["2", "?"], vec!["3", "x"]])
+            .unwrap();
+
+        // A quote in a value can't end the string early
+        let injected = DeltaValue::Str("x' OR '1' = '1".to_owned());
+        let metrics = table.delete_with_params("name = ?", &[injected]).unwrap();
+        assert_eq!(metrics.rows_deleted, 0);
+
+        let params = [DeltaValue::Str("o'brien".to_owned()), DeltaValue::Int(2)];
+        let rows = table
+            .query_with_params(
+                "SELECT id FROM people WHERE name = ? OR (id = ? AND name = '?')",
+                &params,
+            )
+            .unwrap();
+        assert_eq!(rows, df!("id" => [1, 2]).unwrap());
+
+        for params in [
+            &params[..1],
+            &[params[0].clone(), DeltaValue::Int(2), DeltaValue::Null],
+        ] {
+            assert!(matches!(
+                table.delete_with_params("name = ? OR id = ?", params),
+                Err(DeltaError::InvalidQuery(_))
+            ));
+        }
+        assert_eq!(table.count().unwrap(), 3);
+    }
+
+    #[test]
+    fn metadata_columns_are_only_returned_when_selected() {
+        let temp = TempTable::new(vec![("id", "int")]).unwrap();
+        let table = DeltaTable::create(
+            &format!("{}/../people", temp.path()),
+            vec![("id", "int"), ("name", "text")],
+        )
+        .unwrap();
+        table.insert(vec![vec!["1", "a"], vec!["2", "b"]]).unwrap();
+        table.insert(vec![vec!["3", "c"]]).unwrap();
+
+        let all = table.query("SELECT * FROM people").unwrap();
+        assert_eq!(all.get_column_names(), ["id", "name"]);
+
+        let traced = table
+            .query("SELECT *, _file_name, _commit_version, _row_index FROM people ORDER BY id")
+            .unwrap();
+        assert_eq!(
+            traced.get_column_names(),
+            ["id", "name", "_file_name", "_commit_version", "_row_index"]
+        );
+        let versions = traced["_commit_version"].cast(&DataType::Int64).unwrap();
+        assert_eq!(
+            versions.i64().unwrap().to_vec(),
+            [Some(1), Some(1), Some(2)]
+        );
+        let indexes = traced["_row_index"].cast(&DataType::Int64).unwrap();
+        assert_eq!(indexes.i64().unwrap().to_vec(), [Some(0), Some(1), Some(0)]);
+        let files = traced["_file_name"].utf8().unwrap();
+        assert_eq!(files.get(0), files.get(1));
+        assert_ne!(files.get(0), files.get(2));
+        assert!(fs::metadata(format!(
+            "{}/../people/{}",
+            temp.path(),
+            files.get(2).unwrap()
+        ))
+        .unwrap()
+        .is_file());
+
+        // Wildcards over a CTE keep what it selected
+        let nested = table
+            .query("WITH p AS (SELECT id, _row_index FROM people) SELECT * FROM p")
+            .unwrap();
+        assert_eq!(nested.get_column_names(), ["id", "_row_index"]);
+    }
+
+    #[test]
+    fn aggregates_span_every_file() {
+        let temp = TempTable::new(vec![("id", "int")]).unwrap();
+        let table = DeltaTable::create(
+            &format!("{}/../sales", temp.path()),
+            vec![("region", "text"), ("amount", "int")],
+        )
+        .unwrap();
+        table
+            .insert(vec![vec!["north", "1"], vec!["south", "5"]])
+            .unwrap();
+        table
+            .insert(vec![vec!["north", "2"], vec!["east", "7"]])
+            .unwrap();
+
+        // HAVING's aggregate is computed within each group, not over them
+        let rows = table
+            .query(
+                "SELECT region, count(*) AS n, sum(amount) AS total FROM sales \
+                 GROUP BY region HAVING count(*) > 1 ORDER BY region",
+            )
+            .unwrap();
+        assert_eq!(rows["region"].utf8().unwrap().get(0), Some("north"));
+        assert_eq!(rows.height(), 1);
+        assert_eq!(
+            rows["total"]
+                .cast(&DataType::Int64)
+                .unwrap()
+                .i64()
+                .unwrap()
+                .get(0),
+            Some(3)
+        );
+
+        let rows = table
+            .query("SELECT region FROM sales ORDER BY amount DESC LIMIT 2")
+            .unwrap();
+        let regions = rows["region"].utf8().unwrap().into_no_null_iter();
+        assert_eq!(regions.collect::<Vec<&str>>(), ["east", "south"]);
+
+        assert!(matches!(
+            table.query("SELECT region FROM sales GROUP BY region HAVING max(amount) > 1"),
+            Err(DeltaError::InvalidQuery(_))
+        ));
+    }
+
+    #[test]
+    fn size_tiered_optimize_leaves_large_files_alone() {
+        let (temp, table) = table_with_rows(&[]);
+        let many = (0..5000).map(|i| i.to_string()).collect::<Vec<String>>();
+        table
+            .insert(many.iter().map(|id| vec![id.as_str()]).collect())
+            .unwrap();
+        let large = table.files().unwrap()[0].size;
+        for id in ["-1", "-2", "-3"] {
+            table.insert(vec![vec![id]]).unwrap();
+        }
+
+        let tiered = CompactionPolicy::SizeTiered {
+            min_size: large / 2,
+            tier_factor: 4,
+            min_files: 3,
+        };
+        let metrics = table.optimize(&tiered).unwrap();
+        assert_eq!((metrics.files_removed, metrics.files_added), (3, 1));
+        let mut sizes = table
+            .files()
+            .unwrap()
+            .iter()
+            .map(|f| f.size)
+            .collect::<Vec<u64>>();
+        sizes.sort();
+        assert_eq!((sizes.len(), sizes[1]), (2, large));
+
+        // Too few small files are left to fill a tier
+        table.insert(vec![vec!["-4"]]).unwrap();
+        let metrics = table.optimize(&tiered).unwrap();
+        assert_eq!(metrics.files_removed, 0);
+        assert_eq!(metrics.version, table.version().unwrap());
+
+        let packed = CompactionPolicy::BinPack {
+            target_size: large * 4,
+        };
+        assert_eq!(table.optimize(&packed).unwrap().files_removed, 3);
+        assert_eq!(table.files().unwrap().len(), 1);
+        assert_eq!(table.count().unwrap(), 5004);
+        assert_log_invariants(temp.path());
+    }
+
+    #[test]
+    fn archive_moves_rarely_read_files() {
+        let temp = TempTable::new(vec![("id", "int")]).unwrap();
+        let path = format!("{}/../events", temp.path());
+        let table = DeltaTable::create(&path, vec![("id", "int")])
+            .unwrap()
+            .with_usage_tracking();
+        table.insert(vec![vec!["1"]]).unwrap();
+        table.insert(vec![vec!["2"]]).unwrap();
+        // A limited query only reads one of the files
+        table.query("SELECT id FROM events LIMIT 1").unwrap();
+        let read = table.file_usage().unwrap();
+        let read = read.iter().find(|usage| usage.reads > 0).unwrap();
+        let cold = table
+            .files()
+            .unwrap()
+            .into_iter()
+            .map(|file| file.path)
+            .find(|path| *path != read.path)
+            .unwrap();
+
+        let destination = format!("{}/../archive", temp.path());
+        let mut options = ArchiveOptions {
+            destination: destination.clone(),
+            min_age: Duration::from_secs(3600),
+            max_reads: Some(0),
+        };
+        let metrics = table.archive(&options).unwrap();
+        assert!(metrics.files_archived.is_empty());
+        assert_eq!(metrics.version, table.version().unwrap());
+
+        options.min_age = Duration::ZERO;
+        let metrics = table.archive(&options).unwrap();
+        assert_eq!(metrics.files_archived, [cold.as_str()]);
+        let files = table.files().unwrap();
+        let archived = files
+            .iter()
+            .find(|f| f.path.starts_with("file://"))
+            .unwrap();
+        assert!(archived.path.ends_with(&cold));
+        assert!(Path::new(&format!("{}/{}", destination, cold)).is_file());
+        // The original stays for older versions until it's vacuumed
+        assert!(Path::new(&format!("{}/{}", path, cold)).is_file());
+        assert_eq!(ids(&table), vec![1, 2]);
+
+        // Archived files aren't archived again
+        options.max_reads = None;
+        let metrics = table.archive(&options).unwrap();
+        assert_eq!(metrics.files_archived, [read.path.as_str()]);
+        assert_log_invariants(&path);
+    }
+
+    #[test]
+    fn read_policies_mask_columns_for_their_handle() {
+        let temp = TempTable::new(vec![("id", "int")]).unwrap();
+        let path = format!("{}/../people", temp.path());
+        let table = DeltaTable::create(
+            &path,
+            vec![
+                ("id", "int"),
+                ("email", "text NULL"),
+                ("phone", "text NULL"),
+            ],
+        )
+        .unwrap();
+        table
+            .insert(vec![
+                vec![Some("1"), Some("a@x.com"), Some("555")],
+                vec![Some("2"), Some("a@x.com"), None],
+                vec![Some("3"), None, Some("556")],
+            ])
+            .unwrap();
+
+        let masks = ColumnMasks::new()
+            .with("email", Mask::Hash)
+            .with("phone", Mask::Redact("***".to_owned()))
+            .with("id", Mask::Null);
+        let restricted = DeltaTable::open(&path).unwrap().with_read_policy(masks);
+        let rows = restricted.to_polars().unwrap();
+        assert_eq!(rows["id"].null_count(), 3);
+        assert_eq!(rows["id"].dtype(), &DataType::Int32);
+        let emails = rows["email"].utf8().unwrap();
+        assert_eq!(emails.get(0), emails.get(1));
+        assert_ne!(emails.get(0), Some("a@x.com"));
+        assert_eq!(emails.get(2), None);
+        let phones = rows["phone"].utf8().unwrap().into_iter();
+        assert_eq!(phones.collect::<Vec<_>>(), [Some("***"), None, Some("***")]);
+
+        // Queries see the masked values too, and can still group by them
+        let groups = restricted
+            .query("SELECT email, count(*) AS n FROM people GROUP BY email")
+            .unwrap();
+        assert_eq!(groups.height(), 2);
+        assert!(!format!("{:?}", groups).contains("a@x.com"));
+
+        // Other handles read the table as stored
+        let rows = table.to_polars().unwrap();
+        assert_eq!(rows["email"].utf8().unwrap().get(0), Some("a@x.com"));
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn injected_faults_leave_the_log_intact() {
+        use crate::testing::{inject_fault, FaultPoint};
+
+        let (temp, table) = table_with_rows(&["1"]);
+        let other = TempTable::new(vec![("id", "int")]).unwrap();
+        let points = [
+            FaultPoint::AfterDataWrite,
+            FaultPoint::BeforeCommit,
+            FaultPoint::DuringRename,
+        ];
+        for point in points {
+            let guard = inject_fault(temp.path(), point, 1);
+            // Writes to other tables don't hit the fault
+            other.table().unwrap().insert(vec![vec!["1"]]).unwrap();
+            table.insert(vec![vec!["2"]]).unwrap();
+            assert!(!guard.triggered());
+
+            assert!(matches!(
+                table.insert(vec![vec!["3"]]),
+                Err(DeltaError::IOError(_))
+            ));
+            assert!(guard.triggered());
+            assert_log_invariants(temp.path());
+            table.delete("id = 2").unwrap();
+        }
+
+        assert_eq!(ids(&temp.table().unwrap()), vec![1]);
+        assert_eq!(other.table().unwrap().count().unwrap(), 3);
+    }
+
+    #[test]
+    fn resent_txn_is_skipped() {
+        let (temp, table) = table_with_rows(&[]);
+        let first = table.insert_with_txn(vec![vec!["1"]], "job", 0).unwrap();
+        assert!(!first.skipped);
+        assert_eq!(table.app_version("job").unwrap(), Some(0));
+
+        let resent = table.insert_with_txn(vec![vec!["1"]], "job", 0).unwrap();
+        assert!(resent.skipped);
+        assert_eq!(resent.version, first.version);
+        assert_eq!(resent.rows_written, 0);
+        assert_eq!(table.count().unwrap(), 1);
+
+        let next = table.insert_with_txn(vec![vec!["2"]], "job", 1).unwrap();
+        assert!(!next.skipped);
+        assert_eq!(next.version, first.version + 1);
+        assert_eq!(table.app_version("job").unwrap(), Some(1));
+        assert_eq!(table.count().unwrap(), 2);
+        assert_log_invariants(temp.path());
+    }
+
+    #[test]
+    fn deletes_and_updates_skip_files_their_stats_rule_out() {
+        let (temp, table) = table_with_rows(&["1", "2", "3"]);
+        table
+            .insert(vec![vec!["10"], vec!["11"], vec!["12"]])
+            .unwrap();
+
+        let metrics = table.delete("id > 10").unwrap();
+        assert_eq!(metrics.rows_deleted, 2);
+        assert_eq!(metrics.files_scanned, 1);
+        assert_eq!(metrics.pruning.pruned_by_stats, 1);
+
+        let metrics = table.update(&[("id", "id + 100")], "id < 3").unwrap();
+        assert_eq!(metrics.rows_updated, 2);
+        assert_eq!(metrics.files_scanned, 1);
+        assert_eq!(metrics.pruning.pruned_by_stats, 1);
+
+        assert_eq!(ids(&table), [3, 10, 101, 102]);
+        assert_log_invariants(temp.path());
+    }
+
+    #[test]
+    fn delete_keys_only_reads_files_that_could_hold_the_keys() {
+        let (temp, table) = table_with_rows(&["1", "2", "3"]);
+        table.insert(vec![vec!["10"], vec!["11"]]).unwrap();
+
+        let keys = df!("id" => [2, 3, 7, 11]).unwrap();
+        let metrics = table.delete_keys(keys).unwrap();
+        assert_eq!(metrics.rows_deleted, 3);
+        assert_eq!(metrics.files_scanned, 2);
+        assert_eq!(ids(&table), [1, 10]);
+
+        // Keys in the gaps between files' ranges, or null, match nothing
+        let keys = DataFrame::new(vec![Series::new("id", [Some(5), None])]).unwrap();
+        let metrics = table.delete_keys(keys).unwrap();
+        assert_eq!(metrics.rows_deleted, 0);
+        assert_eq!(metrics.files_scanned, 0);
+        assert_eq!(metrics.pruning.pruned_by_stats, 2);
+        assert!(matches!(
+            table.delete_keys(df!("name" => ["a"]).unwrap()),
+            Err(DeltaError::SchemaMismatch(_))
+        ));
+        assert_log_invariants(temp.path());
+    }
+
+    #[test]
+    fn timestamps_are_stored_in_the_configured_unit() {
+        let temp = TempTable::new(vec![("id", "int")]).unwrap();
+        let table = DeltaTable::create(
+            &format!("{}/../events", temp.path()),
+            vec![("at", "timestamp")],
+        )
+        .unwrap()
+        .with_write_options(WriteOptions {
+            timestamp_unit: TimeUnit::Milliseconds,
+            ..Default::default()
+        });
+        table
+            .insert(vec![vec!["2024-01-01 00:00:00.001234"]])
+            .unwrap();
+
+        let add = &table.snapshot().unwrap().files[0];
+        assert!(add.modification_time > 1_700_000_000_000);
+        let file = fs::File::open(table.data_file_path(&add.path).unwrap()).unwrap();
+        let stored = ParquetReader::new(file).finish().unwrap();
+        assert_eq!(
+            stored["at"].dtype(),
+            &DataType::Datetime(TimeUnit::Milliseconds, None)
+        );
+
+        // Read back in the table's type, without what didn't fit
+        let at = table.to_polars().unwrap()["at"].clone();
+        assert_eq!(
+            at.dtype(),
+            &DataType::Datetime(TimeUnit::Microseconds, None)
+        );
+        assert_eq!(at.datetime().unwrap().get(0), Some(1_704_067_200_001_000));
+    }
+
+    #[test]
+    fn row_filters_narrow_counts_reads_and_deletes() {
+        let (temp, table) = table_with_rows(&["1", "2", "3", "4"]);
+        let filtered = table
+            .clone()
+            .with_row_filter("id > 1")
+            .unwrap()
+            .with_row_filter("id < 4")
+            .unwrap();
+        assert_eq!(filtered.count().unwrap(), 2);
+        assert_eq!(ids(&filtered), [2, 3]);
+        assert_eq!(filtered.head(10).unwrap().height(), 2);
+
+        // Rows outside the filter are neither deleted nor updated
+        assert_eq!(filtered.delete("id <> 2").unwrap().rows_deleted, 1);
+        let metrics = filtered.update(&[("id", "id + 10")], "true").unwrap();
+        assert_eq!(metrics.rows_updated, 1);
+        assert_eq!(ids(&table), [1, 4, 12]);
+        assert_eq!(filtered.count().unwrap(), 0);
+        assert!(filtered.dedupe(&["id"]).is_err());
+        assert_log_invariants(temp.path());
     }
 }
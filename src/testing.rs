@@ -0,0 +1,247 @@
+// Helpers for testing code that uses this crate, in particular how it
+// behaves with several concurrent writers. Only available with the
+// `testing` feature, and to this crate's own tests.
+
+use crate::{
+    actions::Action,
+    error::DeltaError,
+    partition::{decode_path, is_absolute_uri},
+    table::DeltaTable,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    thread,
+};
+use uuid::Uuid;
+
+// Places where a write can be made to fail with `inject_fault`, as if the
+// process crashed there
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FaultPoint {
+    // A data file was written but nothing has been committed yet
+    AfterDataWrite,
+    // Just before a commit is written to the log
+    BeforeCommit,
+    // The commit was written under its temporary name but not moved into
+    // place in the log
+    DuringRename,
+}
+
+// Faults waiting to be hit, by table directory and point, with how many
+// more times each is passed before it fails
+type Faults = HashMap<(PathBuf, FaultPoint), usize>;
+
+fn faults() -> &'static Mutex<Faults> {
+    static FAULTS: OnceLock<Mutex<Faults>> = OnceLock::new();
+    FAULTS.get_or_init(Default::default)
+}
+
+// Faults are keyed by the table's canonical directory, so the different
+// paths a table may be opened by all hit them
+fn fault_key(table: &Path, point: FaultPoint) -> (PathBuf, FaultPoint) {
+    let dir = fs::canonicalize(table).unwrap_or_else(|_| table.to_path_buf());
+    (dir, point)
+}
+
+// Make a write to the table at `path` reaching `point` fail after it's been
+// passed `skip` times, in any thread. Writes to other tables are never
+// affected, so tests injecting faults can run alongside others. The fault
+// is hit once, and cleared when the guard is dropped if it hasn't been.
+// Whatever the write did up to there is left behind like after a crash, so
+// `check_log_invariants` can check nothing was corrupted.
+pub fn inject_fault(path: &str, point: FaultPoint, skip: usize) -> FaultGuard {
+    let key = fault_key(Path::new(path), point);
+    let mut faults = faults().lock().unwrap_or_else(|e| e.into_inner());
+    faults.insert(key.clone(), skip);
+    FaultGuard { key }
+}
+
+pub struct FaultGuard {
+    key: (PathBuf, FaultPoint),
+}
+
+impl FaultGuard {
+    // Whether the fault was hit
+    pub fn triggered(&self) -> bool {
+        let faults = faults().lock().unwrap_or_else(|e| e.into_inner());
+        !faults.contains_key(&self.key)
+    }
+}
+
+impl Drop for FaultGuard {
+    fn drop(&mut self) {
+        let mut faults = faults().lock().unwrap_or_else(|e| e.into_inner());
+        faults.remove(&self.key);
+    }
+}
+
+// Fail if a fault was injected at `point` for the table in the directory
+// `table`
+#[cfg(feature = "testing")]
+pub(crate) fn fail_point(table: &Path, point: FaultPoint) -> Result<(), DeltaError> {
+    let mut faults = faults().lock().unwrap_or_else(|e| e.into_inner());
+    if faults.is_empty() {
+        return Ok(());
+    }
+
+    let key = fault_key(table, point);
+    match faults.get_mut(&key) {
+        Some(0) => {
+            faults.remove(&key);
+            Err(DeltaError::IOError(std::io::Error::other(format!(
+                "injected fault at {:?}",
+                point
+            ))))
+        }
+        Some(skip) => {
+            *skip -= 1;
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
+
+// A table in its own temporary directory, deleted when this is dropped
+pub struct TempTable {
+    dir: String,
+    path: String,
+}
+
+impl TempTable {
+    pub fn new(schema: Vec<(&str, &str)>) -> Result<TempTable, DeltaError> {
+        let dir = env::temp_dir()
+            .join(format!("delta-testing-{}", Uuid::new_v4()))
+            .to_string_lossy()
+            .into_owned();
+        fs::create_dir_all(&dir)?;
+
+        let path = format!("{}/table", dir);
+        DeltaTable::create(&path, schema)?;
+
+        Ok(TempTable { dir, path })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    // A new handle to the table, as a separate process would open it
+    pub fn table(&self) -> Result<DeltaTable, DeltaError> {
+        DeltaTable::open(&self.path)
+    }
+}
+
+impl Drop for TempTable {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+// Run `writer` on `n` threads at once, each with its own handle to the
+// table at `path`. The writer gets its index and the handle. Results are
+// returned in index order.
+pub fn run_concurrent_writers<T, F>(path: &str, n: usize, writer: F) -> Vec<Result<T, DeltaError>>
+where
+    T: Send,
+    F: Fn(usize, DeltaTable) -> Result<T, DeltaError> + Sync,
+{
+    thread::scope(|scope| {
+        let handles = (0..n)
+            .map(|i| {
+                let writer = &writer;
+                scope.spawn(move || writer(i, DeltaTable::open(path)?))
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("writer thread panicked"))
+            .collect()
+    })
+}
+
+// Check that the log at `path` is well formed:
+//  - commits are numbered 0..n with no gaps
+//  - commit 0 contains the table metadata
+//  - every line of every commit is a valid action
+//  - files are only added while not live and only removed while live
+//  - every live file exists on disk
+pub fn check_log_invariants(path: &str) -> Result<(), String> {
+    let logs_dir = format!("{}/_delta_log", path);
+    let mut versions = vec![];
+    for entry in fs::read_dir(&logs_dir).map_err(|e| e.to_string())? {
+        let name = entry.map_err(|e| e.to_string())?.file_name();
+        let name = name.to_string_lossy();
+        if let Some(version) = name.strip_suffix(".json") {
+            let version = version
+                .parse::<i64>()
+                .map_err(|_| format!("unexpected log file {}", name))?;
+            versions.push(version);
+        }
+    }
+    versions.sort();
+
+    for (expected, version) in versions.iter().enumerate() {
+        if *version != expected as i64 {
+            return Err(format!("expected commit {}, found {}", expected, version));
+        }
+    }
+
+    let mut live: HashSet<String> = HashSet::new();
+    for version in versions {
+        let commit = fs::read_to_string(format!("{}/{:0>20}.json", logs_dir, version))
+            .map_err(|e| e.to_string())?;
+
+        let mut has_metadata = false;
+        for line in commit.lines() {
+            let action = serde_json::from_str::<Action>(line)
+                .map_err(|e| format!("commit {} has an invalid action: {}", version, e))?;
+
+            match action {
+                Action::Add(add) => {
+                    if !live.insert(add.path.clone()) {
+                        return Err(format!("commit {} re-adds live file {}", version, add.path));
+                    }
+                }
+                Action::Remove(remove) => {
+                    if !live.remove(&remove.path) {
+                        return Err(format!(
+                            "commit {} removes file {} which isn't live",
+                            version, remove.path
+                        ));
+                    }
+                }
+                Action::Metadata(_) => has_metadata = true,
+                Action::Protocol(_) | Action::Txn(_) | Action::CommitInfo(_) => {}
+            }
+        }
+
+        if version == 0 && !has_metadata {
+            return Err("commit 0 has no metadata".to_owned());
+        }
+    }
+
+    for file in live {
+        // Files moved by `DeltaTable::archive` have absolute URIs, and only
+        // local ones can be checked
+        let local = match file.strip_prefix("file:") {
+            Some(local) => decode_path(local.trim_start_matches("//")),
+            None if is_absolute_uri(&file) => continue,
+            None => format!("{}/{}", path, decode_path(&file)),
+        };
+        if !Path::new(&local).exists() {
+            return Err(format!("live file {} is missing", file));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn assert_log_invariants(path: &str) {
+    if let Err(e) = check_log_invariants(path) {
+        panic!("log invariant violated for {}: {}", path, e);
+    }
+}
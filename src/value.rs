@@ -0,0 +1,472 @@
+use crate::{
+    error::DeltaError,
+    options::WriteOptions,
+    schema::{DeltaTableColumnDefinition, DeltaTableType},
+};
+use polars::{
+    export::chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime},
+    prelude::*,
+};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+// Days between 0001-01-01 (chrono's day 1) and the unix epoch
+const UNIX_EPOCH_DAY: i32 = 719_163;
+
+// A single typed cell, for inserting data without going through strings
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeltaValue {
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    Bool(bool),
+    Str(String),
+    Date(NaiveDate),
+    Timestamp(NaiveDateTime),
+    Null,
+}
+
+impl DeltaValue {
+    fn type_name(&self) -> &'static str {
+        match self {
+            DeltaValue::Int(_) => "int",
+            DeltaValue::Long(_) => "long",
+            DeltaValue::Float(_) => "float",
+            DeltaValue::Double(_) => "double",
+            DeltaValue::Bool(_) => "bool",
+            DeltaValue::Str(_) => "string",
+            DeltaValue::Date(_) => "date",
+            DeltaValue::Timestamp(_) => "timestamp",
+            DeltaValue::Null => "null",
+        }
+    }
+
+    fn as_integer(&self) -> Option<i64> {
+        match self {
+            DeltaValue::Int(v) => Some(*v as i64),
+            DeltaValue::Long(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    fn as_double(&self) -> Option<f64> {
+        match self {
+            DeltaValue::Float(v) => Some(*v as f64),
+            DeltaValue::Double(v) => Some(*v),
+            _ => self.as_integer().map(|v| v as f64),
+        }
+    }
+
+    // The value as a SQL literal of its type, see `sql::bind_params`.
+    // Polars' SQL has no date or timestamp literals, so those are cast from
+    // days and milliseconds since the epoch, which is the precision SQL
+    // timestamps have in polars.
+    pub(crate) fn to_sql(&self) -> Result<String, DeltaError> {
+        Ok(match self {
+            DeltaValue::Int(v) => format!("({})", v),
+            DeltaValue::Long(v) => format!("({})", v),
+            DeltaValue::Float(v) if v.is_finite() => format!("({:?})", v),
+            DeltaValue::Double(v) if v.is_finite() => format!("({:?})", v),
+            DeltaValue::Float(_) | DeltaValue::Double(_) => {
+                return Err(DeltaError::InvalidQuery(
+                    "NaN and infinity can't be bound as parameters".to_owned(),
+                ))
+            }
+            DeltaValue::Bool(v) => v.to_string().to_uppercase(),
+            DeltaValue::Str(v) => format!("'{}'", v.replace('\'', "''")),
+            DeltaValue::Date(v) => {
+                format!("CAST({} AS DATE)", v.num_days_from_ce() - UNIX_EPOCH_DAY)
+            }
+            DeltaValue::Timestamp(v) => format!("CAST({} AS TIMESTAMP)", v.timestamp_millis()),
+            DeltaValue::Null => "NULL".to_owned(),
+        })
+    }
+
+    // Convert a serialized struct field. JSON has no date types, so dates
+    // and timestamps arrive as strings (which is how chrono serializes
+    // them) and are parsed based on the column's type.
+    pub fn from_json(value: Value, typ: &DeltaTableType) -> Result<DeltaValue, String> {
+        match value {
+            Value::Null => Ok(DeltaValue::Null),
+            Value::Bool(v) => Ok(DeltaValue::Bool(v)),
+            Value::Number(n) => match (n.as_i64(), n.as_f64()) {
+                (Some(v), _) => Ok(DeltaValue::Long(v)),
+                (None, Some(v)) if n.is_f64() => Ok(DeltaValue::Double(v)),
+                _ => Err(format!("number {} is out of range", n)),
+            },
+            Value::String(s) => match typ {
+                DeltaTableType::Date => NaiveDate::parse_from_str(&s, "%Y-%m-%d")
+                    .map(DeltaValue::Date)
+                    .map_err(|_| format!("'{}' is not a date", s)),
+                DeltaTableType::Timestamp => parse_timestamp(&s)
+                    .map(DeltaValue::Timestamp)
+                    .ok_or_else(|| format!("'{}' is not a timestamp", s)),
+                _ => Ok(DeltaValue::Str(s)),
+            },
+            Value::Array(_) | Value::Object(_) => Err("nested values are not supported".to_owned()),
+        }
+    }
+}
+
+// Timestamps with an offset are converted to UTC, naive ones are assumed
+// to already be in UTC.
+fn parse_timestamp(s: &str) -> Option<NaiveDateTime> {
+    if let Ok(ts) = DateTime::parse_from_rfc3339(s) {
+        return Some(ts.naive_utc());
+    }
+
+    ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+}
+
+// A value given as text for a column of `field`'s type, see
+// `DeltaTable::insert`. Dates, timestamps and bools are parsed here, as
+// described by `WriteOptions::date_format` and
+// `WriteOptions::strict_booleans`, since polars' cast only takes some of
+// those formats. Other values are left as text and cast when the frame is
+// aligned. Errors name the offending row.
+pub fn text_column(
+    field: &DeltaTableColumnDefinition,
+    values: &[Option<&str>],
+    options: &WriteOptions,
+) -> Result<Series, DeltaError> {
+    let invalid = |row: usize, text: &str| DeltaError::InvalidValue {
+        row,
+        column: field.name.clone(),
+        message: format!("'{}' is not a valid {}", text, field.typ.to_sql_type()),
+    };
+
+    let series = match field.typ {
+        DeltaTableType::Date => {
+            let format = options.date_format.as_deref();
+            let days = values
+                .iter()
+                .enumerate()
+                .map(|(row, text)| match text {
+                    Some(text) => parse_date_text(text, format)
+                        .map(|date| Some(date.num_days_from_ce() - UNIX_EPOCH_DAY))
+                        .ok_or_else(|| invalid(row, text)),
+                    None => Ok(None),
+                })
+                .collect::<Result<Vec<Option<i32>>, DeltaError>>()?;
+            Series::new(&field.name, days)
+        }
+        DeltaTableType::Timestamp => {
+            let format = options.timestamp_format.as_deref();
+            let micros = values
+                .iter()
+                .enumerate()
+                .map(|(row, text)| match text {
+                    Some(text) => parse_timestamp_text(text, format)
+                        .map(|ts| Some(ts.timestamp_micros()))
+                        .ok_or_else(|| invalid(row, text)),
+                    None => Ok(None),
+                })
+                .collect::<Result<Vec<Option<i64>>, DeltaError>>()?;
+            Series::new(&field.name, micros)
+        }
+        DeltaTableType::Boolean => {
+            let expected = match options.strict_booleans {
+                true => "true or false",
+                false => "true, false, t, f, 1 or 0",
+            };
+            let bools = values
+                .iter()
+                .enumerate()
+                .map(|(row, text)| match text {
+                    Some(text) => parse_bool_text(text, options.strict_booleans)
+                        .map(Some)
+                        .ok_or_else(|| DeltaError::InvalidValue {
+                            row,
+                            column: field.name.clone(),
+                            message: format!(
+                                "'{}' is not a valid BOOL, expected {}",
+                                text, expected
+                            ),
+                        }),
+                    None => Ok(None),
+                })
+                .collect::<Result<Vec<Option<bool>>, DeltaError>>()?;
+            Series::new(&field.name, bools)
+        }
+        _ => return Ok(Series::new(&field.name, values)),
+    };
+
+    Ok(series.cast(&field.typ.to_polars_type())?)
+}
+
+fn parse_bool_text(text: &str, strict: bool) -> Option<bool> {
+    match (text.trim().to_lowercase().as_str(), strict) {
+        ("true", _) | ("t" | "1", false) => Some(true),
+        ("false", _) | ("f" | "0", false) => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_date_text(text: &str, format: Option<&str>) -> Option<NaiveDate> {
+    let text = text.trim();
+    if let Some(ts) = parse_epoch_millis(text) {
+        return Some(ts.date());
+    }
+    NaiveDate::parse_from_str(text, format.unwrap_or("%Y-%m-%d")).ok()
+}
+
+// Like `parse_timestamp`, but with `format` in place of the default ones
+// when it's set, and dates taken as midnight
+fn parse_timestamp_text(text: &str, format: Option<&str>) -> Option<NaiveDateTime> {
+    let text = text.trim();
+    if let Some(ts) = parse_epoch_millis(text) {
+        return Some(ts);
+    }
+    match format {
+        Some(format) => NaiveDateTime::parse_from_str(text, format)
+            .ok()
+            .or_else(|| Some(DateTime::parse_from_str(text, format).ok()?.naive_utc())),
+        None => parse_timestamp(text).or_else(|| {
+            NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .ok()?
+                .and_hms_opt(0, 0, 0)
+        }),
+    }
+}
+
+fn parse_epoch_millis(text: &str) -> Option<NaiveDateTime> {
+    let digits = text.strip_prefix('-').unwrap_or(text);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    NaiveDateTime::from_timestamp_millis(text.parse().ok()?)
+}
+
+// A query result value as JSON, in the form serde expects when
+// deserializing it into a struct field. Dates and timestamps become strings,
+// which chrono's types deserialize from.
+pub fn to_json(value: &AnyValue) -> Value {
+    match value {
+        AnyValue::Null => Value::Null,
+        AnyValue::Boolean(v) => Value::Bool(*v),
+        AnyValue::Utf8(v) => Value::String((*v).to_owned()),
+        AnyValue::Int8(v) => (*v).into(),
+        AnyValue::Int16(v) => (*v).into(),
+        AnyValue::Int32(v) => (*v).into(),
+        AnyValue::Int64(v) => (*v).into(),
+        AnyValue::UInt8(v) => (*v).into(),
+        AnyValue::UInt16(v) => (*v).into(),
+        AnyValue::UInt32(v) => (*v).into(),
+        AnyValue::UInt64(v) => (*v).into(),
+        // JSON has no NaN or infinity, so those end up as nulls
+        AnyValue::Float32(v) => serde_json::Number::from_f64(*v as f64)
+            .map(Value::Number)
+            .unwrap_or(Value::Null),
+        AnyValue::Float64(v) => serde_json::Number::from_f64(*v)
+            .map(Value::Number)
+            .unwrap_or(Value::Null),
+        AnyValue::Date(days) => NaiveDate::from_num_days_from_ce_opt(days + UNIX_EPOCH_DAY)
+            .map(|date| Value::String(date.to_string()))
+            .unwrap_or(Value::Null),
+        AnyValue::Datetime(v, unit, _) => {
+            let micros = match unit {
+                TimeUnit::Nanoseconds => v / 1_000,
+                TimeUnit::Microseconds => *v,
+                TimeUnit::Milliseconds => v * 1_000,
+            };
+            NaiveDateTime::from_timestamp_micros(micros)
+                .map(|ts| Value::String(ts.format("%Y-%m-%dT%H:%M:%S%.f").to_string()))
+                .unwrap_or(Value::Null)
+        }
+        v => Value::String(v.to_string()),
+    }
+}
+
+// Deserialize each row of `df` into a `T`, with columns matched to fields
+// by name
+pub fn deserialize_rows<T: DeserializeOwned>(df: &DataFrame) -> Result<Vec<T>, DeltaError> {
+    let mut records = Vec::with_capacity(df.height());
+    for row in 0..df.height() {
+        let mut record = serde_json::Map::new();
+        for series in df.get_columns() {
+            record.insert(series.name().to_owned(), to_json(&series.get(row)?));
+        }
+
+        let record = serde_json::from_value(Value::Object(record))
+            .map_err(|e| DeltaError::InvalidData(format!("row {}: {}", row, e)))?;
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+// Build a column of the given field's type from one cell of each row,
+// checking every cell against the field. Errors name the offending row.
+pub fn build_series(
+    field: &DeltaTableColumnDefinition,
+    index: usize,
+    rows: &[Vec<DeltaValue>],
+) -> Result<Series, DeltaError> {
+    let mismatch = |row: usize, value: &DeltaValue, reason: &str| DeltaError::InvalidValue {
+        row,
+        column: field.name.clone(),
+        message: format!("{} value {:?} {}", value.type_name(), value, reason),
+    };
+
+    let cells = rows
+        .iter()
+        .enumerate()
+        .map(|(row, values)| (row, &values[index]));
+
+    // Nulls in a generated column are filled in with the computed value
+    if !field.nullable && field.generation_expression().is_none() {
+        if let Some((row, value)) = cells.clone().find(|(_, v)| **v == DeltaValue::Null) {
+            return Err(mismatch(
+                row,
+                value,
+                "is not allowed in a non-nullable column",
+            ));
+        }
+    }
+
+    let series = match field.typ {
+        DeltaTableType::Long
+        | DeltaTableType::Integer
+        | DeltaTableType::Short
+        | DeltaTableType::Byte => {
+            let (min, max) = match field.typ {
+                DeltaTableType::Long => (i64::MIN, i64::MAX),
+                DeltaTableType::Integer => (i32::MIN as i64, i32::MAX as i64),
+                DeltaTableType::Short => (i16::MIN as i64, i16::MAX as i64),
+                _ => (i8::MIN as i64, i8::MAX as i64),
+            };
+
+            let values = cells
+                .map(|(row, value)| match value {
+                    DeltaValue::Null => Ok(None),
+                    _ => match value.as_integer() {
+                        Some(v) if v >= min && v <= max => Ok(Some(v)),
+                        Some(_) => Err(mismatch(row, value, "is out of range")),
+                        None => Err(mismatch(row, value, "is not an integer")),
+                    },
+                })
+                .collect::<Result<Vec<Option<i64>>, DeltaError>>()?;
+            Series::new(&field.name, values)
+        }
+        DeltaTableType::Float | DeltaTableType::Double => {
+            let values = cells
+                .map(|(row, value)| match value {
+                    DeltaValue::Null => Ok(None),
+                    _ => value
+                        .as_double()
+                        .map(Some)
+                        .ok_or_else(|| mismatch(row, value, "is not a number")),
+                })
+                .collect::<Result<Vec<Option<f64>>, DeltaError>>()?;
+            Series::new(&field.name, values)
+        }
+        DeltaTableType::Boolean => {
+            let values = cells
+                .map(|(row, value)| match value {
+                    DeltaValue::Null => Ok(None),
+                    DeltaValue::Bool(v) => Ok(Some(*v)),
+                    _ => Err(mismatch(row, value, "is not a bool")),
+                })
+                .collect::<Result<Vec<Option<bool>>, DeltaError>>()?;
+            Series::new(&field.name, values)
+        }
+        DeltaTableType::String => {
+            let values = cells
+                .map(|(row, value)| match value {
+                    DeltaValue::Null => Ok(None),
+                    DeltaValue::Str(v) => Ok(Some(v.as_str())),
+                    _ => Err(mismatch(row, value, "is not a string")),
+                })
+                .collect::<Result<Vec<Option<&str>>, DeltaError>>()?;
+            Series::new(&field.name, values)
+        }
+        DeltaTableType::Date => {
+            let values = cells
+                .map(|(row, value)| match value {
+                    DeltaValue::Null => Ok(None),
+                    DeltaValue::Date(v) => Ok(Some(v.num_days_from_ce() - UNIX_EPOCH_DAY)),
+                    _ => Err(mismatch(row, value, "is not a date")),
+                })
+                .collect::<Result<Vec<Option<i32>>, DeltaError>>()?;
+            Series::new(&field.name, values)
+        }
+        DeltaTableType::Timestamp => {
+            let values = cells
+                .map(|(row, value)| match value {
+                    DeltaValue::Null => Ok(None),
+                    DeltaValue::Timestamp(v) => Ok(Some(v.timestamp_micros())),
+                    DeltaValue::Date(v) => {
+                        Ok(v.and_hms_opt(0, 0, 0).map(|ts| ts.timestamp_micros()))
+                    }
+                    _ => Err(mismatch(row, value, "is not a timestamp")),
+                })
+                .collect::<Result<Vec<Option<i64>>, DeltaError>>()?;
+            Series::new(&field.name, values)
+        }
+    };
+
+    Ok(series.cast(&field.typ.to_polars_type())?)
+}
+
+impl From<i32> for DeltaValue {
+    fn from(value: i32) -> Self {
+        DeltaValue::Int(value)
+    }
+}
+
+impl From<i64> for DeltaValue {
+    fn from(value: i64) -> Self {
+        DeltaValue::Long(value)
+    }
+}
+
+impl From<f32> for DeltaValue {
+    fn from(value: f32) -> Self {
+        DeltaValue::Float(value)
+    }
+}
+
+impl From<f64> for DeltaValue {
+    fn from(value: f64) -> Self {
+        DeltaValue::Double(value)
+    }
+}
+
+impl From<bool> for DeltaValue {
+    fn from(value: bool) -> Self {
+        DeltaValue::Bool(value)
+    }
+}
+
+impl From<&str> for DeltaValue {
+    fn from(value: &str) -> Self {
+        DeltaValue::Str(value.to_owned())
+    }
+}
+
+impl From<String> for DeltaValue {
+    fn from(value: String) -> Self {
+        DeltaValue::Str(value)
+    }
+}
+
+impl From<NaiveDate> for DeltaValue {
+    fn from(value: NaiveDate) -> Self {
+        DeltaValue::Date(value)
+    }
+}
+
+impl From<NaiveDateTime> for DeltaValue {
+    fn from(value: NaiveDateTime) -> Self {
+        DeltaValue::Timestamp(value)
+    }
+}
+
+impl<T: Into<DeltaValue>> From<Option<T>> for DeltaValue {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(DeltaValue::Null, Into::into)
+    }
+}